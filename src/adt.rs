@@ -0,0 +1,195 @@
+//! Artificial double tracking (ADT)
+//!
+//! Thickens a lead by mixing in a second "take": a copy of the signal through a
+//! short delay (20 to 40 ms) whose time wanders by up to a millisecond at random,
+//! the way a player never lands a double exactly on the first take. The slow
+//! wander also bends the copy's pitch by a few cents. The dry signal and the copy
+//! are panned to opposite sides on the same constant-power law as the auto-pan,
+//! so the spread widens them apart without changing the loudness. Both come from
+//! the mono sum of the input, as when doubling a single guitar or vocal.
+
+use std::f64::consts::FRAC_1_SQRT_2;
+
+use crate::autopan::pan_gains;
+use crate::dsp::Xorshift32;
+
+/// Shortest delay of the copy in milliseconds
+const MIN_DELAY_MS: f64 = 20.0;
+/// Longest delay of the copy in milliseconds
+const MAX_DELAY_MS: f64 = 40.0;
+/// Largest random deviation of the delay time in milliseconds
+const WANDER_MS: f64 = 1.0;
+/// How often the wander picks a new target in seconds
+const WANDER_PERIOD_SECONDS: f64 = 0.25;
+/// Time constant of the glide to each wander target in seconds
+const WANDER_SMOOTHING_SECONDS: f64 = 0.2;
+
+/// Double tracker: the dry signal on one side, a wandering delayed copy on the other
+pub struct Adt {
+    /// Sample rate in Hz
+    sample_rate: f64,
+    /// Delay line holding the mono sum, sized for the longest delay plus wander
+    buffer: Vec<f64>,
+    /// Write position in the delay line
+    write_index: usize,
+    /// Nominal delay time in milliseconds
+    delay_ms: f64,
+    /// Stereo spread (0.0 = both centred, 1.0 = dry fully left, copy fully right)
+    spread: f64,
+    /// Current wander target in milliseconds
+    wander_target: f64,
+    /// Smoothed wander in milliseconds
+    wander: f64,
+    /// Smoothing coefficient for the wander
+    smoothing: f64,
+    /// Samples until the next wander target
+    countdown: usize,
+    /// Wander noise
+    random: Xorshift32,
+}
+
+impl Adt {
+    /// Create a double tracker with a 30 ms copy at 0.7 spread
+    pub fn new(sample_rate: usize) -> Self {
+        let sample_rate = sample_rate as f64;
+        let length = ((MAX_DELAY_MS + WANDER_MS) * sample_rate / 1000.0) as usize + 2;
+        Self {
+            sample_rate,
+            buffer: vec![0.0; length],
+            write_index: 0,
+            delay_ms: 30.0,
+            spread: 0.7,
+            wander_target: 0.0,
+            wander: 0.0,
+            smoothing: (-1.0 / (WANDER_SMOOTHING_SECONDS * sample_rate)).exp(),
+            countdown: 0,
+            random: Xorshift32::new(0x2545_F491),
+        }
+    }
+
+    /// Process a stereo input sample and return the dry signal and its double
+    pub fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let mono = (input.0 + input.1) * 0.5;
+
+        if self.countdown == 0 {
+            self.wander_target = self.random.bipolar() * WANDER_MS;
+            self.countdown = (WANDER_PERIOD_SECONDS * self.sample_rate) as usize;
+        }
+        self.countdown -= 1;
+        self.wander = self.wander_target + self.smoothing * (self.wander - self.wander_target);
+
+        // Linear interpolation between the two samples around the delay time
+        self.buffer[self.write_index] = mono;
+        let length = self.buffer.len();
+        let delay = (self.delay_ms + self.wander) * self.sample_rate / 1000.0;
+        let whole = delay.floor() as usize;
+        let fraction = delay - whole as f64;
+        let newer = self.buffer[(self.write_index + length - whole) % length];
+        let older = self.buffer[(self.write_index + length - whole - 1) % length];
+        let copy = newer + (older - newer) * fraction;
+        self.write_index = (self.write_index + 1) % length;
+
+        // Dry to the left, copy to the right; each at -3 dB so the pair keeps the level
+        let (dry_left, dry_right) = pan_gains(-self.spread);
+        let (copy_left, copy_right) = pan_gains(self.spread);
+        (
+            (mono * dry_left + copy * copy_left) * FRAC_1_SQRT_2,
+            (mono * dry_right + copy * copy_right) * FRAC_1_SQRT_2,
+        )
+    }
+
+    /// Set the delay of the copy in milliseconds (20 to 40)
+    pub fn set_delay_ms(&mut self, delay_ms: f64) {
+        self.delay_ms = delay_ms.clamp(MIN_DELAY_MS, MAX_DELAY_MS);
+    }
+
+    /// Current delay of the copy in milliseconds
+    pub fn delay_ms(&self) -> f64 {
+        self.delay_ms
+    }
+
+    /// Set how far apart the dry signal and the copy are panned (0.0 to 1.0)
+    pub fn set_spread(&mut self, spread: f64) {
+        self.spread = spread.clamp(0.0, 1.0);
+    }
+
+    /// Current stereo spread
+    pub fn spread(&self) -> f64 {
+        self.spread
+    }
+
+    /// Clear the delay line and settle the wander
+    pub fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.wander = 0.0;
+        self.wander_target = 0.0;
+        self.countdown = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Normalized correlation of two signals
+    fn correlation(a: &[f64], b: &[f64]) -> f64 {
+        let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+        let energy = |s: &[f64]| s.iter().map(|x| x * x).sum::<f64>().sqrt();
+        dot / (energy(a) * energy(b))
+    }
+
+    #[test]
+    fn test_mono_input_becomes_two_decorrelated_opposite_takes() {
+        // Smoothed noise on both channels, correlated over about 100 samples
+        let mut noise = Xorshift32::new(0x1234_5678);
+        let mut smoothed = 0.0;
+        let input: Vec<f64> = (0..44100)
+            .map(|_| {
+                smoothed = 0.99 * smoothed + noise.uniform();
+                smoothed
+            })
+            .collect();
+
+        let mut adt = Adt::new(44100);
+        adt.set_spread(1.0);
+        adt.set_delay_ms(30.0);
+        let output: Vec<(f64, f64)> = input.iter().map(|&x| adt.tick((x, x))).collect();
+        let left: Vec<f64> = output.iter().map(|o| o.0).collect();
+        let right: Vec<f64> = output.iter().map(|o| o.1).collect();
+
+        // Fully spread: the dry take on the left, the copy alone on the right
+        assert!(left.iter().zip(&input).all(|(l, x)| (l - x).abs() < 1e-9));
+        assert!(correlation(&left, &right).abs() < 0.1);
+
+        // The copy follows the input about 30 ms (1323 samples) later, but its
+        // drifting timing keeps it from being an exact delayed duplicate
+        let lag = 1323;
+        let follow = correlation(&right[lag..], &input[..input.len() - lag]);
+        assert!(follow > 0.5 && follow < 0.99, "{}", follow);
+
+        // Half spread still leans each take to its own side
+        let mut adt = Adt::new(44100);
+        adt.set_spread(0.5);
+        let output: Vec<(f64, f64)> = input.iter().map(|&x| adt.tick((x, x))).collect();
+        let left: Vec<f64> = output.iter().map(|o| o.0).collect();
+        let right: Vec<f64> = output.iter().map(|o| o.1).collect();
+        assert!(correlation(&left, &input) > correlation(&right, &input) + 0.3);
+    }
+
+    #[test]
+    fn test_settings_are_clamped() {
+        let mut adt = Adt::new(44100);
+        adt.set_delay_ms(5.0);
+        assert_eq!(adt.delay_ms(), 20.0);
+        adt.set_delay_ms(80.0);
+        assert_eq!(adt.delay_ms(), 40.0);
+        adt.set_spread(-1.0);
+        assert_eq!(adt.spread(), 0.0);
+
+        // The longest delay plus wander stays inside the delay line
+        for _ in 0..44100 {
+            let (left, right) = adt.tick((1.0, 1.0));
+            assert!(left.is_finite() && right.is_finite());
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! Automatic gain staging
+//!
+//! Measures the RMS level going into and coming out of an effect and applies
+//! makeup gain so toggling the effect doesn't cause a big jump in loudness.
+
+/// RMS averaging window in seconds
+const WINDOW_SECONDS: f64 = 0.3;
+/// Gain smoothing time in seconds
+const SMOOTHING_SECONDS: f64 = 0.5;
+/// Input RMS below which the gain is frozen (about -60 dBFS)
+const SILENCE_FLOOR: f64 = 0.001;
+/// Maximum correction in either direction (+/-12 dB)
+const MAX_GAIN: f64 = 4.0;
+
+/// Makeup gain tracker for a single effect
+pub struct AutoGain {
+    /// Smoothed mean-square level before the effect
+    input_power: f64,
+    /// Smoothed mean-square level after the effect
+    output_power: f64,
+    /// Averaging coefficient for the level trackers
+    power_coeff: f64,
+    /// Smoothing coefficient for the applied gain
+    gain_coeff: f64,
+    /// Currently applied gain
+    gain: f64,
+}
+
+impl AutoGain {
+    /// Create a new auto-gain tracker with unity gain
+    pub fn new(sample_rate: usize) -> Self {
+        let sample_rate = sample_rate as f64;
+        Self {
+            input_power: 0.0,
+            output_power: 0.0,
+            power_coeff: (-1.0 / (WINDOW_SECONDS * sample_rate)).exp(),
+            gain_coeff: (-1.0 / (SMOOTHING_SECONDS * sample_rate)).exp(),
+            gain: 1.0,
+        }
+    }
+
+    /// Measure one frame before and after the effect and return the compensated output
+    pub fn process(&mut self, input: (f64, f64), output: (f64, f64)) -> (f64, f64) {
+        let input_square = (input.0 * input.0 + input.1 * input.1) * 0.5;
+        let output_square = (output.0 * output.0 + output.1 * output.1) * 0.5;
+        self.input_power = self.power_coeff * self.input_power + (1.0 - self.power_coeff) * input_square;
+        self.output_power = self.power_coeff * self.output_power + (1.0 - self.power_coeff) * output_square;
+
+        // Freeze the gain on silence so the noise floor isn't boosted
+        if let Some(target) = Self::target_gain(self.input_power.sqrt(), self.output_power.sqrt()) {
+            self.gain = self.gain_coeff * self.gain + (1.0 - self.gain_coeff) * target;
+        }
+
+        (output.0 * self.gain, output.1 * self.gain)
+    }
+
+    /// Makeup gain that brings `output_rms` back to `input_rms`
+    ///
+    /// Returns None when the input is below the silence floor (gain should be held).
+    /// The result is limited to +/-12 dB.
+    pub fn target_gain(input_rms: f64, output_rms: f64) -> Option<f64> {
+        if input_rms < SILENCE_FLOOR {
+            return None;
+        }
+        if output_rms <= f64::EPSILON {
+            return Some(MAX_GAIN);
+        }
+        Some((input_rms / output_rms).clamp(1.0 / MAX_GAIN, MAX_GAIN))
+    }
+
+    /// Currently applied gain
+    pub fn gain(&self) -> f64 {
+        self.gain
+    }
+
+    /// Return to unity gain and clear the level trackers
+    pub fn reset(&mut self) {
+        self.input_power = 0.0;
+        self.output_power = 0.0;
+        self.gain = 1.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_gain_ratios() {
+        // Effect doubled the level -> halve it
+        assert!((AutoGain::target_gain(0.2, 0.4).unwrap() - 0.5).abs() < 1e-12);
+        // Effect halved the level -> double it
+        assert!((AutoGain::target_gain(0.4, 0.2).unwrap() - 2.0).abs() < 1e-12);
+        // Unchanged level -> unity
+        assert!((AutoGain::target_gain(0.3, 0.3).unwrap() - 1.0).abs() < 1e-12);
+        // Large changes are limited to +/-12 dB
+        assert_eq!(AutoGain::target_gain(0.5, 0.01), Some(MAX_GAIN));
+        assert_eq!(AutoGain::target_gain(0.01, 0.5), Some(1.0 / MAX_GAIN));
+    }
+
+    #[test]
+    fn test_silence_freezes_gain() {
+        assert_eq!(AutoGain::target_gain(0.0, 0.0), None);
+        assert_eq!(AutoGain::target_gain(SILENCE_FLOOR * 0.5, 0.1), None);
+
+        let mut auto_gain = AutoGain::new(1000);
+        // Converge on a 6 dB cut
+        for _ in 0..20000 {
+            auto_gain.process((0.5, 0.5), (1.0, 1.0));
+        }
+        let settled = auto_gain.gain();
+        assert!((settled - 0.5).abs() < 0.01);
+
+        // Silence with output noise: gain is held rather than pushed to +12 dB
+        for _ in 0..20000 {
+            auto_gain.process((0.0, 0.0), (0.0001, 0.0001));
+        }
+        assert!((auto_gain.gain() - settled).abs() < 0.01);
+    }
+}
@@ -0,0 +1,300 @@
+//! Parameter automation
+//!
+//! `automate r 0.9 30s` sweeps a reverb parameter from its current value to a
+//! goal over a duration, for slowly evolving ambient textures. A timer thread
+//! advances every running automation in small steps and applies the smoothed
+//! values through the chain lock, the same way the console and OSC settings do.
+//! Several parameters can be automated at once; a new automation of a parameter
+//! replaces the one already running on it.
+//!
+//! Linear curves move at a constant rate. Exponential curves move by a constant
+//! ratio, which sounds even for parameters heard on a log scale (a sweep from
+//! 0.1 to 1.0 passes 0.32 halfway).
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::chain::EffectChain;
+use crate::ipc::status;
+use crate::preset::{apply_value, Value};
+
+/// Interval between automation steps
+const STEP_INTERVAL: Duration = Duration::from_millis(20);
+/// Smallest value an exponential curve starts from or heads to
+const EXPONENTIAL_FLOOR: f64 = 0.001;
+
+/// Automatable parameters: console name, preset section and key
+pub const TARGETS: &[(&str, &str, &str)] = &[
+    ("r", "reverb", "room_size"),
+    ("p", "reverb", "dampening"),
+    ("x", "reverb", "width"),
+    ("diff", "reverb", "diffusion"),
+    ("shim", "reverb", "shimmer"),
+    ("pre", "reverb", "predelay_ms"),
+];
+
+/// Running automations, advanced by the timer thread
+static AUTOMATIONS: Mutex<Automations> = Mutex::new(Automations::new());
+
+/// Shape of the sweep from the start value to the goal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Curve {
+    Linear,
+    Exponential,
+}
+
+impl Curve {
+    /// Parse a curve name ("lin" or "exp")
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "lin" => Some(Curve::Linear),
+            "exp" => Some(Curve::Exponential),
+            _ => None,
+        }
+    }
+
+    /// Name used by the console
+    pub fn name(self) -> &'static str {
+        match self {
+            Curve::Linear => "lin",
+            Curve::Exponential => "exp",
+        }
+    }
+
+    /// Value a fraction `progress` (0.0 to 1.0) of the way from `start` to `goal`
+    fn interpolate(self, start: f64, goal: f64, progress: f64) -> f64 {
+        match self {
+            Curve::Linear => start + (goal - start) * progress,
+            Curve::Exponential => {
+                let start = start.max(EXPONENTIAL_FLOOR);
+                let goal = goal.max(EXPONENTIAL_FLOOR);
+                start * (goal / start).powf(progress)
+            }
+        }
+    }
+}
+
+/// One parameter sweeping towards a goal
+#[derive(Debug, Clone, PartialEq)]
+pub struct Automation {
+    /// Console name of the parameter
+    pub name: &'static str,
+    /// Preset section of the parameter
+    pub section: &'static str,
+    /// Preset key of the parameter
+    pub key: &'static str,
+    start: f64,
+    goal: f64,
+    /// Length of the sweep in seconds
+    duration_seconds: f64,
+    /// Time since the sweep started in seconds
+    elapsed_seconds: f64,
+    curve: Curve,
+}
+
+impl Automation {
+    /// Start a sweep of the parameter with console name `name`
+    pub fn new(name: &str, start: f64, goal: f64, duration_seconds: f64, curve: Curve) -> Result<Self, String> {
+        let &(name, section, key) = TARGETS
+            .iter()
+            .find(|&&(target, _, _)| target == name)
+            .ok_or_else(|| format!("Can't automate '{}' (expected one of {})", name, target_names()))?;
+        Ok(Self {
+            name,
+            section,
+            key,
+            start,
+            goal,
+            duration_seconds: duration_seconds.max(0.0),
+            elapsed_seconds: 0.0,
+            curve,
+        })
+    }
+
+    /// Advance by `seconds` and return the new value
+    pub fn advance(&mut self, seconds: f64) -> f64 {
+        self.elapsed_seconds = (self.elapsed_seconds + seconds).min(self.duration_seconds);
+        self.value()
+    }
+
+    /// Current value
+    pub fn value(&self) -> f64 {
+        if self.is_done() {
+            return self.goal;
+        }
+        self.curve.interpolate(self.start, self.goal, self.elapsed_seconds / self.duration_seconds)
+    }
+
+    /// Whether the goal has been reached
+    pub fn is_done(&self) -> bool {
+        self.elapsed_seconds >= self.duration_seconds
+    }
+
+    /// Goal value
+    pub fn goal(&self) -> f64 {
+        self.goal
+    }
+
+    /// Seconds left until the goal
+    pub fn remaining_seconds(&self) -> f64 {
+        self.duration_seconds - self.elapsed_seconds
+    }
+
+    /// Curve of the sweep
+    pub fn curve(&self) -> Curve {
+        self.curve
+    }
+}
+
+/// The set of running automations
+#[derive(Debug, Default)]
+pub struct Automations {
+    running: Vec<Automation>,
+}
+
+impl Automations {
+    /// Create an empty set
+    pub const fn new() -> Self {
+        Self { running: Vec::new() }
+    }
+
+    /// Add an automation, replacing any running on the same parameter
+    pub fn start(&mut self, automation: Automation) {
+        self.running.retain(|running| running.name != automation.name);
+        self.running.push(automation);
+    }
+
+    /// Advance every automation by `seconds`, returning the values to apply
+    ///
+    /// Automations that reach their goal report it and are removed.
+    pub fn advance(&mut self, seconds: f64) -> Vec<(&'static str, &'static str, f64)> {
+        let updates = self
+            .running
+            .iter_mut()
+            .map(|automation| (automation.section, automation.key, automation.advance(seconds)))
+            .collect();
+        self.running.retain(|automation| !automation.is_done());
+        updates
+    }
+
+    /// Stop every automation where it is
+    pub fn clear(&mut self) {
+        self.running.clear();
+    }
+
+    /// Running automations
+    pub fn iter(&self) -> impl Iterator<Item = &Automation> {
+        self.running.iter()
+    }
+}
+
+/// Console names of the automatable parameters, for messages
+pub fn target_names() -> String {
+    TARGETS.iter().map(|&(name, _, _)| name).collect::<Vec<_>>().join(", ")
+}
+
+/// Current value of the parameter an automation drives, if its stage exists
+pub fn current_value(chain: &mut EffectChain, name: &str) -> Option<f64> {
+    let reverb = chain.reverb_mut()?;
+    match name {
+        "r" => Some(reverb.room_size()),
+        "p" => Some(reverb.dampening()),
+        "x" => Some(reverb.width()),
+        "diff" => Some(reverb.diffusion()),
+        "shim" => Some(reverb.shimmer()),
+        "pre" => Some(reverb.predelay_ms()),
+        _ => None,
+    }
+}
+
+/// Hand an automation to the timer thread
+pub fn start(automation: Automation) {
+    AUTOMATIONS.lock().unwrap().start(automation);
+}
+
+/// Stop every automation where it is
+pub fn stop_all() {
+    AUTOMATIONS.lock().unwrap().clear();
+}
+
+/// Snapshot of the running automations
+pub fn running() -> Vec<Automation> {
+    AUTOMATIONS.lock().unwrap().iter().cloned().collect()
+}
+
+/// Step the automations on their own thread until `running` is cleared
+pub fn spawn_timer(chain: Arc<Mutex<EffectChain>>, running: Arc<AtomicBool>) {
+    thread::spawn(move || {
+        let mut last_step = Instant::now();
+        while running.load(Ordering::Relaxed) {
+            thread::sleep(STEP_INTERVAL);
+            let now = Instant::now();
+            let updates = AUTOMATIONS.lock().unwrap().advance((now - last_step).as_secs_f64());
+            last_step = now;
+            if updates.is_empty() {
+                continue;
+            }
+
+            let mut chain_guard = chain.lock().unwrap();
+            for (section, key, value) in updates {
+                if let Err(e) = apply_value(&mut chain_guard, section, key, Value::Number(value)) {
+                    status!("Automation of {}.{} failed: {}", section, key, e);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn test_interpolation_over_time() {
+        // Room size 0.2 to 0.9 over 30 s, stepped once a second
+        let mut linear = Automation::new("r", 0.2, 0.9, 30.0, Curve::Linear).unwrap();
+        let values: Vec<f64> = (0..30).map(|_| linear.advance(1.0)).collect();
+        assert_close(values[0], 0.2 + 0.7 / 30.0);
+        assert_close(values[14], 0.55);
+        assert_close(values[29], 0.9);
+        assert!(linear.is_done());
+        assert_close(linear.advance(1.0), 0.9);
+
+        // Exponential moves by a constant ratio
+        let mut exponential = Automation::new("r", 0.1, 1.0, 10.0, Curve::Exponential).unwrap();
+        assert_close(exponential.advance(5.0), 0.1_f64.sqrt());
+        assert_close(exponential.advance(2.5), 0.1_f64.powf(0.25));
+        assert_close(exponential.advance(2.5), 1.0);
+
+        // An exponential sweep up from zero starts at the floor
+        let mut from_zero = Automation::new("shim", 0.0, 0.01, 2.0, Curve::Exponential).unwrap();
+        assert_close(from_zero.advance(1.0), 0.001 * 10.0_f64.sqrt());
+
+        assert!(Automation::new("dr", 0.0, 1.0, 1.0, Curve::Linear).is_err());
+    }
+
+    #[test]
+    fn test_simultaneous_automations() {
+        let mut automations = Automations::new();
+        automations.start(Automation::new("r", 0.0, 1.0, 2.0, Curve::Linear).unwrap());
+        automations.start(Automation::new("x", 1.0, 0.0, 1.0, Curve::Linear).unwrap());
+
+        let updates = automations.advance(0.5);
+        assert_eq!(updates, vec![("reverb", "room_size", 0.25), ("reverb", "width", 0.5)]);
+
+        // Width reaches its goal and drops out; room size carries on
+        let updates = automations.advance(0.5);
+        assert_eq!(updates, vec![("reverb", "room_size", 0.5), ("reverb", "width", 0.0)]);
+        assert_eq!(automations.iter().count(), 1);
+
+        // A new sweep of the same parameter replaces the running one
+        automations.start(Automation::new("r", 0.5, 0.0, 1.0, Curve::Linear).unwrap());
+        assert_eq!(automations.advance(0.5), vec![("reverb", "room_size", 0.25)]);
+    }
+}
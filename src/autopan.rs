@@ -0,0 +1,184 @@
+//! Auto-pan
+//!
+//! Sweeps the signal across the stereo field with an LFO. The pan position sets
+//! complementary left/right gains on a constant-power law (cosine/sine of a
+//! quarter turn), so L² + R² of the gains stays the same across the sweep and the
+//! level doesn't dip in the middle the way a linear crossfade would. The centre
+//! position passes the signal unchanged. The position is slightly smoothed so the
+//! square, saw and random waves switch sides without clicking. The rate can follow
+//! the shared tempo in note values instead of Hz.
+
+use std::f64::consts::{FRAC_PI_4, SQRT_2};
+
+use crate::lfo::{Lfo, LfoShape};
+use crate::tempo::NoteValue;
+
+/// Lowest LFO rate in Hz
+const MIN_RATE_HZ: f64 = 0.05;
+/// Highest LFO rate in Hz
+const MAX_RATE_HZ: f64 = 20.0;
+/// Time constant of the pan position smoothing in seconds
+const SMOOTHING_SECONDS: f64 = 0.002;
+
+/// LFO-driven stereo panner
+pub struct AutoPan {
+    /// Sample rate in Hz
+    sample_rate: f64,
+    /// LFO sweeping the pan position
+    lfo: Lfo,
+    /// Sweep depth (0.0 = centred, 1.0 = fully left to fully right)
+    depth: f64,
+    /// Smoothed pan position (-1.0 = left, 1.0 = right)
+    position: f64,
+    /// Smoothing coefficient for the pan position
+    smoothing: f64,
+}
+
+impl AutoPan {
+    /// Create an auto-pan with a 1 Hz sine at half depth
+    pub fn new(sample_rate: usize) -> Self {
+        Self {
+            sample_rate: sample_rate as f64,
+            lfo: Lfo::new(),
+            depth: 0.5,
+            position: 0.0,
+            smoothing: (-1.0 / (SMOOTHING_SECONDS * sample_rate as f64)).exp(),
+        }
+    }
+
+    /// Process a stereo input sample and return stereo output
+    pub fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let target = self.lfo.next(self.sample_rate) * self.depth;
+        self.position = target + self.smoothing * (self.position - target);
+
+        let (left_gain, right_gain) = pan_gains(self.position);
+        (input.0 * left_gain, input.1 * right_gain)
+    }
+
+    /// Set the LFO rate in Hz (0.05 to 20), ending any tempo sync
+    pub fn set_rate_hz(&mut self, rate_hz: f64) {
+        self.lfo.set_rate_hz(rate_hz.clamp(MIN_RATE_HZ, MAX_RATE_HZ));
+    }
+
+    /// Current LFO rate in Hz
+    pub fn rate_hz(&self) -> f64 {
+        self.lfo.rate_hz()
+    }
+
+    /// Follow the tempo with one LFO cycle per note value
+    pub fn set_sync(&mut self, note: NoteValue, bpm: f64) {
+        self.lfo.set_sync(note, bpm);
+    }
+
+    /// Current tempo sync note value
+    pub fn sync(&self) -> Option<NoteValue> {
+        self.lfo.sync()
+    }
+
+    /// Retime a synced LFO for a new tempo
+    pub fn set_tempo_bpm(&mut self, bpm: f64) {
+        self.lfo.set_tempo_bpm(bpm);
+    }
+
+    /// Set whether a tempo or note value change restarts the LFO cycle
+    pub fn set_restart_on_sync(&mut self, restart: bool) {
+        self.lfo.set_restart_on_sync(restart);
+    }
+
+    /// Whether a tempo or note value change restarts the LFO cycle
+    pub fn restart_on_sync(&self) -> bool {
+        self.lfo.restart_on_sync()
+    }
+
+    /// Set the sweep depth (0.0 to 1.0)
+    pub fn set_depth(&mut self, depth: f64) {
+        self.depth = depth.clamp(0.0, 1.0);
+    }
+
+    /// Current sweep depth
+    pub fn depth(&self) -> f64 {
+        self.depth
+    }
+
+    /// Set where in the cycle the sweep starts (0.0 to 1.0; 0 is the centre
+    /// moving right, 0.5 the centre moving left)
+    pub fn set_phase(&mut self, phase: f64) {
+        self.lfo.set_phase_offset(phase.clamp(0.0, 1.0));
+    }
+
+    /// Current start phase of the sweep
+    pub fn phase(&self) -> f64 {
+        self.lfo.phase_offset()
+    }
+
+    /// Set the LFO waveform
+    pub fn set_shape(&mut self, shape: LfoShape) {
+        self.lfo.set_shape(shape);
+    }
+
+    /// Current LFO waveform
+    pub fn shape(&self) -> LfoShape {
+        self.lfo.shape()
+    }
+
+    /// Restart the sweep from the centre
+    pub fn reset(&mut self) {
+        self.lfo.reset();
+        self.position = 0.0;
+    }
+}
+
+/// Constant-power left/right gains for a pan position (-1.0 to 1.0)
+///
+/// Scaled by √2 so both gains are 1.0 in the centre.
+pub fn pan_gains(position: f64) -> (f64, f64) {
+    let angle = FRAC_PI_4 * (position + 1.0);
+    (angle.cos() * SQRT_2, angle.sin() * SQRT_2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_full_depth_reaches_each_side_at_constant_power() {
+        let mut autopan = AutoPan::new(44100);
+        autopan.set_rate_hz(1.0);
+        autopan.set_depth(1.0);
+
+        let output: Vec<(f64, f64)> = (0..44100).map(|_| autopan.tick((1.0, 1.0))).collect();
+
+        // Fully right at the sine's peak, fully left at its trough
+        let (left, right) = output[11025];
+        assert!(left.abs() < 0.01 && (right - SQRT_2).abs() < 0.01, "{:?}", output[11025]);
+        let (left, right) = output[33075];
+        assert!((left - SQRT_2).abs() < 0.01 && right.abs() < 0.01, "{:?}", output[33075]);
+
+        // The power stays even across the whole sweep
+        for &(left, right) in &output {
+            assert!((left * left + right * right - 2.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_shapes_and_depth() {
+        // Every shape sweeps between the sides at full depth
+        for shape in LfoShape::ALL {
+            let mut autopan = AutoPan::new(1000);
+            autopan.set_shape(shape);
+            autopan.set_rate_hz(20.0);
+            autopan.set_depth(1.0);
+            let lefts: Vec<f64> = (0..1000).map(|_| autopan.tick((1.0, 1.0)).0).collect();
+            let spread = lefts.iter().cloned().fold(f64::MIN, f64::max) - lefts.iter().cloned().fold(f64::MAX, f64::min);
+            assert!(spread > 0.5, "{:?} spread {}", shape, spread);
+        }
+
+        // At zero depth the signal stays centred and unchanged
+        let mut autopan = AutoPan::new(44100);
+        autopan.set_depth(0.0);
+        for _ in 0..1000 {
+            let (left, right) = autopan.tick((0.5, -0.25));
+            assert!((left - 0.5).abs() < 1e-12 && (right + 0.25).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Fixed-size processing blocks
+//!
+//! The device asks for as many frames as its callback buffer happens to hold,
+//! and that length can change from one callback to the next. Effects that work
+//! on whole blocks (FFT convolution, some pitch shifters) need the same block
+//! size every time instead, so the adapter sits between the ring buffer and the
+//! chain: it gathers incoming frames until a block is full, runs the block
+//! through the chain in one go, and queues the results for the device.
+//!
+//! The output queue starts with one block less a frame of silence, so whatever
+//! the device asks for there is always a processed frame ready: the block's last
+//! input frame completes it just before its first output frame is due. That is
+//! also the latency it adds, none at the default block size of one frame.
+
+use std::collections::VecDeque;
+
+/// Largest processing block in frames
+pub const MAX_BLOCK_SIZE: usize = 8192;
+
+/// Gathers frames into fixed-size blocks and hands processed frames back one
+/// at a time
+pub struct BlockAdapter {
+    /// Frames of the block being gathered
+    block: Vec<(f64, f64)>,
+    /// Block size in frames
+    block_size: usize,
+    /// Processed frames waiting for the device
+    output: VecDeque<(f64, f64)>,
+}
+
+impl BlockAdapter {
+    /// Create an adapter for blocks of `block_size` frames (1 to 8192)
+    pub fn new(block_size: usize) -> Self {
+        let block_size = block_size.clamp(1, MAX_BLOCK_SIZE);
+        let mut output = VecDeque::with_capacity(2 * block_size);
+        output.extend(std::iter::repeat_n((0.0, 0.0), block_size - 1));
+        Self {
+            block: Vec::with_capacity(block_size),
+            block_size,
+            output,
+        }
+    }
+
+    /// Push one input frame and return the next processed frame, running
+    /// `process` over the block in place whenever one fills up
+    pub fn process(&mut self, input: (f64, f64), process: impl FnOnce(&mut [(f64, f64)])) -> (f64, f64) {
+        self.block.push(input);
+        if self.block.len() == self.block_size {
+            process(&mut self.block);
+            self.output.extend(self.block.drain(..));
+        }
+        self.output.pop_front().unwrap_or((0.0, 0.0))
+    }
+
+    /// Replace the processed frames still queued with silence, keeping the latency
+    ///
+    /// Used when the chain is panicked, so the rest of a runaway block isn't played.
+    pub fn silence(&mut self) {
+        self.output.iter_mut().for_each(|frame| *frame = (0.0, 0.0));
+    }
+}
+
+/// Parse the `--block-size` argument: a frame count from 1 to 8192
+pub fn parse_block_size(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(size) if (1..=MAX_BLOCK_SIZE).contains(&size) => Ok(size),
+        _ => Err(format!("Invalid --block-size value: {} (expected 1 to {} frames)", value, MAX_BLOCK_SIZE)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::EffectChain;
+
+    #[test]
+    fn test_irregular_chunks_come_out_complete_and_in_order() {
+        let mut adapter = BlockAdapter::new(64);
+        let input: Vec<(f64, f64)> = (0..5000).map(|i| (i as f64, -(i as f64))).collect();
+
+        // Device buffers of changing lengths, each block seen whole by the chain
+        let chunk_sizes = [1, 17, 64, 100, 3, 511, 256, 7, 1000];
+        let mut blocks = 0;
+        let mut output = Vec::new();
+        let mut chunks = chunk_sizes.iter().cycle();
+        let mut rest = &input[..];
+        while !rest.is_empty() {
+            let (chunk, remaining) = rest.split_at((*chunks.next().unwrap()).min(rest.len()));
+            for &frame in chunk {
+                output.push(adapter.process(frame, |block| {
+                    assert_eq!(block.len(), 64);
+                    blocks += 1;
+                }));
+            }
+            rest = remaining;
+        }
+
+        // Silence for the latency, then every input frame in order
+        let latency = 63;
+        assert!(output[..latency].iter().all(|&frame| frame == (0.0, 0.0)));
+        assert_eq!(output[latency..], input[..input.len() - latency]);
+        assert_eq!(blocks, 5000 / 64);
+    }
+
+    #[test]
+    fn test_passthrough_chain_runs_block_by_block() {
+        let mut chain = EffectChain::new(44100);
+        chain.set_dc_block(false);
+        let mut adapter = BlockAdapter::new(32);
+        let input: Vec<(f64, f64)> = (0..1000).map(|i| ((i as f64 * 0.01).sin() * 0.5, 0.25)).collect();
+        let output: Vec<(f64, f64)> = input
+            .iter()
+            .map(|&frame| adapter.process(frame, |block| chain.process_block(block)))
+            .collect();
+        assert_eq!(output[31..], input[..input.len() - 31]);
+    }
+
+    #[test]
+    fn test_single_frame_blocks_add_no_latency() {
+        let mut adapter = BlockAdapter::new(1);
+        assert_eq!(adapter.process((0.5, -0.5), |block| block[0].0 *= 2.0), (1.0, -0.5));
+        assert_eq!(BlockAdapter::new(0).block_size, 1);
+
+        // Silencing drops what is queued but keeps the timing
+        let mut adapter = BlockAdapter::new(4);
+        for i in 0..4 {
+            adapter.process((1.0, 1.0), |_| {});
+            assert_eq!(adapter.output.len(), [2, 1, 0, 3][i]);
+        }
+        adapter.silence();
+        assert_eq!(adapter.process((1.0, 1.0), |_| {}), (0.0, 0.0));
+        assert_eq!(adapter.output.len(), 2);
+
+        assert_eq!(parse_block_size("256"), Ok(256));
+        assert!(parse_block_size("0").is_err());
+        assert!(parse_block_size("lots").is_err());
+    }
+}
@@ -0,0 +1,82 @@
+//! Clean boost
+//!
+//! A plain gain stage for solos. It sits at the end of the chain, after every
+//! saturating stage, so it only makes the signal louder without adding drive.
+
+/// Largest boost in dB (keeps a solo boost from slamming the output)
+const MAX_GAIN_DB: f64 = 12.0;
+
+/// Clean volume boost
+pub struct Boost {
+    /// Boost in dB (0 to +12)
+    gain_db: f64,
+    /// Linear gain
+    gain: f64,
+}
+
+impl Default for Boost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Boost {
+    /// Create a boost of +6 dB
+    pub fn new() -> Self {
+        let mut boost = Self { gain_db: 0.0, gain: 1.0 };
+        boost.set_gain_db(6.0);
+        boost
+    }
+
+    /// Process a stereo input sample and return stereo output
+    pub fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        (input.0 * self.gain, input.1 * self.gain)
+    }
+
+    /// Set the boost (0 to +12 dB)
+    pub fn set_gain_db(&mut self, gain_db: f64) {
+        self.gain_db = gain_db.clamp(0.0, MAX_GAIN_DB);
+        self.gain = 10f64.powf(self.gain_db / 20.0);
+    }
+
+    /// Current boost in dB
+    pub fn gain_db(&self) -> f64 {
+        self.gain_db
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::EffectChain;
+
+    #[test]
+    fn test_six_db_doubles_the_level() {
+        let mut boost = Boost::new();
+        boost.set_gain_db(6.0);
+        let (left, right) = boost.tick((0.25, -0.1));
+        assert!((left / 0.25 - 2.0).abs() < 0.01, "{}", left);
+        assert!((right / -0.1 - 2.0).abs() < 0.01, "{}", right);
+
+        // Out-of-range settings are clamped
+        boost.set_gain_db(40.0);
+        assert_eq!(boost.gain_db(), MAX_GAIN_DB);
+        boost.set_gain_db(-6.0);
+        assert_eq!(boost.gain_db(), 0.0);
+    }
+
+    #[test]
+    fn test_off_leaves_signal_unchanged() {
+        let mut chain = EffectChain::new(44100);
+        chain.add("boost", Box::new(Boost::new()));
+        // Switch at once rather than crossfading
+        chain.set_switch_fade_ms(0.0);
+
+        chain.set_enabled("boost", false).unwrap();
+        assert_eq!(chain.process_frame((0.3, -0.2)), (0.3, -0.2));
+
+        chain.set_enabled("boost", true).unwrap();
+        let (left, _) = chain.process_frame((0.3, -0.2));
+        assert!(left > 0.59, "{}", left);
+    }
+}
@@ -0,0 +1,216 @@
+//! Per-device gain calibration
+//!
+//! Interfaces stage their gain differently, so the input pad and output trim that
+//! suit one are wrong for the next. A calibration file keeps them per device,
+//! keyed by the name the audio host reports (as printed at startup), and the
+//! profiles of the devices in use are applied when they are opened:
+//!
+//! ```text
+//! [Scarlett 2i2 USB]
+//! input_pad_db = 10
+//! output_trim_db = -3
+//! ```
+//!
+//! The pad applies when the device is the input and the trim when it is the
+//! output, so an interface used for both gets both. `calibrate save` stores the
+//! current gains for the devices in use, keeping every other device's profile.
+
+use crate::chain::EffectChain;
+use crate::preset::{parse_document, Value};
+use crate::trim::InputPad;
+
+/// Calibration file used unless `--calibration` names another
+pub const DEFAULT_CALIBRATION_FILE: &str = "calibration.conf";
+
+/// Gain staging for one device
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeviceProfile {
+    /// Input pad, applied when the device is the input
+    pub input_pad: InputPad,
+    /// Output trim in dB, applied when the device is the output
+    pub output_trim_db: f64,
+}
+
+impl Default for DeviceProfile {
+    fn default() -> Self {
+        Self {
+            input_pad: InputPad::Off,
+            output_trim_db: 0.0,
+        }
+    }
+}
+
+/// Device profiles by device name, in file order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Calibration {
+    profiles: Vec<(String, DeviceProfile)>,
+}
+
+impl Calibration {
+    /// Read a calibration file; a missing file is an empty calibration
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("Cannot read calibration {}: {}", path, e)),
+        };
+        Self::parse(&text).map_err(|e| format!("Invalid calibration {}: {}", path, e))
+    }
+
+    /// Parse calibration text, one section per device
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut calibration = Self::default();
+        for entry in parse_document(text)? {
+            let field = format!("{}.{}", entry.section, entry.key);
+            let invalid = |expected: &str| format!("line {}: {}: expected {}, got {}", entry.line, field, expected, entry.value);
+
+            let mut profile = calibration.profile(&entry.section).unwrap_or_default();
+            match (entry.key.as_str(), &entry.value) {
+                ("input_pad_db", Value::Number(db)) => {
+                    profile.input_pad = InputPad::from_db(&db.to_string()).ok_or_else(|| invalid("0, 10 or 20"))?;
+                }
+                ("input_pad_db", _) => return Err(invalid("0, 10 or 20")),
+                ("output_trim_db", Value::Number(db)) if (-24.0..=12.0).contains(db) => profile.output_trim_db = *db,
+                ("output_trim_db", _) => return Err(invalid("a number from -24 to 12")),
+                _ => return Err(format!("line {}: unknown setting {}", entry.line, field)),
+            }
+            calibration.set_profile(&entry.section, profile);
+        }
+        Ok(calibration)
+    }
+
+    /// Write the calibration file
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.to_string()).map_err(|e| format!("Cannot write calibration {}: {}", path, e))
+    }
+
+    /// Profile stored for a device
+    pub fn profile(&self, device: &str) -> Option<DeviceProfile> {
+        self.profiles.iter().find(|(name, _)| name == device).map(|&(_, profile)| profile)
+    }
+
+    /// Store the profile for a device, replacing any earlier one
+    pub fn set_profile(&mut self, device: &str, profile: DeviceProfile) {
+        match self.profiles.iter_mut().find(|(name, _)| name == device) {
+            Some((_, stored)) => *stored = profile,
+            None => self.profiles.push((device.to_string(), profile)),
+        }
+    }
+
+    /// Apply the output device's trim, returning the input device's pad for the
+    /// caller to switch to and which devices had a profile
+    pub fn apply(&self, input_device: &str, output_device: &str, chain: &mut EffectChain) -> (Option<InputPad>, Vec<String>) {
+        let mut applied = Vec::new();
+        let input_pad = self.profile(input_device).map(|profile| profile.input_pad);
+        if let Some(pad) = input_pad {
+            applied.push(format!("{} (input pad -{} dB)", input_device, pad.db()));
+        }
+        if let Some(profile) = self.profile(output_device) {
+            chain.set_output_trim_db(profile.output_trim_db);
+            applied.push(format!("{} (output trim {:+.1} dB)", output_device, profile.output_trim_db));
+        }
+        (input_pad, applied)
+    }
+}
+
+impl std::fmt::Display for Calibration {
+    /// Write the calibration in the same format it is read from
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, (device, profile)) in self.profiles.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "[{}]", device)?;
+            writeln!(f, "input_pad_db = {}", profile.input_pad.db())?;
+            writeln!(f, "output_trim_db = {}", profile.output_trim_db)?;
+        }
+        Ok(())
+    }
+}
+
+/// Calibration file and the names of the devices in use, for `calibrate save`
+#[derive(Debug, Clone)]
+pub struct CalibrationTarget {
+    path: String,
+    input_device: String,
+    output_device: String,
+}
+
+impl CalibrationTarget {
+    /// Target the calibration file at `path` for the devices in use
+    pub fn new(path: &str, input_device: &str, output_device: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            input_device: input_device.to_string(),
+            output_device: output_device.to_string(),
+        }
+    }
+
+    /// Save `input_pad` for the input device and `output_trim_db` for the output
+    /// device, returning a description of what was saved
+    pub fn save(&self, input_pad: InputPad, output_trim_db: f64) -> Result<String, String> {
+        let mut calibration = Calibration::load(&self.path)?;
+
+        let mut input = calibration.profile(&self.input_device).unwrap_or_default();
+        input.input_pad = input_pad;
+        calibration.set_profile(&self.input_device, input);
+        let mut output = calibration.profile(&self.output_device).unwrap_or_default();
+        output.output_trim_db = output_trim_db;
+        calibration.set_profile(&self.output_device, output);
+
+        calibration.save(&self.path)?;
+        Ok(format!(
+            "Saved calibration to {}: {} input pad -{} dB, {} output trim {:+.1} dB",
+            self.path,
+            self.input_device,
+            input_pad.db(),
+            self.output_device,
+            output_trim_db
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_saved_profile_reloads_and_applies_the_same_gains() {
+        let path = std::env::temp_dir().join(format!("mac-pedals-calibration-{}.conf", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut calibration = Calibration::default();
+        calibration.set_profile(
+            "Scarlett 2i2 USB",
+            DeviceProfile {
+                input_pad: InputPad::Minus10,
+                output_trim_db: -4.5,
+            },
+        );
+        calibration.set_profile("MacBook Pro Speakers", DeviceProfile::default());
+        calibration.save(path).unwrap();
+
+        let reloaded = Calibration::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(reloaded, calibration);
+
+        // An interface used for both input and output gets both gains
+        let mut chain = EffectChain::new(44100);
+        let (pad, applied) = reloaded.apply("Scarlett 2i2 USB", "Scarlett 2i2 USB", &mut chain);
+        assert_eq!(applied.len(), 2);
+        assert_eq!(pad, Some(InputPad::Minus10));
+        assert_eq!(chain.output_trim_db(), -4.5);
+
+        // Unknown devices are left alone
+        assert_eq!(reloaded.apply("USB Mic", "AirPods", &mut chain), (None, Vec::new()));
+        assert_eq!(chain.output_trim_db(), -4.5);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Calibration::load("/nonexistent/calibration.conf").unwrap().profiles.is_empty());
+        assert!(Calibration::parse("[Interface]\ninput_pad_db = 15\n").unwrap_err().contains("0, 10 or 20"));
+        assert!(Calibration::parse("[Interface]\noutput_trim_db = 20\n").is_err());
+        assert!(Calibration::parse("[Interface]\ngain = 1\n").unwrap_err().contains("unknown setting"));
+    }
+}
@@ -0,0 +1,989 @@
+//! Effect chain
+//!
+//! Owns the ordered effects and runs them in series. Each stage has its own
+//! enable switch and wet/dry mix (on the constant-power law, see `mix`), and a
+//! global output trim is applied after the last stage to compensate for level
+//! changes. The processing order can be changed at runtime, and optional auto
+//! gain keeps each stage's output level close to its input level. An input tilt
+//! shelf sits in front of every stage.
+//!
+//! Switching a stage off normally cuts it at once, tail and all. In trails mode a
+//! delay or reverb switched off stops receiving input instead, but keeps running
+//! and its echoes or tail are added to the dry signal until the stage is switched
+//! back on (or trails mode off), like a pedal with a trails bypass.
+//!
+//! Switching a stage on or off crossfades between its output and the signal
+//! going into it over a few milliseconds, ticking the stage all the while, so
+//! the jump between two very different signals doesn't click.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::autogain::AutoGain;
+use crate::adt::Adt;
+use crate::autopan::AutoPan;
+use crate::boost::Boost;
+use crate::delay::Delay;
+use crate::denoise::Denoiser;
+use crate::denormal;
+use crate::distortion::Distortion;
+use crate::effect::Effect;
+use crate::exciter::Exciter;
+use crate::filter::DcBlocker;
+use crate::insert::InsertLoop;
+use crate::metronome::Metronome;
+use crate::mix::constant_power_gains;
+use crate::multiband::MultibandDistortion;
+use crate::pad::PadLayer;
+use crate::silence::{SilenceDetector, SuspendState};
+use crate::reverb::Reverb;
+use crate::reverse::ReverseDelay;
+use crate::stereo::StereoWidth;
+use crate::stutter::Stutter;
+use crate::swell::Swell;
+use crate::tempo::DEFAULT_BPM;
+use crate::tilt::InputTilt;
+use crate::tremolo::Tremolo;
+
+/// Corner frequency of the output DC blocker in Hz
+const DC_BLOCK_HZ: f64 = 10.0;
+/// Default crossfade time when a stage is switched on or off in milliseconds
+const DEFAULT_SWITCH_FADE_MS: f64 = 5.0;
+/// Longest switch crossfade in milliseconds
+const MAX_SWITCH_FADE_MS: f64 = 100.0;
+
+/// A single stage in the chain
+struct ChainStage {
+    /// Short name used by the controls (e.g. "verb", "dist")
+    name: &'static str,
+    /// The effect processor
+    effect: Box<dyn Effect>,
+    /// Whether the stage is processed
+    enabled: bool,
+    /// Wet/dry mix (0.0 = dry only, 1.0 = effect only)
+    mix: f64,
+    /// Makeup gain tracker for this stage
+    auto_gain: AutoGain,
+    /// Whether the stage is off but still ringing out its tail (trails mode)
+    trailing: bool,
+    /// Share of the stage's output in the frame, ramping after a switch (0 = bypassed)
+    switch_level: f64,
+    /// Peak going into the stage since the last level reading (level taps only)
+    input_peak: f64,
+    /// Peak coming out of the stage since the last level reading (level taps only)
+    output_peak: f64,
+}
+
+/// Peak levels around one stage since the last reading, for finding the stage
+/// that silences a chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageLevels {
+    /// Stage name
+    pub name: &'static str,
+    /// Whether the stage was processed (enabled, fading or ringing out)
+    pub active: bool,
+    /// Peak of the frames going into the stage
+    pub input_peak: f64,
+    /// Peak of the frames coming out of the stage
+    pub output_peak: f64,
+}
+
+/// Ordered chain of effects with per-stage mix and global output trim
+pub struct EffectChain {
+    /// Stages in processing order
+    stages: Vec<ChainStage>,
+    /// Output trim in dB
+    output_trim_db: f64,
+    /// Output trim as linear gain
+    output_trim: f64,
+    /// Whether per-stage auto gain is applied
+    auto_gain_enabled: bool,
+    /// Sample rate, used for the per-stage auto gain trackers
+    sample_rate: usize,
+    /// Output fade-in gain after a panic (1.0 when not fading)
+    fade_gain: f64,
+    /// Fade-in increment per frame
+    fade_step: f64,
+    /// Shared tempo for time-based effects in BPM
+    tempo_bpm: f64,
+    /// Pickup loading tone shelf ahead of the first stage
+    input_tilt: InputTilt,
+    /// DC blockers on the left and right output
+    dc_blockers: [DcBlocker; 2],
+    /// Whether the output DC blocker is applied
+    dc_block: bool,
+    /// Practice click mixed into the output, following the shared tempo
+    metronome: Metronome,
+    /// Captured loop layered under the wet signal
+    pad: PadLayer,
+    /// Auto-suspend: skips the stages after a stretch of silence
+    silence: SilenceDetector,
+    /// Peak of the last processed frame, so ringing tails hold off the suspend
+    last_output_level: f64,
+    /// Whether switching off a delay or reverb lets its tail ring out
+    trails: bool,
+    /// Crossfade time when a stage is switched on or off in milliseconds
+    switch_fade_ms: f64,
+    /// Switch crossfade increment per frame
+    switch_step: f64,
+    /// Whether the peaks between stages are recorded
+    level_taps: bool,
+    /// Whether denormals are flushed, copied into the processing thread each frame
+    flush_denormals: Arc<AtomicBool>,
+}
+
+impl EffectChain {
+    /// Create an empty chain (passthrough)
+    pub fn new(sample_rate: usize) -> Self {
+        Self {
+            stages: Vec::new(),
+            output_trim_db: 0.0,
+            output_trim: 1.0,
+            auto_gain_enabled: false,
+            sample_rate,
+            fade_gain: 1.0,
+            // 50ms fade-in after a panic
+            fade_step: 1.0 / (0.05 * sample_rate as f64),
+            tempo_bpm: DEFAULT_BPM,
+            input_tilt: InputTilt::new(sample_rate),
+            dc_blockers: std::array::from_fn(|_| DcBlocker::new(sample_rate as f64, DC_BLOCK_HZ)),
+            dc_block: true,
+            metronome: Metronome::new(sample_rate, DEFAULT_BPM),
+            pad: PadLayer::new(sample_rate),
+            silence: SilenceDetector::new(sample_rate),
+            last_output_level: 0.0,
+            trails: false,
+            switch_fade_ms: DEFAULT_SWITCH_FADE_MS,
+            switch_step: switch_step(DEFAULT_SWITCH_FADE_MS, sample_rate),
+            level_taps: false,
+            flush_denormals: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Append an effect to the end of the chain, enabled and fully wet
+    pub fn add(&mut self, name: &'static str, effect: Box<dyn Effect>) {
+        self.stages.push(ChainStage {
+            name,
+            effect,
+            enabled: true,
+            mix: 1.0,
+            auto_gain: AutoGain::new(self.sample_rate),
+            trailing: false,
+            switch_level: 1.0,
+            input_peak: 0.0,
+            output_peak: 0.0,
+        });
+    }
+
+    /// Process a stereo frame through the input tilt and every enabled stage,
+    /// mix in the pad, apply the output trim and mix in the metronome click
+    ///
+    /// With auto-suspend on, a long silence fades the stages out and stops ticking
+    /// them (the click keeps going); they are cleared so no stale tail plays when
+    /// the input returns and the output fades back in.
+    pub fn process_frame(&mut self, input: (f64, f64)) -> (f64, f64) {
+        denormal::set_flush_denormals(self.flush_denormals.load(Ordering::Relaxed));
+        let level = input.0.abs().max(input.1.abs()).max(self.last_output_level);
+        let was_suspended = self.silence.state() == SuspendState::Suspended;
+        let frame = match self.silence.update(level) {
+            SuspendState::Suspended => {
+                if !was_suspended {
+                    self.reset();
+                }
+                (0.0, 0.0)
+            }
+            _ => {
+                let wet = self.process_stages(input);
+                self.pad.tick(wet)
+            }
+        };
+        self.last_output_level = frame.0.abs().max(frame.1.abs());
+
+        if self.fade_gain < 1.0 {
+            self.fade_gain = (self.fade_gain + self.fade_step).min(1.0);
+        }
+
+        // The click keeps its own volume, independent of the trim
+        let gain = self.output_trim * self.fade_gain * self.silence.gain();
+        let click = self.metronome.tick() * self.fade_gain;
+        (frame.0 * gain + click, frame.1 * gain + click)
+    }
+
+    /// Run a frame through the input tilt and every enabled stage
+    ///
+    /// A stage ringing out in trails mode gets silence and its tail is added to
+    /// the untouched frame. A stage that was just switched is blended with the
+    /// frame going into it until its crossfade is over.
+    fn process_stages(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let mut frame = self.input_tilt.tick(input);
+
+        let step = self.switch_step;
+        let level_taps = self.level_taps;
+        for stage in self
+            .stages
+            .iter_mut()
+            .filter(|stage| stage.enabled || stage.trailing || stage.switch_level > 0.0)
+        {
+            if level_taps {
+                stage.input_peak = stage.input_peak.max(frame.0.abs()).max(frame.1.abs());
+            }
+
+            if stage.trailing {
+                let (left_tail, right_tail) = stage.effect.tick((0.0, 0.0));
+                let (_, wet_gain) = constant_power_gains(stage.mix);
+                frame = (frame.0 + left_tail * wet_gain, frame.1 + right_tail * wet_gain);
+                if level_taps {
+                    stage.output_peak = stage.output_peak.max(frame.0.abs()).max(frame.1.abs());
+                }
+                continue;
+            }
+
+            let (left_wet, right_wet) = stage.effect.tick(frame);
+            let (dry_gain, wet_gain) = constant_power_gains(stage.mix);
+            let mixed = (
+                frame.0 * dry_gain + left_wet * wet_gain,
+                frame.1 * dry_gain + right_wet * wet_gain,
+            );
+
+            let processed = if self.auto_gain_enabled {
+                stage.auto_gain.process(frame, mixed)
+            } else {
+                mixed
+            };
+
+            stage.switch_level = if stage.enabled {
+                (stage.switch_level + step).min(1.0)
+            } else {
+                (stage.switch_level - step).max(0.0)
+            };
+            let level = stage.switch_level;
+            frame = if level == 1.0 {
+                processed
+            } else {
+                (
+                    frame.0 + (processed.0 - frame.0) * level,
+                    frame.1 + (processed.1 - frame.1) * level,
+                )
+            };
+
+            if level_taps {
+                stage.output_peak = stage.output_peak.max(frame.0.abs()).max(frame.1.abs());
+            }
+        }
+        frame
+    }
+
+    /// Record the peak level going into and coming out of each stage
+    ///
+    /// Off by default, so the audio thread does no extra work unless levels are
+    /// being debugged. Switching off clears the recorded peaks.
+    pub fn set_level_taps(&mut self, enabled: bool) {
+        self.level_taps = enabled;
+        if !enabled {
+            self.take_stage_levels();
+        }
+    }
+
+    /// Whether the peaks between stages are recorded
+    pub fn level_taps(&self) -> bool {
+        self.level_taps
+    }
+
+    /// Follow the engine's denormal flushing switch (see `denormal`) instead of
+    /// flushing always
+    pub fn set_denormal_switch(&mut self, flush_denormals: Arc<AtomicBool>) {
+        self.flush_denormals = flush_denormals;
+    }
+
+    /// Read the peaks around each stage in processing order, resetting them
+    pub fn take_stage_levels(&mut self) -> Vec<StageLevels> {
+        self.stages
+            .iter_mut()
+            .map(|stage| StageLevels {
+                name: stage.name,
+                active: stage.enabled || stage.trailing || stage.switch_level > 0.0,
+                input_peak: std::mem::take(&mut stage.input_peak),
+                output_peak: std::mem::take(&mut stage.output_peak),
+            })
+            .collect()
+    }
+
+    /// Remove DC from a processed frame, the last step before the output device
+    ///
+    /// Each distortion blocks its own DC, but asymmetric stages mixed wet/dry or fed
+    /// through the reverb can still leave an offset in the mixed output.
+    pub fn block_dc(&mut self, frame: (f64, f64)) -> (f64, f64) {
+        if !self.dc_block {
+            return frame;
+        }
+        (self.dc_blockers[0].process(frame.0), self.dc_blockers[1].process(frame.1))
+    }
+
+    /// Process a block of frames in place, each through the chain and the DC
+    /// blocker as the device will play it
+    pub fn process_block(&mut self, block: &mut [(f64, f64)]) {
+        for frame in block.iter_mut() {
+            let processed = self.process_frame(*frame);
+            *frame = self.block_dc(processed);
+        }
+    }
+
+    /// Enable or disable a stage by name
+    ///
+    /// The stage crossfades in or out over the switch fade time. In trails mode a
+    /// delay or reverb being disabled keeps ringing out instead; its tail carries
+    /// on either way, so that switch needs no crossfade.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        let trails = self.trails;
+        let stage = self.stage_mut(name)?;
+        let was_trailing = stage.trailing;
+        stage.trailing = !enabled && (stage.enabled || stage.trailing) && trails && stage.effect.has_tail();
+        stage.enabled = enabled;
+        if stage.trailing || was_trailing {
+            stage.switch_level = if enabled { 1.0 } else { 0.0 };
+        }
+        Ok(())
+    }
+
+    /// Set the crossfade time for switching a stage on or off (0 to 100 ms,
+    /// 0 switches at once)
+    pub fn set_switch_fade_ms(&mut self, fade_ms: f64) {
+        self.switch_fade_ms = fade_ms.clamp(0.0, MAX_SWITCH_FADE_MS);
+        self.switch_step = switch_step(self.switch_fade_ms, self.sample_rate);
+    }
+
+    /// Crossfade time for switching a stage on or off in milliseconds
+    pub fn switch_fade_ms(&self) -> f64 {
+        self.switch_fade_ms
+    }
+
+    /// Switch trails mode: disabling a delay or reverb stops feeding it but lets
+    /// its tail ring out over the dry signal, instead of cutting it
+    ///
+    /// Switching trails mode off cuts any tail still ringing.
+    pub fn set_trails(&mut self, trails: bool) {
+        self.trails = trails;
+        if !trails {
+            for stage in self.stages.iter_mut() {
+                stage.trailing = false;
+            }
+        }
+    }
+
+    /// Whether trails mode is on
+    pub fn trails(&self) -> bool {
+        self.trails
+    }
+
+    /// Whether a stage is enabled
+    pub fn is_enabled(&self, name: &str) -> Result<bool, String> {
+        self.stages
+            .iter()
+            .find(|stage| stage.name == name)
+            .map(|stage| stage.enabled)
+            .ok_or_else(|| format!("Unknown effect stage '{}'", name))
+    }
+
+    /// Clear the internal state of every effect
+    pub fn reset(&mut self) {
+        for stage in self.stages.iter_mut() {
+            stage.effect.reset();
+            stage.auto_gain.reset();
+        }
+        self.input_tilt.reset();
+        for blocker in self.dc_blockers.iter_mut() {
+            blocker.reset();
+        }
+    }
+
+    /// Emergency stop for runaway feedback or unstable settings
+    ///
+    /// Mutes the output, clears every effect's internal state, bypasses all stages,
+    /// stops the click and the pad and resets the output trim and input tilt. The
+    /// output then fades back in over 50ms so the return to passthrough doesn't
+    /// click. Nothing here allocates, so the output callback can panic the chain.
+    pub fn panic(&mut self) {
+        self.reset();
+        self.bypass_all();
+        self.set_output_trim_db(0.0);
+        self.input_tilt.set_amount(0.0);
+        self.metronome.set_enabled(false);
+        self.pad.clear();
+        self.fade_gain = 0.0;
+    }
+
+    /// Enable or disable per-stage auto gain
+    ///
+    /// The trackers restart from unity so stale measurements aren't applied.
+    pub fn set_auto_gain(&mut self, enabled: bool) {
+        if enabled && !self.auto_gain_enabled {
+            for stage in self.stages.iter_mut() {
+                stage.auto_gain.reset();
+            }
+        }
+        self.auto_gain_enabled = enabled;
+    }
+
+    /// Disable every stage so the chain passes the input through untouched
+    ///
+    /// Tails are cut too, even in trails mode.
+    pub fn bypass_all(&mut self) {
+        for stage in self.stages.iter_mut() {
+            stage.enabled = false;
+            stage.trailing = false;
+            stage.switch_level = 0.0;
+        }
+    }
+
+    /// Set the wet/dry mix of a stage (0.0 to 1.0)
+    pub fn set_mix(&mut self, name: &str, mix: f64) -> Result<(), String> {
+        self.stage_mut(name)?.mix = mix.clamp(0.0, 1.0);
+        Ok(())
+    }
+
+    /// Set the global output trim in dB (-24 dB to +12 dB)
+    pub fn set_output_trim_db(&mut self, trim_db: f64) {
+        self.output_trim_db = trim_db.clamp(-24.0, 12.0);
+        self.output_trim = 10.0_f64.powf(self.output_trim_db / 20.0);
+    }
+
+    /// Current output trim in dB
+    pub fn output_trim_db(&self) -> f64 {
+        self.output_trim_db
+    }
+
+    /// Set the input tilt (-1.0 brighter to 1.0 darker, 0.0 flat)
+    pub fn set_input_tilt(&mut self, amount: f64) {
+        self.input_tilt.set_amount(amount);
+    }
+
+    /// Current input tilt
+    pub fn input_tilt(&self) -> f64 {
+        self.input_tilt.amount()
+    }
+
+    /// Enable or disable the output DC blocker (on by default)
+    ///
+    /// Turning it off passes very low frequencies and deliberate offsets through,
+    /// e.g. for measurements.
+    pub fn set_dc_block(&mut self, enabled: bool) {
+        if enabled && !self.dc_block {
+            for blocker in self.dc_blockers.iter_mut() {
+                blocker.reset();
+            }
+        }
+        self.dc_block = enabled;
+    }
+
+    /// Whether the output DC blocker is applied
+    pub fn dc_block(&self) -> bool {
+        self.dc_block
+    }
+
+    /// Switch auto-suspend on or off
+    pub fn set_auto_suspend(&mut self, enabled: bool) {
+        self.silence.set_enabled(enabled);
+    }
+
+    /// Whether auto-suspend is on
+    pub fn auto_suspend(&self) -> bool {
+        self.silence.is_enabled()
+    }
+
+    /// Set how long the signal must stay silent before suspending, in seconds (1 to 600)
+    pub fn set_suspend_after(&mut self, seconds: f64) {
+        self.silence.set_timeout_seconds(seconds);
+    }
+
+    /// Current auto-suspend timeout in seconds
+    pub fn suspend_after(&self) -> f64 {
+        self.silence.timeout_seconds()
+    }
+
+    /// Whether the stages are currently suspended
+    pub fn is_suspended(&self) -> bool {
+        self.silence.state() == SuspendState::Suspended
+    }
+
+    /// Set the shared tempo, retime the delay and the synced LFOs and move the click
+    ///
+    /// A tempo-synced delay follows its note value, a free delay is set to one beat.
+    /// The tremolo and auto-pan follow the tempo only when synced.
+    pub fn set_tempo_bpm(&mut self, bpm: f64) {
+        self.tempo_bpm = bpm;
+        self.metronome.set_bpm(bpm);
+        if let Some(delay) = self.delay_mut() {
+            delay.set_tempo_bpm(bpm);
+        }
+        if let Some(tremolo) = self.tremolo_mut() {
+            tremolo.set_tempo_bpm(bpm);
+        }
+        if let Some(autopan) = self.autopan_mut() {
+            autopan.set_tempo_bpm(bpm);
+        }
+    }
+
+    /// Current shared tempo in BPM
+    pub fn tempo_bpm(&self) -> f64 {
+        self.tempo_bpm
+    }
+
+    /// Mutable access to the metronome (its tempo follows `set_tempo_bpm`)
+    pub fn metronome_mut(&mut self) -> &mut Metronome {
+        &mut self.metronome
+    }
+
+    /// The freeze-and-layer pad
+    pub fn pad_mut(&mut self) -> &mut PadLayer {
+        &mut self.pad
+    }
+
+    /// Names of the stages in processing order
+    pub fn stage_names(&self) -> Vec<&'static str> {
+        self.stages.iter().map(|stage| stage.name).collect()
+    }
+
+    /// Human-readable signal flow, e.g. `in -> dist -> (verb off) -> out (trim +0.0 dB)`
+    ///
+    /// With auto gain enabled each active stage also shows its current makeup gain,
+    /// and a stage ringing out in trails mode shows as `(delay trails)`.
+    pub fn describe(&self) -> String {
+        let stages: Vec<String> = self
+            .stages
+            .iter()
+            .map(|stage| {
+                if stage.trailing {
+                    format!("({} trails)", stage.name)
+                } else if !stage.enabled {
+                    format!("({} off)", stage.name)
+                } else if self.auto_gain_enabled {
+                    format!("{} [auto {:+.1} dB]", stage.name, 20.0 * stage.auto_gain.gain().log10())
+                } else {
+                    stage.name.to_string()
+                }
+            })
+            .collect();
+
+        let mut flow = vec!["in".to_string()];
+        flow.extend(stages);
+        flow.push("out".to_string());
+        format!("{} (trim {:+.1} dB)", flow.join(" -> "), self.output_trim_db)
+    }
+
+    /// Reorder the stages
+    ///
+    /// Every name must refer to an existing stage and may appear only once. Stages
+    /// that are not named keep their relative order and follow the named ones.
+    pub fn set_order(&mut self, order: &[&str]) -> Result<(), String> {
+        for (i, name) in order.iter().enumerate() {
+            if !self.stages.iter().any(|stage| stage.name == *name) {
+                return Err(format!("Unknown effect stage '{}' (available: {})", name, self.stage_names().join(", ")));
+            }
+            if order[..i].contains(name) {
+                return Err(format!("Effect stage '{}' listed more than once", name));
+            }
+        }
+
+        // Stable sort: named stages by their position in `order`, the rest after them
+        self.stages.sort_by_key(|stage| {
+            order.iter().position(|name| *name == stage.name).unwrap_or(order.len())
+        });
+        Ok(())
+    }
+
+    /// Mutable access to the first effect of type `T` in the chain
+    pub fn effect_mut<T: Effect + 'static>(&mut self) -> Option<&mut T> {
+        self.stages
+            .iter_mut()
+            .find_map(|stage| stage.effect.as_any_mut().downcast_mut::<T>())
+    }
+
+    /// Mutable access to the first reverb in the chain
+    pub fn reverb_mut(&mut self) -> Option<&mut Reverb> {
+        self.effect_mut::<Reverb>()
+    }
+
+    /// Mutable access to the first distortion in the chain
+    pub fn distortion_mut(&mut self) -> Option<&mut Distortion> {
+        self.effect_mut::<Distortion>()
+    }
+
+    /// Mutable access to the first multiband distortion in the chain
+    pub fn multiband_mut(&mut self) -> Option<&mut MultibandDistortion> {
+        self.effect_mut::<MultibandDistortion>()
+    }
+
+    /// Mutable access to the first delay in the chain
+    pub fn delay_mut(&mut self) -> Option<&mut Delay> {
+        self.effect_mut::<Delay>()
+    }
+
+    /// Mutable access to the first exciter in the chain
+    pub fn exciter_mut(&mut self) -> Option<&mut Exciter> {
+        self.effect_mut::<Exciter>()
+    }
+
+    /// Mutable access to the first boost in the chain
+    pub fn boost_mut(&mut self) -> Option<&mut Boost> {
+        self.effect_mut::<Boost>()
+    }
+
+    /// Mutable access to the first denoiser in the chain
+    pub fn denoiser_mut(&mut self) -> Option<&mut Denoiser> {
+        self.effect_mut::<Denoiser>()
+    }
+
+    /// Mutable access to the first stereo width stage in the chain
+    pub fn stereo_width_mut(&mut self) -> Option<&mut StereoWidth> {
+        self.effect_mut::<StereoWidth>()
+    }
+
+    /// Mutable access to the first auto-pan in the chain
+    pub fn autopan_mut(&mut self) -> Option<&mut AutoPan> {
+        self.effect_mut::<AutoPan>()
+    }
+
+    /// Mutable access to the first tremolo in the chain
+    pub fn tremolo_mut(&mut self) -> Option<&mut Tremolo> {
+        self.effect_mut::<Tremolo>()
+    }
+
+    /// Mutable access to the first double tracker in the chain
+    pub fn adt_mut(&mut self) -> Option<&mut Adt> {
+        self.effect_mut::<Adt>()
+    }
+
+    /// Mutable access to the first reverse delay in the chain
+    pub fn reverse_delay_mut(&mut self) -> Option<&mut ReverseDelay> {
+        self.effect_mut::<ReverseDelay>()
+    }
+
+    /// Mutable access to the first stutter in the chain
+    pub fn stutter_mut(&mut self) -> Option<&mut Stutter> {
+        self.effect_mut::<Stutter>()
+    }
+
+    /// Mutable access to the first volume swell in the chain
+    pub fn swell_mut(&mut self) -> Option<&mut Swell> {
+        self.effect_mut::<Swell>()
+    }
+
+    /// Mutable access to the effects loop, if one was added
+    pub fn insert_loop_mut(&mut self) -> Option<&mut InsertLoop> {
+        self.effect_mut::<InsertLoop>()
+    }
+
+    /// Look up a stage by name
+    fn stage_mut(&mut self, name: &str) -> Result<&mut ChainStage, String> {
+        self.stages
+            .iter_mut()
+            .find(|stage| stage.name == name)
+            .ok_or_else(|| format!("Unknown effect stage '{}'", name))
+    }
+}
+
+/// Per-frame switch crossfade increment for a fade time (1.0 switches at once)
+fn switch_step(fade_ms: f64, sample_rate: usize) -> f64 {
+    (1000.0 / (fade_ms * sample_rate as f64)).min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::any::Any;
+
+    /// Fixed gain, for order-dependence tests
+    struct Gain(f64);
+
+    impl Effect for Gain {
+        fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+            (input.0 * self.0, input.1 * self.0)
+        }
+
+        fn reset(&mut self) {}
+
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+    }
+
+    /// Hard clip at a fixed threshold, for order-dependence tests
+    struct Clip(f64);
+
+    impl Effect for Clip {
+        fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+            (input.0.clamp(-self.0, self.0), input.1.clamp(-self.0, self.0))
+        }
+
+        fn reset(&mut self) {}
+
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+    }
+
+    fn test_chain() -> EffectChain {
+        let mut chain = EffectChain::new(44100);
+        chain.add("dist", Box::new(Distortion::new(44100)));
+        chain.add("verb", Box::new(Reverb::new(44100)));
+        chain
+    }
+
+    #[test]
+    fn test_all_disabled_is_passthrough() {
+        let mut chain = test_chain();
+        chain.bypass_all();
+
+        for &sample in &[0.0, 0.25, -0.5, 1.0] {
+            assert_eq!(chain.process_frame((sample, -sample)), (sample, -sample));
+        }
+    }
+
+    #[test]
+    fn test_zero_mix_is_passthrough() {
+        let mut chain = test_chain();
+        chain.set_mix("dist", 0.0).unwrap();
+        chain.set_mix("verb", 0.0).unwrap();
+
+        assert_eq!(chain.process_frame((0.3, 0.3)), (0.3, 0.3));
+    }
+
+    #[test]
+    fn test_output_trim_scales_output() {
+        let mut chain = test_chain();
+        chain.bypass_all();
+
+        chain.set_output_trim_db(-6.0);
+        let (left, right) = chain.process_frame((1.0, 0.5));
+        assert!((left - 0.501).abs() < 0.001);
+        assert!((right - 0.2506).abs() < 0.001);
+
+        chain.set_output_trim_db(20.0);
+        assert_eq!(chain.output_trim_db(), 12.0);
+    }
+
+    #[test]
+    fn test_unknown_stage() {
+        let mut chain = test_chain();
+        assert!(chain.set_enabled("delay", true).is_err());
+        assert!(chain.set_mix("delay", 0.5).is_err());
+        assert_eq!(chain.stage_names(), vec!["dist", "verb"]);
+    }
+
+    #[test]
+    fn test_level_taps_find_the_stage_that_mutes() {
+        let mut chain = EffectChain::new(44100);
+        chain.add("gain", Box::new(Gain(0.5)));
+        chain.add("mute", Box::new(Gain(0.0)));
+        chain.add("after", Box::new(Gain(1.0)));
+        chain.add("off", Box::new(Gain(1.0)));
+        chain.set_switch_fade_ms(0.0);
+        chain.set_enabled("off", false).unwrap();
+
+        // Nothing is recorded until the taps are on
+        chain.process_frame((0.8, -0.4));
+        assert!(chain.take_stage_levels().iter().all(|levels| levels.input_peak == 0.0));
+
+        chain.set_level_taps(true);
+        for _ in 0..10 {
+            chain.process_frame((0.8, -0.4));
+        }
+        let levels = chain.take_stage_levels();
+        let peaks: Vec<(&str, bool, f64, f64)> =
+            levels.iter().map(|l| (l.name, l.active, l.input_peak, l.output_peak)).collect();
+        assert_eq!(
+            peaks,
+            vec![
+                ("gain", true, 0.8, 0.4),
+                ("mute", true, 0.4, 0.0),
+                ("after", true, 0.0, 0.0),
+                ("off", false, 0.0, 0.0),
+            ]
+        );
+
+        // Reading resets the peaks
+        assert!(chain.take_stage_levels().iter().all(|levels| levels.input_peak == 0.0));
+    }
+
+    #[test]
+    fn test_reorder_changes_output() {
+        let mut chain = EffectChain::new(44100);
+        chain.add("gain", Box::new(Gain(2.0)));
+        chain.add("clip", Box::new(Clip(0.5)));
+
+        // Gain then clip: 0.8 -> 1.6 -> 0.5
+        assert_eq!(chain.process_frame((0.8, 0.8)), (0.5, 0.5));
+
+        // Clip then gain: 0.8 -> 0.5 -> 1.0
+        chain.set_order(&["clip", "gain"]).unwrap();
+        assert_eq!(chain.stage_names(), vec!["clip", "gain"]);
+        assert_eq!(chain.process_frame((0.8, 0.8)), (1.0, 1.0));
+    }
+
+    #[test]
+    fn test_reorder_validation() {
+        let mut chain = test_chain();
+        assert!(chain.set_order(&["verb", "delay"]).is_err());
+        assert!(chain.set_order(&["verb", "verb"]).is_err());
+        assert_eq!(chain.stage_names(), vec!["dist", "verb"]);
+
+        // Unlisted stages follow the listed ones
+        chain.set_order(&["verb"]).unwrap();
+        assert_eq!(chain.stage_names(), vec!["verb", "dist"]);
+
+        chain.set_enabled("dist", false).unwrap();
+        assert_eq!(chain.describe(), "in -> verb -> (dist off) -> out (trim +0.0 dB)");
+    }
+
+    #[test]
+    fn test_effect_access_by_type() {
+        let mut chain = test_chain();
+        assert!(chain.distortion_mut().is_some());
+        assert!(chain.reverb_mut().is_some());
+        assert!(chain.effect_mut::<Gain>().is_none());
+    }
+
+    #[test]
+    fn test_auto_gain_compensates_stage_level() {
+        let mut chain = EffectChain::new(1000);
+        chain.add("gain", Box::new(Gain(2.0)));
+
+        chain.set_auto_gain(true);
+        let mut output = (0.0, 0.0);
+        for _ in 0..20000 {
+            output = chain.process_frame((0.25, 0.25));
+        }
+        assert!((output.0 - 0.25).abs() < 0.005);
+
+        chain.set_auto_gain(false);
+        assert_eq!(chain.process_frame((0.25, 0.25)), (0.5, 0.5));
+    }
+
+    #[test]
+    fn test_panic_resets_and_bypasses() {
+        let mut chain = test_chain();
+        chain.set_enabled("dist", true).unwrap();
+        chain.set_output_trim_db(6.0);
+
+        // Build up filter state in the distortion
+        for _ in 0..100 {
+            chain.process_frame((0.7, 0.7));
+        }
+        let (left, _) = chain.distortion_mut().unwrap().tick((0.0, 0.0));
+        assert!(left != 0.0);
+
+        chain.panic();
+
+        // Distortion filter state is zeroed: silence in gives silence out
+        assert_eq!(chain.distortion_mut().unwrap().tick((0.0, 0.0)), (0.0, 0.0));
+        assert_eq!(chain.describe(), "in -> (dist off) -> (verb off) -> out (trim +0.0 dB)");
+
+        // Output fades back in to a clean passthrough
+        let (first, _) = chain.process_frame((1.0, 1.0));
+        assert!(first < 0.01);
+        let mut output = (0.0, 0.0);
+        for _ in 0..44100 / 20 {
+            output = chain.process_frame((1.0, 1.0));
+        }
+        assert_eq!(output, (1.0, 1.0));
+    }
+
+    #[test]
+    fn test_output_dc_blocker() {
+        let mut chain = EffectChain::new(44100);
+        let signal = |i: usize| 0.3 + 0.2 * (2.0 * std::f64::consts::PI * 440.0 * i as f64 / 44100.0).sin();
+
+        // Let the blocker settle, then measure the mean over whole cycles
+        for i in 0..44100 {
+            chain.block_dc((signal(i), signal(i)));
+        }
+        let mean = (44100..88200).map(|i| chain.block_dc((signal(i), signal(i))).0).sum::<f64>() / 44100.0;
+        assert!(mean.abs() < 1e-3, "mean {}", mean);
+
+        // Switched off, the offset passes
+        chain.set_dc_block(false);
+        assert!(!chain.dc_block());
+        assert_eq!(chain.block_dc((0.3, -0.3)), (0.3, -0.3));
+    }
+
+    #[test]
+    fn test_auto_suspend_skips_stages_and_fades_back_in() {
+        let mut chain = EffectChain::new(8000);
+        chain.add("gain", Box::new(Gain(2.0)));
+        chain.set_auto_suspend(true);
+        chain.set_suspend_after(1.0);
+
+        for _ in 0..800 {
+            chain.process_frame((0.5, 0.5));
+        }
+        for _ in 0..12000 {
+            chain.process_frame((0.0, 0.0));
+        }
+        assert!(chain.is_suspended());
+
+        // The returning note ramps up to full level instead of jumping
+        let output: Vec<f64> = (0..200).map(|_| chain.process_frame((0.5, 0.5)).0).collect();
+        assert!(!chain.is_suspended());
+        assert!(output[0] < 0.05, "first sample {}", output[0]);
+        assert!(output.windows(2).all(|pair| pair[1] >= pair[0]));
+        assert_eq!(output[199], 1.0);
+    }
+
+    #[test]
+    fn test_trails_let_a_disabled_delay_ring_out() {
+        // Echo every 100ms at half level, dry passed separately by the chain
+        let run = |trails: bool| {
+            let mut chain = EffectChain::new(44100);
+            let mut delay = Delay::new(44100);
+            delay.set_time_ms(100.0);
+            delay.set_feedback(0.5);
+            chain.add("delay", Box::new(delay));
+            chain.set_dc_block(false);
+            chain.set_trails(trails);
+
+            chain.process_frame((1.0, 1.0));
+            chain.set_enabled("delay", false).unwrap();
+            (1..22050).map(|_| chain.process_frame((0.1, 0.1)).0).collect::<Vec<f64>>()
+        };
+
+        let trailing = run(true);
+        let cut = run(false);
+
+        // Without trails the delay is gone and only the dry signal is left
+        assert!(cut.iter().all(|&sample| (sample - 0.1).abs() < 1e-9));
+
+        // With trails the dry signal still passes at unity between the echoes...
+        assert!((trailing[1000] - 0.1).abs() < 1e-9, "dry {}", trailing[1000]);
+        // ...and the echoes already in the line keep coming, but nothing new is fed
+        let echo = |at: usize| trailing[at - 5..at + 5].iter().map(|s| (s - 0.1).abs()).fold(0.0, f64::max);
+        assert!(echo(4410) > 0.1, "first echo {}", echo(4410));
+        assert!(echo(8820) > 0.05, "second echo {}", echo(8820));
+        assert!(echo(8820) < echo(4410));
+    }
+
+    #[test]
+    fn test_switching_stages_crossfades() {
+        let mut chain = EffectChain::new(44100);
+        chain.add("double", Box::new(Gain(2.0)));
+        chain.add("invert", Box::new(Gain(-1.0)));
+        chain.set_enabled("invert", false).unwrap();
+        for _ in 0..1000 {
+            chain.process_frame((0.5, 0.5));
+        }
+        assert_eq!(chain.process_frame((0.5, 0.5)).0, 1.0);
+
+        // Swap one stage for the other: both run and the output moves across
+        chain.set_enabled("double", false).unwrap();
+        chain.set_enabled("invert", true).unwrap();
+        let output: Vec<f64> = (0..300).map(|_| chain.process_frame((0.5, 0.5)).0).collect();
+        assert!(output[0] < 1.0 && output[0] > 0.9, "first sample {}", output[0]);
+        assert!(output.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert!(output[100] > -0.5 && output[100] < 1.0, "mid fade {}", output[100]);
+        assert_eq!(output[299], -0.5);
+
+        // With no fade time the switch is immediate
+        chain.set_switch_fade_ms(0.0);
+        chain.set_enabled("invert", false).unwrap();
+        assert_eq!(chain.process_frame((0.5, 0.5)).0, 0.5);
+    }
+}
@@ -0,0 +1,93 @@
+//! Clip detection
+//!
+//! Latching clip indicators for the input and output. The audio callbacks set
+//! them with a single relaxed store per buffer; the main thread reports them until
+//! they are cleared.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::convert::ConvertSample;
+
+/// Whether any device sample in the buffer is clipped
+pub fn buffer_clips<T: ConvertSample>(buffer: &[T]) -> bool {
+    buffer.iter().any(|sample| sample.is_clipped())
+}
+
+/// Whether a processed sample exceeds full scale
+pub fn sample_clips(sample: f64) -> bool {
+    sample.abs() > 1.0
+}
+
+/// Latching input and output clip flags shared with the audio callbacks
+#[derive(Debug, Default)]
+pub struct ClipIndicator {
+    input: AtomicBool,
+    output: AtomicBool,
+}
+
+impl ClipIndicator {
+    /// Latch the input clip flag
+    pub fn flag_input(&self) {
+        self.input.store(true, Ordering::Relaxed);
+    }
+
+    /// Latch the output clip flag
+    pub fn flag_output(&self) {
+        self.output.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the input has clipped since the last clear
+    pub fn input_clipped(&self) -> bool {
+        self.input.load(Ordering::Relaxed)
+    }
+
+    /// Whether the output has clipped since the last clear
+    pub fn output_clipped(&self) -> bool {
+        self.output.load(Ordering::Relaxed)
+    }
+
+    /// Clear both flags
+    pub fn clear(&self) {
+        self.input.store(false, Ordering::Relaxed);
+        self.output.store(false, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_formats_clip_at_full_scale() {
+        assert!(!buffer_clips(&[0_i16, 1000, -32767, 32766]));
+        assert!(buffer_clips(&[0_i16, i16::MAX, 0]));
+        assert!(buffer_clips(&[i16::MIN]));
+
+        assert!(!buffer_clips(&[1_u16, 32768, 65534]));
+        assert!(buffer_clips(&[32768_u16, u16::MAX]));
+        assert!(buffer_clips(&[0_u16]));
+    }
+
+    #[test]
+    fn test_float_clips_above_full_scale() {
+        assert!(!buffer_clips(&[0.0_f32, 1.0, -1.0, 0.5]));
+        assert!(buffer_clips(&[0.0_f32, 1.01]));
+        assert!(buffer_clips(&[-1.5_f32]));
+        assert!(!buffer_clips::<f32>(&[]));
+
+        assert!(!sample_clips(1.0));
+        assert!(sample_clips(-1.0001));
+    }
+
+    #[test]
+    fn test_indicator_latches_until_cleared() {
+        let indicator = ClipIndicator::default();
+        indicator.flag_input();
+        assert!(indicator.input_clipped());
+        assert!(!indicator.output_clipped());
+        indicator.flag_output();
+        assert!(indicator.input_clipped() && indicator.output_clipped());
+        indicator.clear();
+        assert!(!indicator.input_clipped() && !indicator.output_clipped());
+    }
+}
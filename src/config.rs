@@ -0,0 +1,408 @@
+//! Startup configuration
+//!
+//! A config file uses the preset format with two extra sections for the audio
+//! setup:
+//!
+//! ```text
+//! [devices]
+//! input = "MacBook Pro Microphone"
+//! output = "External Headphones"
+//!
+//! [buffers]
+//! ring_size = 4096
+//! buffer_frames = 256
+//! prime_fill = 0.25
+//!
+//! [output]
+//! upmix = ["1 0", "0 1", "0.5 0.5", "0 0", "1 0", "0 1"]
+//! ```
+//!
+//! `upmix` gives the left and right gain for each output channel of a
+//! multi-channel device, and must have a row per channel. A `[midi]` section
+//! binds MIDI CC numbers to settings (see `midi`). All other sections are effect
+//! settings and are handled by `Preset`.
+//!
+//! With `--watch-config` the file is watched while running. Effect settings that
+//! change are applied to the live chain; device and buffer changes need new
+//! streams, so they are only reported. MIDI bindings are read at startup only,
+//! since learn mode rewrites them while running.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use notify::{RecursiveMode, Watcher};
+
+use crate::chain::EffectChain;
+use crate::ipc::status;
+use crate::midi::MidiMap;
+use crate::output_map::OutputMap;
+use crate::preset::{parse_document, Preset, Value};
+use crate::warmup::DEFAULT_PRIME_FILL;
+
+/// Default ring buffer size between the input and output streams
+pub const DEFAULT_RING_SIZE: usize = 8192;
+
+/// Settings loaded from a `--config` file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Input device name (None = system default)
+    pub input_device: Option<String>,
+    /// Output device name (None = system default)
+    pub output_device: Option<String>,
+    /// Ring buffer size in samples
+    pub ring_size: usize,
+    /// Fixed device buffer size in frames (None = device default)
+    pub buffer_frames: Option<u32>,
+    /// Share of the ring buffer filled before the output unmutes
+    pub prime_fill: f64,
+    /// Upmix matrix for multi-channel output (None = `--output-map`)
+    pub upmix: Option<OutputMap>,
+    /// Effect settings
+    pub preset: Preset,
+    /// MIDI CC bindings
+    pub midi: MidiMap,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            input_device: None,
+            output_device: None,
+            ring_size: DEFAULT_RING_SIZE,
+            buffer_frames: None,
+            prime_fill: DEFAULT_PRIME_FILL,
+            upmix: None,
+            preset: Preset::default(),
+            midi: MidiMap::default(),
+        }
+    }
+}
+
+/// Difference between the running config and a reloaded one
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigDiff {
+    /// Changed fields that only take effect after a restart
+    pub restart_required: Vec<&'static str>,
+    /// Changed effect settings, which can be applied live
+    pub live: Preset,
+}
+
+impl Config {
+    /// Read and parse a config file
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Cannot read config {}: {}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("Invalid config {}: {}", path, e))
+    }
+
+    /// Parse config text
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Config::default();
+
+        for entry in parse_document(text)? {
+            let field = format!("{}.{}", entry.section, entry.key);
+            let invalid = |expected: &str| format!("line {}: {}: expected {}, got {}", entry.line, field, expected, entry.value);
+
+            match (entry.section.as_str(), entry.key.as_str(), &entry.value) {
+                ("devices", "input", Value::Text(name)) => config.input_device = Some(name.clone()),
+                ("devices", "output", Value::Text(name)) => config.output_device = Some(name.clone()),
+                ("devices", "input" | "output", _) => return Err(invalid("a quoted device name")),
+                ("buffers", "ring_size", Value::Number(size)) if *size >= 256.0 && size.fract() == 0.0 => {
+                    config.ring_size = *size as usize;
+                }
+                ("buffers", "ring_size", _) => return Err(invalid("a whole number of at least 256")),
+                ("buffers", "buffer_frames", Value::Number(frames))
+                    if (16.0..=8192.0).contains(frames) && frames.fract() == 0.0 =>
+                {
+                    config.buffer_frames = Some(*frames as u32);
+                }
+                ("buffers", "buffer_frames", _) => return Err(invalid("a whole number from 16 to 8192")),
+                ("buffers", "prime_fill", Value::Number(fill)) if (0.0..=1.0).contains(fill) => config.prime_fill = *fill,
+                ("buffers", "prime_fill", _) => return Err(invalid("a number from 0 to 1")),
+                ("output", "upmix", Value::List(rows)) => {
+                    config.upmix = Some(OutputMap::parse_matrix(rows).map_err(|e| format!("line {}: {}: {}", entry.line, field, e))?);
+                }
+                ("output", "upmix", _) => return Err(invalid("a list of \"left right\" gain rows")),
+                ("midi", key, Value::Text(setting)) => {
+                    let cc = key
+                        .strip_prefix("cc")
+                        .and_then(|cc| cc.parse::<u8>().ok())
+                        .ok_or_else(|| format!("line {}: {}: expected a key such as cc7", entry.line, field))?;
+                    config.midi.bind(cc, setting).map_err(|e| format!("line {}: {}: {}", entry.line, field, e))?;
+                }
+                ("midi", _, _) => return Err(invalid("a quoted setting name such as \"mix.verb\"")),
+                (section, _, _) if Preset::has_section(section) => config
+                    .preset
+                    .set(&entry.section, &entry.key, entry.value.clone())
+                    .map_err(|e| format!("line {}: {}", entry.line, e))?,
+                _ => return Err(format!("line {}: unknown setting {}", entry.line, field)),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Compare with an updated config
+    pub fn diff(&self, updated: &Config) -> ConfigDiff {
+        let mut restart_required = Vec::new();
+        if self.input_device != updated.input_device {
+            restart_required.push("devices.input");
+        }
+        if self.output_device != updated.output_device {
+            restart_required.push("devices.output");
+        }
+        if self.ring_size != updated.ring_size {
+            restart_required.push("buffers.ring_size");
+        }
+        if self.buffer_frames != updated.buffer_frames {
+            restart_required.push("buffers.buffer_frames");
+        }
+        if self.prime_fill != updated.prime_fill {
+            restart_required.push("buffers.prime_fill");
+        }
+        if self.upmix != updated.upmix {
+            restart_required.push("output.upmix");
+        }
+
+        ConfigDiff {
+            restart_required,
+            live: updated.preset.changes_from(&self.preset),
+        }
+    }
+}
+
+/// Replace the `[section]` of a config file with `lines`, keeping everything else
+pub fn save_section(path: &str, section: &str, lines: &[String]) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Cannot read config {}: {}", path, e))?;
+    std::fs::write(path, replace_section(&text, section, lines)).map_err(|e| format!("Cannot write config {}: {}", path, e))
+}
+
+/// Config text with `[section]` replaced by `lines`, appended at the end
+fn replace_section(text: &str, section: &str, lines: &[String]) -> String {
+    let header = format!("[{}]", section);
+    let mut in_section = false;
+    let mut kept: Vec<&str> = Vec::new();
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            in_section = trimmed == header;
+        }
+        if !in_section {
+            kept.push(line);
+        }
+    }
+    while kept.last().is_some_and(|line| line.trim().is_empty()) {
+        kept.pop();
+    }
+
+    let mut result = kept.join("\n");
+    if !result.is_empty() {
+        result.push_str("\n\n");
+    }
+    result.push_str(&header);
+    result.push('\n');
+    for line in lines {
+        result.push_str(line);
+        result.push('\n');
+    }
+    result
+}
+
+/// Watch a config file and apply effect setting changes to the running chain
+///
+/// Runs until `running` is cleared. Invalid files are reported and skipped, and
+/// settings that need new streams are reported without being applied.
+pub fn watch(
+    path: &str,
+    mut current: Config,
+    chain: Arc<Mutex<EffectChain>>,
+    running: Arc<AtomicBool>,
+) -> notify::Result<()> {
+    // Watch the directory: editors often save by replacing the file
+    let file = Path::new(path);
+    let directory = file.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(directory, RecursiveMode::NonRecursive)?;
+
+    while running.load(Ordering::Relaxed) {
+        match receiver.recv_timeout(Duration::from_millis(200)) {
+            Ok(Ok(event))
+                if (event.kind.is_modify() || event.kind.is_create())
+                    && event.paths.iter().any(|changed| changed.file_name() == file.file_name()) => {}
+            Ok(_) | Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        // Let the save finish and collapse its burst of events into one reload
+        thread::sleep(Duration::from_millis(100));
+        while receiver.try_recv().is_ok() {}
+
+        let updated = match Config::load(path) {
+            Ok(updated) => updated,
+            Err(e) => {
+                status!("{} (keeping current settings)", e);
+                continue;
+            }
+        };
+
+        let diff = current.diff(&updated);
+        for field in &diff.restart_required {
+            status!("Config: {} changed, restart to apply", field);
+        }
+        if !diff.live.is_empty() {
+            let mut chain_guard = chain.lock().unwrap();
+            match diff.live.apply(&mut chain_guard) {
+                Ok(()) => status!("Config reloaded: {}", chain_guard.describe()),
+                Err(e) => status!("Config: {}", e),
+            }
+        }
+
+        // Streams keep their original setup, so only the effect settings move on
+        current.preset = updated.preset;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::EffectChain;
+    use crate::distortion::Distortion;
+    use crate::multiband::MultibandDistortion;
+    use crate::reverb::Reverb;
+    use crate::stereo::StereoWidth;
+
+    const SAMPLE: &str = r#"
+[devices]
+input = "Scarlett 2i2"
+
+[buffers]
+ring_size = 4096
+buffer_frames = 128
+prime_fill = 0.5
+
+[output]
+upmix = ["1 0", "0 1", "0.5 0.5", "0 0"]
+
+[chain]
+order = ["verb", "dist"]
+enabled = ["verb", "width"]
+trim_db = -6
+autogain = true
+
+[mix]
+verb = 0.5
+
+[reverb]
+predelay_ms = 20
+shimmer = 0.25
+gated = true
+gate_hold_ms = 300
+
+[stereo]
+width = 1.5
+"#;
+
+    fn test_chain() -> EffectChain {
+        let mut chain = EffectChain::new(44100);
+        chain.add("dist", Box::new(Distortion::new(44100)));
+        chain.add("multi", Box::new(MultibandDistortion::new(44100)));
+        chain.add("verb", Box::new(Reverb::new(44100)));
+        chain.add("width", Box::new(StereoWidth::new(44100)));
+        chain
+    }
+
+    #[test]
+    fn test_sample_config_applies() {
+        let config = Config::parse(SAMPLE).unwrap();
+        assert_eq!(config.input_device.as_deref(), Some("Scarlett 2i2"));
+        assert_eq!(config.output_device, None);
+        assert_eq!(config.ring_size, 4096);
+        assert_eq!(config.buffer_frames, Some(128));
+        assert_eq!(config.prime_fill, 0.5);
+        assert_eq!(
+            config.upmix,
+            Some(OutputMap::Matrix(vec![[1.0, 0.0], [0.0, 1.0], [0.5, 0.5], [0.0, 0.0]]))
+        );
+
+        let mut chain = test_chain();
+        config.preset.apply(&mut chain).unwrap();
+        assert_eq!(
+            chain.describe(),
+            "in -> verb [auto +0.0 dB] -> (dist off) -> (multi off) -> width [auto +0.0 dB] -> out (trim -6.0 dB)"
+        );
+
+        let reverb = chain.reverb_mut().unwrap();
+        assert_eq!(reverb.predelay_ms(), 20.0);
+        assert_eq!(reverb.shimmer(), 0.25);
+        assert!(reverb.is_gated());
+        assert_eq!(reverb.gate_mut().hold_ms(), 300.0);
+        assert_eq!(chain.stereo_width_mut().unwrap().width(), 1.5);
+    }
+
+    #[test]
+    fn test_invalid_config_names_field() {
+        let error = Config::parse("[buffers]\nring_size = 12.5").unwrap_err();
+        assert!(error.contains("line 2") && error.contains("buffers.ring_size"), "{}", error);
+
+        let error = Config::parse("[devices]\nspeaker = \"x\"").unwrap_err();
+        assert!(error.contains("unknown setting devices.speaker"), "{}", error);
+
+        let error = Config::parse("[stereo]\nwidth = 3").unwrap_err();
+        assert!(error.contains("stereo.width"), "{}", error);
+    }
+
+    #[test]
+    fn test_diff_classifies_changes() {
+        let base = Config::parse(SAMPLE).unwrap();
+        assert!(base.diff(&base).restart_required.is_empty());
+        assert!(base.diff(&base).live.is_empty());
+
+        // Effect parameters apply live
+        let updated = Config::parse(&SAMPLE.replace("shimmer = 0.25", "shimmer = 0.5")).unwrap();
+        let diff = base.diff(&updated);
+        assert!(diff.restart_required.is_empty());
+        assert_eq!(diff.live.get("reverb", "shimmer"), Some(&Value::Number(0.5)));
+        assert_eq!(diff.live.get("reverb", "predelay_ms"), None);
+
+        // Devices and buffers need a restart
+        let updated = Config::parse(
+            &SAMPLE
+                .replace("Scarlett 2i2", "Built-in Microphone")
+                .replace("buffer_frames = 128", "buffer_frames = 64"),
+        )
+        .unwrap();
+        let diff = base.diff(&updated);
+        assert_eq!(diff.restart_required, vec!["devices.input", "buffers.buffer_frames"]);
+        assert!(diff.live.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_stage_fails_on_apply() {
+        let config = Config::parse("[chain]\nenabled = [\"chorus\"]").unwrap();
+        let error = config.preset.apply(&mut test_chain()).unwrap_err();
+        assert!(error.contains("chain.enabled") && error.contains("chorus"), "{}", error);
+    }
+
+    #[test]
+    fn test_midi_bindings_round_trip_through_the_file() {
+        let config = Config::parse("[mix]\nverb = 0.2\n\n[midi]\ncc7 = \"mix.verb\"\n").unwrap();
+        assert_eq!(config.midi.config_lines(), vec!["cc7 = \"mix.verb\"".to_string()]);
+        assert!(Config::parse("[midi]\nvolume = \"mix.verb\"").unwrap_err().contains("midi.volume"));
+        assert!(Config::parse("[midi]\ncc7 = \"mix.chorus\"").unwrap_err().contains("mix.chorus"));
+
+        // Saving replaces the section and leaves the rest alone
+        let mut midi = config.midi.clone();
+        midi.bind(74, "distortion.drive").unwrap();
+        let text = replace_section("[mix]\nverb = 0.2\n\n[midi]\ncc7 = \"mix.verb\"\n\n[reverb]\nroom_size = 0.5\n", "midi", &midi.config_lines());
+        let saved = Config::parse(&text).unwrap();
+        assert_eq!(saved.midi, midi);
+        assert_eq!(saved.preset, Config::parse("[mix]\nverb = 0.2\n[reverb]\nroom_size = 0.5").unwrap().preset);
+        assert_eq!(text.matches("[midi]").count(), 1);
+    }
+}
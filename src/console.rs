@@ -0,0 +1,1407 @@
+//! Console commands
+//!
+//! The text commands typed at the console (`w 0.3`, `on delay`, `load crunch`,
+//! `tap`, ...) run against an engine's chain, meters and running flag. A
+//! `Console` also keeps what lives between commands: the tap tempo, footswitch
+//! bindings, morph slots, the preset scroll position and the command history.
+//! The command line program reads lines or keys and hands them to `run`; another
+//! frontend can drive the engine with the same commands through
+//! `Engine::apply_command`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+use crate::automation::{self, Automation, Curve};
+use crate::calibration::CalibrationTarget;
+use crate::chain::EffectChain;
+use crate::denoise::DenoiseMode;
+use crate::distortion::{CASCADE_STAGES, DistortionType};
+use crate::dsp;
+use crate::footswitch::{Footswitch, Footswitches, SwitchEvent, SwitchMode};
+use crate::gate::KeySource;
+use crate::history::CommandHistory;
+use crate::ipc::status;
+use crate::lfo::LfoShape;
+use crate::meter::Meters;
+use crate::midi::MidiMap;
+use crate::multiband::Band;
+use crate::offline;
+use crate::preset::{MorphSlots, Preset, PresetScroll, PRESET_DIR};
+use crate::reverb::ReverbAlgo;
+use crate::tempo::{NoteValue, TapTempo};
+use crate::trim::{InputPad, PadSwitch};
+
+/// Runs console commands, keeping the state that lives between them
+pub struct Console {
+    chain: Arc<Mutex<EffectChain>>,
+    running: Arc<AtomicBool>,
+    meters: Arc<Meters>,
+    /// CC bindings for `midi learn` (None = no MIDI input)
+    midi: Option<Arc<Mutex<MidiMap>>>,
+    /// Input pad the engine's input callback applies
+    input_pad: PadSwitch,
+    /// Whether the engine's output callback lets the processed signal through
+    monitor: Arc<AtomicBool>,
+    /// Where `calibrate save` writes (unset until the engine has devices)
+    calibration: Arc<OnceLock<CalibrationTarget>>,
+    tap_tempo: TapTempo,
+    footswitches: Footswitches,
+    morph: MorphSlots,
+    scroll: PresetScroll,
+    history: CommandHistory,
+}
+
+impl Console {
+    pub fn new(
+        chain: Arc<Mutex<EffectChain>>,
+        running: Arc<AtomicBool>,
+        meters: Arc<Meters>,
+        midi: Option<Arc<Mutex<MidiMap>>>,
+        input_pad: PadSwitch,
+        monitor: Arc<AtomicBool>,
+        calibration: Arc<OnceLock<CalibrationTarget>>,
+    ) -> Self {
+        Self {
+            chain,
+            running,
+            meters,
+            midi,
+            input_pad,
+            monitor,
+            calibration,
+            tap_tempo: TapTempo::new(),
+            footswitches: Footswitches::default(),
+            morph: MorphSlots::default(),
+            scroll: PresetScroll::default(),
+            history: CommandHistory::default(),
+        }
+    }
+
+    /// Footswitch bindings, for the raw key console to tell bound keys apart
+    pub fn footswitches(&self) -> &Footswitches {
+        &self.footswitches
+    }
+
+    /// Recent commands; the caller records what it reads
+    pub fn history_mut(&mut self) -> &mut CommandHistory {
+        &mut self.history
+    }
+
+    /// Send press and release events to the footswitch on `key`, switching its stage
+    pub fn footswitch(&mut self, key: char, events: &[SwitchEvent]) {
+        let mut chain_guard = self.chain.lock().unwrap();
+        for &event in events {
+            if let Some((stage, enabled)) = self.footswitches.handle(key, event) {
+                let _ = chain_guard.set_enabled(stage, enabled);
+                status!("Footswitch '{}': {} {}", key, stage, if enabled { "on" } else { "off" });
+            }
+        }
+    }
+
+    /// Run one line of console input, printing the result
+    pub fn run(&mut self, input: &str) {
+        let parts: Vec<&str> = input.split_whitespace().collect();
+    
+        let footswitch_key = input.chars().next().filter(|&key| input.len() == key.len_utf8() && self.footswitches.is_bound(key));
+        if let Some(key) = footswitch_key {
+            // Line input can't hold a key, so a footswitch gets a press and a release
+            self.footswitch(key, &[SwitchEvent::Press, SwitchEvent::Release]);
+        } else if input == "fs" {
+            if self.footswitches.iter().next().is_none() {
+                println!("No footswitches bound");
+            }
+            for (key, switch) in self.footswitches.iter() {
+                println!("Footswitch '{}': {} ({})", key, switch.stage(), switch.mode());
+            }
+        } else if parts.len() == 3 && parts[0] == "fs" && parts[2] == "off" {
+            match parts[1].chars().next() {
+                Some(key) if parts[1].len() == key.len_utf8() && self.footswitches.unbind(key) => {
+                    println!("Footswitch '{}' removed", key)
+                }
+                _ => println!("No footswitch on '{}'", parts[1]),
+            }
+        } else if parts.len() == 4 && parts[0] == "fs" {
+            // Bind a single-character key to a stage
+            let key = parts[1].chars().next().filter(|key| parts[1].len() == key.len_utf8());
+            let chain_guard = self.chain.lock().unwrap();
+            match (key, chain_guard.is_enabled(parts[2]), SwitchMode::parse(parts[3])) {
+                (None, _, _) | (Some('q'), _, _) => println!("Footswitch keys are single characters other than q"),
+                (_, Err(e), _) | (_, _, Err(e)) => println!("{}", e),
+                (Some(key), Ok(enabled), Ok(mode)) => {
+                    self.footswitches.bind(key, Footswitch::new(parts[2], mode, enabled));
+                    println!("Footswitch '{}': {} ({})", key, parts[2], mode);
+                }
+            }
+        } else if parts.first() == Some(&"load") && (parts.len() == 2 || parts.len() == 3) {
+            // Apply a preset file, with or without its routing (stage on/off states and order)
+            let params_only = match parts.get(2) {
+                None | Some(&"full") => false,
+                Some(&"params") => true,
+                Some(_) => {
+                    println!("Usage: load <preset> [full|params]");
+                    return;
+                }
+            };
+            let preset = match Preset::load(parts[1]) {
+                Ok(preset) if params_only => preset.parameters(),
+                Ok(preset) => preset,
+                Err(e) => {
+                    println!("{}", e);
+                    return;
+                }
+            };
+            let mut chain_guard = self.chain.lock().unwrap();
+            match preset.apply(&mut chain_guard) {
+                Ok(()) if params_only => println!("Preset {} loaded (parameters only)", parts[1]),
+                Ok(()) => println!("Preset {} loaded", parts[1]),
+                Err(e) => println!("Preset {}: {}", parts[1], e),
+            }
+        } else if input == "history" {
+            // Recent commands, oldest first
+            for (i, entry) in self.history.entries().enumerate() {
+                println!("{:3}  {}", i + 1, entry);
+            }
+        } else if input == "next" || input == "prev" {
+            // Step through the preset directory by name
+            match self.scroll.step(PRESET_DIR, input == "next") {
+                Ok((name, preset)) => {
+                    let mut chain_guard = self.chain.lock().unwrap();
+                    match preset.apply(&mut chain_guard) {
+                        Ok(()) => println!("Preset {} loaded", name),
+                        Err(e) => println!("Preset {}: {}", name, e),
+                    }
+                }
+                Err(e) => println!("{}", e),
+            }
+        } else if parts.len() == 3 && parts[0] == "morph" && (parts[1] == "a" || parts[1] == "b") {
+            // Load a preset file into a morph slot
+            match Preset::load(parts[2]) {
+                Ok(preset) => {
+                    self.morph.load(parts[1] == "b", preset);
+                    println!("Preset {} loaded into morph slot {}", parts[2], parts[1].to_uppercase());
+                }
+                Err(e) => println!("{}", e),
+            }
+        } else if parts.len() == 2 && parts[0] == "morph" {
+            // Blend between the A and B presets
+            let Ok(amount) = parts[1].parse::<f64>() else {
+                println!("Usage: morph a <preset> | morph b <preset> | morph <0-1>");
+                return;
+            };
+            let Some(changes) = self.morph.morph_to(amount) else {
+                println!("Load presets into both slots first (morph a <preset>, morph b <preset>)");
+                return;
+            };
+            let mut chain_guard = self.chain.lock().unwrap();
+            match changes.apply(&mut chain_guard) {
+                Ok(()) => println!("Morph {:.2} (A to B)", amount.clamp(0.0, 1.0)),
+                Err(e) => println!("Morph: {}", e),
+            }
+        } else if let Some(db) = input.strip_prefix("pad-") {
+            // Input pad in dB
+            match InputPad::from_db(db) {
+                Some(pad) => {
+                    self.input_pad.set(pad);
+                    println!("Input pad: -{} dB", pad.db());
+                }
+                None => println!("Usage: pad-0 | pad-10 | pad-20"),
+            }
+        } else if input == "calibrate save" {
+            // Store the current input pad and output trim for the devices in use
+            let trim_db = self.chain.lock().unwrap().output_trim_db();
+            let saved = match self.calibration.get() {
+                Some(target) => target.save(self.input_pad.pad(), trim_db),
+                None => Err("no audio devices are open".to_string()),
+            };
+            match saved {
+                Ok(saved) => println!("{}", saved),
+                Err(e) => println!("Cannot save calibration: {}", e),
+            }
+        } else if parts.first() == Some(&"env") && parts.len() <= 3 {
+            // Input envelope for visualizers, and its attack/release times in ms
+            let (mut attack_ms, mut release_ms) = self.meters.envelope_times();
+            match parts[1..] {
+                [] => {}
+                ["attack", ms] | ["release", ms] => {
+                    let Ok(ms) = ms.parse::<f32>() else {
+                        println!("Usage: env | env attack <ms> | env release <ms>");
+                        return;
+                    };
+                    if parts[1] == "attack" {
+                        attack_ms = ms;
+                    } else {
+                        release_ms = ms;
+                    }
+                    self.meters.set_envelope_times(attack_ms, release_ms);
+                    (attack_ms, release_ms) = self.meters.envelope_times();
+                }
+                _ => {
+                    println!("Usage: env | env attack <ms> | env release <ms>");
+                    return;
+                }
+            }
+            println!("Input envelope: {:.3} (attack {:.0} ms, release {:.0} ms)",
+                     self.meters.input_envelope(), attack_ms, release_ms);
+        } else if input == "cpu" {
+            println!("Processing load: {:.1}% of the buffer time", self.meters.load_percent());
+        } else if input == "clear-clip" {
+            self.meters.clip.clear();
+            println!("Clip indicators cleared");
+        } else if parts.len() >= 2 && parts[0] == "order" {
+            // Reorder the processing stages
+            let mut chain_guard = self.chain.lock().unwrap();
+            match chain_guard.set_order(&parts[1..]) {
+                Ok(()) => println!("Signal chain: {}", chain_guard.describe()),
+                Err(e) => println!("{}", e),
+            }
+        } else if parts.len() == 2 && (parts[0] == "on" || parts[0] == "off") {
+            // Enable or disable a chain stage
+            let enabled = parts[0] == "on";
+            let mut chain_guard = self.chain.lock().unwrap();
+            match chain_guard.set_enabled(parts[1], enabled) {
+                Ok(()) => println!("Stage '{}' {}", parts[1], if enabled { "enabled" } else { "disabled" }),
+                Err(e) => println!("{}", e),
+            }
+        } else if parts.len() == 2 && parts[0] == "width" {
+            // Stereo width (0-2, not limited to 0-1)
+            if let Ok(width) = parts[1].parse::<f64>() {
+                let mut chain_guard = self.chain.lock().unwrap();
+                let _ = chain_guard.set_enabled("width", true);
+                if let Some(stereo) = chain_guard.stereo_width_mut() {
+                    stereo.set_width(width);
+                    println!("Stereo width set to {:.2}", stereo.width());
+                }
+            }
+        } else if parts.len() == 3 && parts[0] == "pan" {
+            // Auto-pan rate, tempo sync, depth and waveform
+            let mut chain_guard = self.chain.lock().unwrap();
+            let bpm = chain_guard.tempo_bpm();
+            let _ = chain_guard.set_enabled("pan", true);
+            if let Some(autopan) = chain_guard.autopan_mut() {
+                match (parts[1], parts[2].parse::<f64>()) {
+                    ("rate", Ok(rate_hz)) => autopan.set_rate_hz(rate_hz),
+                    ("depth", Ok(depth)) => autopan.set_depth(depth),
+                    ("phase", Ok(phase)) => autopan.set_phase(phase),
+                    // Keep the current rate, stop following the tempo
+                    ("sync", _) if parts[2] == "off" => autopan.set_rate_hz(autopan.rate_hz()),
+                    ("sync", _) => match NoteValue::parse(parts[2]) {
+                        Ok(note) => autopan.set_sync(note, bpm),
+                        Err(e) => {
+                            println!("{}", e);
+                            return;
+                        }
+                    },
+                    ("restart", _) if parts[2] == "on" || parts[2] == "off" => autopan.set_restart_on_sync(parts[2] == "on"),
+                    ("shape", _) => match LfoShape::from_name(parts[2]) {
+                        Some(shape) => autopan.set_shape(shape),
+                        None => {
+                            println!("Unknown auto-pan shape '{}' (expected {})", parts[2], LfoShape::names(", "));
+                            return;
+                        }
+                    },
+                    _ => {
+                        println!("Usage: pan <rate|depth|phase|shape|sync|restart> <value>");
+                        return;
+                    }
+                }
+                println!(
+                    "Auto-pan: {} at {}, depth {:.2}, phase {:.2}",
+                    autopan.shape().name(),
+                    lfo_rate_text(autopan.sync(), autopan.rate_hz(), autopan.restart_on_sync()),
+                    autopan.depth(),
+                    autopan.phase()
+                );
+            }
+        } else if parts.len() == 3 && parts[0] == "trem" {
+            // Tremolo rate, tempo sync, depth and waveform
+            let mut chain_guard = self.chain.lock().unwrap();
+            let bpm = chain_guard.tempo_bpm();
+            let _ = chain_guard.set_enabled("trem", true);
+            if let Some(tremolo) = chain_guard.tremolo_mut() {
+                match (parts[1], parts[2].parse::<f64>()) {
+                    ("rate", Ok(rate_hz)) => tremolo.set_rate_hz(rate_hz),
+                    ("depth", Ok(depth)) => tremolo.set_depth(depth),
+                    ("sync", _) if parts[2] == "off" => tremolo.set_rate_hz(tremolo.rate_hz()),
+                    ("sync", _) => match NoteValue::parse(parts[2]) {
+                        Ok(note) => tremolo.set_sync(note, bpm),
+                        Err(e) => {
+                            println!("{}", e);
+                            return;
+                        }
+                    },
+                    ("restart", _) if parts[2] == "on" || parts[2] == "off" => tremolo.set_restart_on_sync(parts[2] == "on"),
+                    ("shape", _) => match LfoShape::from_name(parts[2]) {
+                        Some(shape) => tremolo.set_shape(shape),
+                        None => {
+                            println!("Unknown tremolo shape '{}' (expected {})", parts[2], LfoShape::names(", "));
+                            return;
+                        }
+                    },
+                    _ => {
+                        println!("Usage: trem <rate|depth|shape|sync|restart> <value>");
+                        return;
+                    }
+                }
+                println!(
+                    "Tremolo: {} at {}, depth {:.2}",
+                    tremolo.shape().name(),
+                    lfo_rate_text(tremolo.sync(), tremolo.rate_hz(), tremolo.restart_on_sync()),
+                    tremolo.depth()
+                );
+            }
+        } else if parts.len() == 2 && parts[0] == "reverse" && (parts[1] == "on" || parts[1] == "off") {
+            // Reverse delay switch
+            let enabled = parts[1] == "on";
+            let _ = self.chain.lock().unwrap().set_enabled("reverse", enabled);
+            println!("Reverse delay {}", if enabled { "on" } else { "off" });
+        } else if parts.len() == 3 && parts[0] == "reverse" {
+            // Reverse delay chunk length and mix
+            let mut chain_guard = self.chain.lock().unwrap();
+            let _ = chain_guard.set_enabled("reverse", true);
+            if let Some(reverse) = chain_guard.reverse_delay_mut() {
+                match (parts[1], parts[2].parse::<f64>()) {
+                    ("time", Ok(time_ms)) => reverse.set_time_ms(time_ms),
+                    ("mix", Ok(mix)) => reverse.set_mix(mix),
+                    _ => {
+                        println!("Usage: reverse <time|mix> <value>");
+                        return;
+                    }
+                }
+                println!("Reverse delay: {:.0} ms chunks, mix {:.2}", reverse.time_ms(), reverse.mix());
+            }
+        } else if parts.len() == 2 && parts[0] == "adt" && (parts[1] == "on" || parts[1] == "off") {
+            // Double tracking switch
+            let enabled = parts[1] == "on";
+            let _ = self.chain.lock().unwrap().set_enabled("adt", enabled);
+            println!("Double tracking {}", if enabled { "on" } else { "off" });
+        } else if parts.len() == 3 && parts[0] == "adt" {
+            // Double tracking delay and spread
+            let mut chain_guard = self.chain.lock().unwrap();
+            let _ = chain_guard.set_enabled("adt", true);
+            if let Some(adt) = chain_guard.adt_mut() {
+                match (parts[1], parts[2].parse::<f64>()) {
+                    ("delay", Ok(delay_ms)) => adt.set_delay_ms(delay_ms),
+                    ("spread", Ok(spread)) => adt.set_spread(spread),
+                    _ => {
+                        println!("Usage: adt <delay|spread> <value>");
+                        return;
+                    }
+                }
+                println!("Double tracking: copy {:.1} ms late, spread {:.2}", adt.delay_ms(), adt.spread());
+            }
+        } else if parts.len() == 2 && parts[0] == "boost" {
+            // Clean boost footswitch, or its level in dB
+            let mut chain_guard = self.chain.lock().unwrap();
+            match parts[1] {
+                "on" | "off" => {
+                    let enabled = parts[1] == "on";
+                    let _ = chain_guard.set_enabled("boost", enabled);
+                    println!("Boost {}", if enabled { "on" } else { "off" });
+                }
+                value => {
+                    if let Ok(gain_db) = value.parse::<f64>() {
+                        let _ = chain_guard.set_enabled("boost", true);
+                        if let Some(boost) = chain_guard.boost_mut() {
+                            boost.set_gain_db(gain_db);
+                            println!("Boost on - Gain set to +{:.1} dB", boost.gain_db());
+                        }
+                    }
+                }
+            }
+        } else if parts.len() == 2 && parts[0] == "swell" {
+            // Volume swell switch, or its swell time in ms
+            let mut chain_guard = self.chain.lock().unwrap();
+            match parts[1] {
+                "on" | "off" => {
+                    let enabled = parts[1] == "on";
+                    let _ = chain_guard.set_enabled("swell", enabled);
+                    println!("Swell {}", if enabled { "on" } else { "off" });
+                }
+                value => {
+                    if let Ok(swell_ms) = value.parse::<f64>() {
+                        let _ = chain_guard.set_enabled("swell", true);
+                        if let Some(swell) = chain_guard.swell_mut() {
+                            swell.set_swell_ms(swell_ms);
+                            println!("Swell on - Notes fade in over {:.0} ms", swell.swell_ms());
+                        }
+                    }
+                }
+            }
+        } else if parts.first() == Some(&"stutter") && (parts.len() == 1 || parts.len() == 3) {
+            // Stutter trigger, or its slice and hold lengths in ms
+            let mut chain_guard = self.chain.lock().unwrap();
+            let _ = chain_guard.set_enabled("stutter", true);
+            let Some(stutter) = chain_guard.stutter_mut() else {
+                return;
+            };
+            match (parts.get(1).copied(), parts.get(2).map(|value| value.parse::<f64>())) {
+                (None, _) => {
+                    let action = if stutter.is_stuttering() { "retriggered" } else { "repeating" };
+                    stutter.trigger();
+                    println!("Stutter {}: {:.0} ms slice for {:.0} ms", action, stutter.slice_ms(), stutter.hold_ms());
+                }
+                (Some("slice"), Some(Ok(slice_ms))) => {
+                    stutter.set_slice_ms(slice_ms);
+                    println!("Stutter slice set to {:.0} ms", stutter.slice_ms());
+                }
+                (Some("hold"), Some(Ok(hold_ms))) => {
+                    stutter.set_hold_ms(hold_ms);
+                    println!("Stutter hold set to {:.0} ms", stutter.hold_ms());
+                }
+                _ => println!("Usage: stutter | stutter slice <ms> | stutter hold <ms>"),
+            }
+        } else if parts.len() == 2 && parts[0] == "excite" {
+            // Harmonic exciter switch, or its amount
+            let mut chain_guard = self.chain.lock().unwrap();
+            match parts[1] {
+                "on" | "off" => {
+                    let enabled = parts[1] == "on";
+                    let _ = chain_guard.set_enabled("excite", enabled);
+                    println!("Exciter {}", if enabled { "on" } else { "off" });
+                }
+                value => {
+                    if let Ok(amount) = value.parse::<f64>() {
+                        let _ = chain_guard.set_enabled("excite", true);
+                        if let Some(exciter) = chain_guard.exciter_mut() {
+                            exciter.set_amount(amount);
+                            println!("Exciter on - Amount set to {:.2}", exciter.amount());
+                        }
+                    }
+                }
+            }
+        } else if parts.len() == 2 && parts[0] == "autogain" {
+            // Per-stage makeup gain
+            let enabled = match parts[1] {
+                "on" => Some(true),
+                "off" => Some(false),
+                _ => None,
+            };
+            if let Some(enabled) = enabled {
+                let mut chain_guard = self.chain.lock().unwrap();
+                chain_guard.set_auto_gain(enabled);
+                println!("Auto gain {}", if enabled { "enabled" } else { "disabled" });
+            }
+        } else if parts.len() == 2 && parts[0] == "bpm" {
+            // Shared tempo for the click and the delay
+            if let Ok(bpm) = parts[1].parse::<f64>() {
+                let mut chain_guard = self.chain.lock().unwrap();
+                chain_guard.set_tempo_bpm(bpm.clamp(30.0, 300.0));
+                println!("Tempo {:.1} BPM", chain_guard.tempo_bpm());
+            }
+        } else if parts.len() == 2 && parts[0] == "click" && (parts[1] == "on" || parts[1] == "off") {
+            // Practice click
+            let mut chain_guard = self.chain.lock().unwrap();
+            let metronome = chain_guard.metronome_mut();
+            metronome.set_enabled(parts[1] == "on");
+            if metronome.is_enabled() {
+                let (beats, note) = metronome.time_signature();
+                println!("Click on - {:.1} BPM in {}/{}", metronome.bpm(), beats, note);
+            } else {
+                println!("Click off");
+            }
+        } else if parts.len() == 3 && parts[0] == "click" {
+            // Click volume and time signature
+            let mut chain_guard = self.chain.lock().unwrap();
+            let metronome = chain_guard.metronome_mut();
+            match parts[1] {
+                "vol" => {
+                    if let Ok(volume) = parts[2].parse::<f64>() {
+                        metronome.set_volume(volume);
+                        println!("Click volume set to {:.2}", metronome.volume());
+                    }
+                }
+                "sig" => {
+                    let signature = parts[2]
+                        .split_once('/')
+                        .and_then(|(beats, note)| Some((beats.parse::<u32>().ok()?, note.parse::<u32>().ok()?)));
+                    match signature {
+                        Some((beats, note)) => match metronome.set_time_signature(beats, note) {
+                            Ok(()) => println!("Time signature set to {}/{}", beats, note),
+                            Err(e) => println!("{}", e),
+                        },
+                        None => println!("Invalid time signature '{}' (expected e.g. 3/4)", parts[2]),
+                    }
+                }
+                _ => println!("Usage: click <on|off> | click vol <0-1> | click sig <beats>/<note>"),
+            }
+        } else if parts.len() == 2 && parts[0] == "pad" && (parts[1] == "capture" || parts[1] == "clear") {
+            // Freeze the wet signal into a pad layer, or drop it
+            let mut chain_guard = self.chain.lock().unwrap();
+            let pad = chain_guard.pad_mut();
+            if parts[1] == "capture" {
+                pad.capture();
+                println!("Pad captured at level {:.2}, {:+.0} semitones", pad.pad_level(), pad.semitones());
+            } else if pad.is_playing() {
+                pad.clear();
+                println!("Pad cleared");
+            } else {
+                println!("No pad playing");
+            }
+        } else if parts.len() == 3 && parts[0] == "pad" {
+            // Pad level and pitch
+            let Ok(value) = parts[2].parse::<f64>() else {
+                return;
+            };
+            let mut chain_guard = self.chain.lock().unwrap();
+            let pad = chain_guard.pad_mut();
+            match parts[1] {
+                "level" => {
+                    pad.set_pad_level(value);
+                    println!("Pad level set to {:.2}", pad.pad_level());
+                }
+                "pitch" => {
+                    pad.set_semitones(value);
+                    println!("Pad pitch set to {:+.0} semitones", pad.semitones());
+                }
+                _ => println!("Usage: pad capture | pad clear | pad level <0-1> | pad pitch <semitones>"),
+            }
+        } else if parts.len() == 2 && parts[0] == "export-ir" {
+            // Render the reverb's impulse response on a copy, leaving the live reverb alone
+            let Some(mut reverb) = self.chain.lock().unwrap().reverb_mut().map(|reverb| reverb.clone_settings()) else {
+                println!("No reverb stage in the chain");
+                return;
+            };
+            match offline::export_ir(&mut reverb, parts[1]) {
+                Ok(frames) => println!(
+                    "Exported a {:.2} s impulse response to {}",
+                    frames as f64 / reverb.sample_rate() as f64,
+                    parts[1]
+                ),
+                Err(e) => println!("Failed to export the impulse response: {}", e),
+            }
+        } else if parts.len() == 1 && parts[0] == "loop" {
+            // Effects loop buffer health
+            let mut chain_guard = self.chain.lock().unwrap();
+            match chain_guard.insert_loop_mut() {
+                Some(insert) => {
+                    let (dropped, missing) = insert.xruns();
+                    println!("Effects loop: {} frames dropped on send, {} frames missing on return", dropped, missing);
+                }
+                None => println!("No effects loop (start with --insert-pipe <send> <return>)"),
+            }
+        } else if parts.len() == 2 && parts[0] == "autosuspend" {
+            // Skip the effects after a stretch of silence on the input
+            let mut chain_guard = self.chain.lock().unwrap();
+            match parts[1] {
+                "on" => chain_guard.set_auto_suspend(true),
+                "off" => chain_guard.set_auto_suspend(false),
+                value => match value.parse::<f64>() {
+                    Ok(seconds) => chain_guard.set_suspend_after(seconds),
+                    Err(_) => return,
+                },
+            }
+            if chain_guard.auto_suspend() {
+                let now = if chain_guard.is_suspended() { " (suspended now)" } else { "" };
+                println!("Auto-suspend after {:.0} s of silence{}", chain_guard.suspend_after(), now);
+            } else {
+                println!("Auto-suspend disabled");
+            }
+        } else if parts.len() == 1 && parts[0] == "automate" {
+            // Running parameter sweeps
+            let running = automation::running();
+            if running.is_empty() {
+                println!("No automations running");
+            }
+            for automation in running {
+                println!(
+                    "Automating {} to {} over the next {:.1} s ({})",
+                    automation.name,
+                    automation.goal(),
+                    automation.remaining_seconds(),
+                    automation.curve().name()
+                );
+            }
+        } else if parts.len() == 2 && parts[0] == "automate" && parts[1] == "stop" {
+            automation::stop_all();
+            println!("Automations stopped");
+        } else if (parts.len() == 4 || parts.len() == 5) && parts[0] == "automate" {
+            // Sweep a parameter from its current value to a goal on the timer thread
+            let goal = parts[2].parse::<f64>();
+            let seconds = parts[3].strip_suffix('s').unwrap_or(parts[3]).parse::<f64>();
+            let curve = parts.get(4).map_or(Some(Curve::Linear), |name| Curve::from_name(name));
+            let (Ok(goal), Ok(seconds), Some(curve)) = (goal, seconds, curve) else {
+                println!("Usage: automate <{}> <goal> <seconds>[s] [lin|exp]", automation::target_names().replace(", ", "|"));
+                return;
+            };
+            let mut chain_guard = self.chain.lock().unwrap();
+            let Some(start) = automation::current_value(&mut chain_guard, parts[1]) else {
+                println!("Can't automate '{}' (expected one of {}, with a reverb stage)", parts[1], automation::target_names());
+                return;
+            };
+            match Automation::new(parts[1], start, goal, seconds, curve) {
+                Ok(automation) => {
+                    let _ = chain_guard.set_enabled("verb", true);
+                    println!("Automating {} from {:.2} to {:.2} over {:.1} s ({})", parts[1], start, goal, seconds, curve.name());
+                    automation::start(automation);
+                }
+                Err(e) => println!("{}", e),
+            }
+        } else if parts.len() == 2 && parts[0] == "dcblock" {
+            // Final DC blocker on the output
+            let enabled = match parts[1] {
+                "on" => Some(true),
+                "off" => Some(false),
+                _ => None,
+            };
+            if let Some(enabled) = enabled {
+                let mut chain_guard = self.chain.lock().unwrap();
+                chain_guard.set_dc_block(enabled);
+                println!("Output DC blocker {}", if chain_guard.dc_block() { "enabled" } else { "disabled" });
+            }
+        } else if parts.len() == 2 && parts[0] == "trails" {
+            // Trails bypass: switched-off delay and reverb ring out
+            let enabled = match parts[1] {
+                "on" => Some(true),
+                "off" => Some(false),
+                _ => None,
+            };
+            if let Some(enabled) = enabled {
+                let mut chain_guard = self.chain.lock().unwrap();
+                chain_guard.set_trails(enabled);
+                println!("Trails {}", if chain_guard.trails() { "on" } else { "off" });
+            }
+        } else if parts.len() == 2 && parts[0] == "switchfade" {
+            // Crossfade time for switching stages on and off
+            match parts[1].parse::<f64>() {
+                Ok(fade_ms) => {
+                    let mut chain_guard = self.chain.lock().unwrap();
+                    chain_guard.set_switch_fade_ms(fade_ms);
+                    println!("Stage switch crossfade: {:.1} ms", chain_guard.switch_fade_ms());
+                }
+                Err(_) => println!("Usage: switchfade <0-100 ms>"),
+            }
+        } else if parts.len() == 2 && parts[0] == "monitor" {
+            // Output monitoring: off writes silence while capture and processing go on
+            let enabled = match parts[1] {
+                "on" => true,
+                "off" => false,
+                _ => {
+                    println!("Usage: monitor <on|off>");
+                    return;
+                }
+            };
+            self.monitor.store(enabled, Ordering::Relaxed);
+            if enabled {
+                println!("Monitoring on");
+            } else {
+                println!("Monitoring off - output muted, input still captured and processed");
+            }
+        } else if parts.first() == Some(&"midi") && parts.len() <= 3 {
+            // MIDI CC bindings, and learn mode for a new one
+            let Some(midi) = &self.midi else {
+                println!("No MIDI input (start with --midi <port>)");
+                return;
+            };
+            let mut map = midi.lock().unwrap();
+            match parts[1..] {
+                [] => {
+                    for binding in map.bindings() {
+                        println!("CC {} -> {}.{}", binding.cc, binding.section, binding.key);
+                    }
+                    match map.learning() {
+                        Some(setting) => println!("Learning: move a control to bind it to {}", setting),
+                        None if map.bindings().is_empty() => println!("No MIDI bindings (midi learn <setting>)"),
+                        None => {}
+                    }
+                }
+                ["learn", "off"] => {
+                    if map.cancel_learn() {
+                        println!("MIDI learn cancelled");
+                    }
+                }
+                ["learn", setting] => match map.learn(setting) {
+                    Ok(()) => println!("MIDI learn: move a control to bind it to {}", setting),
+                    Err(e) => println!("{}", e),
+                },
+                _ => println!("Usage: midi | midi learn <setting> | midi learn off"),
+            }
+        } else if parts.len() == 2 && parts[0] == "pre" {
+            // Reverb pre-delay in milliseconds (not limited to 0-1)
+            if let Ok(predelay_ms) = parts[1].parse::<f64>() {
+                let mut chain_guard = self.chain.lock().unwrap();
+                let _ = chain_guard.set_enabled("verb", true);
+                if let Some(reverb) = chain_guard.reverb_mut() {
+                    reverb.set_predelay_ms(predelay_ms);
+                    println!("Reverb pre-delay set to {:.1} ms", reverb.predelay_ms());
+                }
+            }
+        } else if parts.len() == 2 && parts[0] == "decay" {
+            // Reverb decay time in seconds, through the room size
+            if let Ok(seconds) = parts[1].parse::<f64>() {
+                let mut chain_guard = self.chain.lock().unwrap();
+                let _ = chain_guard.set_enabled("verb", true);
+                if let Some(reverb) = chain_guard.reverb_mut() {
+                    reverb.set_decay_seconds(seconds);
+                    println!(
+                        "Reverb decay set to {:.2} s (room size {:.2})",
+                        reverb.decay_seconds(),
+                        reverb.room_size()
+                    );
+                }
+            }
+        } else if parts.len() == 2 && parts[0] == "shim" {
+            // Shimmer amount (octave-up feedback in the reverb tail)
+            if let Ok(shimmer) = parts[1].parse::<f64>() {
+                let mut chain_guard = self.chain.lock().unwrap();
+                let _ = chain_guard.set_enabled("verb", true);
+                if let Some(reverb) = chain_guard.reverb_mut() {
+                    reverb.set_shimmer(shimmer);
+                    println!("Reverb shimmer set to {:.2}", reverb.shimmer());
+                }
+            }
+        } else if parts.first() == Some(&"rmod") && (parts.len() == 2 || parts.len() == 3) {
+            // Reverb tail modulation depth, or its rate in Hz
+            let value = match parts[1..] {
+                [depth] | ["rate", depth] => depth.parse::<f64>().ok(),
+                _ => None,
+            };
+            let Some(value) = value else {
+                println!("Usage: rmod <0-1> | rmod rate <hz>");
+                return;
+            };
+            let mut chain_guard = self.chain.lock().unwrap();
+            let _ = chain_guard.set_enabled("verb", true);
+            if let Some(reverb) = chain_guard.reverb_mut() {
+                if parts.len() == 3 {
+                    reverb.set_reverb_mod_rate(value);
+                } else {
+                    reverb.set_reverb_mod_depth(value);
+                }
+                println!("Reverb tail modulation depth {:.2} at {:.2} Hz", reverb.mod_depth(), reverb.mod_rate());
+            }
+        } else if parts.len() == 2 && parts[0] == "verb-algo" {
+            // Reverb tail algorithm, crossfaded
+            let Some(algo) = ReverbAlgo::from_name(parts[1]) else {
+                println!("Unknown reverb algorithm '{}' (expected {})", parts[1], ReverbAlgo::names(", "));
+                return;
+            };
+            let mut chain_guard = self.chain.lock().unwrap();
+            let _ = chain_guard.set_enabled("verb", true);
+            if let Some(reverb) = chain_guard.reverb_mut() {
+                reverb.set_reverb_algo(algo);
+                println!("Reverb algorithm set to {}", reverb.algo().name());
+            }
+        } else if parts.len() == 2 && parts[0] == "damp" {
+            // Dark low-pass on the reverb tail in Hz (off to disable)
+            let cutoff_hz = if parts[1] == "off" { Ok(f64::INFINITY) } else { parts[1].parse::<f64>() };
+            if let Ok(cutoff_hz) = cutoff_hz {
+                let mut chain_guard = self.chain.lock().unwrap();
+                let _ = chain_guard.set_enabled("verb", true);
+                if let Some(reverb) = chain_guard.reverb_mut() {
+                    reverb.set_reverb_damp_lp_hz(cutoff_hz);
+                    match reverb.damp_lp_hz() {
+                        Some(cutoff_hz) => println!("Reverb dark low-pass set to {:.0} Hz", cutoff_hz),
+                        None => println!("Reverb dark low-pass off"),
+                    }
+                }
+            }
+        } else if parts.len() == 2 && (parts[0] == "vhp" || parts[0] == "vlp") {
+            // Send EQ on the reverb input in Hz (off to disable)
+            let off = if parts[0] == "vhp" { 0.0 } else { f64::INFINITY };
+            let cutoff_hz = if parts[1] == "off" { Ok(off) } else { parts[1].parse::<f64>() };
+            if let Ok(cutoff_hz) = cutoff_hz {
+                let mut chain_guard = self.chain.lock().unwrap();
+                let _ = chain_guard.set_enabled("verb", true);
+                if let Some(reverb) = chain_guard.reverb_mut() {
+                    let (name, cutoff_hz) = if parts[0] == "vhp" {
+                        reverb.set_reverb_send_hp_hz(cutoff_hz);
+                        ("high-pass", reverb.send_hp_hz())
+                    } else {
+                        reverb.set_reverb_send_lp_hz(cutoff_hz);
+                        ("low-pass", reverb.send_lp_hz())
+                    };
+                    match cutoff_hz {
+                        Some(cutoff_hz) => println!("Reverb send {} set to {:.0} Hz", name, cutoff_hz),
+                        None => println!("Reverb send {} off", name),
+                    }
+                }
+            }
+        } else if parts.len() == 2 && parts[0] == "gatereverb" {
+            // Gate the reverb tail from the dry input
+            let gated = match parts[1] {
+                "on" => Some(true),
+                "off" => Some(false),
+                _ => None,
+            };
+            if let Some(gated) = gated {
+                let mut chain_guard = self.chain.lock().unwrap();
+                let _ = chain_guard.set_enabled("verb", true);
+                if let Some(reverb) = chain_guard.reverb_mut() {
+                    reverb.set_gated(gated);
+                    println!("Gated reverb {}", if reverb.is_gated() { "enabled" } else { "disabled" });
+                }
+            }
+        } else if parts.len() == 3 && parts[0] == "freeze" && parts[1] == "release" {
+            // Time for a released freeze to ease back into the normal decay, in ms
+            if let Ok(release_ms) = parts[2].parse::<f64>() {
+                let mut chain_guard = self.chain.lock().unwrap();
+                if let Some(reverb) = chain_guard.reverb_mut() {
+                    reverb.set_freeze_release_ms(release_ms);
+                    println!("Reverb freeze release set to {:.0} ms", reverb.freeze_release_ms());
+                }
+            }
+        } else if (parts.len() == 1 || parts.len() == 2) && parts[0] == "freeze" {
+            // Hold the reverb tail; without an argument, toggle
+            let mut chain_guard = self.chain.lock().unwrap();
+            if let Some(reverb) = chain_guard.reverb_mut() {
+                let frozen = match parts.get(1) {
+                    None => Some(!reverb.is_frozen()),
+                    Some(&"on") => Some(true),
+                    Some(&"off") => Some(false),
+                    Some(_) => None,
+                };
+                if let Some(frozen) = frozen {
+                    reverb.set_freeze(frozen);
+                    println!("Reverb {}", if reverb.is_frozen() { "frozen" } else { "released" });
+                    let _ = chain_guard.set_enabled("verb", true);
+                }
+            }
+        } else if parts.len() == 2 && (parts[0] == "gatehold" || parts[0] == "gaterel" || parts[0] == "gatethr" || parts[0] == "gatehyst") {
+            // Gated reverb hold/release in milliseconds, threshold 0-1, hysteresis in dB
+            if let Ok(value) = parts[1].parse::<f64>() {
+                let mut chain_guard = self.chain.lock().unwrap();
+                if let Some(reverb) = chain_guard.reverb_mut() {
+                    let gate = reverb.gate_mut();
+                    match parts[0] {
+                        "gatehold" => gate.set_hold_ms(value),
+                        "gaterel" => gate.set_release_ms(value),
+                        "gatehyst" => gate.set_hysteresis_db(value),
+                        _ => gate.set_threshold(value),
+                    }
+                    println!("Reverb gate: threshold {:.3} (closes below {:.3}), hold {:.0} ms, release {:.0} ms",
+                             gate.threshold(), gate.close_threshold(), gate.hold_ms(), gate.release_ms());
+                }
+            }
+        } else if parts.len() == 2 && parts[0] == "gatekey" {
+            // Gated reverb key: the dry input or the tail itself
+            let key_source = match parts[1] {
+                "dry" => KeySource::Sidechain,
+                "tail" => KeySource::Signal,
+                _ => {
+                    println!("Usage: gatekey <dry|tail>");
+                    return;
+                }
+            };
+            let mut chain_guard = self.chain.lock().unwrap();
+            if let Some(reverb) = chain_guard.reverb_mut() {
+                reverb.set_gate_key(key_source);
+                println!("Reverb gate keyed from the {}", parts[1]);
+            }
+        } else if (parts.len() == 2 || parts.len() == 3) && parts[0] == "duck" {
+            // Reverb tail ducking keyed from the dry input
+            let Some(Ok(value)) = parts.last().map(|value| value.parse::<f64>()) else {
+                return;
+            };
+            let mut chain_guard = self.chain.lock().unwrap();
+            let _ = chain_guard.set_enabled("verb", true);
+            if let Some(reverb) = chain_guard.reverb_mut() {
+                match parts[1..] {
+                    [_] => reverb.set_duck_amount(value),
+                    ["attack", _] => reverb.set_duck_attack(value),
+                    ["release", _] => reverb.set_duck_release(value),
+                    _ => {
+                        println!("Usage: duck <0-1> | duck attack <ms> | duck release <ms>");
+                        return;
+                    }
+                }
+                let ducker = reverb.ducker();
+                println!("Reverb ducking: amount {:.2}, attack {:.0} ms, release {:.0} ms",
+                         ducker.amount(), ducker.attack_ms(), ducker.release_ms());
+            }
+        } else if parts.len() == 2 && parts[0] == "dt" {
+            // Delay time in milliseconds (not limited to 0-1)
+            if let Ok(time_ms) = parts[1].parse::<f64>() {
+                let mut chain_guard = self.chain.lock().unwrap();
+                let _ = chain_guard.set_enabled("delay", true);
+                if let Some(delay) = chain_guard.delay_mut() {
+                    delay.set_time_ms(time_ms);
+                    println!("Delay activated - Time set to {:.0} ms", delay.time_ms());
+                }
+            }
+        } else if parts.len() == 2 && parts[0] == "nr" {
+            // Noise reduction: learn a profile, apply it, or set the reduction in dB
+            let mut chain_guard = self.chain.lock().unwrap();
+            let _ = chain_guard.set_enabled("nr", true);
+            if let Some(denoiser) = chain_guard.denoiser_mut() {
+                match parts[1] {
+                    "learn" => {
+                        denoiser.start_learning();
+                        println!("Learning noise profile - let the noise play without playing, then enter 'nr apply'");
+                    }
+                    "apply" => {
+                        if denoiser.mode() != DenoiseMode::Learn {
+                            println!("Not learning - enter 'nr learn' first");
+                        } else if denoiser.finish_learning() {
+                            println!("Noise profile learned - Reduction {:.0} dB", denoiser.reduction_db());
+                        } else {
+                            println!("No noise heard yet - keeping the previous profile");
+                        }
+                    }
+                    value => match value.parse::<f64>() {
+                        Ok(reduction_db) => {
+                            denoiser.set_reduction_db(reduction_db);
+                            println!("Noise reduction set to {:.0} dB", denoiser.reduction_db());
+                        }
+                        Err(_) => println!("Usage: nr learn | nr apply | nr <dB>"),
+                    },
+                }
+            }
+        } else if parts.len() == 3 && parts[0] == "dly" && parts[1] == "sync" {
+            // Delay time as a note value of the current tempo
+            let mut chain_guard = self.chain.lock().unwrap();
+            let bpm = chain_guard.tempo_bpm();
+            let _ = chain_guard.set_enabled("delay", true);
+            if let Some(delay) = chain_guard.delay_mut() {
+                if parts[2] == "off" {
+                    // Keep the current time, stop following the tempo
+                    delay.set_time_ms(delay.time_ms());
+                    println!("Delay sync off - Time {:.0} ms", delay.time_ms());
+                } else {
+                    match NoteValue::parse(parts[2]) {
+                        Ok(note) => {
+                            delay.set_sync(note, bpm);
+                            println!("Delay synced to {} at {:.1} BPM - Time {:.0} ms", note, bpm, delay.time_ms());
+                        }
+                        Err(e) => println!("{}", e),
+                    }
+                }
+            }
+        } else if parts.len() == 2 && parts[0] == "pingpong" {
+            // Alternate delay repeats between left and right
+            let ping_pong = match parts[1] {
+                "on" => Some(true),
+                "off" => Some(false),
+                _ => None,
+            };
+            if let Some(ping_pong) = ping_pong {
+                let mut chain_guard = self.chain.lock().unwrap();
+                let _ = chain_guard.set_enabled("delay", true);
+                if let Some(delay) = chain_guard.delay_mut() {
+                    delay.set_ping_pong(ping_pong);
+                    println!("Ping-pong delay {}", if delay.is_ping_pong() { "enabled" } else { "disabled" });
+                }
+            }
+        } else if parts.len() == 2 && parts[0] == "trim" {
+            // Output trim in dB (not limited to 0-1)
+            if let Ok(trim_db) = parts[1].parse::<f64>() {
+                let mut chain_guard = self.chain.lock().unwrap();
+                chain_guard.set_output_trim_db(trim_db);
+                println!("Output trim set to {:+.1} dB", chain_guard.output_trim_db());
+            }
+        } else if parts.len() == 2 && parts[0] == "bass" {
+            // Distortion bass preservation cutoff in Hz (not limited to 0-1)
+            if let Ok(freq_hz) = parts[1].parse::<f64>() {
+                let mut chain_guard = self.chain.lock().unwrap();
+                let _ = chain_guard.set_enabled("dist", true);
+                if let Some(distortion) = chain_guard.distortion_mut() {
+                    distortion.set_bass_preserve(freq_hz);
+                    match distortion.bass_preserve() {
+                        0.0 => println!("Distortion activated - Bass preservation off"),
+                        cutoff => println!("Distortion activated - Lows below {:.0} Hz kept clean", cutoff),
+                    }
+                }
+            }
+        } else if parts.len() == 2 && parts[0] == "tight" {
+            // Distortion pre-clip high-pass cutoff in Hz (not limited to 0-1)
+            if let Ok(freq_hz) = parts[1].parse::<f64>() {
+                let mut chain_guard = self.chain.lock().unwrap();
+                let _ = chain_guard.set_enabled("dist", true);
+                if let Some(distortion) = chain_guard.distortion_mut() {
+                    distortion.set_tightness_hz(freq_hz);
+                    match distortion.tightness_hz() {
+                        0.0 => println!("Distortion activated - Tightness off"),
+                        cutoff => println!("Distortion activated - Lows below {:.0} Hz cut before the clipper", cutoff),
+                    }
+                }
+            }
+        } else if parts.len() == 2 && parts[0] == "tilt" {
+            // Input tilt (not limited to 0-1)
+            if let Ok(amount) = parts[1].parse::<f64>() {
+                let mut chain_guard = self.chain.lock().unwrap();
+                chain_guard.set_input_tilt(amount);
+                println!("Input tilt set to {:+.2}", chain_guard.input_tilt());
+            }
+        } else if parts.len() == 2 {
+            let value: Result<f64, _> = parts[1].parse();
+            if let Ok(val) = value {
+                let val = val.clamp(0.0, 1.0);
+                let mut chain_guard = self.chain.lock().unwrap();
+            
+                match parts[0] {
+                    // Reverb controls - activate reverb
+                    "w" => {
+                        let _ = chain_guard.set_enabled("verb", true);
+                        if chain_guard.set_mix("verb", val).is_ok() {
+                            println!("Reverb activated - Mix set to {:.2}", val);
+                        }
+                    }
+                    "r" => {
+                        let _ = chain_guard.set_enabled("verb", true);
+                        if let Some(reverb) = chain_guard.reverb_mut() {
+                            reverb.set_room_size(val);
+                            println!("Reverb activated - Room size set to {:.2}", reverb.room_size());
+                        }
+                    }
+                    "p" => {
+                        let _ = chain_guard.set_enabled("verb", true);
+                        if let Some(reverb) = chain_guard.reverb_mut() {
+                            reverb.set_dampening(val);
+                        }
+                        println!("Reverb activated - Dampening set to {:.2}", val);
+                    }
+                    "x" => {
+                        let _ = chain_guard.set_enabled("verb", true);
+                        if let Some(reverb) = chain_guard.reverb_mut() {
+                            reverb.set_width(val);
+                        }
+                        println!("Reverb activated - Stereo width set to {:.2}", val);
+                    }
+                    "diff" => {
+                        let _ = chain_guard.set_enabled("verb", true);
+                        if let Some(reverb) = chain_guard.reverb_mut() {
+                            reverb.set_diffusion(val);
+                            println!("Reverb activated - Diffusion set to {:.2}", reverb.diffusion());
+                        }
+                    }
+                    // Delay controls - activate delay
+                    "dfb" => {
+                        let _ = chain_guard.set_enabled("delay", true);
+                        if let Some(delay) = chain_guard.delay_mut() {
+                            delay.set_feedback(val);
+                        }
+                        let feedback = chain_guard.delay_mut().map_or(val, |delay| delay.feedback());
+                        println!("Delay activated - Feedback set to {:.2}", feedback);
+                    }
+                    "dl" => {
+                        let _ = chain_guard.set_enabled("delay", true);
+                        if let Some(delay) = chain_guard.delay_mut() {
+                            delay.set_level(val);
+                        }
+                        println!("Delay activated - Level set to {:.2}", val);
+                    }
+                    // Distortion controls - activate distortion
+                    "dr" => {
+                        let _ = chain_guard.set_enabled("dist", true);
+                        if let Some(distortion) = chain_guard.distortion_mut() {
+                            distortion.set_drive(val);
+                        }
+                        println!("Distortion activated - Drive set to {:.2}", val);
+                    }
+                    "l" => {
+                        let _ = chain_guard.set_enabled("dist", true);
+                        if let Some(distortion) = chain_guard.distortion_mut() {
+                            distortion.set_level(val);
+                        }
+                        println!("Distortion activated - Level set to {:.2}", val);
+                    }
+                    "t" => {
+                        let _ = chain_guard.set_enabled("dist", true);
+                        if let Some(distortion) = chain_guard.distortion_mut() {
+                            distortion.set_tone(val);
+                        }
+                        println!("Distortion activated - Tone set to {:.2}", val);
+                    }
+                    "sag" => {
+                        let _ = chain_guard.set_enabled("dist", true);
+                        if let Some(distortion) = chain_guard.distortion_mut() {
+                            distortion.set_sag(val);
+                            println!("Distortion activated - Sag set to {:.2}", distortion.sag());
+                        }
+                    }
+                    "knee" => {
+                        let _ = chain_guard.set_enabled("dist", true);
+                        if let Some(distortion) = chain_guard.distortion_mut() {
+                            distortion.set_soft_knee(val);
+                            println!("Distortion activated - Soft knee set to {:.2}", distortion.soft_knee());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        } else if parts.len() == 2 && parts[0] == "os" {
+            // Distortion oversampling
+            let oversampling = match parts[1] {
+                "on" => true,
+                "off" => false,
+                _ => {
+                    println!("Usage: os <on|off>");
+                    return;
+                }
+            };
+            let mut chain_guard = self.chain.lock().unwrap();
+            if let Some(distortion) = chain_guard.distortion_mut() {
+                distortion.set_oversampling(oversampling);
+                if distortion.oversampling() {
+                    println!("Distortion oversampling on (2x, {} samples of latency)", dsp::HALF_BAND_LATENCY);
+                } else {
+                    println!("Distortion oversampling off");
+                }
+            }
+        } else if parts.len() == 3 && parts[0] == "bc" && parts[1] == "dither" {
+            // Bit crusher dither
+            let dither = match parts[2] {
+                "on" => true,
+                "off" => false,
+                _ => {
+                    println!("Usage: bc dither <on|off>");
+                    return;
+                }
+            };
+            let mut chain_guard = self.chain.lock().unwrap();
+            if let Some(distortion) = chain_guard.distortion_mut() {
+                distortion.set_bit_crusher_dither(dither);
+                println!("Bit crusher dither {}", if distortion.bit_crusher_dither() { "on" } else { "off" });
+            }
+        } else if parts.len() == 3 && parts[0] == "bc" && parts[1] == "jitter" {
+            // Bit crusher clock jitter
+            let Ok(amount) = parts[2].parse::<f64>() else {
+                println!("Usage: bc jitter <0-1>");
+                return;
+            };
+            let mut chain_guard = self.chain.lock().unwrap();
+            if let Some(distortion) = chain_guard.distortion_mut() {
+                distortion.set_bit_crusher_jitter(amount);
+                println!("Bit crusher jitter set to {:.2}", distortion.bit_crusher_jitter());
+            }
+        } else if parts.len() == 3 && parts[0] == "bc" {
+            // Bit crusher parameters (rate and depth)
+            let rate: Result<f64, _> = parts[1].parse();
+            let depth: Result<f64, _> = parts[2].parse();
+        
+            if let (Ok(rate_val), Ok(depth_val)) = (rate, depth) {
+                let mut chain_guard = self.chain.lock().unwrap();
+                let _ = chain_guard.set_enabled("dist", true);
+                if let Some(distortion) = chain_guard.distortion_mut() {
+                    distortion.set_distortion_type(DistortionType::BitCrusher);
+                    distortion.set_bit_crusher_params(rate_val, depth_val);
+                }
+                println!("Distortion activated - Bit crusher: rate={:.2}, depth={:.2}", rate_val, depth_val);
+            }
+        } else if parts.len() == 3 && parts[0] == "cascade" {
+            // Gain of one cascade stage (also selects the cascade)
+            let stage = parts[1].parse::<usize>().ok().filter(|stage| (1..=CASCADE_STAGES).contains(stage));
+            let (Some(stage), Ok(gain)) = (stage, parts[2].parse::<f64>()) else {
+                println!("Usage: cascade <1-{}> <gain>", CASCADE_STAGES);
+                return;
+            };
+            let mut chain_guard = self.chain.lock().unwrap();
+            let _ = chain_guard.set_enabled("dist", true);
+            if let Some(distortion) = chain_guard.distortion_mut() {
+                distortion.set_distortion_type(DistortionType::Cascade);
+                distortion.set_stage_gain(stage - 1, gain);
+                println!("Distortion activated - Cascade stage gains {:?}", distortion.stage_gains());
+            }
+        } else if parts.len() == 3 && parts[0] == "mix" {
+            // Per-stage wet/dry mix
+            if let Ok(mix) = parts[2].parse::<f64>() {
+                let mut chain_guard = self.chain.lock().unwrap();
+                match chain_guard.set_mix(parts[1], mix) {
+                    Ok(()) => println!("Stage '{}' mix set to {:.2}", parts[1], mix.clamp(0.0, 1.0)),
+                    Err(e) => println!("{}", e),
+                }
+            }
+        } else if parts.len() == 3 && parts[0] == "mbx" {
+            // Multiband crossover frequencies
+            if let (Ok(low_freq), Ok(high_freq)) = (parts[1].parse::<f64>(), parts[2].parse::<f64>()) {
+                let mut chain_guard = self.chain.lock().unwrap();
+                let _ = chain_guard.set_enabled("multi", true);
+                if let Some(multiband) = chain_guard.multiband_mut() {
+                    multiband.set_crossovers(low_freq, high_freq);
+                    let (low_freq, high_freq) = multiband.crossovers();
+                    println!("Multiband activated - Crossovers set to {:.0} Hz / {:.0} Hz", low_freq, high_freq);
+                }
+            }
+        } else if parts.len() == 3 && matches!(parts[0], "mbt" | "mbd" | "mbl") {
+            // Per-band multiband settings
+            if let Some(band) = Band::from_name(parts[1]) {
+                let mut chain_guard = self.chain.lock().unwrap();
+                let _ = chain_guard.set_enabled("multi", true);
+                if let Some(multiband) = chain_guard.multiband_mut() {
+                    match (parts[0], parts[2]) {
+                        ("mbt", "clean") => {
+                            multiband.set_band_enabled(band, false);
+                            println!("Multiband activated - {:?} band clean", band);
+                        }
+                        ("mbt", name) => {
+                            if let Some(distortion_type) = DistortionType::from_name(name) {
+                                multiband.set_band_type(band, distortion_type);
+                                println!("Multiband activated - {:?} band type set to {:?}", band, distortion_type);
+                            }
+                        }
+                        ("mbd", value) => {
+                            if let Ok(drive) = value.parse::<f64>() {
+                                multiband.set_band_drive(band, drive);
+                                println!("Multiband activated - {:?} band drive set to {:.2}", band, drive.clamp(0.0, 1.0));
+                            }
+                        }
+                        (_, value) => {
+                            if let Ok(level) = value.parse::<f64>() {
+                                multiband.set_band_level(band, level);
+                                println!("Multiband activated - {:?} band level set to {:.2}", band, level.clamp(0.0, 1.0));
+                            }
+                        }
+                    }
+                }
+            }
+        } else if parts.len() == 1 {
+            let mut chain_guard = self.chain.lock().unwrap();
+            match parts[0] {
+                // Distortion type selection
+                "soft" => {
+                    let _ = chain_guard.set_enabled("dist", true);
+                    if let Some(distortion) = chain_guard.distortion_mut() {
+                        distortion.set_distortion_type(DistortionType::Soft);
+                    }
+                    println!("Distortion activated - Soft clipping selected");
+                }
+                "hard" => {
+                    let _ = chain_guard.set_enabled("dist", true);
+                    if let Some(distortion) = chain_guard.distortion_mut() {
+                        distortion.set_distortion_type(DistortionType::Hard);
+                    }
+                    println!("Distortion activated - Hard clipping selected");
+                }
+                "bit" => {
+                    let _ = chain_guard.set_enabled("dist", true);
+                    if let Some(distortion) = chain_guard.distortion_mut() {
+                        distortion.set_distortion_type(DistortionType::BitCrusher);
+                    }
+                    println!("Distortion activated - Bit crusher selected");
+                }
+                "wave" => {
+                    let _ = chain_guard.set_enabled("dist", true);
+                    if let Some(distortion) = chain_guard.distortion_mut() {
+                        distortion.set_distortion_type(DistortionType::Wavefolder);
+                    }
+                    println!("Distortion activated - Wavefolder selected");
+                }
+                "over" => {
+                    let _ = chain_guard.set_enabled("dist", true);
+                    if let Some(distortion) = chain_guard.distortion_mut() {
+                        distortion.set_distortion_type(DistortionType::Overdrive);
+                    }
+                    println!("Distortion activated - Overdrive selected");
+                }
+                "cascade" => {
+                    let _ = chain_guard.set_enabled("dist", true);
+                    if let Some(distortion) = chain_guard.distortion_mut() {
+                        distortion.set_distortion_type(DistortionType::Cascade);
+                    }
+                    println!("Distortion activated - Gain cascade selected");
+                }
+                "tap" => {
+                    match self.tap_tempo.tap(Instant::now()) {
+                        Some(bpm) => {
+                            chain_guard.set_tempo_bpm(bpm);
+                            match chain_guard.delay_mut() {
+                                Some(delay) => match delay.sync() {
+                                    Some(note) => println!("Tempo {:.1} BPM - Delay time {:.0} ms ({})", bpm, delay.time_ms(), note),
+                                    None => println!("Tempo {:.1} BPM - Delay time {:.0} ms", bpm, delay.time_ms()),
+                                },
+                                None => println!("Tempo {:.1} BPM", bpm),
+                            }
+                        }
+                        None => println!("Tap again to set the tempo"),
+                    }
+                }
+                "chain" => {
+                    println!("Signal chain: {}", chain_guard.describe());
+                    println!("Tempo: {:.1} BPM", chain_guard.tempo_bpm());
+                }
+                "reset" => {
+                    chain_guard.reset();
+                    println!("Effect state cleared");
+                }
+                // Global controls
+                "dry" => {
+                    chain_guard.bypass_all();
+                    println!("Set to dry only (no effects)");
+                }
+                "pass" => {
+                    chain_guard.bypass_all();
+                    if let Some(reverb) = chain_guard.reverb_mut() {
+                        reverb.set_room_size(0.0);
+                        reverb.set_dampening(0.0);
+                        reverb.set_width(0.5);
+                    }
+                    chain_guard.set_output_trim_db(0.0);
+                    println!("Switched to passthrough mode (no effects)");
+                }
+                "panic" => {
+                    chain_guard.panic();
+                    automation::stop_all();
+                    println!("PANIC - All effects reset, output muted and returning to passthrough");
+                }
+                "q" => {
+                    self.running.store(false, Ordering::Relaxed);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+// Describe an LFO rate: free in Hz, or the note value it follows at the tempo
+fn lfo_rate_text(sync: Option<NoteValue>, rate_hz: f64, restart_on_sync: bool) -> String {
+    match sync {
+        Some(note) if restart_on_sync => format!("{} ({:.2} Hz, restarting on tempo changes)", note, rate_hz),
+        Some(note) => format!("{} ({:.2} Hz)", note, rate_hz),
+        None => format!("{:.2} Hz", rate_hz),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::Engine;
+
+    #[test]
+    fn test_commands_change_the_engine_chain() {
+        let mut engine = Engine::offline(48000).unwrap();
+        engine.apply_command("on delay");
+        engine.apply_command("bpm 90");
+        let chain = engine.chain().lock().unwrap();
+        assert!(chain.is_enabled("delay").unwrap());
+        assert_eq!(chain.tempo_bpm(), 90.0);
+    }
+
+    #[test]
+    fn test_reverb_commands_leave_the_distortion_on() {
+        // Effects stack: w turns the reverb on without turning the distortion off
+        let mut engine = Engine::offline(48000).unwrap();
+        engine.apply_command("dr 0.6");
+        engine.apply_command("w 0.3");
+        {
+            let chain = engine.chain().lock().unwrap();
+            assert!(chain.is_enabled("dist").unwrap());
+            assert!(chain.is_enabled("verb").unwrap());
+        }
+
+        // And the other way round
+        engine.apply_command("off dist");
+        engine.apply_command("off verb");
+        engine.apply_command("r 0.8");
+        engine.apply_command("dr 0.5");
+        let chain = engine.chain().lock().unwrap();
+        assert!(chain.is_enabled("verb").unwrap());
+        assert!(chain.is_enabled("dist").unwrap());
+    }
+
+    #[test]
+    fn test_footswitch_binding_lasts_between_commands() {
+        let mut engine = Engine::offline(48000).unwrap();
+        engine.apply_command("fs b boost latch");
+        engine.apply_command("b");
+        assert!(engine.chain().lock().unwrap().is_enabled("boost").unwrap());
+        engine.apply_command("b");
+        assert!(!engine.chain().lock().unwrap().is_enabled("boost").unwrap());
+    }
+
+    #[test]
+    fn test_quit_clears_the_running_flag() {
+        let mut engine = Engine::offline(48000).unwrap();
+        engine.running().store(true, std::sync::atomic::Ordering::Relaxed);
+        engine.apply_command("q");
+        assert!(!engine.is_running());
+    }
+}
@@ -0,0 +1,228 @@
+//! Sample format conversion
+//!
+//! All processing happens in f32/f64. These helpers convert device samples to and
+//! from f32 so the stream builders only need one generic implementation.
+//!
+//! Integer conversions clamp to the representable range before casting, so a loud
+//! peak sits on the rail instead of wrapping to the opposite one, and a NaN from a
+//! misbehaving effect becomes silence rather than a full-scale click.
+//!
+//! With `--dither`, integer output goes through `OutputDither`: TPDF dither of one
+//! LSB turns the quantization error into steady noise instead of distortion that
+//! follows quiet signals such as reverb tails, and first-order error feedback
+//! pushes that noise up in frequency, where it is less audible. Each channel keeps
+//! its own error. The feedback is limited to a few LSBs, so a clipped sample can't
+//! wind it up.
+
+use crate::dsp::Xorshift32;
+
+/// Largest error carried to the next sample, in LSBs
+const MAX_ERROR_LSBS: f32 = 2.0;
+
+/// Replace NaN with silence so it survives clamping
+fn silence_nan(sample: f32) -> f32 {
+    if sample.is_nan() { 0.0 } else { sample }
+}
+
+/// Conversion between a device sample type and f32 in the range -1.0 to 1.0
+pub trait ConvertSample: Copy {
+    /// Size of one quantization step as an f32 sample (0 for float formats)
+    const LSB: f32;
+    /// Convert a device sample to f32
+    fn to_f32(self) -> f32;
+    /// Convert an f32 sample to the device format (out-of-range values saturate)
+    fn from_f32(sample: f32) -> Self;
+    /// Whether the sample is clipped: at full scale for integer formats, beyond it for float
+    fn is_clipped(self) -> bool;
+}
+
+impl ConvertSample for f32 {
+    const LSB: f32 = 0.0;
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_f32(sample: f32) -> Self {
+        sample
+    }
+
+    fn is_clipped(self) -> bool {
+        self.abs() > 1.0
+    }
+}
+
+impl ConvertSample for i16 {
+    const LSB: f32 = 1.0 / i16::MAX as f32;
+
+    fn to_f32(self) -> f32 {
+        f32::from(self) / f32::from(i16::MAX)
+    }
+
+    fn from_f32(sample: f32) -> Self {
+        let scaled = silence_nan(sample) * f32::from(i16::MAX);
+        scaled.clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16
+    }
+
+    fn is_clipped(self) -> bool {
+        self == i16::MAX || self == i16::MIN
+    }
+}
+
+impl ConvertSample for u16 {
+    const LSB: f32 = 2.0 / u16::MAX as f32;
+
+    fn to_f32(self) -> f32 {
+        (f32::from(self) / f32::from(u16::MAX)) * 2.0 - 1.0
+    }
+
+    fn from_f32(sample: f32) -> Self {
+        let normalized = ((silence_nan(sample) + 1.0) * 0.5).clamp(0.0, 1.0);
+        (normalized * f32::from(u16::MAX)) as u16
+    }
+
+    fn is_clipped(self) -> bool {
+        self == u16::MIN || self == u16::MAX
+    }
+}
+
+/// TPDF dither with first-order noise shaping for integer output
+pub struct OutputDither {
+    /// Quantization error of the previous sample, per channel
+    error: Vec<f32>,
+    /// Dither noise
+    noise: Xorshift32,
+}
+
+impl OutputDither {
+    /// Create a quantizer for `channels` output channels
+    pub fn new(channels: usize) -> Self {
+        Self {
+            error: vec![0.0; channels],
+            noise: Xorshift32::new(0x1234_5678),
+        }
+    }
+
+    /// Convert one sample of `channel`, subtracting the previous error and adding dither
+    pub fn quantize<T: ConvertSample>(&mut self, channel: usize, sample: f32) -> T {
+        if T::LSB == 0.0 {
+            return T::from_f32(sample);
+        }
+        let target = silence_nan(sample) - self.error[channel];
+        let output = T::from_f32(target + self.noise.tpdf() as f32 * T::LSB);
+        let limit = MAX_ERROR_LSBS * T::LSB;
+        self.error[channel] = (output.to_f32() - target).clamp(-limit, limit);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_SAMPLES: [f32; 7] = [-1.0, -0.5, -0.001, 0.0, 0.001, 0.5, 1.0];
+
+    #[test]
+    fn test_i16_round_trip() {
+        for &sample in &TEST_SAMPLES {
+            let round_trip = i16::from_f32(sample).to_f32();
+            assert!((round_trip - sample).abs() <= 1.0 / f32::from(i16::MAX), "{} -> {}", sample, round_trip);
+        }
+        assert_eq!(i16::from_f32(1.0), i16::MAX);
+        assert_eq!(i16::from_f32(0.0), 0);
+    }
+
+    #[test]
+    fn test_u16_round_trip() {
+        for &sample in &TEST_SAMPLES {
+            let round_trip = u16::from_f32(sample).to_f32();
+            assert!((round_trip - sample).abs() <= 2.0 / f32::from(u16::MAX), "{} -> {}", sample, round_trip);
+        }
+        assert_eq!(u16::from_f32(-1.0), 0);
+        assert_eq!(u16::from_f32(1.0), u16::MAX);
+    }
+
+    #[test]
+    fn test_out_of_range_saturates() {
+        assert_eq!(i16::from_f32(2.0), i16::MAX);
+        assert_eq!(i16::from_f32(-2.0), i16::MIN);
+        assert_eq!(u16::from_f32(2.0), u16::MAX);
+        assert_eq!(u16::from_f32(-2.0), 0);
+
+        // Far beyond full scale still lands on the rail, never the opposite one
+        for sample in [1000.0, f32::MAX, f32::INFINITY] {
+            assert_eq!(i16::from_f32(sample), i16::MAX);
+            assert_eq!(i16::from_f32(-sample), i16::MIN);
+            assert_eq!(u16::from_f32(sample), u16::MAX);
+            assert_eq!(u16::from_f32(-sample), 0);
+        }
+    }
+
+    #[test]
+    fn test_nan_converts_to_silence() {
+        // Zero for i16, the midpoint for u16, whatever the NaN's sign
+        for nan in [f32::NAN, -f32::NAN] {
+            assert_eq!(i16::from_f32(nan), 0);
+            assert_eq!(i16::from_f32(nan), i16::from_f32(0.0));
+            assert_eq!(u16::from_f32(nan), u16::MAX / 2);
+            assert_eq!(u16::from_f32(nan), u16::from_f32(0.0));
+        }
+    }
+
+    #[test]
+    fn test_f32_is_identity() {
+        for &sample in &TEST_SAMPLES {
+            assert_eq!(f32::from_f32(sample).to_f32(), sample);
+        }
+    }
+
+    #[test]
+    fn test_noise_shaping_keeps_error_bounded() {
+        let mut dither = OutputDither::new(2);
+        let lsb = f64::from(i16::LSB);
+
+        // The error feeds back, so the output's running error telescopes and stays
+        // within a few LSBs instead of drifting
+        let mut cumulative = 0.0;
+        for i in 0..20000 {
+            let sample = 0.3 * (i as f32 * 0.013).sin();
+            let output: i16 = dither.quantize(0, sample);
+            cumulative += f64::from(output.to_f32()) - f64::from(sample);
+            assert!(cumulative.abs() < 4.0 * lsb, "sample {}: {} LSBs", i, cumulative / lsb);
+        }
+
+        // Clipping doesn't wind the feedback up: back in range, the error is small again
+        for _ in 0..1000 {
+            let _: i16 = dither.quantize(1, 3.0);
+        }
+        let output: i16 = dither.quantize(1, 0.5);
+        assert!((f64::from(output.to_f32()) - 0.5).abs() < 4.0 * lsb);
+
+        // Float output passes through untouched
+        assert_eq!(dither.quantize::<f32>(0, 0.123), 0.123);
+    }
+
+    #[test]
+    fn test_dither_decorrelates_quantization_error() {
+        // Correlation of the output error with a sine under half an LSB, which
+        // plain conversion turns into silence (error = -input)
+        let error_correlation = |dithered: bool| {
+            let mut dither = OutputDither::new(1);
+            let (mut cross, mut input_power, mut error_power) = (0.0, 0.0, 0.0);
+            for i in 0..20000 {
+                let sample = 0.4 * i16::LSB * (i as f32 * 0.01).sin();
+                let output = if dithered { dither.quantize::<i16>(0, sample) } else { i16::from_f32(sample) };
+                let error = f64::from(output.to_f32() - sample);
+                cross += f64::from(sample) * error;
+                input_power += f64::from(sample * sample);
+                error_power += error * error;
+            }
+            cross / (input_power * error_power).sqrt()
+        };
+
+        let plain = error_correlation(false);
+        let dithered = error_correlation(true);
+        assert!(plain < -0.99, "correlation without dither {}", plain);
+        assert!(dithered.abs() < 0.1, "correlation with dither {}", dithered);
+    }
+}
@@ -0,0 +1,240 @@
+//! Delay effect
+//!
+//! Stereo echo with one delay line per channel. In ping-pong mode each line's
+//! input and feedback come from the opposite channel, so the repeats bounce
+//! between left and right.
+//!
+//! The delay time can follow the tempo as a note value; it is recomputed when the
+//! tempo changes.
+
+use crate::safety::FeedbackCeiling;
+use crate::tempo::NoteValue;
+
+/// Longest supported delay time in milliseconds
+const MAX_DELAY_MS: f64 = 2000.0;
+/// Highest feedback amount (kept below unity so repeats always die away)
+const MAX_FEEDBACK: f64 = 0.95;
+
+/// Stereo delay processor
+pub struct Delay {
+    /// Delay lines for the left and right channels, sized for the maximum delay
+    buffers: [Vec<f64>; 2],
+    /// Write position in the delay lines
+    write_index: usize,
+    /// Sample rate for converting times to samples
+    sample_rate: f64,
+    /// Delay time in samples
+    delay_samples: usize,
+    /// Feedback amount (0.0 to 0.95, or the feedback ceiling if lower)
+    feedback: f64,
+    /// Feedback ceiling shared with the engine
+    ceiling: FeedbackCeiling,
+    /// Echo level (0.0 to 1.0)
+    level: f64,
+    /// Whether the repeats alternate between channels
+    ping_pong: bool,
+    /// Note value the delay time follows (None = free time)
+    sync: Option<NoteValue>,
+}
+
+impl Delay {
+    /// Create a new delay with 350ms time, 40% feedback and 50% echo level
+    pub fn new(sample_rate: usize) -> Self {
+        let sample_rate = sample_rate as f64;
+        let length = (MAX_DELAY_MS * sample_rate / 1000.0) as usize + 1;
+        let mut delay = Self {
+            buffers: [vec![0.0; length], vec![0.0; length]],
+            write_index: 0,
+            sample_rate,
+            delay_samples: 1,
+            feedback: 0.0,
+            ceiling: FeedbackCeiling::default(),
+            level: 0.5,
+            ping_pong: false,
+            sync: None,
+        };
+        delay.set_time_ms(350.0);
+        delay.set_feedback(0.4);
+        delay
+    }
+
+    /// Process a stereo input sample and return the input plus echoes
+    pub fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let length = self.buffers[0].len();
+        let read_index = (self.write_index + length - self.delay_samples) % length;
+        let echo = (self.buffers[0][read_index], self.buffers[1][read_index]);
+
+        // Ping-pong: left input and taps feed the right line and vice versa
+        let (left_in, right_in) = if self.ping_pong {
+            (input.1 + echo.1 * self.feedback, input.0 + echo.0 * self.feedback)
+        } else {
+            (input.0 + echo.0 * self.feedback, input.1 + echo.1 * self.feedback)
+        };
+        self.buffers[0][self.write_index] = left_in;
+        self.buffers[1][self.write_index] = right_in;
+        self.write_index = (self.write_index + 1) % length;
+
+        (input.0 + echo.0 * self.level, input.1 + echo.1 * self.level)
+    }
+
+    /// Set the delay time in milliseconds (1 to 2000), ending any tempo sync
+    pub fn set_time_ms(&mut self, time_ms: f64) {
+        self.sync = None;
+        self.set_delay_samples(time_ms);
+    }
+
+    /// Follow the tempo with the given note value
+    pub fn set_sync(&mut self, note: NoteValue, bpm: f64) {
+        self.sync = Some(note);
+        self.set_tempo_bpm(bpm);
+    }
+
+    /// Current tempo sync note value
+    pub fn sync(&self) -> Option<NoteValue> {
+        self.sync
+    }
+
+    /// Retime the delay for a new tempo
+    ///
+    /// A synced delay follows its note value; otherwise the delay is set to one beat.
+    pub fn set_tempo_bpm(&mut self, bpm: f64) {
+        let note = self.sync.unwrap_or(NoteValue::QUARTER);
+        self.set_delay_samples(note.duration_ms(bpm));
+    }
+
+    fn set_delay_samples(&mut self, time_ms: f64) {
+        let time_ms = time_ms.clamp(1.0, MAX_DELAY_MS);
+        self.delay_samples = ((time_ms * self.sample_rate / 1000.0).round() as usize).clamp(1, self.buffers[0].len() - 1);
+    }
+
+    /// Current delay time in milliseconds
+    pub fn time_ms(&self) -> f64 {
+        self.delay_samples as f64 * 1000.0 / self.sample_rate
+    }
+
+    /// Set the feedback amount (0.0 to 0.95, or the feedback ceiling if lower)
+    pub fn set_feedback(&mut self, feedback: f64) {
+        self.feedback = self.ceiling.clamp(feedback).min(MAX_FEEDBACK);
+    }
+
+    /// Follow a shared feedback ceiling instead of the default one, limiting the
+    /// current feedback to it
+    pub fn set_feedback_ceiling(&mut self, ceiling: FeedbackCeiling) {
+        self.ceiling = ceiling;
+        self.set_feedback(self.feedback);
+    }
+
+    /// Current feedback amount
+    pub fn feedback(&self) -> f64 {
+        self.feedback
+    }
+
+    /// Set the echo level (0.0 to 1.0)
+    pub fn set_level(&mut self, level: f64) {
+        self.level = level.clamp(0.0, 1.0);
+    }
+
+    /// Alternate the repeats between the left and right channels
+    pub fn set_ping_pong(&mut self, ping_pong: bool) {
+        self.ping_pong = ping_pong;
+    }
+
+    /// Whether ping-pong mode is enabled
+    pub fn is_ping_pong(&self) -> bool {
+        self.ping_pong
+    }
+
+    /// Clear the delay lines
+    pub fn reset(&mut self) {
+        for buffer in self.buffers.iter_mut() {
+            buffer.fill(0.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Output for a left-channel impulse followed by silence
+    fn left_impulse_response(delay: &mut Delay, samples: usize) -> Vec<(f64, f64)> {
+        (0..samples)
+            .map(|i| delay.tick(if i == 0 { (1.0, 0.0) } else { (0.0, 0.0) }))
+            .collect()
+    }
+
+    #[test]
+    fn test_echo_timing() {
+        let mut delay = Delay::new(1000);
+        delay.set_time_ms(100.0);
+        delay.set_feedback(0.5);
+        delay.set_level(1.0);
+
+        let output = left_impulse_response(&mut delay, 250);
+        assert_eq!(output[0], (1.0, 0.0));
+        assert_eq!(output[100], (1.0, 0.0));
+        assert_eq!(output[200], (0.5, 0.0));
+        assert_eq!(output[150], (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_ping_pong_alternates_sides() {
+        let mut delay = Delay::new(1000);
+        delay.set_time_ms(100.0);
+        delay.set_feedback(0.5);
+        delay.set_level(1.0);
+        delay.set_ping_pong(true);
+
+        let output = left_impulse_response(&mut delay, 350);
+        // First echo on the right, second on the left, third on the right
+        assert_eq!(output[100], (0.0, 1.0));
+        assert_eq!(output[200], (0.5, 0.0));
+        assert_eq!(output[300], (0.0, 0.25));
+    }
+
+    #[test]
+    fn test_sync_follows_tempo() {
+        let mut delay = Delay::new(1000);
+        delay.set_sync(NoteValue::parse("1/8.").unwrap(), 120.0);
+        assert_eq!(delay.time_ms(), 375.0);
+
+        delay.set_tempo_bpm(60.0);
+        assert_eq!(delay.time_ms(), 750.0);
+
+        // A manual time ends the sync; tapping then sets one beat
+        delay.set_time_ms(200.0);
+        assert_eq!(delay.sync(), None);
+        delay.set_tempo_bpm(100.0);
+        assert_eq!(delay.time_ms(), 600.0);
+    }
+
+    #[test]
+    fn test_feedback_below_unity() {
+        let mut delay = Delay::new(1000);
+        delay.set_time_ms(10.0);
+        delay.set_feedback(5.0);
+        delay.set_ping_pong(true);
+
+        let output = left_impulse_response(&mut delay, 5000);
+        let late_peak = output[4000..].iter().map(|s| s.0.abs().max(s.1.abs())).fold(0.0, f64::max);
+        assert!(late_peak < 0.01, "late peak {}", late_peak);
+    }
+
+    #[test]
+    fn test_feedback_clamped_to_ceiling() {
+        let mut delay = Delay::new(1000);
+        delay.set_feedback(5.0);
+        assert_eq!(delay.feedback(), MAX_FEEDBACK);
+        delay.set_feedback(-1.0);
+        assert_eq!(delay.feedback(), 0.0);
+
+        // A lower shared ceiling limits the current feedback and anything set later
+        let ceiling = FeedbackCeiling::default();
+        ceiling.set(0.5);
+        delay.set_feedback(0.8);
+        delay.set_feedback_ceiling(ceiling);
+        assert_eq!(delay.feedback(), 0.5);
+        delay.set_feedback(0.9);
+        assert_eq!(delay.feedback(), 0.5);
+    }
+}
@@ -0,0 +1,253 @@
+//! Spectral noise reduction
+//!
+//! Short-time FFT with 50% overlap-add. In learn mode the average magnitude of
+//! each frequency bin is collected from a quiet passage (just the hiss); in apply
+//! mode bins that stay within a margin of that noise floor are attenuated by the
+//! reduction amount, while louder bins (the playing) pass unchanged.
+//!
+//! A square-root Hann window is used for both analysis and synthesis, so with
+//! no attenuation the output is the input delayed by one frame (1024 samples,
+//! about 23ms at 44.1kHz).
+
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+/// FFT frame length in samples (also the added latency)
+const FRAME_SIZE: usize = 1024;
+/// Samples between frames (50% overlap)
+const HOP_SIZE: usize = FRAME_SIZE / 2;
+/// Bins below the noise floor times this margin count as noise (+6 dB)
+const NOISE_MARGIN: f64 = 2.0;
+/// Largest reduction in dB
+const MAX_REDUCTION_DB: f64 = 60.0;
+
+/// What the denoiser does with each frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DenoiseMode {
+    /// Pass audio through and collect the noise profile
+    Learn,
+    /// Attenuate bins at the learned noise floor
+    Apply,
+}
+
+/// Spectral gate driven by a learned noise profile
+pub struct Denoiser {
+    forward: Arc<dyn Fft<f64>>,
+    inverse: Arc<dyn Fft<f64>>,
+    /// Square-root Hann window applied before the FFT and after the inverse FFT
+    window: Vec<f64>,
+    /// Last frame of input per channel; new samples fill the second half
+    inputs: [Vec<f64>; 2],
+    /// Overlap-add accumulators per channel; the first half is ready to play
+    outputs: [Vec<f64>; 2],
+    /// Position within the current hop
+    position: usize,
+    /// Spectrum of the frame being processed
+    spectrum: Vec<Complex<f64>>,
+    /// FFT work space, allocated up front: `Fft::process` allocates its own on
+    /// every call, which the audio thread can't afford
+    scratch: Vec<Complex<f64>>,
+    /// Learn or apply
+    mode: DenoiseMode,
+    /// Sum of bin magnitudes while learning
+    noise_sum: Vec<f64>,
+    /// Number of spectra summed while learning
+    noise_frames: usize,
+    /// Average magnitude per bin from the last learn pass (None = nothing learned)
+    noise_floor: Option<Vec<f64>>,
+    /// Reduction in dB
+    reduction_db: f64,
+    /// Gain applied to noise bins
+    reduction_gain: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Denoiser {
+    /// Create a denoiser in apply mode with no noise profile and 12 dB reduction
+    pub fn new() -> Self {
+        let mut planner = FftPlanner::new();
+        let forward = planner.plan_fft_forward(FRAME_SIZE);
+        let inverse = planner.plan_fft_inverse(FRAME_SIZE);
+        let scratch_len = forward.get_inplace_scratch_len().max(inverse.get_inplace_scratch_len());
+        let window = (0..FRAME_SIZE)
+            .map(|i| (std::f64::consts::PI * i as f64 / FRAME_SIZE as f64).sin())
+            .collect();
+
+        let mut denoiser = Self {
+            forward,
+            inverse,
+            window,
+            inputs: [vec![0.0; FRAME_SIZE], vec![0.0; FRAME_SIZE]],
+            outputs: [vec![0.0; FRAME_SIZE], vec![0.0; FRAME_SIZE]],
+            position: 0,
+            spectrum: vec![Complex::new(0.0, 0.0); FRAME_SIZE],
+            scratch: vec![Complex::new(0.0, 0.0); scratch_len],
+            mode: DenoiseMode::Apply,
+            noise_sum: vec![0.0; FRAME_SIZE / 2 + 1],
+            noise_frames: 0,
+            noise_floor: None,
+            reduction_db: 0.0,
+            reduction_gain: 1.0,
+        };
+        denoiser.set_reduction_db(12.0);
+        denoiser
+    }
+
+    /// Process a stereo input sample; the output lags the input by one frame
+    pub fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let output = (self.outputs[0][self.position], self.outputs[1][self.position]);
+        self.inputs[0][HOP_SIZE + self.position] = input.0;
+        self.inputs[1][HOP_SIZE + self.position] = input.1;
+
+        self.position += 1;
+        if self.position == HOP_SIZE {
+            self.position = 0;
+            for channel in 0..2 {
+                self.process_frame(channel);
+            }
+        }
+
+        output
+    }
+
+    /// Transform, gate and overlap-add the latest frame of one channel
+    fn process_frame(&mut self, channel: usize) {
+        for ((bin, &sample), &window) in self.spectrum.iter_mut().zip(&self.inputs[channel]).zip(&self.window) {
+            *bin = Complex::new(sample * window, 0.0);
+        }
+        self.forward.process_with_scratch(&mut self.spectrum, &mut self.scratch);
+
+        match (self.mode, &self.noise_floor) {
+            (DenoiseMode::Learn, _) => {
+                for (sum, bin) in self.noise_sum.iter_mut().zip(&self.spectrum) {
+                    *sum += bin.norm();
+                }
+                self.noise_frames += 1;
+            }
+            (DenoiseMode::Apply, Some(noise_floor)) => {
+                for (k, bin) in self.spectrum.iter_mut().enumerate() {
+                    // Real input: bin k mirrors bin FRAME_SIZE - k
+                    if bin.norm() < noise_floor[k.min(FRAME_SIZE - k)] * NOISE_MARGIN {
+                        *bin *= self.reduction_gain;
+                    }
+                }
+            }
+            (DenoiseMode::Apply, None) => {}
+        }
+
+        self.inverse.process_with_scratch(&mut self.spectrum, &mut self.scratch);
+
+        let output = &mut self.outputs[channel];
+        output.copy_within(HOP_SIZE.., 0);
+        output[FRAME_SIZE - HOP_SIZE..].fill(0.0);
+        for ((sample, bin), &window) in output.iter_mut().zip(&self.spectrum).zip(&self.window) {
+            // The inverse FFT is unnormalized
+            *sample += bin.re * window / FRAME_SIZE as f64;
+        }
+
+        self.inputs[channel].copy_within(HOP_SIZE.., 0);
+    }
+
+    /// Start collecting a new noise profile; audio passes through unchanged
+    pub fn start_learning(&mut self) {
+        self.noise_sum.fill(0.0);
+        self.noise_frames = 0;
+        self.mode = DenoiseMode::Learn;
+    }
+
+    /// Stop learning and apply the collected profile
+    ///
+    /// Returns false if no full frame was heard, in which case the previous
+    /// profile is kept.
+    pub fn finish_learning(&mut self) -> bool {
+        self.mode = DenoiseMode::Apply;
+        if self.noise_frames == 0 {
+            return false;
+        }
+        let frames = self.noise_frames as f64;
+        self.noise_floor = Some(self.noise_sum.iter().map(|sum| sum / frames).collect());
+        true
+    }
+
+    /// Current mode
+    pub fn mode(&self) -> DenoiseMode {
+        self.mode
+    }
+
+    /// Set how far noise bins are attenuated (0 to 60 dB)
+    pub fn set_reduction_db(&mut self, reduction_db: f64) {
+        self.reduction_db = reduction_db.clamp(0.0, MAX_REDUCTION_DB);
+        self.reduction_gain = 10f64.powf(-self.reduction_db / 20.0);
+    }
+
+    /// Current reduction in dB
+    pub fn reduction_db(&self) -> f64 {
+        self.reduction_db
+    }
+
+    /// Clear the frame buffers (the noise profile is kept)
+    pub fn reset(&mut self) {
+        for buffer in self.inputs.iter_mut().chain(self.outputs.iter_mut()) {
+            buffer.fill(0.0);
+        }
+        self.position = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic white noise in -0.5..0.5
+    fn white_noise(samples: usize) -> Vec<f64> {
+        let mut state: u32 = 0x1234_5678;
+        (0..samples)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f64 / (1u32 << 24) as f64 - 0.5
+            })
+            .collect()
+    }
+
+    fn rms(samples: &[f64]) -> f64 {
+        (samples.iter().map(|s| s * s).sum::<f64>() / samples.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn test_learned_noise_is_reduced() {
+        let noise = white_noise(44100);
+        let mut denoiser = Denoiser::new();
+
+        denoiser.start_learning();
+        for &sample in &noise {
+            denoiser.tick((sample, sample));
+        }
+        assert!(denoiser.finish_learning());
+
+        let output: Vec<f64> = noise.iter().map(|&sample| denoiser.tick((sample, sample)).0).collect();
+        let reduced = rms(&output[FRAME_SIZE..]);
+        let original = rms(&noise);
+        assert!(reduced < original * 0.5, "rms {} -> {}", original, reduced);
+    }
+
+    #[test]
+    fn test_passes_audio_through_without_profile() {
+        let input = white_noise(4 * FRAME_SIZE);
+        let mut denoiser = Denoiser::new();
+        let output: Vec<(f64, f64)> = input.iter().map(|&sample| denoiser.tick((sample, -sample))).collect();
+
+        // Delayed by one frame, otherwise unchanged
+        for i in FRAME_SIZE..input.len() {
+            assert!((output[i].0 - input[i - FRAME_SIZE]).abs() < 1e-9);
+            assert!((output[i].1 + input[i - FRAME_SIZE]).abs() < 1e-9);
+        }
+        assert!(!denoiser.finish_learning());
+    }
+}
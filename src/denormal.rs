@@ -0,0 +1,58 @@
+//! Denormal protection
+//!
+//! When a filter or feedback loop decays towards silence its state eventually
+//! drops below the smallest normal float. Arithmetic on these denormal numbers
+//! is many times slower on some CPUs, so a tail fading out after the playing
+//! stops can spike the processing load. Filter states are flushed with a tiny
+//! offset: adding and subtracting 1e-20 leaves audible values untouched but
+//! rounds anything some 16 orders of magnitude smaller to exactly zero.
+//!
+//! On by default; `--flush-denormals off` disables it, mainly to compare. The
+//! switch is the engine's, but the filters deep inside the effects can't each
+//! hold it, so the setting they read is a copy kept per thread: the chain copies
+//! the engine's switch into the thread running it before every frame.
+
+use std::cell::Cell;
+
+/// Offset far below audible levels but far above the denormal range
+const ANTI_DENORMAL: f64 = 1e-20;
+
+thread_local! {
+    /// Whether filter and feedback states processed on this thread are flushed
+    static FLUSH_DENORMALS: Cell<bool> = const { Cell::new(true) };
+}
+
+/// Switch denormal flushing on or off for the calling thread
+pub fn set_flush_denormals(enabled: bool) {
+    FLUSH_DENORMALS.set(enabled);
+}
+
+/// A filter or feedback state with values far below audibility snapped to zero
+pub fn flush(value: f64) -> f64 {
+    if FLUSH_DENORMALS.get() {
+        (value + ANTI_DENORMAL) - ANTI_DENORMAL
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{Biquad, BiquadCoeffs, BUTTERWORTH_Q};
+
+    #[test]
+    fn test_decaying_filter_never_goes_denormal() {
+        // An impulse through a low-pass, then silence until the tail has long gone
+        let mut filter = Biquad::new(BiquadCoeffs::lowpass(44100.0, 2000.0, BUTTERWORTH_Q));
+        let tail: Vec<f64> = (0..20000).map(|i| filter.process(if i == 0 { 1.0 } else { 0.0 })).collect();
+
+        assert!(tail.iter().all(|&sample| sample == 0.0 || sample.is_normal()));
+        assert!(tail[10000..].iter().all(|sample| sample.abs() < 1e-18));
+
+        // Audible values pass unchanged
+        assert_eq!(flush(0.25), 0.25);
+        assert_eq!(flush(-1e-3), -1e-3);
+        assert!(flush(1e-40) == 0.0 && flush(f64::MIN_POSITIVE / 4.0) == 0.0);
+    }
+}
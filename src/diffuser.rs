@@ -0,0 +1,155 @@
+//! Input diffusion for the reverb
+//!
+//! A series of Schroeder allpass filters in front of freeverb. Allpasses keep the
+//! frequency response flat but smear an impulse into a dense cluster of echoes,
+//! so the more diffusion, the smoother the tail builds; with none, the comb
+//! filters' discrete early echoes come through.
+//!
+//! The decorrelator runs the reverb's wet output through short allpass chains
+//! with the same delays on both channels but opposite-signed gains, so the two
+//! sides get different phase responses without one lagging the other. With the
+//! gain at zero both chains are the same short delay and the channels stay as
+//! they were; raising it pulls them apart, so even a mono input gives a wide tail.
+
+/// Allpass delay times in milliseconds (mutually prime in samples at common rates)
+const STAGE_MS: [f64; 4] = [1.53, 2.33, 3.71, 5.27];
+/// Extra delay for the right channel's allpasses, so the two sides decorrelate
+const STEREO_SPREAD_MS: f64 = 0.31;
+/// Allpass gain at full diffusion
+const MAX_GAIN: f64 = 0.7;
+/// Decorrelator allpass delay times in milliseconds (mutually prime in samples)
+const DECORRELATOR_MS: [f64; 4] = [0.71, 1.37, 2.19, 3.13];
+/// Decorrelator allpass gain at full width (low, so it spreads the tail without
+/// smearing the onset the way diffusion does)
+const MAX_DECORRELATION_GAIN: f64 = 0.3;
+
+/// Schroeder allpass filter
+struct Allpass {
+    /// Delay line
+    buffer: Vec<f64>,
+    /// Read/write position
+    index: usize,
+}
+
+impl Allpass {
+    fn new(delay_samples: usize) -> Self {
+        Self {
+            buffer: vec![0.0; delay_samples.max(1)],
+            index: 0,
+        }
+    }
+
+    fn process(&mut self, input: f64, gain: f64) -> f64 {
+        let delayed = self.buffer[self.index];
+        let fed_back = input + gain * delayed;
+        self.buffer[self.index] = fed_back;
+        self.index = (self.index + 1) % self.buffer.len();
+        delayed - gain * fed_back
+    }
+
+    fn reset(&mut self) {
+        self.buffer.fill(0.0);
+    }
+}
+
+/// Allpass chain with the given delay times in milliseconds
+fn allpass_chain(sample_rate: usize, delays_ms: &[f64], spread_ms: f64) -> Vec<Allpass> {
+    delays_ms
+        .iter()
+        .map(|ms| Allpass::new(((ms + spread_ms) * sample_rate as f64 / 1000.0).round() as usize))
+        .collect()
+}
+
+/// Stereo allpass diffuser
+pub struct Diffuser {
+    /// Allpass chains for the left and right channels
+    stages: [Vec<Allpass>; 2],
+    /// Diffusion amount (0.0 to 1.0)
+    amount: f64,
+}
+
+impl Diffuser {
+    /// Create a diffuser with diffusion off
+    pub fn new(sample_rate: usize) -> Self {
+        Self {
+            stages: [
+                allpass_chain(sample_rate, &STAGE_MS, 0.0),
+                allpass_chain(sample_rate, &STAGE_MS, STEREO_SPREAD_MS),
+            ],
+            amount: 0.0,
+        }
+    }
+
+    /// Diffuse a stereo sample; with diffusion off the input passes straight through
+    pub fn process(&mut self, input: (f64, f64)) -> (f64, f64) {
+        if self.amount == 0.0 {
+            return input;
+        }
+        let gain = self.amount * MAX_GAIN;
+        let run = |stages: &mut [Allpass], sample: f64| {
+            stages.iter_mut().fold(sample, |sample, stage| stage.process(sample, gain))
+        };
+        let [left, right] = &mut self.stages;
+        (run(left, input.0), run(right, input.1))
+    }
+
+    /// Set the diffusion amount (0.0 to 1.0)
+    pub fn set_amount(&mut self, amount: f64) {
+        self.amount = amount.clamp(0.0, 1.0);
+    }
+
+    /// Current diffusion amount
+    pub fn amount(&self) -> f64 {
+        self.amount
+    }
+
+    /// Clear the allpass delay lines
+    pub fn reset(&mut self) {
+        for stage in self.stages.iter_mut().flatten() {
+            stage.reset();
+        }
+    }
+}
+
+/// Stereo decorrelator for the reverb's wet output
+pub struct Decorrelator {
+    /// Allpass chains for the left and right channels
+    stages: [Vec<Allpass>; 2],
+    /// Decorrelation amount (0.0 to 1.0)
+    amount: f64,
+}
+
+impl Decorrelator {
+    /// Create a decorrelator with decorrelation off
+    pub fn new(sample_rate: usize) -> Self {
+        Self {
+            stages: [
+                allpass_chain(sample_rate, &DECORRELATOR_MS, 0.0),
+                allpass_chain(sample_rate, &DECORRELATOR_MS, 0.0),
+            ],
+            amount: 0.0,
+        }
+    }
+
+    /// Decorrelate a stereo sample
+    pub fn process(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let gain = self.amount * MAX_DECORRELATION_GAIN;
+        let run = |stages: &mut [Allpass], sample: f64, gain: f64| {
+            stages.iter_mut().fold(sample, |sample, stage| stage.process(sample, gain))
+        };
+        let [left, right] = &mut self.stages;
+        (run(left, input.0, gain), run(right, input.1, -gain))
+    }
+
+    /// Set the decorrelation amount (0.0 to 1.0)
+    pub fn set_amount(&mut self, amount: f64) {
+        self.amount = amount.clamp(0.0, 1.0);
+    }
+
+    /// Clear the allpass delay lines
+    pub fn reset(&mut self) {
+        for stage in self.stages.iter_mut().flatten() {
+            stage.reset();
+        }
+    }
+}
@@ -1,16 +1,86 @@
-/// Guitar distortion effect module
-/// 
-/// This module provides various distortion algorithms commonly used in guitar effects pedals.
-/// It follows the same pattern as the freeverb library with a tick() function for processing.
-/// 
-/// IMPORTANT: Only ONE distortion effect is applied at a time. Use set_distortion_type()
-/// to choose which effect to apply. The tick() function will apply the selected effect
-/// to the input signal.
+//! Guitar distortion effect module
+//! 
+//! This module provides various distortion algorithms commonly used in guitar effects pedals.
+//! It follows the same pattern as the freeverb library with a tick() function for processing.
+//! 
+//! IMPORTANT: Only ONE distortion effect is applied at a time. Use set_distortion_type()
+//! to choose which effect to apply. The tick() function will apply the selected effect
+//! to the input signal. Switching types crossfades from the old algorithm to the new
+//! one over a few milliseconds so the jump in transfer function doesn't click.
+//!
+//! Amp sag (set_sag()) follows the energy of the input over a few hundred milliseconds
+//! and pulls the drive down while it stays high, like a tube power supply dipping under
+//! sustained heavy playing: single hits stay punchy, held chords compress and bloom.
+//!
+//! The soft clipper's knee (set_soft_knee()) shapes how gradually it saturates. Below
+//! the default the tanh is blended toward x/(1+|x|), which starts compressing earlier
+//! but approaches the ceiling slowly: a smoother, more compressed, "spongy" feel. Above
+//! it the tanh is blended toward a hard clip, staying cleaner until close to the
+//! ceiling and then flattening abruptly: tighter and more aggressive, with more upper
+//! harmonics.
+//!
+//! Bass preservation (set_bass_preserve()) splits off the lows below a cutoff with a
+//! Linkwitz-Riley crossover, distorts only the band above it and adds the clean lows
+//! back at the output level. Palm-muted low notes keep their definition because
+//! the lows no longer intermodulate with everything else in the clipper.
+//!
+//! Tightness (set_tightness_hz()) high-passes the driven signal before the clipper,
+//! so low end that would make a high-gain tone flubby never gets distorted.
+//!
+//! Bit crusher dither (set_bit_crusher_dither()) adds triangular noise of one
+//! quantization step before rounding, so the quantization error becomes noise
+//! instead of harmonics that follow the input: a smoother, hissier lo-fi texture.
+//!
+//! Bit crusher jitter (set_bit_crusher_jitter()) randomizes when the sample-and-hold
+//! latches: each hold ends after a random share of the nominal interval more or
+//! less, averaging out to the set rate, like a cheap converter's unsteady clock.
+//!
+//! The cascade type chains three soft-clip stages like the gain stages of a
+//! high-gain preamp, each with its own gain (set_stage_gain()). Between stages a
+//! coupling high-pass trims the lows and a low-pass rounds off the fizz, so the
+//! saturation compounds the way it does in an amp instead of just clipping harder.
+//!
+//! Oversampling (set_oversampling()) runs the clipper at twice the sample rate
+//! between half-band filters, so the harmonics it generates above the original
+//! Nyquist frequency are filtered out instead of folding back down as inharmonic
+//! aliasing. It costs the filters' latency, about half a millisecond at 44.1kHz.
+//!
+//! The tone filter, DC blocker and bit crusher keep separate state for the left and
+//! right channel, so a stereo input stays stereo without crosstalk.
 
 use std::f64::consts::PI;
 
+use crate::denormal;
+use crate::dsp::{HalfBand, Xorshift32};
+use crate::filter::{Biquad, BiquadCoeffs, Crossover, BUTTERWORTH_Q};
+use crate::mix::mix_constant_power;
+
+/// Crossfade time when switching distortion types in seconds
+const TYPE_FADE_SECONDS: f64 = 0.005;
+/// Time constant of the sag energy follower in seconds
+const SAG_SECONDS: f64 = 0.3;
+/// Gain reduction per unit of input RMS at full sag (RMS 0.5 = -9.5 dB)
+const SAG_DEPTH: f64 = 4.0;
+/// Soft clipper knee giving a plain tanh
+const DEFAULT_SOFT_KNEE: f64 = 0.5;
+/// Lowest and highest bass preservation cutoff in Hz
+const MIN_BASS_PRESERVE_HZ: f64 = 40.0;
+const MAX_BASS_PRESERVE_HZ: f64 = 500.0;
+/// Lowest and highest tightness high-pass cutoff in Hz
+const MIN_TIGHTNESS_HZ: f64 = 20.0;
+const MAX_TIGHTNESS_HZ: f64 = 1000.0;
+/// Number of soft-clip stages in the cascade
+pub const CASCADE_STAGES: usize = 3;
+/// Default gain of each cascade stage
+const DEFAULT_STAGE_GAINS: [f64; CASCADE_STAGES] = [1.0, 3.0, 3.0];
+/// Highest cascade stage gain
+const MAX_STAGE_GAIN: f64 = 20.0;
+/// Coupling high-pass and treble low-pass cutoffs between cascade stages in Hz
+const INTERSTAGE_HIGHPASS_HZ: f64 = 120.0;
+const INTERSTAGE_LOWPASS_HZ: f64 = 6000.0;
+
 /// Distortion types available
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DistortionType {
     /// Soft clipping using tanh function (tube-like)
     Soft,
@@ -22,6 +92,49 @@ pub enum DistortionType {
     Wavefolder,
     /// Overdrive with asymmetric clipping
     Overdrive,
+    /// Three soft-clip stages with filtering in between (high-gain amp)
+    Cascade,
+}
+
+impl DistortionType {
+    /// Parse the short name used by the controls (soft, hard, bit, wave, over, cascade)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "soft" => Some(DistortionType::Soft),
+            "hard" => Some(DistortionType::Hard),
+            "bit" => Some(DistortionType::BitCrusher),
+            "wave" => Some(DistortionType::Wavefolder),
+            "over" => Some(DistortionType::Overdrive),
+            "cascade" => Some(DistortionType::Cascade),
+            _ => None,
+        }
+    }
+}
+
+/// Filters between two cascade stages
+struct InterStage {
+    /// Coupling high-pass trimming the lows
+    highpass: Biquad,
+    /// Low-pass rounding off the fizz
+    lowpass: Biquad,
+}
+
+impl InterStage {
+    fn new(sample_rate: f64) -> Self {
+        Self {
+            highpass: Biquad::new(BiquadCoeffs::highpass(sample_rate, INTERSTAGE_HIGHPASS_HZ, BUTTERWORTH_Q)),
+            lowpass: Biquad::new(BiquadCoeffs::lowpass(sample_rate, INTERSTAGE_LOWPASS_HZ, BUTTERWORTH_Q)),
+        }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        self.lowpass.process(self.highpass.process(input))
+    }
+
+    fn reset(&mut self) {
+        self.highpass.reset();
+        self.lowpass.reset();
+    }
 }
 
 /// Main distortion processor
@@ -36,18 +149,58 @@ pub struct Distortion {
     tone: f64,
     /// Sample rate for internal processing
     sample_rate: f64,
-    /// DC blocking filter state
-    dc_blocker: [f64; 2],
-    /// Tone filter state
-    tone_filter: [f64; 2],
-    /// Bit crusher sample rate divider
-    bit_crusher_counter: f64,
+    /// DC blocking filter state per channel (last input, last output)
+    dc_blocker: [[f64; 2]; 2],
+    /// Tone filter state per channel (last input, previous input)
+    tone_filter: [[f64; 2]; 2],
+    /// Bit crusher sample rate divider per channel
+    bit_crusher_counter: [f64; 2],
     /// Bit crusher sample rate
     bit_crusher_rate: f64,
     /// Bit crusher bit depth
     bit_crusher_depth: f64,
-    /// Last sample for bit crusher
-    last_sample: f64,
+    /// Last held sample per channel for the bit crusher
+    last_sample: [f64; 2],
+    /// Whether the bit crusher dithers before quantizing
+    bit_crusher_dither: bool,
+    /// Dither and clock jitter noise
+    noise: Xorshift32,
+    /// Bit crusher clock jitter (0.0 = steady to 1.0 = holds from half to 1.5x the interval)
+    bit_crusher_jitter: f64,
+    /// Counter value at which each channel's bit crusher latches next (1.0 without jitter)
+    bit_crusher_threshold: [f64; 2],
+    /// Type being faded out after a switch
+    previous_type: DistortionType,
+    /// Samples left in the type crossfade (0 = not fading)
+    fade_remaining: usize,
+    /// Length of the type crossfade in samples
+    fade_samples: usize,
+    /// Type to fade to once the running crossfade ends
+    pending_type: Option<DistortionType>,
+    /// Amp sag amount (0.0 to 1.0)
+    sag: f64,
+    /// Smoothed mean square of the input driving the sag
+    sag_energy: f64,
+    /// One-pole coefficient of the sag energy follower
+    sag_coefficient: f64,
+    /// Soft clipper knee (0.0 = gentle, 0.5 = tanh, 1.0 = hard)
+    soft_knee: f64,
+    /// Cutoff below which the signal stays clean in Hz (0 = off)
+    bass_preserve_hz: f64,
+    /// Crossover splitting off the clean lows, per channel
+    bass_crossover: [Crossover; 2],
+    /// Tightness high-pass cutoff ahead of the clipper in Hz (0 = off)
+    tightness_hz: f64,
+    /// Tightness high-pass, per channel
+    tightness_filters: [Biquad; 2],
+    /// Gain of each cascade stage
+    stage_gains: [f64; CASCADE_STAGES],
+    /// Filters after each cascade stage but the last, per channel
+    interstages: [[InterStage; CASCADE_STAGES - 1]; 2],
+    /// Whether the clipper runs at twice the sample rate
+    oversampling: bool,
+    /// Half-band filters into and out of the doubled rate, per channel
+    resamplers: [(HalfBand, HalfBand); 2],
 }
 
 impl Distortion {
@@ -59,12 +212,34 @@ impl Distortion {
             level: 0.7,
             tone: 0.5,
             sample_rate: sample_rate as f64,
-            dc_blocker: [0.0; 2],
-            tone_filter: [0.0; 2],
-            bit_crusher_counter: 0.0,
+            dc_blocker: [[0.0; 2]; 2],
+            tone_filter: [[0.0; 2]; 2],
+            bit_crusher_counter: [0.0; 2],
             bit_crusher_rate: 0.1,
             bit_crusher_depth: 0.5,
-            last_sample: 0.0,
+            last_sample: [0.0; 2],
+            bit_crusher_dither: false,
+            noise: Xorshift32::new(0x1234_5678),
+            bit_crusher_jitter: 0.0,
+            bit_crusher_threshold: [1.0; 2],
+            previous_type: DistortionType::Soft,
+            fade_remaining: 0,
+            fade_samples: ((TYPE_FADE_SECONDS * sample_rate as f64) as usize).max(1),
+            pending_type: None,
+            sag: 0.0,
+            sag_energy: 0.0,
+            sag_coefficient: (-1.0 / (SAG_SECONDS * sample_rate as f64)).exp(),
+            soft_knee: DEFAULT_SOFT_KNEE,
+            bass_preserve_hz: 0.0,
+            bass_crossover: std::array::from_fn(|_| Crossover::new(sample_rate as f64, MIN_BASS_PRESERVE_HZ)),
+            tightness_hz: 0.0,
+            tightness_filters: std::array::from_fn(|_| {
+                Biquad::new(BiquadCoeffs::highpass(sample_rate as f64, MIN_TIGHTNESS_HZ, BUTTERWORTH_Q))
+            }),
+            stage_gains: DEFAULT_STAGE_GAINS,
+            interstages: std::array::from_fn(|_| std::array::from_fn(|_| InterStage::new(sample_rate as f64))),
+            oversampling: false,
+            resamplers: std::array::from_fn(|_| (HalfBand::new(), HalfBand::new())),
         }
     }
 
@@ -79,35 +254,74 @@ impl Distortion {
     /// # Returns
     /// * Tuple of (left, right) output samples as f64
     pub fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
-        let (left_in, right_in) = input;
+        // Split off the lows that stay clean, if bass preservation is on
+        let ((left_low, left_in), (right_low, right_in)) = if self.bass_preserve_hz > 0.0 {
+            (self.bass_crossover[0].split(input.0), self.bass_crossover[1].split(input.1))
+        } else {
+            ((0.0, input.0), (0.0, input.1))
+        };
         
-        // Apply drive gain (convert drive parameter to actual gain)
-        let drive_gain = self.calculate_drive_gain();
-        let left_driven = left_in * drive_gain;
-        let right_driven = right_in * drive_gain;
+        // Track the input energy for amp sag
+        let energy = (left_in * left_in + right_in * right_in) / 2.0;
+        self.sag_energy = energy + self.sag_coefficient * (self.sag_energy - energy);
+
+        // Apply drive gain (convert drive parameter to actual gain), sagging on loud passages
+        let drive_gain = self.calculate_drive_gain() * self.sag_gain();
+        let left_driven = self.tighten(0, left_in * drive_gain);
+        let right_driven = self.tighten(1, right_in * drive_gain);
         
         // Apply distortion based on type (only ONE effect at a time)
-        let left_distorted = self.apply_distortion(left_driven);
-        let right_distorted = self.apply_distortion(right_driven);
+        let left_distorted = self.oversampled_distortion(0, left_driven);
+        let right_distorted = self.oversampled_distortion(1, right_driven);
+        self.advance_fade();
         
         // Apply tone filter
-        let left_toned = self.apply_tone_filter(left_distorted);
-        let right_toned = self.apply_tone_filter(right_distorted);
+        let left_toned = self.apply_tone_filter(0, left_distorted);
+        let right_toned = self.apply_tone_filter(1, right_distorted);
         
         // Apply DC blocking filter
-        let left_dc_blocked = self.apply_dc_blocker(left_toned);
-        let right_dc_blocked = self.apply_dc_blocker(right_toned);
+        let left_dc_blocked = self.apply_dc_blocker(0, left_toned);
+        let right_dc_blocked = self.apply_dc_blocker(1, right_toned);
         
-        // Apply output level
-        let left_out = left_dc_blocked * self.level;
-        let right_out = right_dc_blocked * self.level;
+        // Apply output level, adding back the clean lows
+        let left_out = (left_dc_blocked + left_low) * self.level;
+        let right_out = (right_dc_blocked + right_low) * self.level;
         
         (left_out, right_out)
     }
 
-    /// Set the distortion type
+    /// Set the distortion type, crossfading from the current one
+    ///
+    /// A switch during a crossfade doesn't restart it from a single type: switching
+    /// back reverses the fade from where it is, and another type is queued to fade
+    /// in once the running fade ends, so the output never jumps.
     pub fn set_distortion_type(&mut self, distortion_type: DistortionType) {
+        if self.fade_remaining > 0 {
+            if distortion_type == self.previous_type {
+                std::mem::swap(&mut self.previous_type, &mut self.distortion_type);
+                self.fade_remaining = self.fade_samples - self.fade_remaining;
+                self.pending_type = None;
+            } else {
+                self.pending_type = Some(distortion_type).filter(|&pending| pending != self.distortion_type);
+            }
+            return;
+        }
+        if distortion_type == self.distortion_type {
+            return;
+        }
+        self.previous_type = self.distortion_type;
         self.distortion_type = distortion_type;
+        self.fade_remaining = self.fade_samples;
+    }
+
+    /// Step the type crossfade by a sample, starting the queued one when it ends
+    fn advance_fade(&mut self) {
+        self.fade_remaining = self.fade_remaining.saturating_sub(1);
+        if self.fade_remaining == 0 && let Some(pending) = self.pending_type.take() {
+            self.previous_type = self.distortion_type;
+            self.distortion_type = pending;
+            self.fade_remaining = self.fade_samples;
+        }
     }
 
     /// Set the drive amount (0.0 to 1.0)
@@ -125,32 +339,225 @@ impl Distortion {
         self.tone = tone.clamp(0.0, 1.0);
     }
 
+    /// Set the amp sag amount (0.0 = stiff supply, 1.0 = heavy sag)
+    pub fn set_sag(&mut self, sag: f64) {
+        self.sag = sag.clamp(0.0, 1.0);
+    }
+
+    /// Current amp sag amount
+    pub fn sag(&self) -> f64 {
+        self.sag
+    }
+
+    /// Set the soft clipper knee (0.0 = gentle, 0.5 = tanh, 1.0 = hard)
+    pub fn set_soft_knee(&mut self, knee: f64) {
+        self.soft_knee = knee.clamp(0.0, 1.0);
+    }
+
+    /// Current soft clipper knee
+    pub fn soft_knee(&self) -> f64 {
+        self.soft_knee
+    }
+
+    /// Keep everything below `freq_hz` clean (40 to 500 Hz; 0 or below = off)
+    pub fn set_bass_preserve(&mut self, freq_hz: f64) {
+        if freq_hz <= 0.0 {
+            self.bass_preserve_hz = 0.0;
+            return;
+        }
+        if self.bass_preserve_hz == 0.0 {
+            for crossover in self.bass_crossover.iter_mut() {
+                crossover.reset();
+            }
+        }
+        self.bass_preserve_hz = freq_hz.clamp(MIN_BASS_PRESERVE_HZ, MAX_BASS_PRESERVE_HZ);
+        for crossover in self.bass_crossover.iter_mut() {
+            crossover.set_frequency(self.sample_rate, self.bass_preserve_hz);
+        }
+    }
+
+    /// Set the high-pass cutoff ahead of the clipper (20 to 1000 Hz; 0 or below = off)
+    pub fn set_tightness_hz(&mut self, freq_hz: f64) {
+        if freq_hz <= 0.0 {
+            self.tightness_hz = 0.0;
+            return;
+        }
+        if self.tightness_hz == 0.0 {
+            for filter in self.tightness_filters.iter_mut() {
+                filter.reset();
+            }
+        }
+        self.tightness_hz = freq_hz.clamp(MIN_TIGHTNESS_HZ, MAX_TIGHTNESS_HZ);
+        let coeffs = BiquadCoeffs::highpass(self.sample_rate, self.tightness_hz, BUTTERWORTH_Q);
+        for filter in self.tightness_filters.iter_mut() {
+            filter.set_coeffs(coeffs);
+        }
+    }
+
+    /// Current tightness cutoff in Hz (0 = off)
+    pub fn tightness_hz(&self) -> f64 {
+        self.tightness_hz
+    }
+
+    /// Current bass preservation cutoff in Hz (0 = off)
+    pub fn bass_preserve(&self) -> f64 {
+        self.bass_preserve_hz
+    }
+
     /// Set bit crusher parameters
     pub fn set_bit_crusher_params(&mut self, rate: f64, depth: f64) {
         self.bit_crusher_rate = rate.clamp(0.01, 1.0);
         self.bit_crusher_depth = depth.clamp(0.1, 1.0);
     }
 
+    /// Current bit crusher parameters (rate, depth)
+    pub fn bit_crusher_params(&self) -> (f64, f64) {
+        (self.bit_crusher_rate, self.bit_crusher_depth)
+    }
+
+    /// Enable or disable triangular dither before the bit crusher's quantization
+    pub fn set_bit_crusher_dither(&mut self, dither: bool) {
+        self.bit_crusher_dither = dither;
+    }
+
+    /// Whether the bit crusher dithers
+    pub fn bit_crusher_dither(&self) -> bool {
+        self.bit_crusher_dither
+    }
+
+    /// Set the bit crusher's clock jitter (0.0 = off to 1.0)
+    ///
+    /// Each hold lasts the nominal interval give or take up to half of it at 1.0.
+    pub fn set_bit_crusher_jitter(&mut self, amount: f64) {
+        self.bit_crusher_jitter = amount.clamp(0.0, 1.0);
+        if self.bit_crusher_jitter == 0.0 {
+            self.bit_crusher_threshold = [1.0; 2];
+        }
+    }
+
+    /// Current bit crusher clock jitter
+    pub fn bit_crusher_jitter(&self) -> f64 {
+        self.bit_crusher_jitter
+    }
+
+    /// Set the gain of cascade stage `index` (0 to 2), from 0 to 20
+    pub fn set_stage_gain(&mut self, index: usize, gain: f64) {
+        if let Some(stage_gain) = self.stage_gains.get_mut(index) {
+            *stage_gain = gain.clamp(0.0, MAX_STAGE_GAIN);
+        }
+    }
+
+    /// Gain of each cascade stage
+    pub fn stage_gains(&self) -> [f64; CASCADE_STAGES] {
+        self.stage_gains
+    }
+
+    /// Run the clipper at twice the sample rate, filtering out the harmonics that
+    /// would alias
+    ///
+    /// The cascade's filters between stages are rebuilt for the clipper's rate.
+    pub fn set_oversampling(&mut self, oversampling: bool) {
+        if oversampling == self.oversampling {
+            return;
+        }
+        self.oversampling = oversampling;
+        let clipper_rate = if oversampling { 2.0 * self.sample_rate } else { self.sample_rate };
+        self.interstages = std::array::from_fn(|_| std::array::from_fn(|_| InterStage::new(clipper_rate)));
+        for (up, down) in self.resamplers.iter_mut() {
+            up.reset();
+            down.reset();
+        }
+    }
+
+    /// Whether the clipper is oversampled
+    pub fn oversampling(&self) -> bool {
+        self.oversampling
+    }
+
     /// Calculate drive gain based on drive setting
     fn calculate_drive_gain(&self) -> f64 {
         // Drive ranges from 1.0 (no drive) to 20.0 (high drive)
         1.0 + (self.drive * 19.0)
     }
 
-    /// Apply the selected distortion algorithm
-    fn apply_distortion(&mut self, input: f64) -> f64 {
-        match self.distortion_type {
+    /// High-pass one channel's driven signal by the tightness cutoff, if set
+    fn tighten(&mut self, channel: usize, input: f64) -> f64 {
+        if self.tightness_hz > 0.0 {
+            self.tightness_filters[channel].process(input)
+        } else {
+            input
+        }
+    }
+
+    /// Pre-clip gain factor from amp sag (1.0 = no sag)
+    fn sag_gain(&self) -> f64 {
+        1.0 / (1.0 + self.sag * SAG_DEPTH * self.sag_energy.sqrt())
+    }
+
+    /// Apply the distortion to one channel, at twice the sample rate if oversampling
+    fn oversampled_distortion(&mut self, channel: usize, input: f64) -> f64 {
+        if !self.oversampling {
+            return self.apply_distortion(channel, input);
+        }
+
+        let upsampled = self.resamplers[channel].0.upsample(input);
+        let distorted = upsampled.map(|sample| self.apply_distortion(channel, sample));
+        self.resamplers[channel].1.downsample(distorted)
+    }
+
+    /// Apply the selected distortion algorithm to one channel (0 = left, 1 = right),
+    /// blended with the previous one while fading
+    ///
+    /// The stateful algorithms (the bit crusher's hold, the cascade's filters) each
+    /// keep their own state, and the two sides of a fade are always different
+    /// types, so every state still advances once per step.
+    fn apply_distortion(&mut self, channel: usize, input: f64) -> f64 {
+        let current = self.distort(self.distortion_type, channel, input);
+        if self.fade_remaining == 0 || self.previous_type == self.distortion_type {
+            return current;
+        }
+
+        let previous = self.distort(self.previous_type, channel, input);
+        let fade_in = 1.0 - self.fade_remaining as f64 / self.fade_samples as f64;
+        previous * (1.0 - fade_in) + current * fade_in
+    }
+
+    /// Apply one distortion algorithm to one channel
+    fn distort(&mut self, distortion_type: DistortionType, channel: usize, input: f64) -> f64 {
+        match distortion_type {
             DistortionType::Soft => self.soft_clip(input),
             DistortionType::Hard => self.hard_clip(input),
-            DistortionType::BitCrusher => self.bit_crush(input),
+            DistortionType::BitCrusher => self.bit_crush(channel, input),
             DistortionType::Wavefolder => self.wavefold(input),
             DistortionType::Overdrive => self.overdrive(input),
+            DistortionType::Cascade => self.cascade(channel, input),
         }
     }
 
-    /// Soft clipping using hyperbolic tangent (tube-like)
+    /// Soft clipping using hyperbolic tangent (tube-like), morphed by the knee
     fn soft_clip(&self, input: f64) -> f64 {
-        input.tanh()
+        let tanh = input.tanh();
+        if self.soft_knee < DEFAULT_SOFT_KNEE {
+            let blend = (DEFAULT_SOFT_KNEE - self.soft_knee) / DEFAULT_SOFT_KNEE;
+            tanh + blend * (input / (1.0 + input.abs()) - tanh)
+        } else if self.soft_knee > DEFAULT_SOFT_KNEE {
+            let blend = (self.soft_knee - DEFAULT_SOFT_KNEE) / (1.0 - DEFAULT_SOFT_KNEE);
+            tanh + blend * (input.clamp(-1.0, 1.0) - tanh)
+        } else {
+            tanh
+        }
+    }
+
+    /// Three soft-clip stages, filtered between stages
+    fn cascade(&mut self, channel: usize, input: f64) -> f64 {
+        let mut signal = input;
+        for stage in 0..CASCADE_STAGES {
+            signal = self.soft_clip(signal * self.stage_gains[stage]);
+            if let Some(interstage) = self.interstages[channel].get_mut(stage) {
+                signal = interstage.process(signal);
+            }
+        }
+        signal
     }
 
     /// Hard clipping with adjustable threshold
@@ -166,19 +573,23 @@ impl Distortion {
     }
 
     /// Bit crusher effect
-    fn bit_crush(&mut self, input: f64) -> f64 {
-        self.bit_crusher_counter += self.bit_crusher_rate;
+    fn bit_crush(&mut self, channel: usize, input: f64) -> f64 {
+        // Hold for the same time whether or not the clipper is oversampled
+        let steps_per_sample = if self.oversampling { 2.0 } else { 1.0 };
+        self.bit_crusher_counter[channel] += self.bit_crusher_rate / steps_per_sample;
         
-        if self.bit_crusher_counter >= 1.0 {
-            self.bit_crusher_counter -= 1.0;
-            self.last_sample = input;
+        if self.bit_crusher_counter[channel] >= self.bit_crusher_threshold[channel] {
+            self.bit_crusher_counter[channel] -= self.bit_crusher_threshold[channel];
+            self.last_sample[channel] = input;
+            if self.bit_crusher_jitter > 0.0 {
+                self.bit_crusher_threshold[channel] = 1.0 + self.bit_crusher_jitter * self.noise.uniform();
+            }
         }
         
         // Quantize the sample
-        let levels = (2.0_f64.powf(self.bit_crusher_depth * 16.0)) as f64;
-        let quantized = (self.last_sample * levels).round() / levels;
-        
-        quantized
+        let levels = 2.0_f64.powf(self.bit_crusher_depth * 16.0);
+        let dither = if self.bit_crusher_dither { self.noise.tpdf() } else { 0.0 };
+        (self.last_sample[channel] * levels + dither).round() / levels
     }
 
     /// Wavefolder distortion
@@ -203,40 +614,58 @@ impl Distortion {
     }
 
     /// Apply tone filter (simple high-pass filter)
-    fn apply_tone_filter(&mut self, input: f64) -> f64 {
+    fn apply_tone_filter(&mut self, channel: usize, input: f64) -> f64 {
         // Simple first-order high-pass filter
         let cutoff = 100.0 + (self.tone * 2000.0); // 100Hz to 2.1kHz
         let rc = 1.0 / (2.0 * PI * cutoff);
         let dt = 1.0 / self.sample_rate;
         let alpha = rc / (rc + dt);
         
-        let output = alpha * (self.tone_filter[0] + input - self.tone_filter[1]);
-        self.tone_filter[1] = self.tone_filter[0];
-        self.tone_filter[0] = input;
+        let state = &mut self.tone_filter[channel];
+        let output = denormal::flush(alpha * (state[0] + input - state[1]));
+        state[1] = state[0];
+        state[0] = input;
         
         // Mix between filtered and unfiltered signal
-        let filtered = output;
-        let unfiltered = input;
-        
-        filtered * self.tone + unfiltered * (1.0 - self.tone)
+        mix_constant_power(input, output, self.tone)
     }
 
     /// Apply DC blocking filter
-    fn apply_dc_blocker(&mut self, input: f64) -> f64 {
+    fn apply_dc_blocker(&mut self, channel: usize, input: f64) -> f64 {
         // Simple DC blocking filter
         let alpha = 0.995;
-        let output = input - self.dc_blocker[0] + alpha * self.dc_blocker[1];
-        self.dc_blocker[0] = input;
-        self.dc_blocker[1] = output;
+        let state = &mut self.dc_blocker[channel];
+        let output = denormal::flush(input - state[0] + alpha * state[1]);
+        state[0] = input;
+        state[1] = output;
         output
     }
 
     /// Reset all internal state
     pub fn reset(&mut self) {
-        self.dc_blocker = [0.0; 2];
-        self.tone_filter = [0.0; 2];
-        self.bit_crusher_counter = 0.0;
-        self.last_sample = 0.0;
+        self.dc_blocker = [[0.0; 2]; 2];
+        self.tone_filter = [[0.0; 2]; 2];
+        self.bit_crusher_counter = [0.0; 2];
+        self.bit_crusher_threshold = [1.0; 2];
+        self.last_sample = [0.0; 2];
+        self.fade_remaining = 0;
+        if let Some(pending) = self.pending_type.take() {
+            self.distortion_type = pending;
+        }
+        self.sag_energy = 0.0;
+        for crossover in self.bass_crossover.iter_mut() {
+            crossover.reset();
+        }
+        for filter in self.tightness_filters.iter_mut() {
+            filter.reset();
+        }
+        for interstage in self.interstages.iter_mut().flatten() {
+            interstage.reset();
+        }
+        for (up, down) in self.resamplers.iter_mut() {
+            up.reset();
+            down.reset();
+        }
     }
 }
 
@@ -314,9 +743,373 @@ mod tests {
         distortion.reset();
         
         // Internal state should be reset
-        assert_eq!(distortion.dc_blocker, [0.0; 2]);
-        assert_eq!(distortion.tone_filter, [0.0; 2]);
-        assert_eq!(distortion.bit_crusher_counter, 0.0);
-        assert_eq!(distortion.last_sample, 0.0);
+        assert_eq!(distortion.dc_blocker, [[0.0; 2]; 2]);
+        assert_eq!(distortion.tone_filter, [[0.0; 2]; 2]);
+        assert_eq!(distortion.bit_crusher_counter, [0.0; 2]);
+        assert_eq!(distortion.last_sample, [0.0; 2]);
+        assert_eq!(distortion.sag_energy, 0.0);
+    }
+
+    #[test]
+    fn test_type_switch_crossfades() {
+        let mut distortion = Distortion::new(44100);
+        distortion.set_distortion_type(DistortionType::Hard);
+        distortion.set_drive(1.0);
+        let input = 0.9;
+        let hard = distortion.hard_clip(input);
+        let folded = distortion.wavefold(input);
+        let jump = (folded - hard).abs();
+        assert!(jump > 0.1);
+
+        // Let the switch to hard clipping finish, then switch again
+        for _ in 0..distortion.fade_samples {
+            distortion.tick((0.0, 0.0));
+        }
+        distortion.set_distortion_type(DistortionType::Wavefolder);
+
+        let mut outputs = Vec::new();
+        for _ in 0..distortion.fade_samples + 1 {
+            outputs.push(distortion.apply_distortion(0, input));
+            distortion.fade_remaining = distortion.fade_remaining.saturating_sub(1);
+        }
+
+        // Starts at the old curve, ends at the new one, in small steps
+        assert_eq!(outputs[0], hard);
+        assert!((outputs[outputs.len() / 2] - (hard + folded) / 2.0).abs() < jump * 0.1);
+        assert_eq!(*outputs.last().unwrap(), folded);
+        assert!(outputs.windows(2).all(|pair| (pair[1] - pair[0]).abs() < jump * 0.01));
+    }
+
+    #[test]
+    fn test_switch_during_a_fade_never_jumps() {
+        let input = 0.9;
+        let mut distortion = Distortion::new(44100);
+        distortion.set_drive(1.0);
+        let fade = distortion.fade_samples;
+        let step_limit = 2.0 / fade as f64;
+        let run = |distortion: &mut Distortion, samples: usize, outputs: &mut Vec<f64>| {
+            for _ in 0..samples {
+                outputs.push(distortion.apply_distortion(0, input));
+                distortion.advance_fade();
+            }
+        };
+
+        // Soft to hard, back to soft halfway: the fade reverses from where it is
+        let mut outputs = Vec::new();
+        distortion.set_distortion_type(DistortionType::Hard);
+        run(&mut distortion, fade / 2, &mut outputs);
+        distortion.set_distortion_type(DistortionType::Soft);
+        run(&mut distortion, fade, &mut outputs);
+        assert!(outputs.windows(2).all(|pair| (pair[1] - pair[0]).abs() < step_limit), "{:?}", outputs);
+        assert_eq!(*outputs.last().unwrap(), distortion.soft_clip(input));
+
+        // Soft to hard, then to the wavefolder halfway: it fades in after the hard clip
+        let mut outputs = Vec::new();
+        distortion.set_distortion_type(DistortionType::Hard);
+        run(&mut distortion, fade / 2, &mut outputs);
+        distortion.set_distortion_type(DistortionType::Wavefolder);
+        run(&mut distortion, 2 * fade, &mut outputs);
+        assert!(outputs.windows(2).all(|pair| (pair[1] - pair[0]).abs() < step_limit), "{:?}", outputs);
+        assert_eq!(*outputs.last().unwrap(), distortion.wavefold(input));
+        assert_eq!(distortion.fade_remaining, 0);
+    }
+
+    #[test]
+    fn test_bit_crusher_holds_at_its_rate_during_a_fade() {
+        // Count the latches of a 1/4 rate crusher over a ramp, fading in and steady
+        let latches = |fading: bool| {
+            let mut distortion = Distortion::new(44100);
+            distortion.set_bit_crusher_params(0.25, 1.0);
+            distortion.set_distortion_type(DistortionType::BitCrusher);
+            if !fading {
+                distortion.fade_remaining = 0;
+            }
+            let mut held = Vec::new();
+            for i in 0..distortion.fade_samples {
+                distortion.apply_distortion(0, i as f64 * 1e-3);
+                distortion.advance_fade();
+                held.push(distortion.last_sample[0]);
+            }
+            held.windows(2).filter(|pair| pair[1] != pair[0]).count()
+        };
+        assert_eq!(latches(true), latches(false));
+        assert!(latches(true) > 0);
+    }
+
+    #[test]
+    fn test_sag_grows_with_sustained_input() {
+        let mut distortion = Distortion::new(44100);
+        distortion.set_sag(1.0);
+        assert_eq!(distortion.sag_gain(), 1.0);
+
+        // A 10ms burst barely moves the supply
+        let loud = |i: usize| if i % 100 < 50 { 0.8 } else { -0.8 };
+        for i in 0..441 {
+            distortion.tick((loud(i), loud(i)));
+        }
+        let burst_gain = distortion.sag_gain();
+
+        // Holding it for a second pulls the gain well down
+        for i in 441..44100 {
+            distortion.tick((loud(i), loud(i)));
+        }
+        let sustained_gain = distortion.sag_gain();
+        assert!(burst_gain > 0.6, "burst gain {}", burst_gain);
+        assert!(sustained_gain < burst_gain * 0.5, "sustained gain {} vs burst {}", sustained_gain, burst_gain);
+
+        // Without sag the same playing leaves the gain alone
+        distortion.set_sag(0.0);
+        assert_eq!(distortion.sag_gain(), 1.0);
+        distortion.set_sag(1.0);
+        distortion.reset();
+        assert_eq!(distortion.sag_gain(), 1.0);
+    }
+
+    #[test]
+    fn test_soft_knee_steepens_clip_region() {
+        let mut distortion = Distortion::new(44100);
+        assert_eq!(distortion.soft_knee(), DEFAULT_SOFT_KNEE);
+        for &x in &[-2.0, -0.3, 0.0, 0.7, 3.0] {
+            assert_eq!(distortion.soft_clip(x), f64::tanh(x));
+        }
+
+        // Slope of the transfer function just below the ceiling
+        let slope = |distortion: &Distortion| (distortion.soft_clip(0.95) - distortion.soft_clip(0.85)) / 0.1;
+        let mut previous = 0.0;
+        for knee in [0.0, 0.25, 0.5, 0.75, 1.0] {
+            distortion.set_soft_knee(knee);
+            let steepness = slope(&distortion);
+            assert!(steepness > previous, "knee {} slope {} after {}", knee, steepness, previous);
+            previous = steepness;
+        }
+
+        // The output stays within the ceiling at every knee
+        for knee in [0.0, 1.0] {
+            distortion.set_soft_knee(knee);
+            assert!(distortion.soft_clip(10.0) <= 1.0 && distortion.soft_clip(-10.0) >= -1.0);
+        }
+    }
+
+    #[test]
+    fn test_channels_are_independent() {
+        let left = |i: usize| 0.5 * (2.0 * PI * 220.0 * i as f64 / 44100.0).sin();
+        let right_a = |i: usize| 0.8 * (2.0 * PI * 331.0 * i as f64 / 44100.0).sin();
+        let right_b = |i: usize| if i % 200 < 100 { 0.3 } else { -0.9 };
+
+        for distortion_type in [DistortionType::Soft, DistortionType::BitCrusher, DistortionType::Overdrive] {
+            let mut first = Distortion::new(44100);
+            let mut second = Distortion::new(44100);
+            for distortion in [&mut first, &mut second] {
+                distortion.set_distortion_type(distortion_type);
+                distortion.set_tone(0.7);
+            }
+
+            // Only the right input differs, so the left output must match exactly
+            for i in 0..4410 {
+                let (left_first, right_first) = first.tick((left(i), right_a(i)));
+                let (left_second, right_second) = second.tick((left(i), right_b(i)));
+                assert_eq!(left_first, left_second, "{:?} sample {}", distortion_type, i);
+                if i > 100 {
+                    assert_ne!(right_first, right_second);
+                }
+            }
+        }
+    }
+
+    /// Third-harmonic to fundamental ratio and fundamental amplitude of the left
+    /// output for a half-scale sine at full drive, after the filters settle
+    fn harmonic_ratio(freq: f64, bass_preserve_hz: f64) -> (f64, f64) {
+        let sample_rate = 44100.0;
+        let mut distortion = Distortion::new(44100);
+        distortion.set_drive(1.0);
+        distortion.set_level(1.0);
+        distortion.set_tone(0.0);
+        distortion.set_bass_preserve(bass_preserve_hz);
+        let output: Vec<f64> = (0..88200)
+            .map(|i| distortion.tick((0.5 * (2.0 * PI * freq * i as f64 / sample_rate).sin(), 0.0)).0)
+            .skip(44100)
+            .collect();
+        let magnitude = |freq: f64| {
+            let (re, im) = output.iter().enumerate().fold((0.0, 0.0), |(re, im), (i, sample)| {
+                let phase = 2.0 * PI * freq * i as f64 / sample_rate;
+                (re + sample * phase.cos(), im + sample * phase.sin())
+            });
+            2.0 * (re * re + im * im).sqrt() / output.len() as f64
+        };
+        (magnitude(3.0 * freq) / magnitude(freq), magnitude(freq))
+    }
+
+    #[test]
+    fn test_bass_preserve_keeps_lows_clean() {
+        // Off by default, and the lows clip like everything else
+        assert_eq!(Distortion::new(44100).bass_preserve(), 0.0);
+        let (distorted, _) = harmonic_ratio(50.0, 0.0);
+        assert!(distorted > 0.1, "third harmonic ratio without preservation {}", distorted);
+
+        // Below the cutoff a low note passes nearly unchanged
+        let (clean, fundamental) = harmonic_ratio(50.0, 200.0);
+        assert!(clean < 0.01, "third harmonic ratio with preservation {}", clean);
+        assert!((fundamental / 0.5 - 1.0).abs() < 0.1, "fundamental {}", fundamental);
+
+        // Above it the signal is still distorted
+        let (high, _) = harmonic_ratio(1000.0, 200.0);
+        assert!(high > 0.1, "third harmonic ratio above the cutoff {}", high);
+
+        let mut distortion = Distortion::new(44100);
+        distortion.set_bass_preserve(1000.0);
+        assert_eq!(distortion.bass_preserve(), MAX_BASS_PRESERVE_HZ);
+        distortion.set_bass_preserve(0.0);
+        assert_eq!(distortion.bass_preserve(), 0.0);
+    }
+
+    #[test]
+    fn test_bit_crusher_dither_decorrelates_error() {
+        // Correlation of the quantization error with a sine under half a step,
+        // which plain rounding turns into silence (error = -input)
+        let error_correlation = |dither: bool| {
+            let mut distortion = Distortion::new(44100);
+            distortion.set_bit_crusher_params(1.0, 0.25);
+            distortion.set_bit_crusher_dither(dither);
+            let step = 1.0 / 16.0;
+            let (mut cross, mut input_power, mut error_power) = (0.0, 0.0, 0.0);
+            for i in 0..20000 {
+                let input = 0.4 * step * (i as f64 * 0.01).sin();
+                let error = distortion.bit_crush(0, input) - input;
+                cross += input * error;
+                input_power += input * input;
+                error_power += error * error;
+            }
+            cross / (input_power * error_power).sqrt()
+        };
+
+        assert!(!Distortion::new(44100).bit_crusher_dither());
+        let plain = error_correlation(false);
+        let dithered = error_correlation(true);
+        assert!(plain < -0.99, "correlation without dither {}", plain);
+        assert!(dithered.abs() < 0.1, "correlation with dither {}", dithered);
+    }
+
+    #[test]
+    fn test_bit_crusher_jitter_varies_the_hold_around_the_rate() {
+        // Samples between latches of a ramp, which changes at every sample
+        let hold_lengths = |jitter: f64| {
+            let mut distortion = Distortion::new(44100);
+            distortion.set_bit_crusher_params(0.1, 1.0);
+            distortion.set_bit_crusher_jitter(jitter);
+            let mut held = f64::NAN;
+            let mut lengths = Vec::new();
+            let mut since = 0;
+            for i in 0..100000 {
+                let output = distortion.bit_crush(0, i as f64 * 1e-5);
+                if output != held {
+                    if !held.is_nan() {
+                        lengths.push(since);
+                    }
+                    held = output;
+                    since = 0;
+                }
+                since += 1;
+            }
+            lengths
+        };
+
+        // Off by default, the sample-and-hold keeps a steady clock
+        assert_eq!(Distortion::new(44100).bit_crusher_jitter(), 0.0);
+        assert!(hold_lengths(0.0).iter().all(|&length| length == 10));
+
+        // With jitter the holds spread out but average to the nominal 10 samples
+        let lengths = hold_lengths(0.8);
+        let mean = lengths.iter().sum::<usize>() as f64 / lengths.len() as f64;
+        assert!((mean - 10.0).abs() < 0.2, "mean hold {}", mean);
+        assert!(lengths.iter().any(|&length| length <= 7), "shortest {:?}", lengths.iter().min());
+        assert!(lengths.iter().any(|&length| length >= 13), "longest {:?}", lengths.iter().max());
+        assert!(lengths.iter().all(|&length| (5..=15).contains(&length)));
+    }
+
+    #[test]
+    fn test_tightness_cuts_lows_before_the_clipper() {
+        // Peak of a 60 Hz sine after the tightness filter, once it has settled
+        let low_peak = |tightness_hz: f64| {
+            let mut distortion = Distortion::new(44100);
+            distortion.set_tightness_hz(tightness_hz);
+            (0..44100)
+                .map(|i| distortion.tighten(0, (2.0 * PI * 60.0 * i as f64 / 44100.0).sin()))
+                .skip(22050)
+                .fold(0.0, |peak: f64, sample| peak.max(sample.abs()))
+        };
+
+        assert_eq!(Distortion::new(44100).tightness_hz(), 0.0);
+        let gentle = low_peak(40.0);
+        let tight = low_peak(300.0);
+        assert!(low_peak(0.0) > 0.99);
+        assert!(gentle > 0.5 && gentle < 0.99, "{}", gentle);
+        assert!(tight < 0.05, "{}", tight);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_cascade_adds_harmonics_over_a_single_stage() {
+        // Harmonic energy above the fundamental over the fundamental, for a quiet
+        // 220 Hz sine at minimum drive, after the filters settle
+        let harmonic_ratio = |distortion_type: DistortionType| {
+            let sample_rate = 44100.0;
+            let mut distortion = Distortion::new(44100);
+            distortion.set_drive(0.0);
+            distortion.set_level(1.0);
+            distortion.set_tone(0.0);
+            distortion.set_distortion_type(distortion_type);
+            distortion.reset();
+            let output: Vec<f64> = (0..88200)
+                .map(|i| distortion.tick((0.3 * (2.0 * PI * 220.0 * i as f64 / sample_rate).sin(), 0.0)).0)
+                .skip(44100)
+                .collect();
+            let magnitude = |freq: f64| {
+                let (re, im) = output.iter().enumerate().fold((0.0, 0.0), |(re, im), (i, sample)| {
+                    let phase = 2.0 * PI * freq * i as f64 / sample_rate;
+                    (re + sample * phase.cos(), im + sample * phase.sin())
+                });
+                2.0 * (re * re + im * im).sqrt() / output.len() as f64
+            };
+            let harmonics: f64 = (2..=9).map(|n| magnitude(n as f64 * 220.0).powi(2)).sum();
+            harmonics.sqrt() / magnitude(220.0)
+        };
+
+        let single = harmonic_ratio(DistortionType::Soft);
+        let cascade = harmonic_ratio(DistortionType::Cascade);
+        assert!(cascade > 3.0 * single, "cascade {} vs single stage {}", cascade, single);
+
+        let mut distortion = Distortion::new(44100);
+        assert_eq!(distortion.stage_gains(), DEFAULT_STAGE_GAINS);
+        distortion.set_stage_gain(1, 50.0);
+        distortion.set_stage_gain(CASCADE_STAGES, 5.0);
+        assert_eq!(distortion.stage_gains(), [1.0, MAX_STAGE_GAIN, 3.0]);
+    }
+
+    #[test]
+    fn test_oversampling_reduces_aliasing() {
+        // Level at 900 Hz when clipping a 5 kHz sine hard: the ninth harmonic at
+        // 45 kHz folds back there at 44.1 kHz, but is filtered out when oversampled
+        let alias = |oversampling: bool| {
+            let sample_rate = 44100.0;
+            let mut distortion = Distortion::new(44100);
+            distortion.set_drive(1.0);
+            distortion.set_level(1.0);
+            distortion.set_tone(0.0);
+            distortion.set_oversampling(oversampling);
+            let output: Vec<f64> = (0..88200)
+                .map(|i| distortion.tick((0.5 * (2.0 * PI * 5000.0 * i as f64 / sample_rate).sin(), 0.0)).0)
+                .skip(44100)
+                .collect();
+            let (re, im) = output.iter().enumerate().fold((0.0, 0.0), |(re, im), (i, sample)| {
+                let phase = 2.0 * PI * 900.0 * i as f64 / sample_rate;
+                (re + sample * phase.cos(), im + sample * phase.sin())
+            });
+            2.0 * (re * re + im * im).sqrt() / output.len() as f64
+        };
+
+        assert!(!Distortion::new(44100).oversampling());
+        let plain = alias(false);
+        let oversampled = alias(true);
+        assert!(plain > 0.05, "alias without oversampling {}", plain);
+        assert!(oversampled < plain * 0.01, "alias {} -> {}", plain, oversampled);
+    }
+}
@@ -0,0 +1,176 @@
+//! Shared DSP building blocks
+//!
+//! `HalfBand` is the 2x resampler for effects that run part of their work at
+//! twice the device rate, like the distortion's oversampled clipper. A half-band
+//! low-pass has its cutoff at a quarter of the doubled rate and every other tap
+//! zero apart from the centre one, so its polyphase halves are almost free: one
+//! half is a plain delay, and only the odd taps need multiplying. The same
+//! windowed-sinc taps serve both directions, removing the images when going up
+//! and the content that would alias when coming back down.
+//!
+//! The filter is linear phase: up and down together delay the signal by
+//! `HALF_BAND_LATENCY` samples at the device rate.
+//!
+//! `Xorshift32` is the noise source for dither, clock jitter and random LFO
+//! steps: cheap, allocation-free and the same sequence on every run, so renders
+//! stay reproducible.
+
+use std::f64::consts::PI;
+
+/// Nonzero odd taps on each side of the centre tap
+const HALF_BAND_SIDE_TAPS: usize = 12;
+/// Delay of an upsample followed by a downsample, in samples at the base rate
+pub const HALF_BAND_LATENCY: usize = 2 * HALF_BAND_SIDE_TAPS - 1;
+
+/// 2x up and down sampler with a linear-phase half-band FIR
+pub struct HalfBand {
+    /// Odd taps of the filter (symmetric)
+    taps: [f64; 2 * HALF_BAND_SIDE_TAPS],
+    /// Recent inputs for the odd taps, newest first
+    history: [f64; 2 * HALF_BAND_SIDE_TAPS],
+    /// Recent inputs for the centre tap, newest first
+    centre: [f64; HALF_BAND_SIDE_TAPS + 1],
+}
+
+impl Default for HalfBand {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HalfBand {
+    /// Create a resampler with empty state
+    pub fn new() -> Self {
+        // Blackman-windowed sinc at a quarter of the doubled rate; the even taps
+        // are zero and the centre tap is 0.5
+        let length = (4 * HALF_BAND_SIDE_TAPS - 1) as f64;
+        let taps = std::array::from_fn(|i| {
+            let n = 2.0 * i as f64 - 2.0 * HALF_BAND_SIDE_TAPS as f64 + 1.0;
+            let sinc = (PI * n / 2.0).sin() / (PI * n);
+            let x = 2.0 * PI * (n + (length - 1.0) / 2.0) / (length - 1.0);
+            sinc * (0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos())
+        });
+        Self {
+            taps,
+            history: [0.0; 2 * HALF_BAND_SIDE_TAPS],
+            centre: [0.0; HALF_BAND_SIDE_TAPS + 1],
+        }
+    }
+
+    /// Double the rate: one input sample in, two filtered samples out
+    pub fn upsample(&mut self, x: f64) -> [f64; 2] {
+        self.history.rotate_right(1);
+        self.history[0] = x;
+        // The zero-stuffed input doubles the gain: the odd taps sum to 0.5 and the
+        // centre tap becomes a plain delay
+        let filtered = 2.0 * self.odd_taps();
+        [filtered, self.history[HALF_BAND_SIDE_TAPS - 1]]
+    }
+
+    /// Halve the rate: two samples in, one filtered sample out
+    pub fn downsample(&mut self, x: [f64; 2]) -> f64 {
+        self.history.rotate_right(1);
+        self.history[0] = x[0];
+        self.centre.rotate_right(1);
+        self.centre[0] = x[1];
+        self.odd_taps() + 0.5 * self.centre[HALF_BAND_SIDE_TAPS]
+    }
+
+    fn odd_taps(&self) -> f64 {
+        self.taps.iter().zip(&self.history).map(|(tap, sample)| tap * sample).sum()
+    }
+
+    /// Clear the filter state
+    pub fn reset(&mut self) {
+        self.history.fill(0.0);
+        self.centre.fill(0.0);
+    }
+}
+
+/// Small xorshift32 pseudo-random generator
+#[derive(Debug, Clone)]
+pub struct Xorshift32 {
+    state: u32,
+}
+
+impl Xorshift32 {
+    /// Start the sequence from `seed` (zero, which would stay zero, is replaced by one)
+    pub fn new(seed: u32) -> Self {
+        Self { state: seed.max(1) }
+    }
+
+    /// Next raw value
+    pub fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+
+    /// Uniform value from -0.5 to 0.5
+    pub fn uniform(&mut self) -> f64 {
+        self.next_u32() as f64 / u32::MAX as f64 - 0.5
+    }
+
+    /// Uniform value from -1.0 to 1.0
+    pub fn bipolar(&mut self) -> f64 {
+        self.next_u32() as f64 / u32::MAX as f64 * 2.0 - 1.0
+    }
+
+    /// Triangular (TPDF) value from -1.0 to 1.0, the sum of two uniform values
+    pub fn tpdf(&mut self) -> f64 {
+        self.uniform() + self.uniform()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Peak level of `signal` after the first 1000 samples
+    fn settled_peak(signal: &[f64]) -> f64 {
+        signal[1000..].iter().map(|s| s.abs()).fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn test_round_trip_returns_the_input_delayed() {
+        let mut up = HalfBand::new();
+        let mut down = HalfBand::new();
+        let input: Vec<f64> = (0..4000)
+            .map(|i| 0.5 * (i as f64 * 0.05).sin() + 0.3 * (i as f64 * 0.31).cos())
+            .collect();
+        let output: Vec<f64> = input.iter().map(|&x| down.downsample(up.upsample(x))).collect();
+
+        // Up and down together: once the filters have filled, the input again,
+        // HALF_BAND_LATENCY samples late
+        for (i, &sample) in output.iter().enumerate().skip(100) {
+            let expected = input[i - HALF_BAND_LATENCY];
+            assert!((sample - expected).abs() < 2e-3, "sample {}: {} vs {}", i, sample, expected);
+        }
+
+        // Content above the base rate's Nyquist frequency doesn't fold back down
+        let mut down = HalfBand::new();
+        let high: Vec<f64> = (0..8000).map(|i| (2.0 * PI * 0.34 * i as f64).sin()).collect();
+        let decimated: Vec<f64> = high.chunks(2).map(|pair| down.downsample([pair[0], pair[1]])).collect();
+        assert!(settled_peak(&decimated) < 0.01, "aliased peak {}", settled_peak(&decimated));
+
+        down.reset();
+        assert_eq!(down.downsample([0.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn test_xorshift_ranges_and_repeatability() {
+        let mut noise = Xorshift32::new(0x1234_5678);
+        let mut again = Xorshift32::new(0x1234_5678);
+        for _ in 0..10000 {
+            let (uniform, bipolar, tpdf) = (noise.uniform(), noise.bipolar(), noise.tpdf());
+            assert!((-0.5..=0.5).contains(&uniform));
+            assert!((-1.0..=1.0).contains(&bipolar));
+            assert!((-1.0..=1.0).contains(&tpdf));
+            assert_eq!((again.uniform(), again.bipolar(), again.tpdf()), (uniform, bipolar, tpdf));
+        }
+
+        // A zero seed would never leave zero
+        assert_ne!(Xorshift32::new(0).next_u32(), 0);
+    }
+}
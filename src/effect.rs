@@ -0,0 +1,314 @@
+//! Common interface shared by every effect in the chain
+
+use std::any::Any;
+
+use crate::adt::Adt;
+use crate::autopan::AutoPan;
+use crate::boost::Boost;
+use crate::delay::Delay;
+use crate::denoise::Denoiser;
+use crate::distortion::Distortion;
+use crate::exciter::Exciter;
+use crate::insert::InsertLoop;
+use crate::multiband::MultibandDistortion;
+use crate::reverb::Reverb;
+use crate::reverse::ReverseDelay;
+use crate::stereo::StereoWidth;
+use crate::stutter::Stutter;
+use crate::swell::Swell;
+use crate::tremolo::Tremolo;
+
+/// A stereo audio effect that can be placed in the effect chain
+pub trait Effect: Send {
+    /// Process a stereo input sample and return stereo output
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64);
+
+    /// Clear all internal state (filters, delay lines, tails)
+    fn reset(&mut self);
+
+    /// Whether the effect rings on after its input stops (echoes, a reverb
+    /// tail), so switching it off in trails mode lets that ring out
+    fn has_tail(&self) -> bool {
+        false
+    }
+
+    /// Access the concrete effect for parameter changes
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl Effect for Distortion {
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        Distortion::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        Distortion::reset(self)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Effect for MultibandDistortion {
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        MultibandDistortion::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        MultibandDistortion::reset(self)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Effect for Reverb {
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        Reverb::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        Reverb::reset(self)
+    }
+
+    fn has_tail(&self) -> bool {
+        true
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Effect for Delay {
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        Delay::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        Delay::reset(self)
+    }
+
+    fn has_tail(&self) -> bool {
+        true
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Effect for Boost {
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        Boost::tick(self, input)
+    }
+
+    fn reset(&mut self) {}
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Effect for Exciter {
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        Exciter::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        Exciter::reset(self)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Effect for Denoiser {
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        Denoiser::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        Denoiser::reset(self)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Effect for StereoWidth {
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        StereoWidth::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        StereoWidth::reset(self)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Effect for AutoPan {
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        AutoPan::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        AutoPan::reset(self)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Effect for Adt {
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        Adt::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        Adt::reset(self)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Effect for ReverseDelay {
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        ReverseDelay::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        ReverseDelay::reset(self)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Effect for Tremolo {
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        Tremolo::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        Tremolo::reset(self)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Effect for Swell {
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        Swell::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        Swell::reset(self)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Effect for Stutter {
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        Stutter::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        Stutter::reset(self)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Effect for InsertLoop {
+    fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        InsertLoop::tick(self, input)
+    }
+
+    fn reset(&mut self) {
+        InsertLoop::reset(self)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fixed gain
+    struct Gain(f64);
+
+    impl Effect for Gain {
+        fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+            (input.0 * self.0, input.1 * self.0)
+        }
+
+        fn reset(&mut self) {}
+
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+    }
+
+    /// One-sample delay
+    struct Delay1 {
+        last: (f64, f64),
+    }
+
+    impl Effect for Delay1 {
+        fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+            std::mem::replace(&mut self.last, input)
+        }
+
+        fn reset(&mut self) {
+            self.last = (0.0, 0.0);
+        }
+
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+    }
+
+    fn run(effects: &mut [Box<dyn Effect>], input: (f64, f64)) -> (f64, f64) {
+        effects.iter_mut().fold(input, |frame, effect| effect.tick(frame))
+    }
+
+    #[test]
+    fn test_effects_compose() {
+        let mut effects: Vec<Box<dyn Effect>> = vec![
+            Box::new(Gain(2.0)),
+            Box::new(Delay1 { last: (0.0, 0.0) }),
+        ];
+
+        assert_eq!(run(&mut effects, (0.25, -0.5)), (0.0, 0.0));
+        assert_eq!(run(&mut effects, (0.0, 0.0)), (0.5, -1.0));
+
+        // Reset through the trait clears the delay state
+        run(&mut effects, (0.25, 0.25));
+        for effect in effects.iter_mut() {
+            effect.reset();
+        }
+        assert_eq!(run(&mut effects, (0.0, 0.0)), (0.0, 0.0));
+    }
+}
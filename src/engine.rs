@@ -0,0 +1,882 @@
+//! Effect engine
+//!
+//! `Engine` is the part of the program another frontend can reuse: it opens the
+//! audio devices, builds the effect chain at the device rate and runs it between
+//! the input and output streams, with the chain, meters and running flag shared
+//! for control threads. The command line program is one frontend over it; a GUI
+//! can drive the same engine through `set_parameter` and the accessors, or with
+//! the console's text commands through `apply_command`.
+//!
+//! An engine made with `Engine::offline` has no devices: it is driven by calling
+//! `process` with blocks of frames, for rendering files and for tests.
+//!
+//! The input callback picks the input channel and pushes mono samples into a
+//! ring buffer; the output callback pops them, runs the chain in fixed-size
+//! blocks and writes the stereo result through the output channel map. Starting
+//! makes a fresh ring buffer and streams, so an engine can be stopped and started
+//! again.
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    SampleFormat,
+};
+use ringbuf::{Consumer, Producer, RingBuffer};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+use crate::adt::Adt;
+use crate::autopan::AutoPan;
+use crate::block::BlockAdapter;
+use crate::boost::Boost;
+use crate::calibration::CalibrationTarget;
+use crate::chain::EffectChain;
+use crate::clip::{buffer_clips, sample_clips};
+use crate::config::DEFAULT_RING_SIZE;
+use crate::console::Console;
+use crate::convert::{ConvertSample, OutputDither};
+use crate::delay::Delay;
+use crate::denoise::Denoiser;
+use crate::distortion::{Distortion, DistortionType};
+use crate::error::Error;
+use crate::exciter::Exciter;
+use crate::gate::EnvelopeFollower;
+use crate::host::AudioHost;
+use crate::input_channel::InputChannel;
+use crate::insert::{InsertEnds, InsertLoop};
+use crate::ipc::status;
+use crate::meter::{self, Meters};
+use crate::midi::MidiMap;
+use crate::monitor;
+use crate::multiband::MultibandDistortion;
+use crate::offline;
+use crate::output_map::{self, OutputMap};
+use crate::preset::{self, Value};
+use crate::reverb::Reverb;
+use crate::reverse::ReverseDelay;
+use crate::safety::{FeedbackCeiling, RunawayDetector};
+use crate::selftest::SelfTestProbe;
+use crate::shutdown;
+use crate::stereo::StereoWidth;
+use crate::stutter::Stutter;
+use crate::swell::Swell;
+use crate::tremolo::Tremolo;
+use crate::trim::PadSwitch;
+use crate::warmup::{SafeStart, Warmup, DEFAULT_PRIME_FILL};
+
+/// How to open the audio devices and run the streams
+#[derive(Debug, Clone, Default)]
+pub struct EngineOptions {
+    /// Audio host (the platform default unless named)
+    pub host: AudioHost,
+    /// Input device name (None = the host's default)
+    pub input_device: Option<String>,
+    /// Output device name (None = the host's default)
+    pub output_device: Option<String>,
+    /// Ring buffer size in samples (0 = the default)
+    pub ring_size: usize,
+    /// Share of the ring buffer the input fills before the output unmutes (None = the default)
+    pub prime_fill: Option<f64>,
+    /// Fixed device buffer size in frames (None = device default)
+    pub buffer_frames: Option<u32>,
+    /// Which input channel to process, or how to mix them
+    pub input_channel: InputChannel,
+    /// Where the stereo output goes on devices with more channels
+    pub output_map: OutputMap,
+    /// Frames the chain processes at a time (0 or 1 = every frame)
+    pub block_size: usize,
+}
+
+/// The opened devices and their streams
+struct AudioIo {
+    /// Device names as the host reports them
+    input_name: String,
+    output_name: String,
+    input_device: cpal::Device,
+    input_config: cpal::SupportedStreamConfig,
+    output_device: cpal::Device,
+    output_config: cpal::SupportedStreamConfig,
+    options: EngineOptions,
+    /// Playing streams (empty while stopped)
+    streams: Vec<cpal::Stream>,
+}
+
+/// The effect chain running between the audio devices, or offline
+pub struct Engine {
+    /// The chain, shared with control threads
+    chain: Arc<Mutex<EffectChain>>,
+    /// Levels and clip indicators set by the audio callbacks
+    meters: Arc<Meters>,
+    /// Whether the engine is running; the callbacks go quiet when it is cleared
+    running: Arc<AtomicBool>,
+    /// Set by the output callback once the ring buffer has filled enough to unmute
+    primed: Arc<AtomicBool>,
+    /// Sample rate the chain runs at
+    sample_rate: usize,
+    /// Devices and streams (None = offline)
+    audio: Option<AudioIo>,
+    /// Input pad, switched by the consoles and applied by the input callback
+    input_pad: PadSwitch,
+    /// Whether the processed signal reaches the output (on at startup)
+    monitor: Arc<AtomicBool>,
+    /// Whether integer output is dithered and noise shaped, read when the streams are built
+    dither: Arc<AtomicBool>,
+    /// Feedback ceiling of the delay and reverb
+    max_feedback: FeedbackCeiling,
+    /// Whether the chain flushes denormals (see `denormal`)
+    flush_denormals: Arc<AtomicBool>,
+    /// Calibration file and devices `calibrate save` writes to (unset offline)
+    calibration: Arc<OnceLock<CalibrationTarget>>,
+    /// Runs the commands passed to `apply_command`
+    console: Console,
+}
+
+impl Engine {
+    /// Open the audio devices and build the chain at the output device's rate
+    ///
+    /// Nothing is heard until `start`, so the chain can be set up first.
+    pub fn open(options: EngineOptions) -> Result<Self, Error> {
+        // Get the selected host (the platform default unless another is named)
+        let host = options.host.open()?;
+        if options.host != AudioHost::Default {
+            status!("Audio host: {}", options.host);
+        }
+
+        // Get the configured input and output devices, or the defaults
+        let input_device = match &options.input_device {
+            Some(name) => find_device(host.input_devices()?, name)?,
+            None => host.default_input_device().ok_or_else(|| Error::NoDefaultDevice(options.host.no_device_error("input")))?,
+        };
+        let output_device = match &options.output_device {
+            Some(name) => find_device(host.output_devices()?, name)?,
+            None => host.default_output_device().ok_or_else(|| Error::NoDefaultDevice(options.host.no_device_error("output")))?,
+        };
+
+        let input_name = input_device.name()?;
+        let output_name = output_device.name()?;
+        status!("Input device: {}", input_name);
+        status!("Output device: {}", output_name);
+
+        // Get the default input and output configs, preferring f32 streams
+        let input_config = negotiate_f32_config(
+            input_device.supported_input_configs()?,
+            input_device.default_input_config()?,
+        );
+        let output_config = negotiate_f32_config(
+            output_device.supported_output_configs()?,
+            output_device.default_output_config()?,
+        );
+
+        status!("Input config: {:?}", input_config);
+        status!("Output config: {:?}", output_config);
+
+        // Print detailed device configuration
+        print_device_config(&input_device, &output_device, &input_config, &output_config)?;
+        if output_config.channels() > 2 {
+            options.output_map.validate(output_config.channels() as usize).map_err(Error::Config)?;
+            status!("Output channel map: {}", options.output_map);
+        }
+        if input_config.channels() > 1 {
+            status!("Input channel: {}", options.input_channel);
+        }
+        if options.block_size > 1 {
+            status!("Processing block: {} frames", options.block_size);
+        }
+
+        let sample_rate = output_config.sample_rate().0 as usize;
+        let mut engine = Self::offline(sample_rate)?;
+        engine.audio = Some(AudioIo {
+            input_name,
+            output_name,
+            input_device,
+            input_config,
+            output_device,
+            output_config,
+            options,
+            streams: Vec::new(),
+        });
+        Ok(engine)
+    }
+
+    /// Build the chain without any devices, to be driven through `process`
+    pub fn offline(sample_rate: usize) -> Result<Self, Error> {
+        let max_feedback = FeedbackCeiling::default();
+        let flush_denormals = Arc::new(AtomicBool::new(true));
+        let mut chain = build_chain(sample_rate, &max_feedback)?;
+        chain.set_denormal_switch(flush_denormals.clone());
+        let chain = Arc::new(Mutex::new(chain));
+        let meters = Arc::new(Meters::new());
+        let running = Arc::new(AtomicBool::new(false));
+        let input_pad = PadSwitch::default();
+        let monitor = Arc::new(AtomicBool::new(true));
+        let calibration = Arc::new(OnceLock::new());
+        Ok(Self {
+            console: Console::new(
+                chain.clone(),
+                running.clone(),
+                meters.clone(),
+                None,
+                input_pad.clone(),
+                monitor.clone(),
+                calibration.clone(),
+            ),
+            chain,
+            meters,
+            running,
+            primed: Arc::new(AtomicBool::new(false)),
+            sample_rate,
+            audio: None,
+            input_pad,
+            monitor,
+            dither: Arc::new(AtomicBool::new(false)),
+            max_feedback,
+            flush_denormals,
+            calibration,
+        })
+    }
+
+    /// Start processing: build and play the streams
+    pub fn start(&mut self) -> Result<(), Error> {
+        self.start_with_probe(None)
+    }
+
+    /// Start processing, with the self-test's probe replacing the input by its
+    /// test tone and watching what arrives at the output
+    pub fn start_with_probe(&mut self, probe: Option<Arc<SelfTestProbe>>) -> Result<(), Error> {
+        self.running.store(true, Ordering::Relaxed);
+        let Some(audio) = &mut self.audio else {
+            return Ok(());
+        };
+        if !audio.streams.is_empty() {
+            return Ok(());
+        }
+
+        // A fresh ring buffer, so a restart doesn't play what was left in the old one
+        let ring_size = match audio.options.ring_size {
+            0 => DEFAULT_RING_SIZE,
+            size => size,
+        };
+        let (producer, consumer) = RingBuffer::<f32>::new(ring_size).split();
+        // Muted until the input has filled the new buffer to the threshold
+        let prime_fill = audio.options.prime_fill.unwrap_or(DEFAULT_PRIME_FILL);
+        let safe_start = SafeStart::new(prime_fill, ring_size, self.primed.clone());
+        let buffer_size = match audio.options.buffer_frames {
+            Some(frames) => cpal::BufferSize::Fixed(frames),
+            None => cpal::BufferSize::Default,
+        };
+
+        let input_stream = build_input_stream(
+            &audio.input_device,
+            &audio.input_config,
+            &buffer_size,
+            producer,
+            audio.options.input_channel,
+            self.input_pad.clone(),
+            self.meters.clone(),
+            self.running.clone(),
+            probe.clone(),
+        )?;
+        let output_stream = build_output_stream(
+            &audio.output_device,
+            &audio.output_config,
+            &buffer_size,
+            consumer,
+            safe_start,
+            self.chain.clone(),
+            audio.options.output_map.clone(),
+            audio.options.block_size.max(1),
+            self.monitor.clone(),
+            self.dither.clone(),
+            self.meters.clone(),
+            self.running.clone(),
+            probe,
+        )?;
+        input_stream.play()?;
+        output_stream.play()?;
+        audio.streams = vec![input_stream, output_stream];
+        Ok(())
+    }
+
+    /// Stop processing: the callbacks go quiet and the streams are stopped and dropped
+    pub fn stop(&mut self) -> Result<(), Error> {
+        let streams = self.audio.as_mut().map(|audio| std::mem::take(&mut audio.streams)).unwrap_or_default();
+        if streams.is_empty() {
+            self.running.store(false, Ordering::Relaxed);
+            return Ok(());
+        }
+        shutdown::shutdown(&self.running, streams, Vec::new())?;
+        Ok(())
+    }
+
+    /// Change a setting, addressed like a preset entry (e.g. `mix`, `verb`)
+    pub fn set_parameter(&self, section: &str, key: &str, value: Value) -> Result<(), Error> {
+        let mut chain = self.chain.lock().unwrap();
+        preset::apply_value(&mut chain, section, key, value).map_err(Error::Config)
+    }
+
+    /// Run a console command (e.g. `w 0.3`, `on delay`, `load crunch`), printing
+    /// the result as the console would
+    ///
+    /// Tap tempo, footswitches and morph slots carry over between calls.
+    pub fn apply_command(&mut self, input: &str) {
+        self.console.run(input.trim());
+    }
+
+    /// A console over this engine with its own tap tempo, footswitches and
+    /// history, for a control thread (`midi` = the CC bindings `midi learn` edits)
+    pub fn console(&self, midi: Option<Arc<Mutex<MidiMap>>>) -> Console {
+        Console::new(
+            self.chain.clone(),
+            self.running.clone(),
+            self.meters.clone(),
+            midi,
+            self.input_pad.clone(),
+            self.monitor.clone(),
+            self.calibration.clone(),
+        )
+    }
+
+    /// Switch a chain stage on or off
+    pub fn set_enabled(&self, stage: &str, enabled: bool) -> Result<(), Error> {
+        self.chain.lock().unwrap().set_enabled(stage, enabled).map_err(Error::Chain)
+    }
+
+    /// Run a block of frames through the chain in place, as the output callback
+    /// would (for offline use; a started engine with devices is fed by its streams)
+    pub fn process(&self, block: &mut [(f64, f64)]) {
+        self.chain.lock().unwrap().process_block(block);
+    }
+
+    /// Run mono samples through the chain and return the interleaved stereo output
+    /// (for offline use, see `offline::process_buffer`)
+    pub fn process_buffer(&self, input: &[f32]) -> Vec<f32> {
+        offline::process_buffer(input, &mut self.chain.lock().unwrap())
+    }
+
+    /// The effect chain, for control threads and direct access to the effects
+    pub fn chain(&self) -> &Arc<Mutex<EffectChain>> {
+        &self.chain
+    }
+
+    /// Levels and clip indicators set by the audio callbacks
+    pub fn meters(&self) -> &Arc<Meters> {
+        &self.meters
+    }
+
+    /// The running flag: clearing it makes the callbacks go quiet and tells
+    /// control threads to finish
+    pub fn running(&self) -> &Arc<AtomicBool> {
+        &self.running
+    }
+
+    /// Whether the engine is running
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// Whether the output has unmuted after the input filled the ring buffer
+    /// (always true offline)
+    pub fn is_primed(&self) -> bool {
+        self.audio.is_none() || self.primed.load(Ordering::Relaxed)
+    }
+
+    /// Names of the input and output devices (None = offline)
+    pub fn device_names(&self) -> Option<(&str, &str)> {
+        self.audio.as_ref().map(|audio| (audio.input_name.as_str(), audio.output_name.as_str()))
+    }
+
+    /// Sample rate the chain runs at
+    pub fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    /// The input pad the input callback applies, shared with the consoles
+    pub fn input_pad(&self) -> &PadSwitch {
+        &self.input_pad
+    }
+
+    /// Set the feedback ceiling (0.0 to 0.99), limiting the chain's current
+    /// delay feedback and reverb room size to it as well as anything set later
+    pub fn set_max_feedback(&self, ceiling: f64) {
+        self.max_feedback.set(ceiling);
+        let mut chain = self.chain.lock().unwrap();
+        if let Some(delay) = chain.effect_mut::<Delay>() {
+            delay.set_feedback(delay.feedback());
+        }
+        if let Some(reverb) = chain.effect_mut::<Reverb>() {
+            reverb.set_room_size(reverb.room_size());
+        }
+    }
+
+    /// Switch denormal flushing in the chain on or off
+    pub fn set_flush_denormals(&self, enabled: bool) {
+        self.flush_denormals.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Dither and noise shape integer output (takes effect when the streams are
+    /// next built, at `start`)
+    pub fn set_dither(&self, enabled: bool) {
+        self.dither.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Save the input pad and output trim to the calibration file at `path` on
+    /// `calibrate save` (no-op offline, or once set)
+    pub fn set_calibration_file(&self, path: &str) {
+        if let Some((input_name, output_name)) = self.device_names() {
+            let _ = self.calibration.set(CalibrationTarget::new(path, input_name, output_name));
+        }
+    }
+}
+
+/// Create the effects with their default settings and build the chain
+pub fn build_chain(sample_rate: usize, max_feedback: &FeedbackCeiling) -> Result<EffectChain, Error> {
+    let mut reverb = Reverb::new(sample_rate);
+    let mut delay = Delay::new(sample_rate);
+    let mut distortion = Distortion::new(sample_rate);
+    reverb.set_feedback_ceiling(max_feedback.clone());
+    delay.set_feedback_ceiling(max_feedback.clone());
+    
+    // Configure reverb settings (the reverb is fully wet; the chain mix blends in the dry signal)
+    reverb.set_room_size(0.5); // Medium room
+    reverb.set_dampening(0.5); // Moderate dampening
+    reverb.set_width(0.5);    // Stereo width
+
+    // Configure distortion settings
+    distortion.set_distortion_type(DistortionType::Soft);
+    distortion.set_drive(0.5);
+    distortion.set_level(0.8);
+    distortion.set_tone(0.5);
+    distortion.set_bit_crusher_params(0.1, 0.5);
+
+    // Build the effect chain: noise reduction first, then the volume swell (so it
+    // sees clean pick attacks, ahead of anything that compresses them), distortion
+    // and multiband distortion and the exciter into the stutter, delay and reverb
+    // (so repeats get their echoes and tails), with the tremolo and the reverse
+    // delay ahead of the delay so the echoes keep their pulse and swells, then the
+    // double tracker (late, so the double carries the whole processed sound), the
+    // stereo width and auto-pan, with the clean boost last so it never drives a
+    // saturating stage. Start with only the reverb active, and the stutter passing
+    // through until triggered.
+    let mut chain = EffectChain::new(sample_rate);
+    chain.add("nr", Box::new(Denoiser::new()));
+    chain.add("swell", Box::new(Swell::new(sample_rate)));
+    chain.add("dist", Box::new(distortion));
+    chain.add("multi", Box::new(MultibandDistortion::new(sample_rate)));
+    chain.add("excite", Box::new(Exciter::new(sample_rate)));
+    chain.add("stutter", Box::new(Stutter::new(sample_rate)));
+    chain.add("trem", Box::new(Tremolo::new(sample_rate)));
+    chain.add("reverse", Box::new(ReverseDelay::new(sample_rate)));
+    chain.add("delay", Box::new(delay));
+    chain.add("verb", Box::new(reverb));
+    chain.add("adt", Box::new(Adt::new(sample_rate)));
+    chain.add("width", Box::new(StereoWidth::new(sample_rate)));
+    chain.add("pan", Box::new(AutoPan::new(sample_rate)));
+    chain.add("boost", Box::new(Boost::new()));
+    chain.set_enabled("nr", false).map_err(Error::Chain)?;
+    chain.set_enabled("swell", false).map_err(Error::Chain)?;
+    chain.set_enabled("dist", false).map_err(Error::Chain)?;
+    chain.set_enabled("multi", false).map_err(Error::Chain)?;
+    chain.set_enabled("excite", false).map_err(Error::Chain)?;
+    chain.set_enabled("trem", false).map_err(Error::Chain)?;
+    chain.set_enabled("reverse", false).map_err(Error::Chain)?;
+    chain.set_enabled("delay", false).map_err(Error::Chain)?;
+    chain.set_enabled("adt", false).map_err(Error::Chain)?;
+    chain.set_enabled("pan", false).map_err(Error::Chain)?;
+    chain.set_enabled("boost", false).map_err(Error::Chain)?;
+
+    // Start with mostly dry signal: 10% reverb tail (start conservative)
+    chain.set_mix("verb", 0.1).map_err(Error::Chain)?;
+
+    Ok(chain)
+}
+
+/// Add the effects loop stage, after the distortion stages and before the delay
+/// like the loop on an amp, and return the ends for its bridge threads
+pub fn add_insert_loop(chain: &mut EffectChain, capacity: usize) -> Result<InsertEnds, Error> {
+    let (insert, ends) = InsertLoop::new(capacity);
+    chain.add("loop", Box::new(insert));
+    let mut order = chain.stage_names();
+    order.retain(|name| *name != "loop");
+    let position = order.iter().position(|name| *name == "delay").unwrap_or(order.len());
+    order.insert(position, "loop");
+    chain.set_order(&order).map_err(Error::Chain)?;
+    Ok(ends)
+}
+
+// Print detailed device configuration
+fn print_device_config(input_device: &cpal::Device, output_device: &cpal::Device, 
+                      input_config: &cpal::SupportedStreamConfig, 
+                      output_config: &cpal::SupportedStreamConfig) -> Result<(), Error> {
+    status!("\n=== Detailed Device Configuration ===");
+    status!("Input Device: {}", input_device.name()?);
+    status!("  Sample Rate: {} Hz", input_config.sample_rate().0);
+    status!("  Channels: {}", input_config.channels());
+    status!("  Sample Format: {:?}", input_config.sample_format());
+    status!("  Buffer Size: {:?}", input_config.buffer_size());
+    
+    status!("\nOutput Device: {}", output_device.name()?);
+    status!("  Sample Rate: {} Hz", output_config.sample_rate().0);
+    status!("  Channels: {}", output_config.channels());
+    status!("  Sample Format: {:?}", output_config.sample_format());
+    status!("  Buffer Size: {:?}", output_config.buffer_size());
+    
+    // Check for potential issues
+    if input_config.sample_rate() != output_config.sample_rate() {
+        status!("\n⚠️  WARNING: Sample rate mismatch!");
+        status!("   Input: {} Hz, Output: {} Hz", 
+                input_config.sample_rate().0, output_config.sample_rate().0);
+    }
+    
+    if input_config.channels() != output_config.channels() {
+        status!("\n⚠️  WARNING: Channel count mismatch!");
+        status!("   Input: {} channels, Output: {} channels", 
+                input_config.channels(), output_config.channels());
+    }
+    
+    if input_config.sample_format() != output_config.sample_format() {
+        status!("\n⚠️  WARNING: Sample format mismatch!");
+        status!("   Input: {:?}, Output: {:?}", 
+                input_config.sample_format(), output_config.sample_format());
+    }
+    
+    status!("=====================================\n");
+    Ok(())
+}
+
+// Find a device by name
+fn find_device(
+    mut devices: impl Iterator<Item = cpal::Device>,
+    name: &str,
+) -> Result<cpal::Device, Error> {
+    devices
+        .find(|device| device.name().is_ok_and(|device_name| device_name == name))
+        .ok_or_else(|| Error::DeviceNotFound(name.to_string()))
+}
+
+// Prefer an f32 stream at the default rate and channel count so no conversion is needed.
+// Falls back to the default config (and the i16/u16 conversion path) otherwise.
+fn negotiate_f32_config(
+    mut supported: impl Iterator<Item = cpal::SupportedStreamConfigRange>,
+    default_config: cpal::SupportedStreamConfig,
+) -> cpal::SupportedStreamConfig {
+    if default_config.sample_format() == SampleFormat::F32 {
+        return default_config;
+    }
+
+    let sample_rate = default_config.sample_rate();
+    supported
+        .find(|range| {
+            range.sample_format() == SampleFormat::F32
+                && range.channels() == default_config.channels()
+                && range.min_sample_rate() <= sample_rate
+                && sample_rate <= range.max_sample_rate()
+        })
+        .map(|range| range.with_sample_rate(sample_rate))
+        .unwrap_or(default_config)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_input_stream(
+    device: &cpal::Device,
+    config: &cpal::SupportedStreamConfig,
+    buffer_size: &cpal::BufferSize,
+    producer: Producer<f32>,
+    input_channel: InputChannel,
+    input_pad: PadSwitch,
+    meters: Arc<Meters>,
+    running: Arc<AtomicBool>,
+    probe: Option<Arc<SelfTestProbe>>,
+) -> Result<cpal::Stream, Error> {
+    let mut stream_config: cpal::StreamConfig = config.clone().into();
+    stream_config.buffer_size = buffer_size.clone();
+
+    match config.sample_format() {
+        SampleFormat::F32 => build_input_stream_for::<f32>(device, &stream_config, producer, input_channel, input_pad, meters, running, probe),
+        SampleFormat::I16 => build_input_stream_for::<i16>(device, &stream_config, producer, input_channel, input_pad, meters, running, probe),
+        SampleFormat::U16 => build_input_stream_for::<u16>(device, &stream_config, producer, input_channel, input_pad, meters, running, probe),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_input_stream_for<T: cpal::Sample + ConvertSample>(
+    device: &cpal::Device,
+    stream_config: &cpal::StreamConfig,
+    mut producer: Producer<f32>,
+    input_channel: InputChannel,
+    input_pad: PadSwitch,
+    meters: Arc<Meters>,
+    running: Arc<AtomicBool>,
+    probe: Option<Arc<SelfTestProbe>>,
+) -> Result<cpal::Stream, Error> {
+    let err_fn = |err| eprintln!("Input stream error: {}", err);
+    
+    // Capture channel count and sample rate for the callback
+    let input_channels = stream_config.channels as usize;
+    let sample_rate = stream_config.sample_rate.0 as f64;
+    let (attack_ms, release_ms) = meters.envelope_times();
+    let mut envelope = EnvelopeFollower::new(sample_rate as usize, f64::from(attack_ms), f64::from(release_ms));
+
+    let stream = device.build_input_stream(
+        stream_config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            if !running.load(Ordering::Relaxed) {
+                return;
+            }
+
+            if buffer_clips(data) {
+                meters.clip.flag_input();
+            }
+
+            // The self-test replaces the input with its test tone
+            let tone_start = probe.as_ref().map(|probe| probe.input_callback());
+            let mut sent = 0;
+
+            let pad_gain = input_pad.gain();
+            let (attack_ms, release_ms) = meters.envelope_times();
+            envelope.set_attack_ms(f64::from(attack_ms));
+            envelope.set_release_ms(f64::from(release_ms));
+            let mut peak = 0.0_f32;
+            for frame in data.chunks(input_channels) {
+                // Convert to f32, pick or average the channels down to mono and pad
+                let mut sample = input_channel.select(frame, |sample| f64::from(sample.to_f32())) as f32 * pad_gain;
+                if let Some(start) = tone_start {
+                    sample = SelfTestProbe::tone_sample(start + sent, sample_rate);
+                }
+                peak = peak.max(sample.abs());
+                envelope.process(f64::from(sample));
+
+                if producer.push(sample).is_err() {
+                    // Buffer is full, skip the rest of this callback
+                    break;
+                }
+                sent += 1;
+            }
+            meters.record_input_peak(peak);
+            meters.record_input_envelope(envelope.envelope() as f32);
+            if let Some(probe) = &probe {
+                probe.record_sent(sent);
+            }
+        },
+        err_fn,
+    )
+    .map_err(|e| Error::StreamBuild("input", e))?;
+
+    Ok(stream)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_output_stream(
+    device: &cpal::Device,
+    config: &cpal::SupportedStreamConfig,
+    buffer_size: &cpal::BufferSize,
+    consumer: Consumer<f32>,
+    safe_start: SafeStart,
+    chain: Arc<Mutex<EffectChain>>,
+    output_map: OutputMap,
+    block_size: usize,
+    monitor: Arc<AtomicBool>,
+    dither: Arc<AtomicBool>,
+    meters: Arc<Meters>,
+    running: Arc<AtomicBool>,
+    probe: Option<Arc<SelfTestProbe>>,
+) -> Result<cpal::Stream, Error> {
+    let mut stream_config: cpal::StreamConfig = config.clone().into();
+    stream_config.buffer_size = buffer_size.clone();
+
+    match config.sample_format() {
+        SampleFormat::F32 => build_output_stream_for::<f32>(device, &stream_config, consumer, safe_start, chain, output_map, block_size, monitor, dither, meters, running, probe),
+        SampleFormat::I16 => build_output_stream_for::<i16>(device, &stream_config, consumer, safe_start, chain, output_map, block_size, monitor, dither, meters, running, probe),
+        SampleFormat::U16 => build_output_stream_for::<u16>(device, &stream_config, consumer, safe_start, chain, output_map, block_size, monitor, dither, meters, running, probe),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_output_stream_for<T: cpal::Sample + ConvertSample>(
+    device: &cpal::Device,
+    stream_config: &cpal::StreamConfig,
+    mut consumer: Consumer<f32>,
+    mut safe_start: SafeStart,
+    chain: Arc<Mutex<EffectChain>>,
+    output_map: OutputMap,
+    block_size: usize,
+    monitor: Arc<AtomicBool>,
+    dither: Arc<AtomicBool>,
+    meters: Arc<Meters>,
+    running: Arc<AtomicBool>,
+    probe: Option<Arc<SelfTestProbe>>,
+) -> Result<cpal::Stream, Error> {
+    let err_fn = |err| eprintln!("Output stream error: {}", err);
+    
+    // Capture channel count for the callback
+    let output_channels = stream_config.channels as usize;
+    let mut runaway = RunawayDetector::new(stream_config.sample_rate.0 as usize);
+    let sample_rate = stream_config.sample_rate.0;
+    // Fade in from silence so the stream doesn't start with a pop
+    let mut warmup = Warmup::new(sample_rate);
+    let mut dither = (dither.load(Ordering::Relaxed) && T::LSB > 0.0).then(|| OutputDither::new(output_channels));
+    // Gains of the left and right signal for each output channel
+    let upmix_matrix = output_map.matrix(output_channels);
+    // Run the chain in fixed-size blocks whatever the device's buffer length
+    let mut blocks = BlockAdapter::new(block_size);
+
+    let stream = device.build_output_stream(
+        stream_config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            if !running.load(Ordering::Relaxed) {
+                return;
+            }
+            // Stay muted, leaving the samples where they are, until the input has
+            // filled the ring buffer far enough
+            if !safe_start.ready(consumer.len()) {
+                data.fill(T::from_f32(0.0));
+                return;
+            }
+            let started = Instant::now();
+
+            // Hold the chain for the whole buffer rather than locking per frame
+            let mut chain_guard = chain.lock().unwrap();
+            let mut clipped = false;
+            let mut peak = 0.0_f64;
+            let mut underruns = 0;
+            let mut received_peak = 0.0_f32;
+
+            for frame in data.chunks_mut(output_channels) {
+                // Get input sample from ring buffer
+                let input_sample = consumer.pop().unwrap_or_else(|| {
+                    underruns += 1;
+                    0.0
+                });
+                received_peak = received_peak.max(input_sample.abs());
+                
+                // Run the effect chain
+                let input = (input_sample as f64, input_sample as f64);
+                let (left, right) = blocks.process(input, |block| chain_guard.process_block(block));
+                let gain = warmup.next_gain();
+                let (left, right) = (left * gain, right * gain);
+                if runaway.process((left, right)) {
+                    // Runaway feedback: mute and bypass before it reaches the speakers
+                    chain_guard.panic();
+                    blocks.silence();
+                    meters.flag_runaway();
+                }
+                let (left, right) = monitor::monitored((left, right), monitor.load(Ordering::Relaxed));
+                clipped |= sample_clips(left) || sample_clips(right);
+                peak = peak.max(left.abs()).max(right.abs());
+                
+                // Convert to the device format, dithered if enabled
+                let mut convert = |channel: usize, sample: f32| match &mut dither {
+                    Some(dither) => dither.quantize::<T>(channel, sample),
+                    None => T::from_f32(sample),
+                };
+
+                // Fill output frame based on channel configuration
+                match output_channels {
+                    1 => {
+                        // Mono output - mix stereo to mono
+                        frame[0] = convert(0, (left + right) as f32 * 0.5);
+                    }
+                    2 => {
+                        // Stereo output
+                        frame[0] = convert(0, left as f32);
+                        frame[1] = convert(1, right as f32);
+                    }
+                    _ => {
+                        // Multi-channel output - upmix the stereo pair through the output map
+                        for (i, (sample, row)) in frame.iter_mut().zip(&upmix_matrix).enumerate() {
+                            *sample = convert(i, output_map::upmix(*row, left as f32, right as f32));
+                        }
+                    }
+                }
+            }
+
+            if clipped {
+                meters.clip.flag_output();
+            }
+            meters.record_output_peak(peak as f32);
+            if underruns > 0 {
+                meters.add_underruns(underruns);
+            }
+            let frames = data.len() / output_channels;
+            if let Some(probe) = &probe {
+                probe.record_received(frames as u64 - underruns, received_peak);
+            }
+            meters.record_load(meter::load_percent(started.elapsed(), frames, sample_rate));
+        },
+        err_fn,
+    )
+    .map_err(|e| Error::StreamBuild("output", e))?;
+
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trim::InputPad;
+
+    /// Peak level of a 1 kHz tone run through the engine, over the second half of the block
+    fn tone_peak(engine: &Engine) -> f64 {
+        let mut block: Vec<(f64, f64)> = (0..4096)
+            .map(|i| {
+                let x = 0.25 * (2.0 * std::f64::consts::PI * 1000.0 * i as f64 / 44100.0).sin();
+                (x, x)
+            })
+            .collect();
+        engine.process(&mut block);
+        block[2048..].iter().map(|(left, _)| left.abs()).fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn test_offline_engine_follows_set_parameter() {
+        let mut engine = Engine::offline(44100).unwrap();
+        assert_eq!(engine.sample_rate(), 44100);
+        engine.start().unwrap();
+        assert!(engine.is_running());
+
+        // Switch stages instantly, and take the default reverb out
+        engine.set_parameter("chain", "switch_fade_ms", Value::Number(0.0)).unwrap();
+        engine.set_enabled("verb", false).unwrap();
+        let dry = tone_peak(&engine);
+        assert!((dry - 0.25).abs() < 0.01, "dry peak {}", dry);
+
+        // A boost set through the engine is heard in the next block
+        engine.set_enabled("boost", true).unwrap();
+        engine.set_parameter("boost", "gain_db", Value::Number(6.0)).unwrap();
+        let boosted = tone_peak(&engine);
+        assert!((boosted / dry - 2.0).abs() < 0.02, "boosted peak {} from {}", boosted, dry);
+        assert_eq!(engine.chain().lock().unwrap().boost_mut().unwrap().gain_db(), 6.0);
+
+        // Unknown settings and stages are reported, not ignored
+        assert!(matches!(engine.set_parameter("boost", "volume", Value::Number(1.0)), Err(Error::Config(_))));
+        assert!(matches!(engine.set_enabled("wah", true), Err(Error::Chain(_))));
+
+        engine.stop().unwrap();
+        assert!(!engine.is_running());
+    }
+
+    #[test]
+    fn test_engines_keep_their_own_pad_and_feedback_ceiling() {
+        let mut engine = Engine::offline(44100).unwrap();
+        let other = Engine::offline(44100).unwrap();
+        let delay_feedback = |engine: &Engine, feedback: Option<f64>| {
+            let mut chain = engine.chain().lock().unwrap();
+            let delay = chain.effect_mut::<Delay>().unwrap();
+            if let Some(feedback) = feedback {
+                delay.set_feedback(feedback);
+            }
+            delay.feedback()
+        };
+
+        // The ceiling limits the feedback already set and anything set later
+        delay_feedback(&engine, Some(0.9));
+        engine.set_max_feedback(0.5);
+        assert_eq!(delay_feedback(&engine, None), 0.5);
+        assert_eq!(delay_feedback(&engine, Some(0.8)), 0.5);
+        assert_eq!(delay_feedback(&other, Some(0.8)), 0.8);
+
+        // The console switches its own engine's pad
+        engine.apply_command("pad-10");
+        assert_eq!(engine.input_pad().pad(), InputPad::Minus10);
+        assert_eq!(other.input_pad().pad(), InputPad::Off);
+    }
+}
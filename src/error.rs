@@ -0,0 +1,77 @@
+//! Error type for the engine and the option helpers
+//!
+//! Failures callers may want to tell apart get their own variant: a missing
+//! device, a sample format the stream callbacks or the WAV writer can't handle,
+//! a stream the audio host refused to build or start, and an invalid command
+//! line option or config file setting. Option and setting errors are plain
+//! messages, so a `String` or `&str` error converts into `Error::Config` with `?`.
+
+use thiserror::Error;
+
+/// Errors from opening the devices, building the streams, controlling the engine
+/// and reading the options
+#[derive(Debug, Error)]
+pub enum Error {
+    /// A device named on the command line or in the config file isn't there
+    #[error("Device '{0}' not found")]
+    DeviceNotFound(String),
+    /// The host has no default device for a direction; the message says which
+    #[error("{0}")]
+    NoDefaultDevice(String),
+    /// The host couldn't list its devices
+    #[error("Couldn't list the audio devices: {0}")]
+    Devices(#[from] cpal::DevicesError),
+    /// A device couldn't report its name
+    #[error("Couldn't read the device name: {0}")]
+    DeviceName(#[from] cpal::DeviceNameError),
+    /// A device couldn't report its default stream config
+    #[error("Couldn't read the device's default config: {0}")]
+    DefaultConfig(#[from] cpal::DefaultStreamConfigError),
+    /// A device couldn't list the stream configs it supports
+    #[error("Couldn't read the device's supported configs: {0}")]
+    SupportedConfigs(#[from] cpal::SupportedStreamConfigsError),
+    /// A sample format no conversion path handles
+    #[error("Unsupported sample format: {0}")]
+    UnsupportedFormat(String),
+    /// The audio host refused to build the input or output stream
+    #[error("Couldn't build the {0} stream: {1}")]
+    StreamBuild(&'static str, #[source] cpal::BuildStreamError),
+    /// A built stream failed to start
+    #[error("Couldn't start the stream: {0}")]
+    StreamStart(#[from] cpal::PlayStreamError),
+    /// Invalid command line option or config file setting
+    #[error("{0}")]
+    Config(String),
+    /// A stage the chain setup refers to is missing
+    #[error("Effect chain: {0}")]
+    Chain(String),
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Config(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error::Config(message.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_convert_to_config_errors() {
+        let error: Error = "--keys requires a value (line|raw)".into();
+        assert!(matches!(error, Error::Config(_)));
+        assert_eq!(error.to_string(), "--keys requires a value (line|raw)");
+
+        let error = Error::from(format!("Unknown argument: {}", "--fast"));
+        assert!(matches!(&error, Error::Config(message) if message == "Unknown argument: --fast"));
+
+        assert_eq!(Error::DeviceNotFound("Scarlett 2i2".to_string()).to_string(), "Device 'Scarlett 2i2' not found");
+    }
+}
@@ -0,0 +1,228 @@
+//! Biquad filters
+//!
+//! Second-order IIR filters using the RBJ audio EQ cookbook formulas, shared by
+//! the effects that need tone shaping or band splitting, a Linkwitz-Riley
+//! crossover built from them, and a one-pole DC blocker. Their states are flushed
+//! of denormals as they decay (see `denormal`).
+
+use std::f64::consts::PI;
+
+use crate::denormal;
+
+/// Butterworth Q for second-order sections
+pub const BUTTERWORTH_Q: f64 = std::f64::consts::FRAC_1_SQRT_2;
+
+/// Normalized biquad coefficients (a0 = 1)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BiquadCoeffs {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+}
+
+impl BiquadCoeffs {
+    /// Second-order low-pass
+    pub fn lowpass(sample_rate: f64, freq: f64, q: f64) -> Self {
+        let (cos_w0, alpha) = Self::prewarp(sample_rate, freq, q);
+        let b1 = 1.0 - cos_w0;
+        Self::normalize(b1 * 0.5, b1, b1 * 0.5, 1.0 + alpha, -2.0 * cos_w0, 1.0 - alpha)
+    }
+
+    /// Second-order high-pass
+    pub fn highpass(sample_rate: f64, freq: f64, q: f64) -> Self {
+        let (cos_w0, alpha) = Self::prewarp(sample_rate, freq, q);
+        let b0 = (1.0 + cos_w0) * 0.5;
+        Self::normalize(b0, -(1.0 + cos_w0), b0, 1.0 + alpha, -2.0 * cos_w0, 1.0 - alpha)
+    }
+
+    /// Second-order all-pass (flat magnitude, phase shift around `freq`)
+    pub fn allpass(sample_rate: f64, freq: f64, q: f64) -> Self {
+        let (cos_w0, alpha) = Self::prewarp(sample_rate, freq, q);
+        Self::normalize(1.0 - alpha, -2.0 * cos_w0, 1.0 + alpha, 1.0 + alpha, -2.0 * cos_w0, 1.0 - alpha)
+    }
+
+    /// cos(w0) and alpha for the given frequency, clamped below Nyquist
+    fn prewarp(sample_rate: f64, freq: f64, q: f64) -> (f64, f64) {
+        let freq = freq.clamp(1.0, sample_rate * 0.49);
+        let w0 = 2.0 * PI * freq / sample_rate;
+        (w0.cos(), w0.sin() / (2.0 * q))
+    }
+
+    fn normalize(b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) -> Self {
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+}
+
+/// Mono biquad filter (transposed direct form II)
+#[derive(Debug, Clone)]
+pub struct Biquad {
+    coeffs: BiquadCoeffs,
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    /// Create a filter with the given coefficients
+    pub fn new(coeffs: BiquadCoeffs) -> Self {
+        Self { coeffs, z1: 0.0, z2: 0.0 }
+    }
+
+    /// Change the coefficients, keeping the filter state
+    pub fn set_coeffs(&mut self, coeffs: BiquadCoeffs) {
+        self.coeffs = coeffs;
+    }
+
+    /// Filter one sample
+    pub fn process(&mut self, input: f64) -> f64 {
+        let c = &self.coeffs;
+        let output = c.b0 * input + self.z1;
+        self.z1 = denormal::flush(c.b1 * input - c.a1 * output + self.z2);
+        self.z2 = denormal::flush(c.b2 * input - c.a2 * output);
+        output
+    }
+
+    /// Clear the filter state
+    pub fn reset(&mut self) {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
+}
+
+/// 4th-order Linkwitz-Riley crossover (two cascaded Butterworth sections per side)
+///
+/// The low and high outputs sum to an all-pass response, so splitting and
+/// recombining leaves the magnitude flat.
+pub struct Crossover {
+    lowpass: [Biquad; 2],
+    highpass: [Biquad; 2],
+}
+
+impl Crossover {
+    /// Create a crossover at `freq` Hz
+    pub fn new(sample_rate: f64, freq: f64) -> Self {
+        let lowpass = BiquadCoeffs::lowpass(sample_rate, freq, BUTTERWORTH_Q);
+        let highpass = BiquadCoeffs::highpass(sample_rate, freq, BUTTERWORTH_Q);
+        Self {
+            lowpass: [Biquad::new(lowpass), Biquad::new(lowpass)],
+            highpass: [Biquad::new(highpass), Biquad::new(highpass)],
+        }
+    }
+
+    /// Move the crossover to `freq` Hz
+    pub fn set_frequency(&mut self, sample_rate: f64, freq: f64) {
+        let lowpass = BiquadCoeffs::lowpass(sample_rate, freq, BUTTERWORTH_Q);
+        let highpass = BiquadCoeffs::highpass(sample_rate, freq, BUTTERWORTH_Q);
+        for filter in self.lowpass.iter_mut() {
+            filter.set_coeffs(lowpass);
+        }
+        for filter in self.highpass.iter_mut() {
+            filter.set_coeffs(highpass);
+        }
+    }
+
+    /// Split into (low, high)
+    pub fn split(&mut self, input: f64) -> (f64, f64) {
+        let low = self.lowpass.iter_mut().fold(input, |sample, filter| filter.process(sample));
+        let high = self.highpass.iter_mut().fold(input, |sample, filter| filter.process(sample));
+        (low, high)
+    }
+
+    /// Clear the filter state
+    pub fn reset(&mut self) {
+        for filter in self.lowpass.iter_mut().chain(self.highpass.iter_mut()) {
+            filter.reset();
+        }
+    }
+}
+
+/// One-pole DC blocking high-pass: y[n] = x[n] - x[n-1] + R * y[n-1]
+pub struct DcBlocker {
+    /// Pole radius R, just below 1
+    pole: f64,
+    /// Previous input
+    last_input: f64,
+    /// Previous output
+    last_output: f64,
+}
+
+impl DcBlocker {
+    /// Create a blocker with its corner at `corner_hz`
+    pub fn new(sample_rate: f64, corner_hz: f64) -> Self {
+        Self {
+            pole: (-2.0 * PI * corner_hz / sample_rate).exp(),
+            last_input: 0.0,
+            last_output: 0.0,
+        }
+    }
+
+    /// Filter one sample
+    pub fn process(&mut self, input: f64) -> f64 {
+        let output = denormal::flush(input - self.last_input + self.pole * self.last_output);
+        self.last_input = input;
+        self.last_output = output;
+        output
+    }
+
+    /// Clear the filter state
+    pub fn reset(&mut self) {
+        self.last_input = 0.0;
+        self.last_output = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Steady-state peak amplitude of a filtered sine
+    fn sine_gain(filter: &mut Biquad, freq: f64) -> f64 {
+        let sample_rate = 44100.0;
+        let mut peak: f64 = 0.0;
+        for i in 0..44100 {
+            let output = filter.process((2.0 * PI * freq * i as f64 / sample_rate).sin());
+            if i > 22050 {
+                peak = peak.max(output.abs());
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn test_lowpass() {
+        let coeffs = BiquadCoeffs::lowpass(44100.0, 1000.0, BUTTERWORTH_Q);
+        assert!((sine_gain(&mut Biquad::new(coeffs), 100.0) - 1.0).abs() < 0.01);
+        assert!((sine_gain(&mut Biquad::new(coeffs), 1000.0) - BUTTERWORTH_Q).abs() < 0.01);
+        assert!(sine_gain(&mut Biquad::new(coeffs), 10000.0) < 0.02);
+    }
+
+    #[test]
+    fn test_highpass() {
+        let coeffs = BiquadCoeffs::highpass(44100.0, 1000.0, BUTTERWORTH_Q);
+        assert!(sine_gain(&mut Biquad::new(coeffs), 100.0) < 0.02);
+        assert!((sine_gain(&mut Biquad::new(coeffs), 10000.0) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_allpass_is_flat() {
+        let coeffs = BiquadCoeffs::allpass(44100.0, 1000.0, BUTTERWORTH_Q);
+        for &freq in &[100.0, 1000.0, 5000.0] {
+            assert!((sine_gain(&mut Biquad::new(coeffs), freq) - 1.0).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut filter = Biquad::new(BiquadCoeffs::lowpass(44100.0, 1000.0, BUTTERWORTH_Q));
+        filter.process(1.0);
+        filter.reset();
+        assert_eq!(filter.process(0.0), 0.0);
+    }
+}
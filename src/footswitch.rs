@@ -0,0 +1,199 @@
+//! Footswitches
+//!
+//! Maps keys to chain stage enables the way a pedal's footswitch works: a
+//! latching switch toggles its stage on each press, a momentary switch keeps it
+//! on only while the key is held (e.g. a boost for a single phrase). Several
+//! switches can be bound at once, one stage per key.
+
+use std::fmt;
+
+/// How a footswitch responds to presses
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SwitchMode {
+    /// Active while held
+    Momentary,
+    /// Toggles on each press
+    Latching,
+}
+
+impl SwitchMode {
+    /// Parse a mode name: `momentary` or `latch`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "momentary" => Ok(SwitchMode::Momentary),
+            "latch" | "latching" => Ok(SwitchMode::Latching),
+            _ => Err(format!("Unknown footswitch mode '{}' (expected momentary or latch)", value)),
+        }
+    }
+}
+
+impl fmt::Display for SwitchMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SwitchMode::Momentary => write!(f, "momentary"),
+            SwitchMode::Latching => write!(f, "latch"),
+        }
+    }
+}
+
+/// A key going down or coming back up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SwitchEvent {
+    Press,
+    Release,
+}
+
+/// One footswitch bound to a chain stage
+#[derive(Debug, Clone, PartialEq)]
+pub struct Footswitch {
+    /// Stage the switch enables
+    stage: String,
+    /// Momentary or latching
+    mode: SwitchMode,
+    /// Whether the key is currently down
+    held: bool,
+    /// Whether the switch currently has its stage on
+    engaged: bool,
+}
+
+impl Footswitch {
+    /// Create a switch for `stage`, starting from the stage's current state
+    pub fn new(stage: &str, mode: SwitchMode, engaged: bool) -> Self {
+        Self {
+            stage: stage.to_string(),
+            mode,
+            held: false,
+            engaged,
+        }
+    }
+
+    /// Feed a key event, returning the new stage state if it changed
+    ///
+    /// Repeated presses without a release (key auto-repeat) are ignored.
+    pub fn handle(&mut self, event: SwitchEvent) -> Option<bool> {
+        let engaged = match (event, self.held) {
+            (SwitchEvent::Press, true) => return None,
+            (SwitchEvent::Press, false) => {
+                self.held = true;
+                match self.mode {
+                    SwitchMode::Momentary => true,
+                    SwitchMode::Latching => !self.engaged,
+                }
+            }
+            (SwitchEvent::Release, _) => {
+                self.held = false;
+                match self.mode {
+                    SwitchMode::Momentary => false,
+                    SwitchMode::Latching => self.engaged,
+                }
+            }
+        };
+
+        if engaged == self.engaged {
+            return None;
+        }
+        self.engaged = engaged;
+        Some(engaged)
+    }
+
+    /// Stage the switch enables
+    pub fn stage(&self) -> &str {
+        &self.stage
+    }
+
+    /// Momentary or latching
+    pub fn mode(&self) -> SwitchMode {
+        self.mode
+    }
+}
+
+/// The set of bound footswitches, one per key
+#[derive(Debug, Clone, Default)]
+pub struct Footswitches {
+    switches: Vec<(char, Footswitch)>,
+}
+
+impl Footswitches {
+    /// Bind `key`, replacing any switch already on it
+    pub fn bind(&mut self, key: char, switch: Footswitch) {
+        self.unbind(key);
+        self.switches.push((key, switch));
+    }
+
+    /// Remove the switch on `key`; returns false if there was none
+    pub fn unbind(&mut self, key: char) -> bool {
+        let count = self.switches.len();
+        self.switches.retain(|&(bound, _)| bound != key);
+        self.switches.len() != count
+    }
+
+    /// Whether a switch is bound to `key`
+    pub fn is_bound(&self, key: char) -> bool {
+        self.switches.iter().any(|&(bound, _)| bound == key)
+    }
+
+    /// Feed a key event, returning the stage and its new state if it changed
+    pub fn handle(&mut self, key: char, event: SwitchEvent) -> Option<(&str, bool)> {
+        let (_, switch) = self.switches.iter_mut().find(|(bound, _)| *bound == key)?;
+        let engaged = switch.handle(event)?;
+        Some((switch.stage(), engaged))
+    }
+
+    /// Bound switches in binding order
+    pub fn iter(&self) -> impl Iterator<Item = &(char, Footswitch)> {
+        self.switches.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use SwitchEvent::{Press, Release};
+
+    fn run(switch: &mut Footswitch, events: &[SwitchEvent]) -> Vec<Option<bool>> {
+        events.iter().map(|&event| switch.handle(event)).collect()
+    }
+
+    #[test]
+    fn test_momentary_is_on_while_held() {
+        let mut switch = Footswitch::new("boost", SwitchMode::Momentary, false);
+        assert_eq!(
+            run(&mut switch, &[Press, Press, Release, Release, Press, Release]),
+            [Some(true), None, Some(false), None, Some(true), Some(false)]
+        );
+    }
+
+    #[test]
+    fn test_latching_toggles_on_press() {
+        let mut switch = Footswitch::new("dist", SwitchMode::Latching, false);
+        assert_eq!(
+            run(&mut switch, &[Press, Press, Release, Press, Release, Release]),
+            [Some(true), None, None, Some(false), None, None]
+        );
+
+        // Starting from an enabled stage, the first press turns it off
+        let mut switch = Footswitch::new("verb", SwitchMode::Latching, true);
+        assert_eq!(run(&mut switch, &[Press, Release]), [Some(false), None]);
+    }
+
+    #[test]
+    fn test_multiple_bindings() {
+        let mut switches = Footswitches::default();
+        switches.bind('b', Footswitch::new("boost", SwitchMode::Momentary, false));
+        switches.bind('d', Footswitch::new("dist", SwitchMode::Latching, false));
+
+        assert_eq!(switches.handle('b', Press), Some(("boost", true)));
+        assert_eq!(switches.handle('d', Press), Some(("dist", true)));
+        assert_eq!(switches.handle('b', Release), Some(("boost", false)));
+        assert_eq!(switches.handle('d', Release), None);
+        assert_eq!(switches.handle('x', Press), None);
+
+        // Rebinding a key replaces its switch
+        switches.bind('d', Footswitch::new("verb", SwitchMode::Momentary, false));
+        assert_eq!(switches.iter().count(), 2);
+        assert_eq!(switches.handle('d', Press), Some(("verb", true)));
+        assert!(switches.unbind('d'));
+        assert!(!switches.is_bound('d'));
+        assert!(!switches.unbind('d'));
+    }
+}
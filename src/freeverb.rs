@@ -0,0 +1,244 @@
+//! Freeverb
+//!
+//! Jezar's Freeverb, the classic Schroeder-Moorer room: the mono sum of the input
+//! feeds eight parallel low-pass feedback combs per side, whose sum is smeared by
+//! four series allpasses. The right side's delays are a few samples longer than
+//! the left's, so the two tails are decorrelated, and the width crossfades between
+//! them. The delay lengths are the original ones at 44.1 kHz, scaled to the rate.
+//!
+//! Room size sets the comb feedback (0.7 to 0.98) and dampening the comb
+//! low-pass; freeze switches the combs to full feedback without dampening and
+//! mutes the input. Everything lives in buffers allocated up front, so `reset`
+//! can clear the tail from the audio thread.
+
+use crate::denormal;
+
+/// Comb delays in samples at 44.1 kHz for the left side
+const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+/// Allpass delays in samples at 44.1 kHz for the left side
+const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+/// Extra delay for the right side in samples at 44.1 kHz
+const STEREO_SPREAD: usize = 23;
+/// Allpass feedback
+const ALLPASS_FEEDBACK: f64 = 0.5;
+/// Input level into the combs, which would otherwise sum to a much louder tail
+const FIXED_GAIN: f64 = 0.015;
+/// Wet gain at a wet level of 1
+const SCALE_WET: f64 = 3.0;
+/// Dry gain at a dry level of 1
+const SCALE_DRY: f64 = 2.0;
+/// Comb low-pass coefficient at full dampening
+const SCALE_DAMP: f64 = 0.4;
+/// Comb feedback added per unit of room size
+const SCALE_ROOM: f64 = 0.28;
+/// Comb feedback at room size 0
+const OFFSET_ROOM: f64 = 0.7;
+
+/// Delay line with a single tap at its full length
+struct DelayLine {
+    buffer: Vec<f64>,
+    index: usize,
+}
+
+impl DelayLine {
+    fn new(length: usize) -> Self {
+        Self {
+            buffer: vec![0.0; length.max(1)],
+            index: 0,
+        }
+    }
+
+    /// Oldest sample in the line
+    fn read(&self) -> f64 {
+        self.buffer[self.index]
+    }
+
+    /// Replace the oldest sample and advance
+    fn write(&mut self, value: f64) {
+        self.buffer[self.index] = value;
+        self.index = (self.index + 1) % self.buffer.len();
+    }
+
+    fn reset(&mut self) {
+        self.buffer.fill(0.0);
+    }
+}
+
+/// Feedback comb with a one-pole low-pass in the loop
+struct Comb {
+    line: DelayLine,
+    /// Loop low-pass state
+    filter_state: f64,
+}
+
+impl Comb {
+    fn new(length: usize) -> Self {
+        Self {
+            line: DelayLine::new(length),
+            filter_state: 0.0,
+        }
+    }
+
+    fn tick(&mut self, input: f64, feedback: f64, damping: f64) -> f64 {
+        let output = self.line.read();
+        self.filter_state = denormal::flush(output * (1.0 - damping) + self.filter_state * damping);
+        self.line.write(input + self.filter_state * feedback);
+        output
+    }
+
+    fn reset(&mut self) {
+        self.line.reset();
+        self.filter_state = 0.0;
+    }
+}
+
+/// Freeverb's allpass, which passes the input inverted plus the delayed signal
+fn allpass(line: &mut DelayLine, input: f64) -> f64 {
+    let delayed = line.read();
+    line.write(denormal::flush(input + delayed * ALLPASS_FEEDBACK));
+    delayed - input
+}
+
+/// Freeverb with a left and a right set of combs and allpasses
+pub struct Freeverb {
+    /// Combs for the left and right side
+    combs: [[Comb; 8]; 2],
+    /// Allpasses for the left and right side
+    allpasses: [[DelayLine; 4]; 2],
+    /// Comb feedback from the room size
+    feedback: f64,
+    /// Comb low-pass coefficient from the dampening
+    damping: f64,
+    /// Wet gain for each side's own tail and for the other side's
+    wet_gains: (f64, f64),
+    /// Wet level (0.0 to 1.0)
+    wet: f64,
+    /// Dry gain
+    dry: f64,
+    /// Stereo width (0.0 to 1.0)
+    width: f64,
+    /// Whether the tail is frozen
+    frozen: bool,
+}
+
+impl Freeverb {
+    /// Create a freeverb at room size, dampening and width 0.5, fully wet
+    pub fn new(sample_rate: usize) -> Self {
+        let scale = |samples: usize| samples * sample_rate / 44100;
+        let combs = |spread: usize| COMB_TUNING.map(|samples| Comb::new(scale(samples + spread)));
+        let allpasses = |spread: usize| ALLPASS_TUNING.map(|samples| DelayLine::new(scale(samples + spread)));
+        let mut freeverb = Self {
+            combs: [combs(0), combs(STEREO_SPREAD)],
+            allpasses: [allpasses(0), allpasses(STEREO_SPREAD)],
+            feedback: 0.0,
+            damping: 0.0,
+            wet_gains: (0.0, 0.0),
+            wet: 1.0,
+            dry: 0.0,
+            width: 0.5,
+            frozen: false,
+        };
+        freeverb.set_room_size(0.5);
+        freeverb.set_dampening(0.5);
+        freeverb.update_wet_gains();
+        freeverb
+    }
+
+    /// Process a stereo input sample and return the wet tail plus the dry input
+    pub fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let (feedback, damping, input_gain) = if self.frozen {
+            (1.0, 0.0, 0.0)
+        } else {
+            (self.feedback, self.damping, FIXED_GAIN)
+        };
+        let mono = (input.0 + input.1) * input_gain;
+
+        let mut sides = [0.0; 2];
+        for ((side, combs), allpasses) in sides.iter_mut().zip(self.combs.iter_mut()).zip(self.allpasses.iter_mut()) {
+            *side = combs.iter_mut().map(|comb| comb.tick(mono, feedback, damping)).sum();
+            for line in allpasses.iter_mut() {
+                *side = allpass(line, *side);
+            }
+        }
+
+        let [left, right] = sides;
+        let (wet1, wet2) = self.wet_gains;
+        (
+            left * wet1 + right * wet2 + input.0 * self.dry,
+            right * wet1 + left * wet2 + input.1 * self.dry,
+        )
+    }
+
+    /// Set the room size (0.0 to 1.0), the comb feedback from 0.7 to 0.98
+    pub fn set_room_size(&mut self, room_size: f64) {
+        self.feedback = OFFSET_ROOM + SCALE_ROOM * room_size;
+    }
+
+    /// Set the dampening (0.0 to 1.0)
+    pub fn set_dampening(&mut self, dampening: f64) {
+        self.damping = dampening * SCALE_DAMP;
+    }
+
+    /// Set the wet level (0.0 to 1.0)
+    pub fn set_wet(&mut self, wet: f64) {
+        self.wet = wet;
+        self.update_wet_gains();
+    }
+
+    /// Set the dry level (0.0 to 1.0)
+    pub fn set_dry(&mut self, dry: f64) {
+        self.dry = dry * SCALE_DRY;
+    }
+
+    /// Set the stereo width (0.0 to 1.0)
+    pub fn set_width(&mut self, width: f64) {
+        self.width = width;
+        self.update_wet_gains();
+    }
+
+    /// Freeze or release the tail
+    pub fn set_freeze(&mut self, frozen: bool) {
+        self.frozen = frozen;
+    }
+
+    /// Clear the tail in place, keeping the settings
+    pub fn reset(&mut self) {
+        for comb in self.combs.iter_mut().flatten() {
+            comb.reset();
+        }
+        for line in self.allpasses.iter_mut().flatten() {
+            line.reset();
+        }
+    }
+
+    fn update_wet_gains(&mut self) {
+        let wet = self.wet * SCALE_WET;
+        self.wet_gains = (wet * (self.width / 2.0 + 0.5), wet * (1.0 - self.width) / 2.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reset_clears_the_tail_and_keeps_the_settings() {
+        let mut freeverb = Freeverb::new(44100);
+        freeverb.set_room_size(0.9);
+        freeverb.tick((1.0, 1.0));
+        let ringing: f64 = (0..4410).map(|_| freeverb.tick((0.0, 0.0)).0.abs()).sum();
+        assert!(ringing > 1e-3, "{}", ringing);
+
+        freeverb.reset();
+        assert!((0..4410).all(|_| freeverb.tick((0.0, 0.0)) == (0.0, 0.0)));
+
+        // The same impulse rings the same way as before the reset
+        let mut fresh = Freeverb::new(44100);
+        fresh.set_room_size(0.9);
+        freeverb.tick((1.0, 1.0));
+        fresh.tick((1.0, 1.0));
+        for _ in 0..4410 {
+            assert_eq!(freeverb.tick((0.0, 0.0)), fresh.tick((0.0, 0.0)));
+        }
+    }
+}
@@ -0,0 +1,472 @@
+//! Noise gate and ducker
+//!
+//! Both follow the level of a key signal with the same envelope follower and turn
+//! a stereo signal up or down from it:
+//!
+//! - The noise gate opens while the key is above a threshold, stays open for a hold
+//!   time after the key drops, then closes over the release time. With hysteresis
+//!   the key has to fall further, below a close threshold that many dB under the
+//!   open one, before the hold starts, so a key hovering around the threshold
+//!   doesn't chatter the gate open and shut.
+//! - The ducker does the opposite: it turns the signal down while the key is loud
+//!   and swells it back up when the key goes quiet.
+//!
+//! Routing: `process` takes the key separately from the signal it changes, so
+//! either can be driven by a different signal than the one it acts on. The reverb
+//! passes the dry input as the key to both, so the gate closes the tail as soon as
+//! the player stops and the ducker dips the tail while they play. The gate can
+//! instead key from the signal it gates (`KeySource::Signal`), which makes it an
+//! ordinary noise gate on the tail itself.
+
+/// Release time of the gate's key level detector in seconds
+const DETECTOR_RELEASE_SECONDS: f64 = 0.005;
+/// Time for the gate to open fully in seconds
+const ATTACK_SECONDS: f64 = 0.001;
+/// Key level at which the ducker dips by the full amount (-20 dBFS)
+const FULL_DUCK_LEVEL: f64 = 0.1;
+/// Shortest and longest duck attack in milliseconds
+const MIN_DUCK_ATTACK_MS: f64 = 1.0;
+const MAX_DUCK_ATTACK_MS: f64 = 500.0;
+/// Shortest and longest duck release in milliseconds
+const MIN_DUCK_RELEASE_MS: f64 = 10.0;
+const MAX_DUCK_RELEASE_MS: f64 = 5000.0;
+/// Widest gap between the gate's open and close thresholds in dB
+const MAX_HYSTERESIS_DB: f64 = 24.0;
+
+/// Envelope follower with separate attack and release times
+pub struct EnvelopeFollower {
+    /// Sample rate for converting times to coefficients
+    sample_rate: f64,
+    /// Smoothing coefficient while the level rises (0 = instant)
+    attack: f64,
+    /// Smoothing coefficient while the level falls
+    release: f64,
+    /// Current envelope
+    envelope: f64,
+}
+
+impl EnvelopeFollower {
+    /// Create a follower with the given attack and release times in milliseconds
+    pub fn new(sample_rate: usize, attack_ms: f64, release_ms: f64) -> Self {
+        let mut follower = Self {
+            sample_rate: sample_rate as f64,
+            attack: 0.0,
+            release: 0.0,
+            envelope: 0.0,
+        };
+        follower.set_attack_ms(attack_ms);
+        follower.set_release_ms(release_ms);
+        follower
+    }
+
+    /// Follow one sample of `level` and return the envelope
+    pub fn process(&mut self, level: f64) -> f64 {
+        let level = level.abs();
+        let coefficient = if level > self.envelope { self.attack } else { self.release };
+        self.envelope = level + coefficient * (self.envelope - level);
+        self.envelope
+    }
+
+    /// Set how fast the envelope rises (0 = instantly)
+    pub fn set_attack_ms(&mut self, attack_ms: f64) {
+        self.attack = self.coefficient(attack_ms);
+    }
+
+    /// Set how fast the envelope falls (0 = instantly)
+    pub fn set_release_ms(&mut self, release_ms: f64) {
+        self.release = self.coefficient(release_ms);
+    }
+
+    /// Current envelope
+    pub fn envelope(&self) -> f64 {
+        self.envelope
+    }
+
+    /// Clear the envelope
+    pub fn reset(&mut self) {
+        self.envelope = 0.0;
+    }
+
+    /// One-pole smoothing coefficient for a time in milliseconds
+    fn coefficient(&self, ms: f64) -> f64 {
+        if ms <= 0.0 {
+            0.0
+        } else {
+            (-1000.0 / (ms * self.sample_rate)).exp()
+        }
+    }
+}
+
+/// Signal the gate listens to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeySource {
+    /// The key passed to `process` (a sidechain, e.g. the dry input)
+    Sidechain,
+    /// The level of the gated signal itself
+    Signal,
+}
+
+/// Noise gate with a selectable key
+pub struct NoiseGate {
+    /// Sample rate for converting times to samples
+    sample_rate: f64,
+    /// Key level above which the gate opens
+    threshold: f64,
+    /// How far below the open threshold the key must fall to close the gate, in dB
+    hysteresis_db: f64,
+    /// Hold time in milliseconds
+    hold_ms: f64,
+    /// Release time in milliseconds
+    release_ms: f64,
+    /// Signal the gate opens on
+    key_source: KeySource,
+    /// Peak envelope of the key signal
+    detector: EnvelopeFollower,
+    /// Samples left before the gate starts closing
+    hold_remaining: usize,
+    /// Current gain (0.0 closed to 1.0 open)
+    gain: f64,
+    /// Whether the key has opened the gate and not yet fallen below the close threshold
+    open: bool,
+}
+
+impl NoiseGate {
+    /// Create a sidechain-keyed gate with a -40 dB threshold, 150ms hold and 20ms release
+    pub fn new(sample_rate: usize) -> Self {
+        Self {
+            sample_rate: sample_rate as f64,
+            threshold: 0.01,
+            hysteresis_db: 0.0,
+            hold_ms: 150.0,
+            release_ms: 20.0,
+            key_source: KeySource::Sidechain,
+            detector: EnvelopeFollower::new(sample_rate, 0.0, DETECTOR_RELEASE_SECONDS * 1000.0),
+            hold_remaining: 0,
+            gain: 0.0,
+            open: false,
+        }
+    }
+
+    /// Gate a stereo sample, opening on the level of `key` (or of the input itself
+    /// when keyed from the signal)
+    pub fn process(&mut self, input: (f64, f64), key: f64) -> (f64, f64) {
+        let key = match self.key_source {
+            KeySource::Sidechain => key,
+            KeySource::Signal => input.0.abs().max(input.1.abs()),
+        };
+
+        // Once open, the key only has to stay above the lower close threshold
+        let threshold = if self.open { self.close_threshold() } else { self.threshold };
+        if self.detector.process(key) > threshold {
+            self.open = true;
+            self.hold_remaining = (self.hold_ms * self.sample_rate / 1000.0) as usize;
+            self.gain = (self.gain + 1.0 / (ATTACK_SECONDS * self.sample_rate)).min(1.0);
+        } else if self.hold_remaining > 0 {
+            self.hold_remaining -= 1;
+        } else {
+            self.open = false;
+            let release_samples = (self.release_ms * self.sample_rate / 1000.0).max(1.0);
+            self.gain = (self.gain - 1.0 / release_samples).max(0.0);
+        }
+
+        (input.0 * self.gain, input.1 * self.gain)
+    }
+
+    /// Set the key level that opens the gate (0.0 to 1.0)
+    pub fn set_threshold(&mut self, threshold: f64) {
+        self.threshold = threshold.clamp(0.0, 1.0);
+    }
+
+    /// Set how far below the open threshold the key must fall before the gate
+    /// closes (0 to 24 dB, 0 = a single threshold)
+    pub fn set_hysteresis_db(&mut self, hysteresis_db: f64) {
+        self.hysteresis_db = hysteresis_db.clamp(0.0, MAX_HYSTERESIS_DB);
+    }
+
+    /// Set how long the gate stays open after the key drops (0 to 2000ms)
+    pub fn set_hold_ms(&mut self, hold_ms: f64) {
+        self.hold_ms = hold_ms.clamp(0.0, 2000.0);
+    }
+
+    /// Set how long the gate takes to close (0 to 2000ms)
+    pub fn set_release_ms(&mut self, release_ms: f64) {
+        self.release_ms = release_ms.clamp(0.0, 2000.0);
+    }
+
+    /// Set which signal opens the gate
+    pub fn set_key_source(&mut self, key_source: KeySource) {
+        self.key_source = key_source;
+    }
+
+    /// Current threshold
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    /// Current hysteresis in dB
+    pub fn hysteresis_db(&self) -> f64 {
+        self.hysteresis_db
+    }
+
+    /// Key level below which an open gate starts to close
+    pub fn close_threshold(&self) -> f64 {
+        self.threshold * 10f64.powf(-self.hysteresis_db / 20.0)
+    }
+
+    /// Current hold time in milliseconds
+    pub fn hold_ms(&self) -> f64 {
+        self.hold_ms
+    }
+
+    /// Current release time in milliseconds
+    pub fn release_ms(&self) -> f64 {
+        self.release_ms
+    }
+
+    /// Current key source
+    pub fn key_source(&self) -> KeySource {
+        self.key_source
+    }
+
+    /// Close the gate and clear the key envelope
+    pub fn reset(&mut self) {
+        self.detector.reset();
+        self.hold_remaining = 0;
+        self.gain = 0.0;
+        self.open = false;
+    }
+}
+
+/// Ducker: the gate turned around, dipping the signal while the key is loud
+pub struct Ducker {
+    /// How far the signal dips at full key level (0.0 = off, 1.0 = silent)
+    amount: f64,
+    /// Attack time in milliseconds
+    attack_ms: f64,
+    /// Release time in milliseconds
+    release_ms: f64,
+    /// Envelope of the key level
+    follower: EnvelopeFollower,
+}
+
+impl Ducker {
+    /// Create a ducker that is off, with a 10ms attack and 300ms release
+    pub fn new(sample_rate: usize) -> Self {
+        let mut ducker = Self {
+            amount: 0.0,
+            attack_ms: 0.0,
+            release_ms: 0.0,
+            follower: EnvelopeFollower::new(sample_rate, 0.0, 0.0),
+        };
+        ducker.set_attack_ms(10.0);
+        ducker.set_release_ms(300.0);
+        ducker
+    }
+
+    /// Duck a stereo sample by the level of `key`
+    pub fn process(&mut self, input: (f64, f64), key: f64) -> (f64, f64) {
+        self.follower.process(key);
+        let gain = self.gain();
+        (input.0 * gain, input.1 * gain)
+    }
+
+    /// Current gain on the ducked signal (1.0 = not ducked)
+    pub fn gain(&self) -> f64 {
+        1.0 - self.amount * (self.follower.envelope() / FULL_DUCK_LEVEL).min(1.0)
+    }
+
+    /// Set how far the signal dips while the key is loud (0.0 to 1.0, 0 = off)
+    pub fn set_amount(&mut self, amount: f64) {
+        self.amount = amount.clamp(0.0, 1.0);
+    }
+
+    /// Set how fast the signal dips (1 to 500ms)
+    pub fn set_attack_ms(&mut self, attack_ms: f64) {
+        self.attack_ms = attack_ms.clamp(MIN_DUCK_ATTACK_MS, MAX_DUCK_ATTACK_MS);
+        self.follower.set_attack_ms(self.attack_ms);
+    }
+
+    /// Set how slowly the signal returns (10 to 5000ms)
+    pub fn set_release_ms(&mut self, release_ms: f64) {
+        self.release_ms = release_ms.clamp(MIN_DUCK_RELEASE_MS, MAX_DUCK_RELEASE_MS);
+        self.follower.set_release_ms(self.release_ms);
+    }
+
+    /// Current amount
+    pub fn amount(&self) -> f64 {
+        self.amount
+    }
+
+    /// Current attack time in milliseconds
+    pub fn attack_ms(&self) -> f64 {
+        self.attack_ms
+    }
+
+    /// Current release time in milliseconds
+    pub fn release_ms(&self) -> f64 {
+        self.release_ms
+    }
+
+    /// Clear the key envelope
+    pub fn reset(&mut self) {
+        self.follower.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope_follower_time_constants() {
+        let mut follower = EnvelopeFollower::new(1000, 10.0, 100.0);
+
+        // A step rises to 1 - 1/e of its level after the attack time
+        let rise: Vec<f64> = (0..200).map(|_| follower.process(-1.0)).collect();
+        assert!((rise[9] - (1.0 - (-1.0_f64).exp())).abs() < 1e-9, "{}", rise[9]);
+        assert!(rise.windows(2).all(|pair| pair[1] > pair[0]));
+        assert!(rise[199] > 0.999_999);
+
+        // And falls to 1/e of it after the release time
+        let start = follower.envelope();
+        let fall: Vec<f64> = (0..100).map(|_| follower.process(0.0)).collect();
+        assert!((fall[99] / start - (-1.0_f64).exp()).abs() < 1e-9, "{}", fall[99]);
+
+        // Zero times follow instantly
+        let mut instant = EnvelopeFollower::new(1000, 0.0, 0.0);
+        assert_eq!(instant.process(0.5), 0.5);
+        assert_eq!(instant.process(0.0), 0.0);
+    }
+
+    #[test]
+    fn test_opens_holds_and_closes() {
+        let mut gate = NoiseGate::new(1000);
+        gate.set_hold_ms(50.0);
+        gate.set_release_ms(10.0);
+
+        // Key present: gate opens within the attack time
+        for _ in 0..10 {
+            gate.process((1.0, 1.0), 0.5);
+        }
+        assert_eq!(gate.process((1.0, 1.0), 0.5), (1.0, 1.0));
+
+        // Key gone: still open during the hold (plus the detector release)
+        for _ in 0..40 {
+            assert_eq!(gate.process((1.0, 1.0), 0.0), (1.0, 1.0));
+        }
+
+        // Closed once hold and release have passed
+        for _ in 0..100 {
+            gate.process((1.0, 1.0), 0.0);
+        }
+        assert_eq!(gate.process((1.0, 1.0), 0.0), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_hysteresis_stops_chatter_around_the_threshold() {
+        // Key swinging between 0.012 and 0.008, across the 0.01 open threshold,
+        // with no hold and an instant release so every dip would close the gate
+        let toggles = |hysteresis_db: f64| {
+            let mut gate = NoiseGate::new(1000);
+            gate.set_hold_ms(0.0);
+            gate.set_release_ms(0.0);
+            gate.set_hysteresis_db(hysteresis_db);
+            let mut open = false;
+            let mut toggles = 0;
+            for i in 0..1000 {
+                let key = if (i / 20) % 2 == 0 { 0.012 } else { 0.008 };
+                let now_open = gate.process((1.0, 1.0), key).0 > 0.5;
+                if now_open != open {
+                    toggles += 1;
+                    open = now_open;
+                }
+            }
+            (toggles, open)
+        };
+
+        // A single threshold opens and closes on every swing
+        let (single, _) = toggles(0.0);
+        assert!(single >= 40, "{}", single);
+
+        // 6 dB of hysteresis puts the close threshold at about 0.005, below the
+        // swing: the gate opens once and stays open
+        assert_eq!(toggles(6.0), (1, true));
+
+        let mut gate = NoiseGate::new(1000);
+        gate.set_hysteresis_db(6.0);
+        assert!((gate.close_threshold() - 0.01 * 10f64.powf(-0.3)).abs() < 1e-12);
+        gate.set_hysteresis_db(40.0);
+        assert_eq!(gate.hysteresis_db(), 24.0);
+    }
+
+    #[test]
+    fn test_key_is_independent_of_input() {
+        let mut gate = NoiseGate::new(1000);
+        // A loud input doesn't open the gate without a key
+        for _ in 0..100 {
+            assert_eq!(gate.process((1.0, -1.0), 0.0), (0.0, 0.0));
+        }
+
+        // Keyed from the signal, the same input opens it
+        gate.set_key_source(KeySource::Signal);
+        for _ in 0..10 {
+            gate.process((1.0, -1.0), 0.0);
+        }
+        assert_eq!(gate.process((1.0, -1.0), 0.0), (1.0, -1.0));
+    }
+
+    #[test]
+    fn test_ducks_while_playing_and_recovers() {
+        let mut ducker = Ducker::new(1000);
+        ducker.set_amount(0.75);
+        ducker.set_attack_ms(10.0);
+        ducker.set_release_ms(100.0);
+
+        // Nothing playing: the wet signal passes at full level
+        assert_eq!(ducker.process((1.0, -1.0), 0.0), (1.0, -1.0));
+
+        // A sustained dry note pulls the wet gain down by the amount
+        for _ in 0..100 {
+            ducker.process((1.0, 1.0), 0.5);
+        }
+        let (left, right) = ducker.process((1.0, 1.0), 0.5);
+        assert!((left - 0.25).abs() < 1e-3 && left == right, "{}", left);
+
+        // Shortly after the note stops the wet is still ducked, then it swells back
+        let gains: Vec<f64> = (0..1000).map(|_| ducker.process((1.0, 1.0), 0.0).0).collect();
+        assert!(gains[50] < 0.5, "{}", gains[50]);
+        assert!(gains.windows(2).all(|pair| pair[1] >= pair[0]));
+        assert!(gains[999] > 0.999, "{}", gains[999]);
+
+        // Switched off it never ducks
+        ducker.set_amount(0.0);
+        assert_eq!(ducker.process((1.0, 1.0), 1.0), (1.0, 1.0));
+    }
+
+    #[test]
+    fn test_duck_gain_follows_a_rising_key() {
+        let mut ducker = Ducker::new(1000);
+        ducker.set_amount(1.0);
+        ducker.set_attack_ms(1.0);
+
+        // Key swelling from silence to -20 dBFS over 100ms, then held
+        let gains: Vec<f64> = (0..200)
+            .map(|i| {
+                let key = FULL_DUCK_LEVEL * (i as f64 / 100.0).min(1.0);
+                ducker.process((1.0, 1.0), key).0
+            })
+            .collect();
+
+        // The gain falls steadily as the key rises, tracking its level
+        assert!(gains.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert!((gains[50] - 0.5).abs() < 0.02, "{}", gains[50]);
+        assert!(gains[199] < 1e-3, "{}", gains[199]);
+
+        // A slower attack lags behind the same rise
+        let mut slow = Ducker::new(1000);
+        slow.set_amount(1.0);
+        slow.set_attack_ms(50.0);
+        let slow_gain = (0..=50).map(|i| slow.process((1.0, 1.0), FULL_DUCK_LEVEL * i as f64 / 100.0).0).last();
+        assert!(slow_gain.unwrap() > gains[50] + 0.1, "{:?}", slow_gain);
+    }
+}
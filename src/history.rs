@@ -0,0 +1,144 @@
+//! Command history
+//!
+//! Remembers the recent console commands so a tone can be dialled in by
+//! recalling and editing the last one instead of retyping it. In raw key mode
+//! Up and Down step through the history while typing a line command; in either
+//! mode `history` lists it. The oldest entries are dropped once it is full.
+
+use std::collections::VecDeque;
+
+/// Number of commands kept
+const HISTORY_CAPACITY: usize = 100;
+
+/// Bounded list of entered commands with a recall position
+pub struct CommandHistory {
+    /// Commands, oldest first
+    entries: VecDeque<String>,
+    /// Most commands kept
+    capacity: usize,
+    /// Entry being recalled, counted back from the newest (None = editing a new line)
+    cursor: Option<usize>,
+    /// The line being typed before recall started, restored by stepping past the newest entry
+    draft: String,
+}
+
+impl Default for CommandHistory {
+    fn default() -> Self {
+        Self::new(HISTORY_CAPACITY)
+    }
+}
+
+impl CommandHistory {
+    /// Create an empty history keeping up to `capacity` commands
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            cursor: None,
+            draft: String::new(),
+        }
+    }
+
+    /// Add an entered command and end any recall
+    ///
+    /// Blank lines and repeats of the newest entry aren't stored.
+    pub fn push(&mut self, line: &str) {
+        self.end_recall();
+        let line = line.trim();
+        if line.is_empty() || self.entries.back().is_some_and(|newest| newest == line) {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(line.to_string());
+    }
+
+    /// Forget the recall position, e.g. when a line is cancelled
+    pub fn end_recall(&mut self) {
+        self.cursor = None;
+        self.draft.clear();
+    }
+
+    /// Replace `line` with the next older command (Up), keeping the oldest at the start
+    pub fn older(&mut self, line: &mut String) {
+        let back = match self.cursor {
+            None if self.entries.is_empty() => return,
+            None => {
+                self.draft = line.clone();
+                0
+            }
+            Some(back) => (back + 1).min(self.entries.len() - 1),
+        };
+        self.cursor = Some(back);
+        *line = self.entries[self.entries.len() - 1 - back].clone();
+    }
+
+    /// Replace `line` with the next newer command (Down), or the line being
+    /// typed before recall once past the newest
+    pub fn newer(&mut self, line: &mut String) {
+        match self.cursor {
+            None => {}
+            Some(0) => {
+                self.cursor = None;
+                *line = std::mem::take(&mut self.draft);
+            }
+            Some(back) => {
+                self.cursor = Some(back - 1);
+                *line = self.entries[self.entries.len() - back].clone();
+            }
+        }
+    }
+
+    /// Stored commands, oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_and_recall() {
+        let mut history = CommandHistory::new(3);
+        let mut line = String::new();
+
+        // Nothing to recall yet
+        history.older(&mut line);
+        assert_eq!(line, "");
+
+        for command in ["w 0.3", "  ", "r 0.8", "r 0.8", "d 0.5", "mix verb 0.4"] {
+            history.push(command);
+        }
+        // Blanks and repeats are skipped, and the oldest falls out at capacity
+        assert_eq!(history.entries().collect::<Vec<_>>(), ["r 0.8", "d 0.5", "mix verb 0.4"]);
+
+        // Up walks back and stops at the oldest; Down walks forward to the draft
+        line.push_str("tri");
+        history.older(&mut line);
+        assert_eq!(line, "mix verb 0.4");
+        history.older(&mut line);
+        history.older(&mut line);
+        history.older(&mut line);
+        assert_eq!(line, "r 0.8");
+        history.newer(&mut line);
+        assert_eq!(line, "d 0.5");
+        history.newer(&mut line);
+        history.newer(&mut line);
+        assert_eq!(line, "tri");
+        history.newer(&mut line);
+        assert_eq!(line, "tri");
+
+        // Entering a recalled and edited line starts the next recall from the newest
+        history.older(&mut line);
+        history.older(&mut line);
+        line.push('5');
+        history.push(&line);
+        assert_eq!(history.entries().last(), Some("d 0.55"));
+        let mut line = String::new();
+        history.older(&mut line);
+        assert_eq!(line, "d 0.55");
+    }
+}
@@ -0,0 +1,144 @@
+//! Audio host selection
+//!
+//! Picks the cpal host that provides the devices: the platform default
+//! (CoreAudio, ALSA, WASAPI) or another host compiled into cpal, such as JACK for
+//! low-latency routing on Linux. Only some hosts are compiled in on each platform
+//! (JACK only with the `jack` feature), so hosts are looked up by name among the
+//! compiled ones rather than named directly. A host that is compiled in but can't
+//! be opened falls back to the default host with a warning.
+
+use std::fmt;
+
+/// Name cpal gives the JACK host
+const JACK_HOST_NAME: &str = "JACK";
+
+/// Which audio host to open
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum AudioHost {
+    /// The platform's default host
+    #[default]
+    Default,
+    /// A host by its cpal name in lowercase (jack, coreaudio, alsa, wasapi, ...)
+    Named(String),
+}
+
+impl AudioHost {
+    /// Parse the `--host` argument: `default` or a host name from `--list-hosts`
+    ///
+    /// Names are matched case-insensitively; whether the host is compiled in is
+    /// only checked when it is opened.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "" => Err("Empty host name (expected default or a name from --list-hosts)".to_string()),
+            "default" => Ok(AudioHost::Default),
+            name => Ok(AudioHost::Named(name.to_string())),
+        }
+    }
+
+    /// Host id to request among the `compiled` hosts (None = the default host)
+    pub fn host_id(&self, compiled: &[cpal::HostId]) -> Result<Option<cpal::HostId>, String> {
+        match self {
+            AudioHost::Default => Ok(None),
+            AudioHost::Named(name) => compiled
+                .iter()
+                .find(|id| id.name().eq_ignore_ascii_case(name))
+                .map(|&id| Some(id))
+                .ok_or_else(|| {
+                    if self.is_jack() {
+                        "JACK support is not built in (build with --features jack on Linux)".to_string()
+                    } else {
+                        format!("Host '{}' is not built into this program (built in: {})", name, host_names(compiled))
+                    }
+                }),
+        }
+    }
+
+    /// Open the host, falling back to the default host if it is unavailable
+    pub fn open(&self) -> Result<cpal::Host, String> {
+        match self.host_id(cpal::ALL_HOSTS)? {
+            None => Ok(cpal::default_host()),
+            Some(id) => Ok(cpal::host_from_id(id).unwrap_or_else(|_| {
+                eprintln!("⚠️  {} is not available, using the default host", self);
+                cpal::default_host()
+            })),
+        }
+    }
+
+    /// Error for a missing default device, `direction` being "input" or "output"
+    ///
+    /// JACK only has devices while its server is running, so that is the likely cause.
+    pub fn no_device_error(&self, direction: &str) -> String {
+        if self.is_jack() {
+            format!("No JACK {} device found: is the JACK server running?", direction)
+        } else {
+            format!("No {} device found", direction)
+        }
+    }
+
+    fn is_jack(&self) -> bool {
+        matches!(self, AudioHost::Named(name) if name.eq_ignore_ascii_case(JACK_HOST_NAME))
+    }
+}
+
+impl fmt::Display for AudioHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioHost::Default => write!(f, "default"),
+            AudioHost::Named(name) => match cpal::ALL_HOSTS.iter().find(|id| id.name().eq_ignore_ascii_case(name)) {
+                Some(id) => write!(f, "{}", id.name()),
+                None => write!(f, "{}", name),
+            },
+        }
+    }
+}
+
+/// Names of `hosts` as `--host` takes them, comma separated
+pub fn host_names(hosts: &[cpal::HostId]) -> String {
+    if hosts.is_empty() {
+        return "none".to_string();
+    }
+    hosts.iter().map(|id| id.name().to_lowercase()).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jack() -> AudioHost {
+        AudioHost::Named("jack".to_string())
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(AudioHost::parse("default"), Ok(AudioHost::Default));
+        assert_eq!(AudioHost::parse("jack"), Ok(jack()));
+        assert_eq!(AudioHost::parse("JACK"), Ok(jack()));
+        assert!(AudioHost::parse("").is_err());
+    }
+
+    #[test]
+    fn test_host_id_selection() {
+        assert_eq!(AudioHost::Default.host_id(cpal::ALL_HOSTS), Ok(None));
+
+        // JACK is found by name when compiled in, and reported when it isn't
+        match cpal::ALL_HOSTS.iter().copied().find(|id| id.name() == "JACK") {
+            Some(id) => assert_eq!(jack().host_id(cpal::ALL_HOSTS), Ok(Some(id))),
+            None => assert!(jack().host_id(cpal::ALL_HOSTS).is_err()),
+        }
+        let error = jack().host_id(&[]).unwrap_err();
+        assert!(error.contains("--features jack"), "{}", error);
+    }
+
+    #[test]
+    fn test_host_names_map_to_their_ids() {
+        // Every compiled host is reachable by the name --list-hosts prints
+        for &id in cpal::ALL_HOSTS {
+            let host = AudioHost::parse(&id.name().to_lowercase()).unwrap();
+            assert_eq!(host.host_id(cpal::ALL_HOSTS), Ok(Some(id)));
+            assert_eq!(host.to_string(), id.name());
+        }
+
+        let error = AudioHost::parse("nosuchhost").unwrap().host_id(cpal::ALL_HOSTS).unwrap_err();
+        assert!(error.contains("not built into") && error.contains(&host_names(cpal::ALL_HOSTS)), "{}", error);
+    }
+}
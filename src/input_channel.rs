@@ -0,0 +1,174 @@
+//! Input channel selection
+//!
+//! Decides which input channels feed the mono signal into the effects. By default
+//! all channels are averaged; a single channel can be picked instead when only one
+//! input of a stereo interface is connected, so the other doesn't add its noise.
+//!
+//! How mixed channels collapse to mono is the mono-sum law. Averaging keeps a
+//! signal present on both channels at its level but drops one present on a single
+//! channel by 6 dB; summing keeps the single channel but doubles a shared one; the
+//! equal-power (rms) law sits between, at 3 dB either way.
+
+use std::fmt;
+
+/// How a multi-channel frame is summed to mono
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum MonoSum {
+    /// Mean of the channels (original behavior)
+    #[default]
+    Avg,
+    /// Plain sum of the channels
+    Sum,
+    /// Sum scaled by 1/sqrt(channels), equal power
+    Rms,
+}
+
+impl MonoSum {
+    /// Parse the `--mono-sum` argument: `avg`, `sum` or `rms`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "avg" => Ok(MonoSum::Avg),
+            "sum" => Ok(MonoSum::Sum),
+            "rms" => Ok(MonoSum::Rms),
+            _ => Err(format!("Unknown mono sum '{}' (expected avg, sum or rms)", value)),
+        }
+    }
+
+    /// Collapse `samples` to mono
+    pub fn sum(&self, samples: impl Iterator<Item = f64>) -> f64 {
+        let (total, count) = samples.fold((0.0, 0usize), |(total, count), sample| (total + sample, count + 1));
+        match self {
+            MonoSum::Avg => total / count as f64,
+            MonoSum::Sum => total,
+            MonoSum::Rms => total / (count as f64).sqrt(),
+        }
+    }
+}
+
+impl fmt::Display for MonoSum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MonoSum::Avg => write!(f, "avg"),
+            MonoSum::Sum => write!(f, "sum"),
+            MonoSum::Rms => write!(f, "rms"),
+        }
+    }
+}
+
+/// Which input channels make up the mono signal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputChannel {
+    /// First channel only
+    Left,
+    /// Second channel only (the first on a mono device)
+    Right,
+    /// All channels, summed by the given law
+    Mix(MonoSum),
+}
+
+impl Default for InputChannel {
+    fn default() -> Self {
+        InputChannel::Mix(MonoSum::default())
+    }
+}
+
+impl InputChannel {
+    /// Parse the `--input-channel` argument: `left`, `right` or `mix`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "left" => Ok(InputChannel::Left),
+            "right" => Ok(InputChannel::Right),
+            "mix" => Ok(InputChannel::default()),
+            _ => Err(format!("Unknown input channel '{}' (expected left, right or mix)", value)),
+        }
+    }
+
+    /// The same selection with mixed channels summed by `law`
+    pub fn with_mono_sum(self, law: MonoSum) -> Self {
+        match self {
+            InputChannel::Mix(_) => InputChannel::Mix(law),
+            channel => channel,
+        }
+    }
+
+    /// Mono sample for one interleaved input frame, converting samples with `to_f64`
+    pub fn select<T: Copy>(&self, frame: &[T], to_f64: impl Fn(T) -> f64) -> f64 {
+        match self {
+            InputChannel::Left => to_f64(frame[0]),
+            InputChannel::Right => to_f64(*frame.get(1).unwrap_or(&frame[0])),
+            InputChannel::Mix(law) => law.sum(frame.iter().map(|&sample| to_f64(sample))),
+        }
+    }
+}
+
+impl fmt::Display for InputChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputChannel::Left => write!(f, "left"),
+            InputChannel::Right => write!(f, "right"),
+            InputChannel::Mix(MonoSum::Avg) => write!(f, "mix"),
+            InputChannel::Mix(law) => write!(f, "mix ({})", law),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select(channel: InputChannel, frame: &[f64]) -> f64 {
+        channel.select(frame, |sample| sample)
+    }
+
+    #[test]
+    fn test_stereo_frame_selection() {
+        let frame = [0.5, -0.25];
+        assert_eq!(select(InputChannel::Left, &frame), 0.5);
+        assert_eq!(select(InputChannel::Right, &frame), -0.25);
+        assert_eq!(select(InputChannel::default(), &frame), 0.125);
+    }
+
+    #[test]
+    fn test_mono_frame_uses_only_channel() {
+        for channel in [InputChannel::Left, InputChannel::Right, InputChannel::default()] {
+            assert_eq!(select(channel, &[0.75]), 0.75);
+        }
+    }
+
+    #[test]
+    fn test_avg_law_takes_the_mean() {
+        let channel = InputChannel::default().with_mono_sum(MonoSum::Avg);
+        assert_eq!(select(channel, &[0.5, -0.25]), 0.125);
+        assert_eq!(select(channel, &[0.4, 0.4]), 0.4);
+    }
+
+    #[test]
+    fn test_sum_law_adds_the_channels() {
+        let channel = InputChannel::default().with_mono_sum(MonoSum::Sum);
+        assert_eq!(select(channel, &[0.5, -0.25]), 0.25);
+        assert_eq!(select(channel, &[0.4, 0.0]), 0.4);
+    }
+
+    #[test]
+    fn test_rms_law_keeps_equal_power() {
+        let channel = InputChannel::default().with_mono_sum(MonoSum::Rms);
+        assert!((select(channel, &[0.5, -0.25]) - 0.25 / 2.0_f64.sqrt()).abs() < 1e-12);
+        // 3 dB up from one channel, 3 dB down from a shared one
+        assert!((select(channel, &[0.4, 0.4]) - 0.4 * 2.0_f64.sqrt()).abs() < 1e-12);
+        assert_eq!(select(channel, &[0.75]), 0.75);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(InputChannel::parse("left").unwrap(), InputChannel::Left);
+        assert_eq!(InputChannel::parse("right").unwrap().to_string(), "right");
+        assert_eq!(InputChannel::default(), InputChannel::Mix(MonoSum::Avg));
+        assert!(InputChannel::parse("center").is_err());
+
+        assert_eq!(MonoSum::parse("rms").unwrap(), MonoSum::Rms);
+        assert!(MonoSum::parse("mean").is_err());
+        // A single channel ignores the law
+        assert_eq!(InputChannel::Left.with_mono_sum(MonoSum::Sum), InputChannel::Left);
+        assert_eq!(InputChannel::default().with_mono_sum(MonoSum::Sum).to_string(), "mix (sum)");
+    }
+}
@@ -0,0 +1,214 @@
+//! Effects loop insert
+//!
+//! Sends the signal out to another process and back, like a pedal's effects loop
+//! jacks. The `loop` stage pushes each frame into a send ring buffer and takes the
+//! processed frame from a return ring buffer; two bridge threads move the frames
+//! between the ring buffers and the files, which are normally named pipes (FIFOs).
+//! Only the bridge threads block on the pipes, so the audio thread never waits: a
+//! full send buffer drops frames and an empty return buffer gives silence, and
+//! both are counted.
+//!
+//! Frames travel as interleaved stereo 32-bit floats, little endian. The external
+//! process must keep up in real time; the round trip adds the latency of
+//! whatever it buffers.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use ringbuf::{Consumer, Producer, RingBuffer};
+
+use crate::ipc::status;
+
+/// Bytes per stereo frame on the pipes
+const FRAME_BYTES: usize = 8;
+/// How long the send thread sleeps when there is nothing to write
+const IDLE_SLEEP: Duration = Duration::from_millis(1);
+
+/// The chain stage: audio-thread side of the loop
+pub struct InsertLoop {
+    /// Frames waiting to be written to the send pipe
+    send: Producer<(f32, f32)>,
+    /// Processed frames read from the return pipe
+    ret: Consumer<(f32, f32)>,
+    /// Frames dropped because the send buffer was full
+    dropped: usize,
+    /// Frames replaced by silence because nothing had returned yet
+    missing: usize,
+}
+
+/// The bridge side of the loop, moved into the bridge threads
+pub struct InsertEnds {
+    /// Frames to write to the send pipe
+    send: Consumer<(f32, f32)>,
+    /// Frames read from the return pipe
+    ret: Producer<(f32, f32)>,
+}
+
+impl InsertLoop {
+    /// Create the stage and its bridge ends, buffering up to `capacity` frames each way
+    pub fn new(capacity: usize) -> (Self, InsertEnds) {
+        let (send_producer, send_consumer) = RingBuffer::new(capacity).split();
+        let (ret_producer, ret_consumer) = RingBuffer::new(capacity).split();
+        let insert = Self {
+            send: send_producer,
+            ret: ret_consumer,
+            dropped: 0,
+            missing: 0,
+        };
+        (insert, InsertEnds { send: send_consumer, ret: ret_producer })
+    }
+
+    /// Send a frame out and return the next processed one, never blocking
+    pub fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        if self.send.push((input.0 as f32, input.1 as f32)).is_err() {
+            self.dropped += 1;
+        }
+        match self.ret.pop() {
+            Some((left, right)) => (left as f64, right as f64),
+            None => {
+                self.missing += 1;
+                (0.0, 0.0)
+            }
+        }
+    }
+
+    /// Frames dropped on the way out and frames missing on the way back
+    pub fn xruns(&self) -> (usize, usize) {
+        (self.dropped, self.missing)
+    }
+
+    /// Discard the frames that have come back but not been played
+    pub fn reset(&mut self) {
+        while self.ret.pop().is_some() {}
+    }
+}
+
+impl InsertEnds {
+    /// Start the bridge threads for the send and return paths
+    ///
+    /// Opening a FIFO blocks until the other process opens its end, so the files
+    /// are opened in the threads, which stop when `running` is cleared or a pipe
+    /// closes.
+    pub fn spawn(self, send_path: String, return_path: String, running: Arc<AtomicBool>) {
+        let Self { mut send, mut ret } = self;
+
+        let send_running = running.clone();
+        thread::spawn(move || {
+            let result = OpenOptions::new().write(true).open(&send_path).and_then(|file| {
+                status!("Effects loop send connected: {}", send_path);
+                let mut writer = BufWriter::new(file);
+                while send_running.load(Ordering::Relaxed) {
+                    if drain_to(&mut send, &mut writer)? == 0 {
+                        thread::sleep(IDLE_SLEEP);
+                    }
+                }
+                Ok(())
+            });
+            if let Err(e) = result {
+                eprintln!("Effects loop send stopped: {}", e);
+            }
+        });
+
+        thread::spawn(move || {
+            let result = File::open(&return_path).and_then(|mut file| {
+                status!("Effects loop return connected: {}", return_path);
+                while running.load(Ordering::Relaxed) && fill_from(&mut file, &mut ret)? {}
+                Ok(())
+            });
+            match result {
+                Ok(()) => eprintln!("Effects loop return closed"),
+                Err(e) => eprintln!("Effects loop return stopped: {}", e),
+            }
+        });
+    }
+}
+
+/// Write every frame waiting in `send` and flush; returns the number written
+fn drain_to<W: Write>(send: &mut Consumer<(f32, f32)>, writer: &mut W) -> io::Result<usize> {
+    let mut count = 0;
+    while let Some((left, right)) = send.pop() {
+        writer.write_all(&left.to_le_bytes())?;
+        writer.write_all(&right.to_le_bytes())?;
+        count += 1;
+    }
+    if count > 0 {
+        writer.flush()?;
+    }
+    Ok(count)
+}
+
+/// Read one frame into `ret`; returns false at the end of the stream
+///
+/// A frame that doesn't fit because the audio thread isn't taking them is dropped.
+fn fill_from<R: Read>(reader: &mut R, ret: &mut Producer<(f32, f32)>) -> io::Result<bool> {
+    let mut bytes = [0u8; FRAME_BYTES];
+    match reader.read_exact(&mut bytes) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+        Err(e) => return Err(e),
+    }
+    let left = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let right = f32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    let _ = ret.push((left, right));
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slow_consumer_never_blocks() {
+        let (mut insert, mut ends) = InsertLoop::new(8);
+
+        // Nothing drains the send side and nothing has come back: the stage keeps
+        // running, dropping what doesn't fit and playing silence
+        for i in 0..20 {
+            assert_eq!(insert.tick((i as f64, -(i as f64))), (0.0, 0.0));
+        }
+        assert_eq!(insert.xruns(), (12, 20));
+
+        // The consumer catches up with the frames that fit, in order
+        let mut written = Vec::new();
+        assert_eq!(drain_to(&mut ends.send, &mut written).unwrap(), 8);
+        assert_eq!(written.len(), 8 * FRAME_BYTES);
+        assert_eq!(&written[..8], &[0.0f32.to_le_bytes(), (-0.0f32).to_le_bytes()].concat()[..]);
+        assert_eq!(&written[56..], &[7.0f32.to_le_bytes(), (-7.0f32).to_le_bytes()].concat()[..]);
+        assert_eq!(drain_to(&mut ends.send, &mut written).unwrap(), 0);
+
+        // Frames coming back are played in order, then silence again
+        let mut reader = io::Cursor::new(written);
+        while fill_from(&mut reader, &mut ends.ret).unwrap() {}
+        for i in 0..8 {
+            assert_eq!(insert.tick((0.0, 0.0)), (i as f64, -(i as f64)));
+        }
+        assert_eq!(insert.tick((0.0, 0.0)), (0.0, 0.0));
+        // Nine more sends into an eight-frame buffer nobody drained
+        assert_eq!(insert.xruns(), (13, 21));
+    }
+
+    #[test]
+    fn test_full_return_drops_and_reset_clears() {
+        let (mut insert, mut ends) = InsertLoop::new(2);
+        let frames: Vec<u8> = (0..4).flat_map(|i| [(i as f32).to_le_bytes(), 0.0f32.to_le_bytes()].concat()).collect();
+
+        // Only the first two fit while the audio thread isn't taking them
+        let mut reader = io::Cursor::new(frames.clone());
+        while fill_from(&mut reader, &mut ends.ret).unwrap() {}
+        assert_eq!(insert.tick((0.0, 0.0)), (0.0, 0.0));
+        assert_eq!(insert.tick((0.0, 0.0)), (1.0, 0.0));
+        assert_eq!(insert.tick((0.0, 0.0)), (0.0, 0.0));
+        assert_eq!(insert.xruns().1, 1);
+
+        // Reset discards what has come back but not been played
+        let mut reader = io::Cursor::new(frames);
+        while fill_from(&mut reader, &mut ends.ret).unwrap() {}
+        insert.reset();
+        assert_eq!(insert.tick((0.0, 0.0)), (0.0, 0.0));
+        assert_eq!(insert.xruns().1, 2);
+    }
+}
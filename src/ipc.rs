@@ -0,0 +1,277 @@
+//! JSON control protocol for GUI frontends
+//!
+//! With `--ipc json` the console controls are replaced by newline-delimited JSON:
+//! one `Command` object per line on stdin, one `Event` object per line on stdout.
+//! Human-readable messages go to stderr so stdout carries only events.
+//!
+//! Both are tagged by a `type` field, e.g.
+//!
+//! ```text
+//! {"type":"set","section":"mix","key":"verb","value":0.4}
+//! {"type":"param_changed","section":"mix","key":"verb","value":0.4}
+//! ```
+//!
+//! Settings are addressed by the same section and key as in presets and config
+//! files, and are validated the same way.
+
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::chain::EffectChain;
+use crate::clip::ClipIndicator;
+use crate::meter::{MeterReading, Meters};
+use crate::preset::{apply_value, Value};
+use crate::tempo::TapTempo;
+
+/// Whether `--ipc json` is active
+static JSON_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Switch status messages to stderr so stdout carries only JSON events
+pub fn enable_json_mode() {
+    JSON_MODE.store(true, Ordering::Relaxed);
+}
+
+/// Whether `--ipc json` is active
+pub fn json_mode() -> bool {
+    JSON_MODE.load(Ordering::Relaxed)
+}
+
+/// Print a human-readable status line: stdout normally, stderr in JSON mode
+///
+/// In raw key mode the terminal doesn't return to the first column on a newline,
+/// so lines end in `\r\n` there.
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::ipc::json_mode() {
+            eprintln!($($arg)*)
+        } else if $crate::keys::raw_mode() {
+            print!("{}\r\n", format!($($arg)*).replace('\n', "\r\n"))
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+pub use status;
+
+/// A command from the frontend
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Command {
+    /// Change a setting, addressed like a preset entry (e.g. mix.verb)
+    Set { section: String, key: String, value: Value },
+    /// Enable or disable a chain stage
+    Enable { stage: String, enabled: bool },
+    /// Register a tap tempo tap
+    Tap,
+    /// Start learning the noise profile
+    NoiseLearn,
+    /// Stop learning and apply the noise profile
+    NoiseApply,
+    /// Clear the latched clip indicators
+    ClearClip,
+    /// Clear all effect tails and filter state
+    Reset,
+    /// Mute, clear all effect state and return to passthrough
+    Panic,
+    /// Report the signal chain and tempo
+    Status,
+    /// Stop the application
+    Quit,
+}
+
+/// An event for the frontend
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// A setting was changed
+    ParamChanged { section: String, key: String, value: Value },
+    /// A chain stage was enabled or disabled
+    StageEnabled { stage: String, enabled: bool },
+    /// The tempo changed
+    Tempo { bpm: f64 },
+    /// Noise profile learning started (true) or the learned profile is applied (false)
+    NoiseReduction { learning: bool },
+    /// Signal chain description and tempo (reply to status, reset and panic)
+    Status { chain: String, tempo_bpm: f64 },
+    /// Peak levels since the previous meter event, the smoothed input envelope,
+    /// total underruns and the smoothed output callback load in percent
+    Meter { input_peak: f64, output_peak: f64, input_envelope: f64, underruns: u64, load_percent: f64 },
+    /// Latched clip indicators (sent while either is set)
+    Clip { input: bool, output: bool },
+    /// A command failed or a message was not understood
+    Error { message: String },
+}
+
+/// Write an event as one line of JSON on stdout
+pub fn emit(event: &Event) {
+    match serde_json::to_string(event) {
+        Ok(line) => println!("{}", line),
+        Err(e) => eprintln!("Cannot encode event: {}", e),
+    }
+}
+
+/// Read JSON commands from stdin until `quit` or end of input
+pub fn command_thread(chain: Arc<Mutex<EffectChain>>, running: Arc<AtomicBool>, meters: Arc<Meters>) {
+    let mut tap_tempo = TapTempo::new();
+
+    for line in std::io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let command = match serde_json::from_str::<Command>(&line) {
+            Ok(command) => command,
+            Err(e) => {
+                emit(&Event::Error { message: format!("Invalid command: {}", e) });
+                continue;
+            }
+        };
+        let mut chain_guard = chain.lock().unwrap();
+        let event = match command {
+            Command::Set { section, key, value } => apply_value(&mut chain_guard, &section, &key, value.clone())
+                .map(|()| Event::ParamChanged { section, key, value }),
+            Command::Enable { stage, enabled } => chain_guard
+                .set_enabled(&stage, enabled)
+                .map(|()| Event::StageEnabled { stage, enabled }),
+            Command::Tap => match tap_tempo.tap(Instant::now()) {
+                Some(bpm) => {
+                    chain_guard.set_tempo_bpm(bpm);
+                    Ok(Event::Tempo { bpm })
+                }
+                None => continue,
+            },
+            Command::NoiseLearn => {
+                let _ = chain_guard.set_enabled("nr", true);
+                match chain_guard.denoiser_mut() {
+                    Some(denoiser) => {
+                        denoiser.start_learning();
+                        Ok(Event::NoiseReduction { learning: true })
+                    }
+                    None => Err("No noise reduction stage in the chain".to_string()),
+                }
+            }
+            Command::NoiseApply => match chain_guard.denoiser_mut().map(|denoiser| denoiser.finish_learning()) {
+                Some(true) => Ok(Event::NoiseReduction { learning: false }),
+                Some(false) => Err("No noise heard yet - keeping the previous profile".to_string()),
+                None => Err("No noise reduction stage in the chain".to_string()),
+            },
+            Command::ClearClip => {
+                meters.clip.clear();
+                Ok(Event::Clip { input: false, output: false })
+            }
+            Command::Reset | Command::Panic | Command::Status => {
+                if command == Command::Reset {
+                    chain_guard.reset();
+                } else if command == Command::Panic {
+                    chain_guard.panic();
+                }
+                Ok(Event::Status {
+                    chain: chain_guard.describe(),
+                    tempo_bpm: chain_guard.tempo_bpm(),
+                })
+            }
+            Command::Quit => break,
+        };
+        drop(chain_guard);
+
+        emit(&event.unwrap_or_else(|message| Event::Error { message }));
+    }
+
+    running.store(false, Ordering::Relaxed);
+}
+
+/// Emit a meter reading, plus the clip indicators while either is latched
+pub fn report_meters(reading: &MeterReading, clip: &ClipIndicator) {
+    emit(&Event::Meter {
+        input_peak: f64::from(reading.input_peak),
+        output_peak: f64::from(reading.output_peak),
+        input_envelope: f64::from(reading.input_envelope),
+        underruns: reading.underruns,
+        load_percent: f64::from(reading.load_percent),
+    });
+
+    let (input, output) = (clip.input_clipped(), clip.output_clipped());
+    if input || output {
+        emit(&Event::Clip { input, output });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_round_trip<T>(value: T, json: &str)
+    where
+        T: Serialize + for<'de> Deserialize<'de> + PartialEq + std::fmt::Debug,
+    {
+        assert_eq!(serde_json::to_string(&value).unwrap(), json);
+        assert_eq!(serde_json::from_str::<T>(json).unwrap(), value);
+    }
+
+    #[test]
+    fn test_command_round_trip() {
+        let set = |value| Command::Set {
+            section: "reverb".to_string(),
+            key: "wet".to_string(),
+            value,
+        };
+        assert_round_trip(set(Value::Number(0.4)), r#"{"type":"set","section":"reverb","key":"wet","value":0.4}"#);
+        assert_round_trip(set(Value::Bool(true)), r#"{"type":"set","section":"reverb","key":"wet","value":true}"#);
+        assert_round_trip(set(Value::Text("1/8.".to_string())), r#"{"type":"set","section":"reverb","key":"wet","value":"1/8."}"#);
+        assert_round_trip(
+            set(Value::List(vec!["verb".to_string(), "dist".to_string()])),
+            r#"{"type":"set","section":"reverb","key":"wet","value":["verb","dist"]}"#,
+        );
+        assert_round_trip(
+            Command::Enable { stage: "dist".to_string(), enabled: true },
+            r#"{"type":"enable","stage":"dist","enabled":true}"#,
+        );
+        assert_round_trip(Command::Tap, r#"{"type":"tap"}"#);
+        assert_round_trip(Command::NoiseLearn, r#"{"type":"noise_learn"}"#);
+        assert_round_trip(Command::NoiseApply, r#"{"type":"noise_apply"}"#);
+        assert_round_trip(Command::ClearClip, r#"{"type":"clear_clip"}"#);
+        assert_round_trip(Command::Reset, r#"{"type":"reset"}"#);
+        assert_round_trip(Command::Panic, r#"{"type":"panic"}"#);
+        assert_round_trip(Command::Status, r#"{"type":"status"}"#);
+        assert_round_trip(Command::Quit, r#"{"type":"quit"}"#);
+
+        assert!(serde_json::from_str::<Command>(r#"{"type":"explode"}"#).is_err());
+    }
+
+    #[test]
+    fn test_event_round_trip() {
+        assert_round_trip(
+            Event::ParamChanged {
+                section: "delay".to_string(),
+                key: "sync".to_string(),
+                value: Value::Text("1/8t".to_string()),
+            },
+            r#"{"type":"param_changed","section":"delay","key":"sync","value":"1/8t"}"#,
+        );
+        assert_round_trip(
+            Event::StageEnabled { stage: "verb".to_string(), enabled: false },
+            r#"{"type":"stage_enabled","stage":"verb","enabled":false}"#,
+        );
+        assert_round_trip(Event::Tempo { bpm: 120.5 }, r#"{"type":"tempo","bpm":120.5}"#);
+        assert_round_trip(Event::NoiseReduction { learning: true }, r#"{"type":"noise_reduction","learning":true}"#);
+        assert_round_trip(
+            Event::Status { chain: "in -> verb -> out".to_string(), tempo_bpm: 90.0 },
+            r#"{"type":"status","chain":"in -> verb -> out","tempo_bpm":90.0}"#,
+        );
+        assert_round_trip(
+            Event::Meter { input_peak: 0.5, output_peak: 0.25, input_envelope: 0.125, underruns: 3, load_percent: 12.5 },
+            r#"{"type":"meter","input_peak":0.5,"output_peak":0.25,"input_envelope":0.125,"underruns":3,"load_percent":12.5}"#,
+        );
+        assert_round_trip(Event::Clip { input: true, output: false }, r#"{"type":"clip","input":true,"output":false}"#);
+        assert_round_trip(
+            Event::Error { message: "bad".to_string() },
+            r#"{"type":"error","message":"bad"}"#,
+        );
+    }
+}
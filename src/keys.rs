@@ -0,0 +1,308 @@
+//! Raw key input
+//!
+//! With `--keys raw` the console reads single keystrokes instead of lines, so
+//! footswitches and other performance keys act immediately, without Enter. The
+//! full line commands stay available behind `:` (or Enter), which reads one
+//! command with its own small line editor: Backspace deletes, Up and Down recall
+//! earlier commands (see `history`) and Esc cancels.
+//!
+//! Raw mode is enabled by a `RawTerminal` guard that restores the terminal when
+//! it is dropped; a panic hook restores it too, whichever thread panics. Key
+//! releases are only reported by terminals that support the keyboard
+//! enhancement protocol (kitty, WezTerm, foot, ...); elsewhere momentary
+//! footswitches can't tell when a key is let go.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crossterm::event::{
+    self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+    PushKeyboardEnhancementFlags,
+};
+use crossterm::style::Print;
+use crossterm::terminal::{Clear, ClearType};
+use crossterm::{cursor, execute, terminal};
+
+use crate::footswitch::{Footswitches, SwitchEvent};
+use crate::history::CommandHistory;
+
+/// Whether the terminal is currently in raw mode
+static RAW_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the terminal is currently in raw mode (output needs `\r\n` line endings)
+pub fn raw_mode() -> bool {
+    RAW_MODE.load(Ordering::Relaxed)
+}
+
+/// Keys the dispatch table knows about
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Esc,
+    Backspace,
+    Up,
+    Down,
+    /// Ctrl-C (raw mode doesn't turn it into a signal)
+    CtrlC,
+}
+
+/// What happened to a key
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyState {
+    Press,
+    /// Auto-repeat while held
+    Repeat,
+    Release,
+}
+
+/// What a keystroke does
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyAction {
+    /// Press or release a bound footswitch
+    Footswitch(char, SwitchEvent),
+    /// Tap the tempo
+    Tap,
+    /// Load the next preset in the preset directory
+    NextPreset,
+    /// Load the previous preset in the preset directory
+    PrevPreset,
+    /// Mute and clear everything
+    Panic,
+    /// Read one line command
+    Command,
+    /// Show the key list
+    Help,
+    /// Stop the program
+    Quit,
+}
+
+/// Keys with a fixed action, for the help text
+pub const KEY_HELP: &[(&str, &str)] = &[
+    ("space", "tap the tempo"),
+    ("!", "panic"),
+    ("] / [", "load the next / previous preset in presets/"),
+    (": or Enter", "type a line command (e.g., w 0.3); Up / Down recall earlier ones"),
+    ("?", "show these keys"),
+    ("q, Esc or Ctrl-C", "quit"),
+    ("<footswitch key>", "switch its stage (bind with the fs command)"),
+];
+
+/// Map a keystroke to an action
+///
+/// Footswitches see presses and releases of their key; every other action fires
+/// on the press only, ignoring auto-repeat.
+pub fn dispatch(key: Key, state: KeyState, footswitches: &Footswitches) -> Option<KeyAction> {
+    if let Key::Char(c) = key
+        && footswitches.is_bound(c)
+    {
+        return match state {
+            KeyState::Press => Some(KeyAction::Footswitch(c, SwitchEvent::Press)),
+            KeyState::Release => Some(KeyAction::Footswitch(c, SwitchEvent::Release)),
+            KeyState::Repeat => None,
+        };
+    }
+    if state != KeyState::Press {
+        return None;
+    }
+
+    match key {
+        Key::Char(' ') => Some(KeyAction::Tap),
+        Key::Char('!') => Some(KeyAction::Panic),
+        Key::Char(']') => Some(KeyAction::NextPreset),
+        Key::Char('[') => Some(KeyAction::PrevPreset),
+        Key::Char(':') | Key::Enter => Some(KeyAction::Command),
+        Key::Char('?') => Some(KeyAction::Help),
+        Key::Char('q') | Key::Esc | Key::CtrlC => Some(KeyAction::Quit),
+        Key::Char(_) | Key::Backspace | Key::Up | Key::Down => None,
+    }
+}
+
+/// Wait up to `timeout` for a keystroke the dispatch table knows about
+pub fn read_key(timeout: Duration) -> io::Result<Option<(Key, KeyState)>> {
+    if !event::poll(timeout)? {
+        return Ok(None);
+    }
+    let Event::Key(key_event) = event::read()? else {
+        return Ok(None);
+    };
+
+    let key = match key_event.code {
+        KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => Key::CtrlC,
+        KeyCode::Char(c) => Key::Char(c),
+        KeyCode::Enter => Key::Enter,
+        KeyCode::Esc => Key::Esc,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Up => Key::Up,
+        KeyCode::Down => Key::Down,
+        _ => return Ok(None),
+    };
+    let state = match key_event.kind {
+        KeyEventKind::Press => KeyState::Press,
+        KeyEventKind::Repeat => KeyState::Repeat,
+        KeyEventKind::Release => KeyState::Release,
+    };
+    Ok(Some((key, state)))
+}
+
+/// Read a line command in raw mode after a `: ` prompt, with Up and Down
+/// recalling earlier commands from `history`
+///
+/// The entered line is added to the history. Returns None if the line is
+/// cancelled with Esc or Ctrl-C, or the program stops while it is being typed.
+pub fn read_command(history: &mut CommandHistory, running: &AtomicBool) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    redraw_command(&line)?;
+    while running.load(Ordering::Relaxed) {
+        let Some((key, state)) = read_key(Duration::from_millis(100))? else {
+            continue;
+        };
+        if state == KeyState::Release {
+            continue;
+        }
+        match key {
+            Key::Char(c) => line.push(c),
+            Key::Backspace => {
+                line.pop();
+            }
+            Key::Up => history.older(&mut line),
+            Key::Down => history.newer(&mut line),
+            Key::Enter => {
+                print!("\r\n");
+                history.push(&line);
+                return Ok(Some(line));
+            }
+            Key::Esc | Key::CtrlC => break,
+        }
+        redraw_command(&line)?;
+    }
+    print!("\r\n");
+    history.end_recall();
+    Ok(None)
+}
+
+/// Show the line command being typed in place of the current terminal line
+fn redraw_command(line: &str) -> io::Result<()> {
+    execute!(
+        io::stdout(),
+        cursor::MoveToColumn(0),
+        Clear(ClearType::CurrentLine),
+        Print(format!(": {}", line))
+    )
+}
+
+/// Guard that keeps the terminal in raw mode while it lives
+pub struct RawTerminal {
+    /// Whether key release reporting was turned on (and must be turned off)
+    enhanced: bool,
+}
+
+impl RawTerminal {
+    /// Switch the terminal to raw mode, with key releases where supported
+    pub fn enable() -> io::Result<Self> {
+        install_panic_hook();
+        terminal::enable_raw_mode()?;
+        RAW_MODE.store(true, Ordering::Relaxed);
+
+        let enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false)
+            && execute!(
+                io::stdout(),
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )
+            .is_ok();
+        Ok(Self { enhanced })
+    }
+
+    /// Whether the terminal reports key releases
+    pub fn reports_releases(&self) -> bool {
+        self.enhanced
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        if self.enhanced {
+            let _ = execute!(io::stdout(), PopKeyboardEnhancementFlags);
+        }
+        restore();
+    }
+}
+
+/// Leave raw mode for a moment, e.g. to read a line with echo and editing
+pub fn suspend() {
+    restore();
+}
+
+/// Return to raw mode after `suspend`
+pub fn resume() -> io::Result<()> {
+    terminal::enable_raw_mode()?;
+    RAW_MODE.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Back to normal line input
+fn restore() {
+    let _ = terminal::disable_raw_mode();
+    RAW_MODE.store(false, Ordering::Relaxed);
+    let _ = io::stdout().flush();
+}
+
+/// Restore the terminal before any panic message is printed
+fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore();
+        previous(info);
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::footswitch::{Footswitch, SwitchMode};
+
+    #[test]
+    fn test_dispatch_table() {
+        let none = Footswitches::default();
+        assert_eq!(dispatch(Key::Char(' '), KeyState::Press, &none), Some(KeyAction::Tap));
+        assert_eq!(dispatch(Key::Char('!'), KeyState::Press, &none), Some(KeyAction::Panic));
+        assert_eq!(dispatch(Key::Char(']'), KeyState::Press, &none), Some(KeyAction::NextPreset));
+        assert_eq!(dispatch(Key::Char('['), KeyState::Press, &none), Some(KeyAction::PrevPreset));
+        assert_eq!(dispatch(Key::Char(':'), KeyState::Press, &none), Some(KeyAction::Command));
+        assert_eq!(dispatch(Key::Enter, KeyState::Press, &none), Some(KeyAction::Command));
+        assert_eq!(dispatch(Key::Char('?'), KeyState::Press, &none), Some(KeyAction::Help));
+        assert_eq!(dispatch(Key::Char('q'), KeyState::Press, &none), Some(KeyAction::Quit));
+        assert_eq!(dispatch(Key::Esc, KeyState::Press, &none), Some(KeyAction::Quit));
+        assert_eq!(dispatch(Key::CtrlC, KeyState::Press, &none), Some(KeyAction::Quit));
+        assert_eq!(dispatch(Key::Char('z'), KeyState::Press, &none), None);
+        assert_eq!(dispatch(Key::Up, KeyState::Press, &none), None);
+
+        // Only presses act; holding or letting go of a key does nothing
+        assert_eq!(dispatch(Key::Char(' '), KeyState::Repeat, &none), None);
+        assert_eq!(dispatch(Key::Char('!'), KeyState::Release, &none), None);
+    }
+
+    #[test]
+    fn test_footswitch_keys() {
+        let mut footswitches = Footswitches::default();
+        footswitches.bind('b', Footswitch::new("boost", SwitchMode::Momentary, false));
+        footswitches.bind('!', Footswitch::new("dist", SwitchMode::Latching, false));
+
+        assert_eq!(
+            dispatch(Key::Char('b'), KeyState::Press, &footswitches),
+            Some(KeyAction::Footswitch('b', SwitchEvent::Press))
+        );
+        assert_eq!(dispatch(Key::Char('b'), KeyState::Repeat, &footswitches), None);
+        assert_eq!(
+            dispatch(Key::Char('b'), KeyState::Release, &footswitches),
+            Some(KeyAction::Footswitch('b', SwitchEvent::Release))
+        );
+
+        // A bound key takes over its fixed action
+        assert_eq!(
+            dispatch(Key::Char('!'), KeyState::Press, &footswitches),
+            Some(KeyAction::Footswitch('!', SwitchEvent::Press))
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Low-frequency oscillator
+//!
+//! Shared by the modulation effects so they all offer the same waveforms with the
+//! same names. The phase runs from 0.0 to 1.0 per cycle; a phase offset shifts the
+//! waveform against another LFO at the same rate (a quarter cycle puts a sine a
+//! quarter turn ahead, for quadrature or stereo-spread modulation). The random
+//! waveform holds a new value for each cycle (sample and hold).
+//!
+//! The rate is either free in Hz or synced to the tempo, one cycle per note
+//! value. A synced LFO is retimed when the tempo changes and carries on from
+//! where it is in its cycle, unless it is set to restart on every tempo or note
+//! value change (to line the cycle up with the beat again).
+
+use std::f64::consts::TAU;
+
+use crate::dsp::Xorshift32;
+use crate::tempo::NoteValue;
+
+/// LFO waveform
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    /// Hard alternation between the extremes
+    Square,
+    /// Ramp from -1 up to 1
+    SawUp,
+    /// Ramp from 1 down to -1
+    SawDown,
+    /// A new random value each cycle
+    Random,
+}
+
+impl LfoShape {
+    /// Every waveform, in the order the controls list them
+    pub const ALL: [LfoShape; 6] = [
+        LfoShape::Sine,
+        LfoShape::Triangle,
+        LfoShape::Square,
+        LfoShape::SawUp,
+        LfoShape::SawDown,
+        LfoShape::Random,
+    ];
+
+    /// Parse the short name used by the controls (sine, tri, square, sawup, sawdown, random)
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|shape| shape.name() == name)
+    }
+
+    /// Short name used by the controls
+    pub fn name(&self) -> &'static str {
+        match self {
+            LfoShape::Sine => "sine",
+            LfoShape::Triangle => "tri",
+            LfoShape::Square => "square",
+            LfoShape::SawUp => "sawup",
+            LfoShape::SawDown => "sawdown",
+            LfoShape::Random => "random",
+        }
+    }
+
+    /// Names of every waveform joined by `separator`, for messages
+    pub fn names(separator: &str) -> String {
+        Self::ALL.map(|shape| shape.name()).join(separator)
+    }
+
+    /// Waveform value (-1.0 to 1.0) at `phase` (0.0 to 1.0), with `held` as the
+    /// random value of the current cycle
+    fn value(&self, phase: f64, held: f64) -> f64 {
+        match self {
+            LfoShape::Sine => (TAU * phase).sin(),
+            LfoShape::Triangle => 4.0 * ((phase + 0.75) % 1.0 - 0.5).abs() - 1.0,
+            LfoShape::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            LfoShape::SawUp => 2.0 * phase - 1.0,
+            LfoShape::SawDown => 1.0 - 2.0 * phase,
+            LfoShape::Random => held,
+        }
+    }
+}
+
+/// Free-running low-frequency oscillator
+#[derive(Debug, Clone)]
+pub struct Lfo {
+    /// Waveform
+    shape: LfoShape,
+    /// Rate in Hz
+    rate_hz: f64,
+    /// Phase (0.0 to 1.0)
+    phase: f64,
+    /// Phase offset in cycles (0.0 to 1.0)
+    phase_offset: f64,
+    /// Offset phase of the previous sample, to spot the start of a cycle
+    last_phase: f64,
+    /// Random value held for the current cycle
+    held: f64,
+    /// Random step generator
+    random: Xorshift32,
+    /// Note value the rate follows (None = free rate)
+    sync: Option<NoteValue>,
+    /// Whether a tempo or note value change restarts the cycle
+    restart_on_sync: bool,
+}
+
+impl Default for Lfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lfo {
+    /// Create a 1 Hz sine LFO
+    pub fn new() -> Self {
+        Self {
+            shape: LfoShape::Sine,
+            rate_hz: 1.0,
+            phase: 0.0,
+            phase_offset: 0.0,
+            last_phase: 1.0,
+            held: 0.0,
+            random: Xorshift32::new(0x9E37_79B9),
+            sync: None,
+            restart_on_sync: false,
+        }
+    }
+
+    /// Return the value (-1.0 to 1.0) at the current phase and advance by one sample
+    pub fn next(&mut self, sample_rate: f64) -> f64 {
+        let phase = (self.phase + self.phase_offset) % 1.0;
+        if phase < self.last_phase {
+            self.held = self.random.bipolar();
+        }
+        self.last_phase = phase;
+        self.phase = (self.phase + self.rate_hz / sample_rate) % 1.0;
+        self.shape.value(phase, self.held)
+    }
+
+    /// Set the rate in Hz, ending any tempo sync
+    pub fn set_rate_hz(&mut self, rate_hz: f64) {
+        self.sync = None;
+        self.rate_hz = rate_hz.max(0.0);
+    }
+
+    /// Follow the tempo with one cycle per note value
+    pub fn set_sync(&mut self, note: NoteValue, bpm: f64) {
+        self.sync = Some(note);
+        self.set_tempo_bpm(bpm);
+    }
+
+    /// Current tempo sync note value
+    pub fn sync(&self) -> Option<NoteValue> {
+        self.sync
+    }
+
+    /// Retime a synced LFO for a new tempo (a free rate is left as it is)
+    pub fn set_tempo_bpm(&mut self, bpm: f64) {
+        if let Some(note) = self.sync {
+            self.rate_hz = note.rate_hz(bpm);
+            if self.restart_on_sync {
+                self.reset();
+            }
+        }
+    }
+
+    /// Set whether a tempo or note value change restarts the cycle
+    pub fn set_restart_on_sync(&mut self, restart: bool) {
+        self.restart_on_sync = restart;
+    }
+
+    /// Whether a tempo or note value change restarts the cycle
+    pub fn restart_on_sync(&self) -> bool {
+        self.restart_on_sync
+    }
+
+    /// Current rate in Hz
+    pub fn rate_hz(&self) -> f64 {
+        self.rate_hz
+    }
+
+    /// Set the phase offset in cycles (wrapped to 0.0 to 1.0; 0.25 is a quarter turn)
+    pub fn set_phase_offset(&mut self, offset: f64) {
+        self.phase_offset = offset.rem_euclid(1.0);
+    }
+
+    /// Current phase offset in cycles
+    pub fn phase_offset(&self) -> f64 {
+        self.phase_offset
+    }
+
+    /// Set the waveform
+    pub fn set_shape(&mut self, shape: LfoShape) {
+        self.shape = shape;
+    }
+
+    /// Current waveform
+    pub fn shape(&self) -> LfoShape {
+        self.shape
+    }
+
+    /// Restart from the beginning of the cycle
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+        self.last_phase = 1.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Values of one cycle of an LFO stepping four samples per cycle
+    fn quarter_points(shape: LfoShape) -> Vec<f64> {
+        let mut lfo = Lfo::new();
+        lfo.set_shape(shape);
+        (0..4).map(|_| lfo.next(4.0)).collect()
+    }
+
+    #[test]
+    fn test_waveforms_at_key_phases() {
+        // Phases 0, 0.25, 0.5 and 0.75
+        let sine = quarter_points(LfoShape::Sine);
+        let expected = [0.0, 1.0, 0.0, -1.0];
+        assert!(sine.iter().zip(expected).all(|(value, expected)| (value - expected).abs() < 1e-12), "{:?}", sine);
+        assert_eq!(quarter_points(LfoShape::Triangle), vec![0.0, 1.0, 0.0, -1.0]);
+        assert_eq!(quarter_points(LfoShape::Square), vec![1.0, 1.0, -1.0, -1.0]);
+        assert_eq!(quarter_points(LfoShape::SawUp), vec![-1.0, -0.5, 0.0, 0.5]);
+        assert_eq!(quarter_points(LfoShape::SawDown), vec![1.0, 0.5, 0.0, -0.5]);
+
+        // Random holds one value through a cycle and picks another for the next
+        let mut lfo = Lfo::new();
+        lfo.set_shape(LfoShape::Random);
+        let values: Vec<f64> = (0..40).map(|_| lfo.next(4.0)).collect();
+        for cycle in values.chunks(4) {
+            assert!(cycle.iter().all(|&value| value == cycle[0] && (-1.0..=1.0).contains(&value)));
+        }
+        assert!(values.chunks(4).zip(values.chunks(4).skip(1)).all(|(a, b)| a[0] != b[0]));
+
+        for shape in LfoShape::ALL {
+            assert_eq!(LfoShape::from_name(shape.name()), Some(shape));
+        }
+    }
+
+    #[test]
+    fn test_phase_offset_between_two_lfos() {
+        let sample_rate = 1000.0;
+        for shape in [LfoShape::Sine, LfoShape::Triangle] {
+            let mut reference = Lfo::new();
+            let mut ahead = Lfo::new();
+            for lfo in [&mut reference, &mut ahead] {
+                lfo.set_shape(shape);
+                lfo.set_rate_hz(2.0);
+            }
+            // A quarter cycle at 2 Hz is 125 samples
+            ahead.set_phase_offset(0.25);
+            let reference: Vec<f64> = (0..1000).map(|_| reference.next(sample_rate)).collect();
+            let ahead: Vec<f64> = (0..875).map(|_| ahead.next(sample_rate)).collect();
+            for (i, value) in ahead.iter().enumerate() {
+                assert!((value - reference[i + 125]).abs() < 1e-9, "{:?} sample {}", shape, i);
+            }
+        }
+
+        // The ramps start part way up; offsets wrap into one cycle
+        let mut lfo = Lfo::new();
+        lfo.set_shape(LfoShape::SawUp);
+        lfo.set_phase_offset(0.25);
+        assert_eq!(lfo.next(sample_rate), -0.5);
+        lfo.set_phase_offset(-0.25);
+        assert_eq!(lfo.phase_offset(), 0.75);
+    }
+
+    #[test]
+    fn test_tempo_sync_keeps_or_restarts_the_cycle() {
+        let sample_rate = 1000.0;
+        let mut lfo = Lfo::new();
+        lfo.set_shape(LfoShape::SawUp);
+        lfo.set_sync(NoteValue::parse("1/4").unwrap(), 120.0);
+        assert_eq!(lfo.rate_hz(), 2.0);
+
+        // Retimed mid-cycle, the ramp carries on from where it was
+        for _ in 0..100 {
+            lfo.next(sample_rate);
+        }
+        lfo.set_tempo_bpm(60.0);
+        assert_eq!(lfo.rate_hz(), 1.0);
+        assert!((lfo.next(sample_rate) + 0.6).abs() < 1e-9);
+
+        // Set to restart, a tempo or note value change starts the cycle again
+        lfo.set_restart_on_sync(true);
+        lfo.set_sync(NoteValue::parse("1/8t").unwrap(), 60.0);
+        assert!((lfo.rate_hz() - 3.0).abs() < 1e-9);
+        assert_eq!(lfo.next(sample_rate), -1.0);
+
+        // A free rate ends the sync, and tempo changes no longer move it
+        lfo.set_rate_hz(0.5);
+        lfo.set_tempo_bpm(180.0);
+        assert_eq!((lfo.sync(), lfo.rate_hz()), (None, 0.5));
+    }
+}
@@ -0,0 +1,70 @@
+//! Guitar effects engine
+//!
+//! The effects, the chain that runs them and the `Engine` that connects the chain
+//! to the audio devices. The `mac-pedals` program is a command line frontend over
+//! this library; other frontends drive the same `Engine`.
+
+pub mod adt;
+pub mod autogain;
+pub mod automation;
+pub mod autopan;
+pub mod block;
+pub mod boost;
+pub mod calibration;
+pub mod chain;
+pub mod clip;
+pub mod config;
+pub mod console;
+pub mod convert;
+pub mod delay;
+pub mod denoise;
+pub mod denormal;
+pub mod diffuser;
+pub mod distortion;
+pub mod dsp;
+pub mod effect;
+pub mod engine;
+pub mod error;
+pub mod exciter;
+pub mod filter;
+pub mod freeverb;
+pub mod footswitch;
+pub mod gate;
+pub mod history;
+pub mod host;
+pub mod input_channel;
+pub mod insert;
+pub mod ipc;
+pub mod keys;
+pub mod lfo;
+pub mod meter;
+pub mod metronome;
+pub mod midi;
+pub mod mix;
+pub mod monitor;
+pub mod multiband;
+pub mod offline;
+pub mod osc;
+pub mod output_map;
+pub mod pad;
+pub mod pitchshift;
+pub mod pitchtracker;
+pub mod plate;
+pub mod preset;
+pub mod reverb;
+pub mod reverse;
+pub mod safety;
+pub mod selftest;
+pub mod shutdown;
+pub mod silence;
+pub mod stereo;
+pub mod stutter;
+pub mod swell;
+pub mod tempo;
+pub mod tilt;
+pub mod tremolo;
+pub mod trim;
+pub mod warmup;
+
+pub use engine::{Engine, EngineOptions};
+pub use error::Error;
@@ -1,624 +1,765 @@
-use cpal::{
-    traits::{DeviceTrait, HostTrait, StreamTrait},
-    SampleFormat,
-};
-use freeverb::Freeverb;
-use ringbuf::{RingBuffer, Producer, Consumer};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
 use std::thread;
-use std::time::Duration;
-
-mod distortion;
-use distortion::{Distortion, DistortionType};
-
-// Function to print detailed device configuration
-fn print_device_config(input_device: &cpal::Device, output_device: &cpal::Device, 
-                      input_config: &cpal::SupportedStreamConfig, 
-                      output_config: &cpal::SupportedStreamConfig) -> Result<(), Box<dyn std::error::Error>> {
-    println!("\n=== Detailed Device Configuration ===");
-    println!("Input Device: {}", input_device.name()?);
-    println!("  Sample Rate: {} Hz", input_config.sample_rate().0);
-    println!("  Channels: {}", input_config.channels());
-    println!("  Sample Format: {:?}", input_config.sample_format());
-    println!("  Buffer Size: {:?}", input_config.buffer_size());
-    
-    println!("\nOutput Device: {}", output_device.name()?);
-    println!("  Sample Rate: {} Hz", output_config.sample_rate().0);
-    println!("  Channels: {}", output_config.channels());
-    println!("  Sample Format: {:?}", output_config.sample_format());
-    println!("  Buffer Size: {:?}", output_config.buffer_size());
-    
-    // Check for potential issues
-    if input_config.sample_rate() != output_config.sample_rate() {
-        println!("\n⚠️  WARNING: Sample rate mismatch!");
-        println!("   Input: {} Hz, Output: {} Hz", 
-                input_config.sample_rate().0, output_config.sample_rate().0);
+use std::time::{Duration, Instant};
+
+use mac_pedals::{
+    automation, block, calibration, chain, config, footswitch, host, input_channel, ipc, keys,
+    meter, midi, offline, osc, output_map, safety, selftest, shutdown, trim,
+};
+use mac_pedals::engine::add_insert_loop;
+use mac_pedals::console::Console;
+use mac_pedals::{Engine, EngineOptions, Error};
+
+use calibration::Calibration;
+use chain::{EffectChain, StageLevels};
+use config::Config;
+use footswitch::SwitchEvent;
+use selftest::SelfTestProbe;
+use trim::InputPad;
+use offline::WavFormat;
+use output_map::OutputMap;
+use host::AudioHost;
+use keys::{KeyAction, RawTerminal};
+use ipc::status;
+use input_channel::{InputChannel, MonoSum};
+
+// Command line options
+struct CliOptions {
+    host: AudioHost,
+    output_map: OutputMap,
+    input_channel: InputChannel,
+    input_file: Option<String>,
+    output_file: Option<String>,
+    wav_format: WavFormat,
+    normalize: Option<f64>,
+    config: Config,
+    config_path: Option<String>,
+    watch_config: bool,
+    json_ipc: bool,
+    raw_keys: bool,
+    osc_port: Option<u16>,
+    midi_port: Option<String>,
+    osc_return: Option<std::net::SocketAddr>,
+    insert_pipe: Option<(String, String)>,
+    selftest: bool,
+    max_feedback: Option<f64>,
+    input_pad: Option<InputPad>,
+    calibration_path: String,
+    dither: bool,
+    flush_denormals: bool,
+    list_hosts: bool,
+    block_size: usize,
+    debug_levels: bool,
+}
+
+// Parse command line arguments
+fn parse_args() -> Result<CliOptions, Error> {
+    let mut options = CliOptions {
+        host: AudioHost::default(),
+        output_map: OutputMap::default(),
+        input_channel: InputChannel::default(),
+        input_file: None,
+        output_file: None,
+        wav_format: WavFormat::default(),
+        normalize: None,
+        config: Config::default(),
+        config_path: None,
+        watch_config: false,
+        json_ipc: false,
+        raw_keys: false,
+        osc_port: None,
+        midi_port: None,
+        osc_return: None,
+        insert_pipe: None,
+        selftest: false,
+        max_feedback: None,
+        input_pad: None,
+        calibration_path: calibration::DEFAULT_CALIBRATION_FILE.to_string(),
+        dither: false,
+        flush_denormals: true,
+        list_hosts: false,
+        block_size: 1,
+        debug_levels: false,
+    };
+    let mut wav_bits = None;
+    let mut wav_sample_format = None;
+    let mut mono_sum = None;
+    let mut output_map = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--host" => {
+                let value = args.next().ok_or("--host requires a value (default or a name from --list-hosts)")?;
+                options.host = AudioHost::parse(&value)?;
+            }
+            "--list-hosts" => {
+                options.list_hosts = true;
+            }
+            "--block-size" => {
+                let value = args.next().ok_or("--block-size requires a frame count")?;
+                options.block_size = block::parse_block_size(&value)?;
+            }
+            "--output-map" => {
+                let value = args.next().ok_or("--output-map requires a value (duplicate|front-only|custom:<L,R,M,->)")?;
+                output_map = Some(OutputMap::parse(&value)?);
+            }
+            "--input-channel" => {
+                let value = args.next().ok_or("--input-channel requires a value (left|right|mix)")?;
+                options.input_channel = InputChannel::parse(&value)?;
+            }
+            "--mono-sum" => {
+                let value = args.next().ok_or("--mono-sum requires a value (avg|sum|rms)")?;
+                mono_sum = Some(MonoSum::parse(&value)?);
+            }
+            "--input-file" => {
+                options.input_file = Some(args.next().ok_or("--input-file requires a path")?);
+            }
+            "--output-file" => {
+                options.output_file = Some(args.next().ok_or("--output-file requires a path")?);
+            }
+            "--wav-bits" => {
+                let value = args.next().ok_or("--wav-bits requires a value (16|24|32)")?;
+                wav_bits = Some(value.parse::<u16>().map_err(|_| format!("Invalid --wav-bits value: {}", value))?);
+            }
+            "--normalize" => {
+                let value = args.next().ok_or("--normalize requires a peak level in dBFS (e.g. -1)")?;
+                let target_dbfs = value.parse::<f64>().map_err(|_| format!("Invalid --normalize value: {}", value))?;
+                if !(offline::MIN_NORMALIZE_DBFS..=0.0).contains(&target_dbfs) {
+                    return Err(format!("--normalize must be between {} and 0 dBFS", offline::MIN_NORMALIZE_DBFS).into());
+                }
+                options.normalize = Some(target_dbfs);
+            }
+            "--wav-format" => {
+                let value = args.next().ok_or("--wav-format requires a value (int|float)")?;
+                wav_sample_format = Some(WavFormat::parse_sample_format(&value)?);
+            }
+            "--config" => {
+                let path = args.next().ok_or("--config requires a path")?;
+                options.config = Config::load(&path)?;
+                options.config_path = Some(path);
+            }
+            "--watch-config" => {
+                options.watch_config = true;
+            }
+            "--ipc" => {
+                options.json_ipc = match args.next().as_deref() {
+                    Some("json") => true,
+                    Some("text") => false,
+                    _ => return Err("--ipc requires a value (text|json)".into()),
+                };
+            }
+            "--keys" => {
+                options.raw_keys = match args.next().as_deref() {
+                    Some("raw") => true,
+                    Some("line") => false,
+                    _ => return Err("--keys requires a value (line|raw)".into()),
+                };
+            }
+            "--osc-port" => {
+                let value = args.next().ok_or("--osc-port requires a port number")?;
+                options.osc_port = Some(value.parse().map_err(|_| format!("Invalid --osc-port value: {}", value))?);
+            }
+            "--midi" => {
+                options.midi_port = Some(args.next().ok_or("--midi requires a MIDI input port name")?);
+            }
+            "--osc-return" => {
+                let value = args.next().ok_or("--osc-return requires an address (ip:port)")?;
+                options.osc_return = Some(value.parse().map_err(|_| format!("Invalid --osc-return address: {}", value))?);
+            }
+            "--max-feedback" => {
+                let value = args.next().ok_or("--max-feedback requires a value (0-0.99)")?;
+                let ceiling = value.parse::<f64>().map_err(|_| format!("Invalid --max-feedback value: {}", value))?;
+                if !(0.0..=safety::DEFAULT_MAX_FEEDBACK).contains(&ceiling) {
+                    return Err(format!("--max-feedback must be 0 to {}", safety::DEFAULT_MAX_FEEDBACK).into());
+                }
+                options.max_feedback = Some(ceiling);
+            }
+            "--pad" => {
+                let value = args.next().ok_or("--pad requires a value (0|10|20)")?;
+                options.input_pad = Some(InputPad::from_db(&value).ok_or_else(|| format!("Invalid --pad value: {} (expected 0, 10 or 20)", value))?);
+            }
+            "--flush-denormals" => {
+                options.flush_denormals = match args.next().as_deref() {
+                    Some("on") => true,
+                    Some("off") => false,
+                    _ => return Err("--flush-denormals requires a value (on|off)".into()),
+                };
+            }
+            "--dither" => {
+                options.dither = true;
+            }
+            "--selftest" => {
+                options.selftest = true;
+            }
+            "--calibration" => {
+                options.calibration_path = args.next().ok_or("--calibration requires a path")?;
+            }
+            "--debug-levels" => {
+                options.debug_levels = true;
+            }
+            "--insert-pipe" => {
+                let send = args.next().ok_or("--insert-pipe requires a send path and a return path")?;
+                let ret = args.next().ok_or("--insert-pipe requires a send path and a return path")?;
+                options.insert_pipe = Some((send, ret));
+            }
+            _ => return Err(format!("Unknown argument: {}", arg).into()),
+        }
     }
-    
-    if input_config.channels() != output_config.channels() {
-        println!("\n⚠️  WARNING: Channel count mismatch!");
-        println!("   Input: {} channels, Output: {} channels", 
-                input_config.channels(), output_config.channels());
+
+    if options.watch_config && options.config_path.is_none() {
+        return Err("--watch-config requires --config <file>".into());
     }
-    
-    if input_config.sample_format() != output_config.sample_format() {
-        println!("\n⚠️  WARNING: Sample format mismatch!");
-        println!("   Input: {:?}, Output: {:?}", 
-                input_config.sample_format(), output_config.sample_format());
+
+    if options.raw_keys && options.json_ipc {
+        return Err("--keys raw can't be combined with --ipc json".into());
     }
-    
-    println!("=====================================\n");
-    Ok(())
+
+    if options.osc_return.is_some() && options.osc_port.is_none() {
+        return Err("--osc-return requires --osc-port <port>".into());
+    }
+
+    if options.input_file.is_some() != options.output_file.is_some() {
+        return Err("--input-file and --output-file must be used together".into());
+    }
+
+    if options.normalize.is_some() && options.input_file.is_none() {
+        return Err("--normalize only applies to offline rendering (--input-file)".into());
+    }
+
+    if options.selftest && options.input_file.is_some() {
+        return Err("--selftest can't be combined with --input-file".into());
+    }
+
+    if options.insert_pipe.is_some() && options.input_file.is_some() {
+        return Err("--insert-pipe can't be combined with --input-file".into());
+    }
+
+    if options.debug_levels && options.input_file.is_some() {
+        return Err("--debug-levels only applies to live audio, not --input-file".into());
+    }
+
+    // Float output is always 32-bit; integer output defaults to 24-bit
+    if wav_bits.is_some() || wav_sample_format.is_some() {
+        let sample_format = wav_sample_format.unwrap_or(hound::SampleFormat::Int);
+        let bits = wav_bits.unwrap_or(match sample_format {
+            hound::SampleFormat::Float => 32,
+            hound::SampleFormat::Int => 24,
+        });
+        options.wav_format = WavFormat::new(bits, sample_format)?;
+    }
+
+    // An upmix matrix in the config replaces the --output-map presets
+    options.output_map = match (output_map, options.config.upmix.clone()) {
+        (Some(_), Some(_)) => return Err("--output-map can't be combined with an [output] upmix in the config".into()),
+        (Some(map), None) | (None, Some(map)) => map,
+        (None, None) => OutputMap::default(),
+    };
+
+    // The law only matters when the channels are mixed
+    if let Some(law) = mono_sum {
+        options.input_channel = options.input_channel.with_mono_sum(law);
+    }
+
+    Ok(options)
 }
 
-fn input_thread(
-    reverb_clone: Arc<Mutex<Freeverb>>, 
-    distortion_clone: Arc<Mutex<Distortion>>, 
-    effect_selection: Arc<AtomicBool>,
-    running_clone: Arc<AtomicBool>
-) {
+fn input_thread(mut console: Console, running_clone: Arc<AtomicBool>) {
     let stdin = std::io::stdin();
     let mut buffer = String::new();
+    print_controls();
     
+    while running_clone.load(Ordering::Relaxed) {
+        buffer.clear();
+        if stdin.read_line(&mut buffer).is_ok() {
+            console.history_mut().push(&buffer);
+            console.run(buffer.trim());
+        }
+    }
+}
+
+// Single-keystroke console for --keys raw; line commands are read after ':'
+fn raw_input_thread(mut console: Console, running_clone: Arc<AtomicBool>, reports_releases: bool) {
+    keys::suspend();
+    print_controls();
+    print_keys(reports_releases);
+    let _ = keys::resume();
+
+    while running_clone.load(Ordering::Relaxed) {
+        let (key, state) = match keys::read_key(Duration::from_millis(100)) {
+            Ok(Some(key)) => key,
+            Ok(None) => continue,
+            Err(e) => {
+                status!("Key input stopped: {}", e);
+                return;
+            }
+        };
+
+        match keys::dispatch(key, state, console.footswitches()) {
+            Some(KeyAction::Footswitch(key, event)) => {
+                // Without release reports a press is a press and release, as in line mode
+                let events: &[SwitchEvent] = if reports_releases { &[event] } else { &[SwitchEvent::Press, SwitchEvent::Release] };
+                console.footswitch(key, events);
+            }
+            Some(KeyAction::Tap) => {
+                console.run("tap")
+            }
+            Some(KeyAction::NextPreset) => {
+                console.run("next")
+            }
+            Some(KeyAction::PrevPreset) => {
+                console.run("prev")
+            }
+            Some(KeyAction::Panic) => {
+                console.run("panic")
+            }
+            Some(KeyAction::Command) => {
+                // One line command, typed with history recall, then run with normal output
+                let line = match keys::read_command(console.history_mut(), &running_clone) {
+                    Ok(Some(line)) => line,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        status!("Key input stopped: {}", e);
+                        return;
+                    }
+                };
+                keys::suspend();
+                console.run(line.trim());
+                let _ = keys::resume();
+            }
+            Some(KeyAction::Help) => {
+                keys::suspend();
+                print_keys(reports_releases);
+                let _ = keys::resume();
+            }
+            Some(KeyAction::Quit) => running_clone.store(false, Ordering::Relaxed),
+            None => {}
+        }
+    }
+}
+
+// Print the raw key mode keys
+fn print_keys(reports_releases: bool) {
+    println!("\n=== Keys (--keys raw) ===");
+    for (key, action) in keys::KEY_HELP {
+        println!("  {} - {}", key, action);
+    }
+    if !reports_releases {
+        println!("  (this terminal doesn't report key releases, so momentary footswitches only blip)");
+    }
+}
+
+// Print the console controls
+fn print_controls() {
     println!("\nControls:");
+    println!("Setting a parameter switches its stage on; the other stages stay as they are, so effects stack (off <stage> switches one off)");
     println!("=== Reverb Controls (activate with any reverb parameter) ===");
-    println!("  w <0-1> - Set wet level (e.g., w 0.5)");
-    println!("  d <0-1> - Set dry level (e.g., d 0.5)");
+    println!("  w <0-1> - Set the reverb mix, 0 dry to 1 tail only (same as mix verb, e.g., w 0.3)");
     println!("  r <0-1> - Set room size (e.g., r 0.8)");
+    println!("  decay <seconds> - Set how long the tail takes to fall by 60 dB, 0.75 to 12 s, instead of the room size (e.g., decay 2.5)");
     println!("  p <0-1> - Set dampening (e.g., p 0.4)");
     println!("  x <0-1> - Set stereo width (e.g., x 0.5)");
+    println!("  diff <0-1> - Set diffusion: 0 discrete echoes, 1 smeared (e.g., diff 0.7)");
+    println!("  pre <ms> - Set reverb pre-delay, 0 to 250 ms (e.g., pre 30)");
+    println!("  shim <0-1> - Set shimmer, octave-up feedback in the tail (e.g., shim 0.5)");
+    println!("  rmod <0-1> - Set tail modulation, a slow detune that keeps the tail evolving, 0 off (e.g., rmod 0.4)");
+    println!("  rmod rate <hz> - Set the tail modulation rate, 0.05 to 5 Hz (default 0.5)");
+    println!("  verb-algo <freeverb|plate> - Choose the reverb algorithm, crossfading to it (default freeverb)");
+    println!("  damp <hz|off> - Low-pass the reverb tail, 200 Hz to 20 kHz (e.g., damp 3000)");
+    println!("  vhp <hz|off> - High-pass the reverb input to keep lows out of the tail, 20 Hz to 2 kHz (e.g., vhp 300)");
+    println!("  vlp <hz|off> - Low-pass the reverb input to keep it from turning harsh, 500 Hz to 20 kHz (e.g., vlp 5000)");
+    println!("  gatereverb <on|off> - Cut the reverb tail with a gate keyed from the dry input");
+    println!("  freeze [on|off] - Hold the reverb tail indefinitely (no argument toggles)");
+    println!("  freeze release <ms> - Time a released freeze takes to ease into the decay, 0 to 10000 ms (default 500)");
+    println!("  export-ir <file.wav> - Write the impulse response of the current reverb settings to a WAV file");
+    println!("  gatehold <ms> / gaterel <ms> - Set the reverb gate hold / release time (e.g., gatehold 150)");
+    println!("  gatethr <0-1> - Set the dry level that opens the reverb gate (e.g., gatethr 0.01)");
+    println!("  gatehyst <dB> - Close the reverb gate only this far below the open level, 0 to 24 dB (e.g., gatehyst 6)");
+    println!("  gatekey <dry|tail> - Open the reverb gate on the dry input (default) or on the tail's own level");
+    println!("  duck <0-1> - Dip the reverb tail while playing and swell it back in the gaps, 0 off (e.g., duck 0.6)");
+    println!("  duck attack <ms> / duck release <ms> - Set how fast the tail dips / swells back (e.g., duck release 400)");
+    println!("  automate <r|p|x|diff|shim|pre> <goal> <seconds>[s] [lin|exp] - Sweep a reverb parameter to a goal (e.g., automate r 0.9 30s)");
+    println!("  automate / automate stop - List the running automations / stop them where they are");
     println!("\n=== Distortion Controls (activate with any distortion parameter) ===");
     println!("  dr <0-1> - Set drive (e.g., dr 0.5)");
     println!("  l <0-1> - Set level (e.g., l 0.5)");
     println!("  t <0-1> - Set tone (e.g., t 0.5)");
+    println!("  sag <0-1> - Set amp sag: sustained loud playing pulls the drive down (e.g., sag 0.5)");
+    println!("  knee <0-1> - Set the soft clipper knee: 0 gentle, 0.5 tanh, 1 hard (e.g., knee 0.7)");
+    println!("  tight <hz> - High-pass the signal before the clipper at 20-1000 Hz for a tighter high-gain tone, 0 off (e.g., tight 120)");
+    println!("  bass <hz> - Keep the lows below 40-500 Hz clean while the rest distorts, 0 off (e.g., bass 150)");
     println!("  bc <rate> <depth> - Set bit crusher params (e.g., bc 0.3 0.4)");
+    println!("  bc dither <on|off> - Dither before the bit crusher's quantization for a smoother, hissier texture");
+    println!("  bc jitter <0-1> - Randomize when the bit crusher latches a new sample, for grittier decimation (0 = off)");
+    println!("  os <on|off> - Run the clipper at twice the sample rate to cut aliasing on high notes and heavy drive");
     println!("  soft - Switch to soft clipping");
     println!("  hard - Switch to hard clipping");
     println!("  bit - Switch to bit crusher");
     println!("  wave - Switch to wavefolder");
     println!("  over - Switch to overdrive");
+    println!("  cascade - Switch to the three-stage gain cascade (high-gain amp)");
+    println!("  cascade <1-3> <gain> - Set a cascade stage's gain, 0 to 20 (e.g., cascade 2 5)");
+    println!("\n=== Multiband Distortion Controls (activate with any multiband parameter) ===");
+    println!("  mbx <low Hz> <high Hz> - Set crossover frequencies (e.g., mbx 200 2000)");
+    println!("  mbt <band> <type> - Set band type: soft, hard, bit, wave, over, cascade or clean (e.g., mbt low clean)");
+    println!("  mbd <band> <0-1> - Set band drive (e.g., mbd high 0.8)");
+    println!("  mbl <band> <0-1> - Set band level (e.g., mbl mid 0.6)");
+    println!("  Bands: low, mid, high");
+    println!("\n=== Delay Controls (activate with any delay parameter) ===");
+    println!("  dt <ms> - Set delay time, 1 to 2000 ms (e.g., dt 350)");
+    println!("  dfb <0-1> - Set delay feedback, limited to 0.95 (e.g., dfb 0.4)");
+    println!("  dl <0-1> - Set echo level (e.g., dl 0.5)");
+    println!("  pingpong <on|off> - Bounce the repeats between left and right");
+    println!("  dly sync <note|off> - Sync the delay to the tempo: 1/4, 1/8. (dotted), 1/8t (triplet)");
+    println!("  tap - Tap the tempo (press Enter on each beat); retimes a synced delay, else sets one beat");
+    println!("\n=== Metronome ===");
+    println!("  click <on|off> - Switch the practice click, accented on the downbeat");
+    println!("  bpm <30-300> - Set the tempo for the click, the delay and synced modulation (e.g., bpm 96)");
+    println!("  click vol <0-1> - Set the click volume (e.g., click vol 0.3)");
+    println!("  click sig <beats>/<note> - Set the time signature (e.g., click sig 3/4, click sig 6/8)");
+    println!("\n=== Pad ===");
+    println!("  pad capture - Freeze the last 0.4 s of the wet signal into a loop under your playing");
+    println!("  pad clear - Fade the pad out");
+    println!("  pad level <0-1> - Set the pad level (e.g., pad level 0.3)");
+    println!("  pad pitch <semitones> - Shift the pad, -24 to 24 (e.g., pad pitch -12)");
+    println!("\n=== Noise Reduction Controls (activate with any nr command; adds ~23 ms latency) ===");
+    println!("  nr learn - Start learning the noise profile (stop playing, let the hiss run)");
+    println!("  nr apply - Stop learning and reduce noise at the learned profile");
+    println!("  nr <dB> - Set the reduction, 0 to 60 dB (e.g., nr 12)");
+    println!("\n=== Stereo Controls ===");
+    println!("  width <0-2> - Set stereo width: 0 mono, 1 unchanged, 2 wide (e.g., width 1.5)");
+    println!("  pan rate <hz> - Set the auto-pan sweep rate, 0.05 to 20 Hz (e.g., pan rate 2)");
+    println!("  pan depth <0-1> - Set how far the auto-pan sweeps, 1 fully left to right (e.g., pan depth 0.8)");
+    println!("  pan phase <0-1> - Set where in the cycle the sweep is, 0.5 swings the other way first (e.g., pan phase 0.5)");
+    println!("  pan shape <sine|tri|square|sawup|sawdown|random> - Set the auto-pan waveform");
+    println!("  pan sync <note|off> - Sweep once per note value of the tempo instead of in Hz (e.g., pan sync 1/2)");
+    println!("  pan restart <on|off> - Restart the sweep when the tempo or note value changes (off keeps it smooth)");
+    println!("\n=== Tremolo Controls (activate with any trem parameter) ===");
+    println!("  trem rate <hz> - Set the tremolo rate, 0.05 to 20 Hz (e.g., trem rate 5)");
+    println!("  trem depth <0-1> - Set how far the level dips, 1 down to silence (e.g., trem depth 0.7)");
+    println!("  trem shape <sine|tri|square|sawup|sawdown|random> - Set the tremolo waveform (square chops)");
+    println!("  trem sync <note|off> - Pulse once per note value of the tempo instead of in Hz (e.g., trem sync 1/8)");
+    println!("  trem restart <on|off> - Restart the cycle when the tempo or note value changes (off keeps it smooth)");
+    println!("\n=== Reverse Delay Controls (activate with any reverse parameter) ===");
+    println!("  reverse <on|off> - Switch the reverse delay: each chunk of playing comes back backwards one chunk later");
+    println!("  reverse time <ms> - Set the chunk length, 50 to 2000 ms (e.g., reverse time 400)");
+    println!("  reverse mix <0-1> - Set how much of the reversed signal is heard (e.g., reverse mix 0.7)");
+    println!("\n=== Double Tracking Controls (activate with any adt parameter) ===");
+    println!("  adt <on|off> - Switch the double tracker: the dry take on one side, a wandering delayed copy on the other");
+    println!("  adt delay <ms> - Set how late the copy is, 20 to 40 ms (e.g., adt delay 25)");
+    println!("  adt spread <0-1> - Set how far apart the takes are panned, 1 fully left and right (e.g., adt spread 0.8)");
+    println!("\n=== Exciter Controls ===");
+    println!("  excite <on|off> - Switch the high-frequency harmonic exciter");
+    println!("  excite <0-1> - Set the amount of even harmonics added to the highs (e.g., excite 0.4)");
+    println!("\n=== Stutter Controls ===");
+    println!("  stutter - Repeat the last slice of audio for the hold time, then return to live");
+    println!("  stutter slice <ms> - Set the repeated slice, 10 to 1000 ms (default 125)");
+    println!("  stutter hold <ms> - Set how long a trigger repeats, up to 10000 ms (default 500)");
+    println!("\n=== Swell Controls ===");
+    println!("  swell <on|off> - Fade each note in from silence, hiding the pick attack");
+    println!("  swell <ms> - Set how long a note takes to reach full level, 10 to 2000 ms (default 300)");
+    println!("\n=== Boost Controls ===");
+    println!("  boost <on|off> - Switch the clean solo boost at the end of the chain");
+    println!("  boost <dB> - Set the boost, 0 to +12 dB (e.g., boost 6)");
+    println!("\n=== Chain Controls (stages: nr, swell, dist, multi, excite, stutter, trem, reverse, delay, verb, adt, width, pan, boost) ===");
+    println!("  on <stage> - Enable a stage (e.g., on dist)");
+    println!("  off <stage> - Disable a stage (e.g., off verb)");
+    println!("  mix <stage> <0-1> - Set a stage's wet/dry mix (e.g., mix verb 0.3)");
+    println!("  trim <dB> - Set output trim, -24 to +12 dB (e.g., trim -3)");
+    println!("  tilt <-1 to 1> - Set input tilt: pickup loading, 1 darker, -1 brighter, 0 flat (e.g., tilt 0.5)");
+    println!("  autogain <on|off> - Keep each stage's output level matched to its input");
+    println!("  dcblock <on|off> - Remove DC offset from the final output (on by default)");
+    println!("  trails <on|off> - Let delay and reverb tails ring out when switched off (off by default)");
+    println!("  switchfade <ms> - Crossfade time when a stage is switched on or off, 0 to 100 ms (5 ms by default)");
+    println!("  midi - List the MIDI CC bindings (with --midi)");
+    println!("  midi learn <setting> - Bind the next CC that arrives to a setting (e.g., midi learn mix.verb); midi learn off cancels");
+    println!("  monitor <on|off> - Mute the output while input capture and processing continue (on by default)");
+    println!("  autosuspend <on|off|seconds> - Stop processing after a stretch of silence, 1 to 600 s (default 10)");
+    println!("  loop - Show frames dropped or missing in the effects loop (stage 'loop' with --insert-pipe)");
+    println!("  order <stage> ... - Set processing order (e.g., order verb dist)");
+    println!("  chain - Show the signal chain");
+    println!("  reset - Clear all effect tails and filter state");
+    println!("\n=== Presets ===");
+    println!("  load <file> [full|params] - Apply a preset file; 'params' keeps the current stage on/off states and order");
+    println!("  next / prev - Load the next / previous preset in the presets/ directory, in name order");
+    println!("\n=== Preset Morph ===");
+    println!("  morph a <file> / morph b <file> - Load a preset file (config file effect sections) into slot A / B");
+    println!("  morph <0-1> - Blend from preset A (0) to B (1); numbers glide, types and switches change at 0.5");
+    println!("\n=== Footswitches ===");
+    println!("  fs <key> <stage> <momentary|latch> - Bind a key to a stage (e.g., fs b boost momentary)");
+    println!("  fs <key> off - Remove a footswitch");
+    println!("  fs - List the footswitches");
+    println!("  <key> - Press a footswitch (momentary switches need held keys; this is a press and release)");
     println!("\n=== Global Controls ===");
     println!("  dry - Set to dry only (no effects)");
     println!("  pass - Switch to passthrough mode");
+    println!("  panic - Mute, clear all effect state and return to passthrough");
+    println!("  clear-clip - Clear the latched INPUT CLIP / OUTPUT CLIP warnings");
+    println!("  pad-0 / pad-10 / pad-20 - Attenuate the raw input by 0, 10 or 20 dB for hot pickups and line-level sources");
+    println!("  calibrate save - Save the input pad and output trim for the devices in use, applied whenever they are opened");
+    println!("  env - Show the smoothed input envelope sent to visualizers with the meters");
+    println!("  env attack <ms> / env release <ms> - Set how fast the input envelope rises / falls (e.g., env release 500)");
+    println!("  cpu - Show the processing load as a percentage of the time each buffer lasts");
+    println!("  history - List the recent commands (with --keys raw, Up / Down recall them after ':')");
     println!("  q - Quit");
-    
-    while running_clone.load(Ordering::Relaxed) {
-        buffer.clear();
-        if stdin.read_line(&mut buffer).is_ok() {
-            let input = buffer.trim();
-            let parts: Vec<&str> = input.split_whitespace().collect();
-            
-            if parts.len() == 2 {
-                let value: Result<f64, _> = parts[1].parse();
-                if let Ok(val) = value {
-                    let val = val.clamp(0.0, 1.0);
-                    
-                    match parts[0] {
-                        // Reverb controls - activate reverb
-                        "w" => {
-                            effect_selection.store(true, Ordering::Relaxed);
-                            let mut reverb_guard = reverb_clone.lock().unwrap();
-                            reverb_guard.set_wet(val);
-                            println!("Reverb activated - Wet level set to {:.2}, Effect selection: {}", val, effect_selection.load(Ordering::Relaxed));
-                        }
-                        "d" => {
-                            effect_selection.store(true, Ordering::Relaxed);
-                            let mut reverb_guard = reverb_clone.lock().unwrap();
-                            reverb_guard.set_dry(val);
-                            println!("Reverb activated - Dry level set to {:.2}", val);
-                        }
-                        "r" => {
-                            effect_selection.store(true, Ordering::Relaxed);
-                            let mut reverb_guard = reverb_clone.lock().unwrap();
-                            reverb_guard.set_room_size(val);
-                            println!("Reverb activated - Room size set to {:.2}", val);
-                        }
-                        "p" => {
-                            effect_selection.store(true, Ordering::Relaxed);
-                            let mut reverb_guard = reverb_clone.lock().unwrap();
-                            reverb_guard.set_dampening(val);
-                            println!("Reverb activated - Dampening set to {:.2}", val);
-                        }
-                        "x" => {
-                            effect_selection.store(true, Ordering::Relaxed);
-                            let mut reverb_guard = reverb_clone.lock().unwrap();
-                            reverb_guard.set_width(val);
-                            println!("Reverb activated - Stereo width set to {:.2}", val);
-                        }
-                        // Distortion controls - activate distortion
-                        "dr" => {
-                            effect_selection.store(false, Ordering::Relaxed);
-                            let mut distortion_guard = distortion_clone.lock().unwrap();
-                            distortion_guard.set_drive(val);
-                            println!("Distortion activated - Drive set to {:.2}, Effect selection: {}", val, effect_selection.load(Ordering::Relaxed));
-                        }
-                        "l" => {
-                            effect_selection.store(false, Ordering::Relaxed);
-                            let mut distortion_guard = distortion_clone.lock().unwrap();
-                            distortion_guard.set_level(val);
-                            println!("Distortion activated - Level set to {:.2}", val);
-                        }
-                        "t" => {
-                            effect_selection.store(false, Ordering::Relaxed);
-                            let mut distortion_guard = distortion_clone.lock().unwrap();
-                            distortion_guard.set_tone(val);
-                            println!("Distortion activated - Tone set to {:.2}", val);
-                        }
-                        _ => {}
-                    }
-                }
-            } else if parts.len() == 3 && parts[0] == "bc" {
-                // Bit crusher parameters (rate and depth)
-                let rate: Result<f64, _> = parts[1].parse();
-                let depth: Result<f64, _> = parts[2].parse();
-                
-                if let (Ok(rate_val), Ok(depth_val)) = (rate, depth) {
-                    effect_selection.store(false, Ordering::Relaxed);
-                    let mut distortion_guard = distortion_clone.lock().unwrap();
-                    distortion_guard.set_distortion_type(DistortionType::BitCrusher);
-                    distortion_guard.set_bit_crusher_params(rate_val, depth_val);
-                    println!("Distortion activated - Bit crusher: rate={:.2}, depth={:.2}", rate_val, depth_val);
-                }
-            } else if parts.len() == 1 {
-                match parts[0] {
-                    // Distortion type selection
-                    "soft" => {
-                        effect_selection.store(false, Ordering::Relaxed);
-                        let mut distortion_guard = distortion_clone.lock().unwrap();
-                        distortion_guard.set_distortion_type(DistortionType::Soft);
-                        println!("Distortion activated - Soft clipping selected");
-                    }
-                    "hard" => {
-                        effect_selection.store(false, Ordering::Relaxed);
-                        let mut distortion_guard = distortion_clone.lock().unwrap();
-                        distortion_guard.set_distortion_type(DistortionType::Hard);
-                        println!("Distortion activated - Hard clipping selected");
-                    }
-                    "bit" => {
-                        effect_selection.store(false, Ordering::Relaxed);
-                        let mut distortion_guard = distortion_clone.lock().unwrap();
-                        distortion_guard.set_distortion_type(DistortionType::BitCrusher);
-                        println!("Distortion activated - Bit crusher selected");
-                    }
-                    "wave" => {
-                        effect_selection.store(false, Ordering::Relaxed);
-                        let mut distortion_guard = distortion_clone.lock().unwrap();
-                        distortion_guard.set_distortion_type(DistortionType::Wavefolder);
-                        println!("Distortion activated - Wavefolder selected");
-                    }
-                    "over" => {
-                        effect_selection.store(false, Ordering::Relaxed);
-                        let mut distortion_guard = distortion_clone.lock().unwrap();
-                        distortion_guard.set_distortion_type(DistortionType::Overdrive);
-                        println!("Distortion activated - Overdrive selected");
-                    }
-                    // Global controls
-                    "dry" => {
-                        let mut reverb_guard = reverb_clone.lock().unwrap();
-                        reverb_guard.set_wet(0.0);
-                        reverb_guard.set_dry(1.0);
-                        let mut distortion_guard = distortion_clone.lock().unwrap();
-                        distortion_guard.set_level(0.0);
-                        println!("Set to dry only (no effects)");
-                    }
-                    "pass" => {
-                        let mut reverb_guard = reverb_clone.lock().unwrap();
-                        reverb_guard.set_wet(0.0);
-                        reverb_guard.set_dry(1.0);
-                        reverb_guard.set_room_size(0.0);
-                        reverb_guard.set_dampening(0.0);
-                        reverb_guard.set_width(0.5);
-                        let mut distortion_guard = distortion_clone.lock().unwrap();
-                        distortion_guard.set_level(0.0);
-                        println!("Switched to passthrough mode (no effects)");
-                    }
-                    "q" => {
-                        running_clone.store(false, Ordering::Relaxed);
-                        break;
-                    }
-                    _ => {}
-                }
-            }
-        }
-    }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Get the default host
-    let host = cpal::default_host();
-
-    // Get the default input and output devices
-    let input_device = host.default_input_device()
-        .ok_or("No input device found")?;
-    let output_device = host.default_output_device()
-        .ok_or("No output device found")?;
+    let options = parse_args()?;
+    if options.list_hosts {
+        let default = cpal::default_host().id();
+        println!("Available audio hosts (built in: {}):", host::host_names(cpal::ALL_HOSTS));
+        for id in cpal::available_hosts() {
+            let marker = if id == default { " (default)" } else { "" };
+            println!("  {}{}", id.name().to_lowercase(), marker);
+        }
+        return Ok(());
+    }
+    if options.json_ipc {
+        ipc::enable_json_mode();
+    }
+    // Offline mode: render a file instead of opening the audio devices
+    if let (Some(input_file), Some(output_file)) = (&options.input_file, &options.output_file) {
+        let (spec, _) = offline::read_wav(input_file)?;
+        let engine = Engine::offline(spec.sample_rate as usize)?;
+        configure_engine(&engine, &options);
+        let mut chain = engine.chain().lock().unwrap();
+        options.config.preset.apply(&mut chain)?;
+        println!("Rendering {} -> {} ({})", input_file, output_file, options.wav_format);
+        let gain = offline::render_file(input_file, output_file, &mut chain, options.wav_format, options.input_channel, options.normalize)?;
+        match (options.normalize, gain) {
+            (Some(target_dbfs), Some(gain)) => println!("Normalized to {:.1} dBFS ({:+.1} dB)", target_dbfs, 20.0 * gain.log10()),
+            (Some(_), None) => println!("Output is silent; not normalized"),
+            _ => {}
+        }
+        println!("Done");
+        return Ok(());
+    }
 
-    println!("Input device: {}", input_device.name()?);
-    println!("Output device: {}", output_device.name()?);
+    // Open the devices and build the chain at the output rate
+    let mut engine = Engine::open(EngineOptions {
+        host: options.host.clone(),
+        input_device: options.config.input_device.clone(),
+        output_device: options.config.output_device.clone(),
+        ring_size: options.config.ring_size,
+        prime_fill: Some(options.config.prime_fill),
+        buffer_frames: options.config.buffer_frames,
+        input_channel: options.input_channel,
+        output_map: options.output_map.clone(),
+        block_size: options.block_size,
+    })?;
+    configure_engine(&engine, &options);
 
-    // Get the default input and output configs
-    let input_config = input_device.default_input_config().unwrap();
-    let output_config = output_device.default_output_config().unwrap();
+    if options.selftest {
+        return run_selftest(engine);
+    }
 
-    println!("Input config: {:?}", input_config);
-    println!("Output config: {:?}", output_config);
-    
-    // Print detailed device configuration
-    print_device_config(&input_device, &output_device, &input_config, &output_config)?;
+    // Set up the effect chain
+    let insert_ends = {
+        let mut chain = engine.chain().lock().unwrap();
+        let insert_ends = match &options.insert_pipe {
+            Some(_) => Some(add_insert_loop(&mut chain, options.config.ring_size)?),
+            None => None,
+        };
+        // Device calibration first, so an explicit --pad or trim_db in the config wins
+        if let Some((input_name, output_name)) = engine.device_names() {
+            let calibration = Calibration::load(&options.calibration_path)?;
+            let (pad, applied) = calibration.apply(input_name, output_name, &mut chain);
+            if let Some(pad) = pad {
+                engine.input_pad().set(pad);
+            }
+            if !applied.is_empty() {
+                status!("Calibration from {}: {}", options.calibration_path, applied.join(", "));
+            }
+            engine.set_calibration_file(&options.calibration_path);
+        }
+        if let Some(pad) = options.input_pad {
+            engine.input_pad().set(pad);
+        }
+        options.config.preset.apply(&mut chain)?;
+        chain.set_level_taps(options.debug_levels);
+        insert_ends
+    };
 
-    // Create ring buffers for audio data
-    let ring_buffer = RingBuffer::<f32>::new(8192);
-    let (producer, consumer) = ring_buffer.split();
+    // Start the streams
+    engine.start()?;
+    let chain = engine.chain().clone();
 
-    // Create reverb instance
-    let sample_rate = output_config.sample_rate().0 as usize;
-    let reverb = Arc::new(Mutex::new(Freeverb::new(sample_rate)));
-    let distortion = Arc::new(Mutex::new(Distortion::new(sample_rate)));
-    
-    // Configure reverb settings - start with mostly dry signal
-    {
-        let mut reverb_guard = reverb.lock().unwrap();
-        reverb_guard.set_wet(0.1);      // 10% wet signal (start conservative)
-        reverb_guard.set_dry(0.9);      // 90% dry signal
-        reverb_guard.set_room_size(0.5); // Medium room
-        reverb_guard.set_dampening(0.5); // Moderate dampening
-        reverb_guard.set_width(0.5);    // Stereo width
+    // Flag to control the audio processing
+    let running = engine.running().clone();
+    let running_clone = running.clone();
+    // Ctrl-C quits through the same shutdown as q
+    if let Err(e) = shutdown::handle_interrupt(running.clone()) {
+        status!("⚠️  {}", e);
     }
-    // Configure distortion settings
-    {
-        let mut distortion_guard = distortion.lock().unwrap();
-        distortion_guard.set_distortion_type(DistortionType::Soft);
-        distortion_guard.set_drive(0.5);
-        distortion_guard.set_level(0.8);
-        distortion_guard.set_tone(0.5);
-        distortion_guard.set_bit_crusher_params(0.1, 0.5);
+
+    if let (Some(ends), Some((send_path, return_path))) = (insert_ends, options.insert_pipe.clone()) {
+        status!("Effects loop: send {}, return {}", send_path, return_path);
+        ends.spawn(send_path, return_path, running.clone());
     }
 
-    // Effect selection state (true = reverb active, false = distortion active)
-    let effect_selection = Arc::new(AtomicBool::new(true)); // Start with reverb
-    let effect_selection_clone = effect_selection.clone();
+    // Levels and latching clip indicators set by the audio callbacks
+    let meters = engine.meters().clone();
 
-    // Flag to control the audio processing
-    let running = Arc::new(AtomicBool::new(true));
-    let running_clone = running.clone();
+    // Parameter automation steps on its own timer
+    automation::spawn_timer(chain.clone(), running.clone());
+
+    // CC bindings, shared by the MIDI input and the console's learn command
+    let midi_map = options.midi_port.as_ref().map(|_| Arc::new(Mutex::new(options.config.midi.clone())));
+    let _midi_connection = match (&options.midi_port, &midi_map) {
+        (Some(port), Some(map)) => {
+            let connection = midi::connect(port, map.clone(), chain.clone(), options.config_path.clone())?;
+            status!("Listening for MIDI CCs on {}", port);
+            Some(connection)
+        }
+        _ => None,
+    };
 
     // Spawn a thread to handle user input for real-time parameter adjustment
-    let reverb_clone = reverb.clone();
-    let distortion_clone = distortion.clone();
-    thread::spawn(move || input_thread(reverb_clone, distortion_clone, effect_selection_clone, running_clone));
-
-    // Build the input stream
-    let input_stream = build_input_stream(
-        input_device,
-        input_config,
-        producer,
-        running.clone(),
-    )?;
-
-    // Build the output stream
-    let output_stream = build_output_stream(
-        output_device,
-        output_config,
-        consumer,
-        reverb.clone(),
-        distortion.clone(),
-        effect_selection.clone(),
-        running.clone(),
-    )?;
-
-    // Play the streams
-    input_stream.play()?;
-    output_stream.play()?;
-
-    // Wait for the user to stop the program
+    let chain_clone = chain.clone();
+    let meters_clone = meters.clone();
+    let console = engine.console(midi_map.clone());
+    // In raw key mode the guard restores the terminal when main returns
+    let mut raw_terminal = None;
+    if options.json_ipc {
+        thread::spawn(move || ipc::command_thread(chain_clone, running_clone, meters_clone));
+    } else if options.raw_keys {
+        let terminal = RawTerminal::enable()?;
+        let reports_releases = terminal.reports_releases();
+        raw_terminal = Some(terminal);
+        thread::spawn(move || raw_input_thread(console, running_clone, reports_releases));
+    } else {
+        thread::spawn(move || input_thread(console, running_clone));
+    }
+
+    // Accept OSC control messages alongside the console
+    let osc = match options.osc_port {
+        Some(port) => {
+            let server = osc::OscServer::bind(port, options.osc_return)?;
+            server.spawn_listener(chain.clone(), running.clone())?;
+            status!("Listening for OSC on UDP port {}", port);
+            Some(server)
+        }
+        None => None,
+    };
+
+    // Re-apply effect settings when the config file changes
+    if options.watch_config && let Some(path) = options.config_path.clone() {
+        let config = options.config.clone();
+        let chain_clone = chain.clone();
+        let running_clone = running.clone();
+        status!("Watching {} for changes", path);
+        thread::spawn(move || {
+            if let Err(e) = config::watch(&path, config, chain_clone, running_clone) {
+                eprintln!("Config watcher stopped: {}", e);
+            }
+        });
+    }
+
+    // Wait for the user to stop the program, repeating any clip warning every second
+    // (in JSON mode, meter events every 100ms instead; OSC meters are also sent every 100ms)
+    let mut ticks = 0;
     while running.load(Ordering::Relaxed) {
         thread::sleep(Duration::from_millis(100));
+        ticks += 1;
+        if options.json_ipc || osc.is_some() {
+            let reading = meters.take_reading();
+            if options.json_ipc {
+                ipc::report_meters(&reading, &meters.clip);
+            }
+            if let Some(osc) = &osc {
+                osc.send_meters(&reading);
+            }
+        }
+        if meters.take_runaway() {
+            status!("⚠️  RUNAWAY FEEDBACK: output over full scale for 0.5 s - muted and bypassed (panic)");
+        }
+        if !options.json_ipc && ticks % 10 == 0 {
+            if meters.clip.input_clipped() {
+                status!("⚠️  INPUT CLIP (clear-clip to reset)");
+            }
+            if meters.clip.output_clipped() {
+                status!("⚠️  OUTPUT CLIP (clear-clip to reset)");
+            }
+            let load = meters.load_percent();
+            if load > meter::LOAD_WARNING_PERCENT {
+                status!("⚠️  CPU LOAD {:.0}% of the buffer time (dropouts likely; disable stages or raise buffers.buffer_frames)", load);
+            }
+        }
+        if options.debug_levels && ticks % 10 == 0 {
+            let levels = chain.lock().unwrap().take_stage_levels();
+            status!("Levels (peak dBFS, in > out): {}", stage_levels_text(&levels));
+        }
     }
 
-    println!("\nShutting down...");
+    // No recordings are open in live mode
+    engine.stop()?;
+    drop(raw_terminal);
     Ok(())
 }
 
-fn build_input_stream(
-    device: cpal::Device,
-    config: cpal::SupportedStreamConfig,
-    mut producer: Producer<f32>,
-    running: Arc<AtomicBool>,
-) -> Result<cpal::Stream, Box<dyn std::error::Error>> {
-    let err_fn = |err| eprintln!("Input stream error: {}", err);
-    
-    // Capture channel count for the callback
-    let input_channels = config.channels() as usize;
-
-    let stream = match config.sample_format() {
-        SampleFormat::F32 => device.build_input_stream(
-            &config.clone().into(),
-            move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                if !running.load(Ordering::Relaxed) {
-                    return;
-                }
-                
-                // Process audio based on actual channel configuration
-                let samples = if input_channels == 1 {
-                    // Mono input - direct processing
-                    data.to_vec()
-                } else if input_channels == 2 {
-                    // Stereo input - convert to mono by averaging
-                    data.chunks(2)
-                        .map(|chunk| (chunk[0] + chunk[1]) * 0.5)
-                        .collect()
-                } else {
-                    // Multi-channel input - average all channels
-                    data.chunks(input_channels)
-                        .map(|chunk| chunk.iter().sum::<f32>() / input_channels as f32)
-                        .collect()
-                };
-
-                for &sample in &samples {
-                    if producer.push(sample).is_err() {
-                        // Buffer is full, skip this sample
-                        break;
-                    }
-                }
-            },
-            err_fn,
-        )?,
-        SampleFormat::I16 => device.build_input_stream(
-            &config.clone().into(),
-            move |data: &[i16], _: &cpal::InputCallbackInfo| {
-                if !running.load(Ordering::Relaxed) {
-                    return;
-                }
-                
-                // Convert i16 to f32 with proper channel handling
-                let samples: Vec<f32> = if input_channels == 1 {
-                    // Mono input - direct conversion
-                    data.iter()
-                        .map(|&sample| f32::from(sample) / f32::from(i16::MAX))
-                        .collect()
-                } else if input_channels == 2 {
-                    // Stereo input - convert to mono by averaging
-                    data.chunks(2)
-                        .map(|chunk| {
-                            let left = f32::from(chunk[0]) / f32::from(i16::MAX);
-                            let right = f32::from(chunk[1]) / f32::from(i16::MAX);
-                            (left + right) * 0.5
-                        })
-                        .collect()
-                } else {
-                    // Multi-channel input - average all channels
-                    data.chunks(input_channels)
-                        .map(|chunk| {
-                            chunk.iter()
-                                .map(|&sample| f32::from(sample) / f32::from(i16::MAX))
-                                .sum::<f32>() / input_channels as f32
-                        })
-                        .collect()
-                };
-
-                for &sample in &samples {
-                    if producer.push(sample).is_err() {
-                        break;
-                    }
-                }
-            },
-            err_fn,
-        )?,
-        SampleFormat::U16 => device.build_input_stream(
-            &config.clone().into(),
-            move |data: &[u16], _: &cpal::InputCallbackInfo| {
-                if !running.load(Ordering::Relaxed) {
-                    return;
-                }
-                
-                // Convert u16 to f32 with proper channel handling
-                let samples: Vec<f32> = if input_channels == 1 {
-                    // Mono input - direct conversion
-                    data.iter()
-                        .map(|&sample| (f32::from(sample) / f32::from(u16::MAX)) * 2.0 - 1.0)
-                        .collect()
-                } else if input_channels == 2 {
-                    // Stereo input - convert to mono by averaging
-                    data.chunks(2)
-                        .map(|chunk| {
-                            let left = (f32::from(chunk[0]) / f32::from(u16::MAX)) * 2.0 - 1.0;
-                            let right = (f32::from(chunk[1]) / f32::from(u16::MAX)) * 2.0 - 1.0;
-                            (left + right) * 0.5
-                        })
-                        .collect()
-                } else {
-                    // Multi-channel input - average all channels
-                    data.chunks(input_channels)
-                        .map(|chunk| {
-                            chunk.iter()
-                                .map(|&sample| (f32::from(sample) / f32::from(u16::MAX)) * 2.0 - 1.0)
-                                .sum::<f32>() / input_channels as f32
-                        })
-                        .collect()
-                };
-
-                for &sample in &samples {
-                    if producer.push(sample).is_err() {
-                        break;
-                    }
-                }
-            },
-            err_fn,
-        )?,
-        _ => return Err("Unsupported sample format".into()),
-    };
-
-    Ok(stream)
+// Describe the peaks around each stage, flagging a stage that silences a signal
+fn stage_levels_text(levels: &[StageLevels]) -> String {
+    let db = |peak: f64| if peak > 0.0 { format!("{:.1}", 20.0 * peak.log10()) } else { "-inf".to_string() };
+    levels
+        .iter()
+        .map(|stage| match stage.active {
+            false => format!("({} off)", stage.name),
+            true if stage.input_peak > 0.0 && stage.output_peak == 0.0 => {
+                format!("{} {} > {} ⚠️  silenced", stage.name, db(stage.input_peak), db(stage.output_peak))
+            }
+            true => format!("{} {} > {}", stage.name, db(stage.input_peak), db(stage.output_peak)),
+        })
+        .collect::<Vec<_>>()
+        .join(" | ")
 }
 
-fn build_output_stream(
-    device: cpal::Device,
-    config: cpal::SupportedStreamConfig,
-    mut consumer: Consumer<f32>,
-    reverb: Arc<Mutex<Freeverb>>,
-    distortion: Arc<Mutex<Distortion>>,
-    effect_selection: Arc<AtomicBool>,
-    running: Arc<AtomicBool>,
-) -> Result<cpal::Stream, Box<dyn std::error::Error>> {
-    let err_fn = |err| eprintln!("Output stream error: {}", err);
-    
-    // Capture channel count for the callback
-    let output_channels = config.channels() as usize;
-
-    let stream = match config.sample_format() {
-        SampleFormat::F32 => device.build_output_stream(
-            &config.clone().into(),
-            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                if !running.load(Ordering::Relaxed) {
-                    return;
-                }
+// Apply the startup settings before any preset or calibration, so every feedback
+// setting respects the ceiling
+fn configure_engine(engine: &Engine, options: &CliOptions) {
+    if let Some(ceiling) = options.max_feedback {
+        engine.set_max_feedback(ceiling);
+    }
+    engine.set_flush_denormals(options.flush_denormals);
+    engine.set_dither(options.dither);
+}
 
-                for frame in data.chunks_mut(output_channels) {
-                    // Get input sample from ring buffer
-                    let input_sample = consumer.pop().unwrap_or(0.0);
-                    
-                    // Apply effect based on selection
-                    let (left, right) = if effect_selection.load(Ordering::Relaxed) {
-                        // Use reverb
-                        let mut reverb_guard = reverb.lock().unwrap();
-                        reverb_guard.tick((input_sample as f64, input_sample as f64))
-                    } else {
-                        // Use distortion
-                        let mut distortion_guard = distortion.lock().unwrap();
-                        distortion_guard.tick((input_sample as f64, input_sample as f64))
-                    };
-                    
-                    // Fill output frame based on channel configuration
-                    match output_channels {
-                        1 => {
-                            // Mono output - mix stereo reverb to mono
-                            frame[0] = (left + right) as f32 * 0.5;
-                        }
-                        2 => {
-                            // Stereo output - use reverb stereo output
-                            frame[0] = left as f32;
-                            frame[1] = right as f32;
-                        }
-                        _ => {
-                            // Multi-channel output - distribute stereo reverb
-                            frame[0] = left as f32;
-                            frame[1] = right as f32;
-                            // Duplicate stereo signal to remaining channels
-                            for i in 2..frame.len() {
-                                frame[i] = if i % 2 == 0 { left as f32 } else { right as f32 };
-                            }
-                        }
-                    }
-                }
-            },
-            err_fn,
-        )?,
-        SampleFormat::I16 => device.build_output_stream(
-            &config.clone().into(),
-            move |data: &mut [i16], _: &cpal::OutputCallbackInfo| {
-                if !running.load(Ordering::Relaxed) {
-                    return;
-                }
+// Check that a test tone makes the round trip from the input callback through the
+// ring buffer and out of the output callback, then print PASS or FAIL
+fn run_selftest(mut engine: Engine) -> Result<(), Box<dyn std::error::Error>> {
+    // Passthrough chain: only the streams and the buffer are under test
+    let sample_rate = engine.sample_rate();
+    *engine.chain().lock().unwrap() = EffectChain::new(sample_rate);
+    let probe = Arc::new(SelfTestProbe::new());
+    let required = (selftest::REQUIRED_SECONDS * sample_rate as f64) as u64;
 
-                for frame in data.chunks_mut(output_channels) {
-                    let input_sample = consumer.pop().unwrap_or(0.0);
-                    
-                    // Apply effect based on selection
-                    let (left, right) = if effect_selection.load(Ordering::Relaxed) {
-                        // Use reverb
-                        let mut reverb_guard = reverb.lock().unwrap();
-                        reverb_guard.tick((input_sample as f64, input_sample as f64))
-                    } else {
-                        // Use distortion
-                        let mut distortion_guard = distortion.lock().unwrap();
-                        distortion_guard.tick((input_sample as f64, input_sample as f64))
-                    };
-                    
-                    // Fill output frame based on channel configuration
-                    match output_channels {
-                        1 => {
-                            // Mono output - mix stereo reverb to mono
-                            let mono_sample = (left + right) as f32 * 0.5;
-                            frame[0] = (mono_sample * f32::from(i16::MAX)) as i16;
-                        }
-                        2 => {
-                            // Stereo output - use reverb stereo output
-                            frame[0] = (left as f32 * f32::from(i16::MAX)) as i16;
-                            frame[1] = (right as f32 * f32::from(i16::MAX)) as i16;
-                        }
-                        _ => {
-                            // Multi-channel output - distribute stereo reverb
-                            frame[0] = (left as f32 * f32::from(i16::MAX)) as i16;
-                            frame[1] = (right as f32 * f32::from(i16::MAX)) as i16;
-                            // Duplicate stereo signal to remaining channels
-                            for i in 2..frame.len() {
-                                let sample = if i % 2 == 0 { left as f32 } else { right as f32 };
-                                frame[i] = (sample * f32::from(i16::MAX)) as i16;
-                            }
-                        }
-                    }
-                }
-            },
-            err_fn,
-        )?,
-        SampleFormat::U16 => device.build_output_stream(
-            &config.clone().into(),
-            move |data: &mut [u16], _: &cpal::OutputCallbackInfo| {
-                if !running.load(Ordering::Relaxed) {
-                    return;
-                }
+    println!("Self-test: sending a test tone from the input callback through the ring buffer...");
+    if let Err(e) = engine.start_with_probe(Some(probe.clone())) {
+        println!("FAIL: the audio streams could not be started: {}", e);
+        return Err("Self-test failed".into());
+    }
 
-                for frame in data.chunks_mut(output_channels) {
-                    let input_sample = consumer.pop().unwrap_or(0.0);
-                    
-                    // Apply effect based on selection
-                    let (left, right) = if effect_selection.load(Ordering::Relaxed) {
-                        // Use reverb
-                        let mut reverb_guard = reverb.lock().unwrap();
-                        reverb_guard.tick((input_sample as f64, input_sample as f64))
-                    } else {
-                        // Use distortion
-                        let mut distortion_guard = distortion.lock().unwrap();
-                        distortion_guard.tick((input_sample as f64, input_sample as f64))
-                    };
-                    
-                    // Fill output frame based on channel configuration
-                    match output_channels {
-                        1 => {
-                            // Mono output - mix stereo reverb to mono
-                            let mono_sample = (left + right) as f32 * 0.5;
-                            let normalized = (mono_sample + 1.0) * 0.5;
-                            frame[0] = (normalized * f32::from(u16::MAX)) as u16;
-                        }
-                        2 => {
-                            // Stereo output - use reverb stereo output
-                            let left_normalized = (left as f32 + 1.0) * 0.5;
-                            let right_normalized = (right as f32 + 1.0) * 0.5;
-                            frame[0] = (left_normalized * f32::from(u16::MAX)) as u16;
-                            frame[1] = (right_normalized * f32::from(u16::MAX)) as u16;
-                        }
-                        _ => {
-                            // Multi-channel output - distribute stereo reverb
-                            let left_normalized = (left as f32 + 1.0) * 0.5;
-                            let right_normalized = (right as f32 + 1.0) * 0.5;
-                            frame[0] = (left_normalized * f32::from(u16::MAX)) as u16;
-                            frame[1] = (right_normalized * f32::from(u16::MAX)) as u16;
-                            // Duplicate stereo signal to remaining channels
-                            for i in 2..frame.len() {
-                                let sample = if i % 2 == 0 { left as f32 } else { right as f32 };
-                                let normalized = (sample + 1.0) * 0.5;
-                                frame[i] = (normalized * f32::from(u16::MAX)) as u16;
-                            }
-                        }
-                    }
-                }
-            },
-            err_fn,
-        )?,
-        _ => return Err("Unsupported sample format".into()),
-    };
+    let start = Instant::now();
+    let timeout = Duration::from_secs_f64(selftest::TIMEOUT_SECONDS);
+    let mut result = selftest::evaluate(&probe.observations(), required);
+    while result.is_err() && start.elapsed() < timeout {
+        thread::sleep(Duration::from_millis(50));
+        result = selftest::evaluate(&probe.observations(), required);
+    }
+    engine.running().store(false, Ordering::Relaxed);
 
-    Ok(stream)
+    println!("  {}", probe.observations());
+    println!("  {} ring buffer underruns", engine.meters().underruns());
+    match result {
+        Ok(()) => {
+            println!("PASS: audio flowed through the ring buffer in {} ms", start.elapsed().as_millis());
+            Ok(())
+        }
+        Err(e) => {
+            println!("FAIL: {} after {:.1} s", e, selftest::TIMEOUT_SECONDS);
+            Err("Self-test failed".into())
+        }
+    }
 }
@@ -0,0 +1,219 @@
+//! Level meters
+//!
+//! Peak levels, the input envelope, ring buffer underruns, the processing load
+//! and the clip indicators, shared between the audio callbacks and the main
+//! thread. The callbacks update them with relaxed atomic operations once per
+//! buffer; the main thread reads and resets the peaks each time it reports.
+//!
+//! The input envelope is a smoothed level for visualizers (GUI meters, LEDs): the
+//! input callback runs an envelope follower over every sample and publishes its
+//! value at the end of each buffer. Unlike the peaks it isn't reset when read.
+//! The attack and release times are stored here so the console can change them
+//! while the callback reads them.
+//!
+//! The load is the time the output callback spends on a buffer as a percentage of
+//! the time the buffer lasts. At 100% the processing can't keep up and the output
+//! glitches, so a load approaching that means more effects risk xruns.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::clip::ClipIndicator;
+
+/// Share of each new buffer's load in the smoothed load (about a 10-buffer average)
+const LOAD_SMOOTHING: f32 = 0.1;
+/// Smoothed load in percent above which the console warns
+pub const LOAD_WARNING_PERCENT: f32 = 80.0;
+/// Default input envelope attack and release times in milliseconds
+const DEFAULT_ENVELOPE_ATTACK_MS: f32 = 10.0;
+const DEFAULT_ENVELOPE_RELEASE_MS: f32 = 300.0;
+/// Longest input envelope attack and release time in milliseconds
+const MAX_ENVELOPE_MS: f32 = 5000.0;
+
+/// Peak levels since the previous reading, the current input envelope, total
+/// underruns and the smoothed load
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeterReading {
+    pub input_peak: f32,
+    pub output_peak: f32,
+    pub input_envelope: f32,
+    pub underruns: u64,
+    pub load_percent: f32,
+}
+
+/// Time spent processing a buffer as a percentage of the buffer's duration
+pub fn load_percent(processing: Duration, frames: usize, sample_rate: u32) -> f32 {
+    let available = frames as f64 / sample_rate as f64;
+    if available <= 0.0 {
+        return 0.0;
+    }
+    (processing.as_secs_f64() / available * 100.0) as f32
+}
+
+/// Meters shared with the audio callbacks
+#[derive(Debug, Default)]
+pub struct Meters {
+    /// Latching clip flags
+    pub clip: ClipIndicator,
+    /// Input peak since the last read, as f32 bits
+    input_peak: AtomicU32,
+    /// Output peak since the last read, as f32 bits
+    output_peak: AtomicU32,
+    /// Output samples played without input available
+    underruns: AtomicU64,
+    /// Set when the runaway detector muted the output
+    runaway: AtomicBool,
+    /// Smoothed output callback load in percent, as f32 bits
+    load: AtomicU32,
+    /// Input envelope at the end of the last input buffer, as f32 bits
+    input_envelope: AtomicU32,
+    /// Input envelope attack time in milliseconds, as f32 bits
+    envelope_attack_ms: AtomicU32,
+    /// Input envelope release time in milliseconds, as f32 bits
+    envelope_release_ms: AtomicU32,
+}
+
+impl Meters {
+    /// Create meters with zero levels, clear clip flags and a 10ms attack, 300ms
+    /// release input envelope
+    pub fn new() -> Self {
+        let meters = Self::default();
+        meters.set_envelope_times(DEFAULT_ENVELOPE_ATTACK_MS, DEFAULT_ENVELOPE_RELEASE_MS);
+        meters
+    }
+
+    /// Raise the input peak to `peak` if it is higher
+    pub fn record_input_peak(&self, peak: f32) {
+        // Non-negative floats order the same as their bit patterns
+        self.input_peak.fetch_max(peak.abs().to_bits(), Ordering::Relaxed);
+    }
+
+    /// Raise the output peak to `peak` if it is higher
+    pub fn record_output_peak(&self, peak: f32) {
+        self.output_peak.fetch_max(peak.abs().to_bits(), Ordering::Relaxed);
+    }
+
+    /// Publish the input envelope at the end of a buffer
+    pub fn record_input_envelope(&self, envelope: f32) {
+        self.input_envelope.store(envelope.abs().to_bits(), Ordering::Relaxed);
+    }
+
+    /// Current input envelope
+    pub fn input_envelope(&self) -> f32 {
+        f32::from_bits(self.input_envelope.load(Ordering::Relaxed))
+    }
+
+    /// Set the input envelope attack and release times in milliseconds (0 to 5000)
+    pub fn set_envelope_times(&self, attack_ms: f32, release_ms: f32) {
+        self.envelope_attack_ms.store(attack_ms.clamp(0.0, MAX_ENVELOPE_MS).to_bits(), Ordering::Relaxed);
+        self.envelope_release_ms.store(release_ms.clamp(0.0, MAX_ENVELOPE_MS).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Input envelope attack and release times in milliseconds
+    pub fn envelope_times(&self) -> (f32, f32) {
+        (
+            f32::from_bits(self.envelope_attack_ms.load(Ordering::Relaxed)),
+            f32::from_bits(self.envelope_release_ms.load(Ordering::Relaxed)),
+        )
+    }
+
+    /// Count output samples that found the ring buffer empty
+    pub fn add_underruns(&self, count: u64) {
+        self.underruns.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Fold the load of one output callback into the smoothed load
+    ///
+    /// Only the output callback records loads, so the read and write don't race.
+    pub fn record_load(&self, percent: f32) {
+        let smoothed = self.load_percent();
+        self.load.store((smoothed + (percent - smoothed) * LOAD_SMOOTHING).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Smoothed output callback load in percent
+    pub fn load_percent(&self) -> f32 {
+        f32::from_bits(self.load.load(Ordering::Relaxed))
+    }
+
+    /// Record that the output ran away and was muted
+    pub fn flag_runaway(&self) {
+        self.runaway.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the output ran away since the last call, resetting the flag
+    pub fn take_runaway(&self) -> bool {
+        self.runaway.swap(false, Ordering::Relaxed)
+    }
+
+    /// Input peak since the last call, resetting it
+    pub fn take_input_peak(&self) -> f32 {
+        f32::from_bits(self.input_peak.swap(0, Ordering::Relaxed))
+    }
+
+    /// Output peak since the last call, resetting it
+    pub fn take_output_peak(&self) -> f32 {
+        f32::from_bits(self.output_peak.swap(0, Ordering::Relaxed))
+    }
+
+    /// Total underruns since startup
+    pub fn underruns(&self) -> u64 {
+        self.underruns.load(Ordering::Relaxed)
+    }
+
+    /// Read both peaks, resetting them, along with the underrun count
+    pub fn take_reading(&self) -> MeterReading {
+        MeterReading {
+            input_peak: self.take_input_peak(),
+            output_peak: self.take_output_peak(),
+            input_envelope: self.input_envelope(),
+            underruns: self.underruns(),
+            load_percent: self.load_percent(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peaks_hold_until_taken() {
+        let meters = Meters::new();
+        meters.record_input_peak(0.25);
+        meters.record_input_peak(-0.5);
+        meters.record_input_peak(0.1);
+        assert_eq!(meters.take_input_peak(), 0.5);
+        assert_eq!(meters.take_input_peak(), 0.0);
+
+        meters.record_output_peak(1.5);
+        assert_eq!(meters.take_output_peak(), 1.5);
+
+        meters.add_underruns(3);
+        meters.add_underruns(2);
+        assert_eq!(meters.underruns(), 5);
+
+        // The envelope is a level, not a peak: reading it doesn't reset it
+        meters.record_input_envelope(0.3);
+        assert_eq!(meters.take_reading().input_envelope, 0.3);
+        assert_eq!(meters.input_envelope(), 0.3);
+        assert_eq!(meters.envelope_times(), (10.0, 300.0));
+    }
+
+    #[test]
+    fn test_load_percentage() {
+        // 256 frames at 48 kHz last 5.33 ms
+        assert!((load_percent(Duration::from_micros(2667), 256, 48000) - 50.0).abs() < 0.01);
+        assert!((load_percent(Duration::from_micros(5333), 256, 48000) - 100.0).abs() < 0.01);
+        assert_eq!(load_percent(Duration::ZERO, 512, 44100), 0.0);
+        assert_eq!(load_percent(Duration::from_millis(1), 0, 44100), 0.0);
+
+        // The smoothed load follows a steady load and ignores a single spike
+        let meters = Meters::new();
+        for _ in 0..100 {
+            meters.record_load(40.0);
+        }
+        assert!((meters.load_percent() - 40.0).abs() < 0.01);
+        meters.record_load(140.0);
+        assert!((meters.load_percent() - 50.0).abs() < 0.01, "{}", meters.load_percent());
+    }
+}
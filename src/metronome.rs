@@ -0,0 +1,261 @@
+//! Metronome
+//!
+//! Generates a click track mixed into the output for practice. Beat times are
+//! computed from the output sample count rather than accumulated, so every click
+//! starts on the first sample at or after its exact time and the timing never
+//! drifts, whatever the buffer size. The first beat of each bar is accented with a
+//! louder, higher click.
+//!
+//! The tempo is in quarter notes per minute like the rest of the program; the
+//! time signature's note value sets what gets a click, so 6/8 clicks eighths.
+
+use std::f64::consts::TAU;
+
+/// Length of a click in seconds
+const CLICK_SECONDS: f64 = 0.02;
+/// Pitch of a normal click in Hz
+const CLICK_HZ: f64 = 1000.0;
+/// Pitch of the accented downbeat click in Hz
+const ACCENT_HZ: f64 = 1500.0;
+/// Level of a normal click relative to the accent
+const BEAT_LEVEL: f64 = 0.6;
+/// Most beats per bar
+const MAX_BEATS_PER_BAR: u32 = 16;
+/// Rounding allowance when comparing a sample position with a beat time
+const TIME_EPSILON: f64 = 1e-6;
+
+/// Click track generator
+pub struct Metronome {
+    /// Sample rate in Hz
+    sample_rate: f64,
+    /// Whether clicks are generated
+    enabled: bool,
+    /// Tempo in quarter notes per minute
+    bpm: f64,
+    /// Click volume (0.0 to 1.0)
+    volume: f64,
+    /// Beats per bar (time signature numerator)
+    beats_per_bar: u32,
+    /// Note value of a beat (time signature denominator)
+    beat_unit: u32,
+    /// Output samples generated since the metronome was (re)started
+    position: u64,
+    /// Sample time of the first beat counted in `beats` (moves on tempo changes)
+    origin: f64,
+    /// Beats started since `origin`
+    beats: u64,
+    /// Beat within the bar of the next click (0 = downbeat)
+    next_beat: u32,
+    /// Samples left in the current click (0 = silent)
+    click_remaining: usize,
+    /// Length of a click in samples
+    click_samples: usize,
+    /// Oscillator phase of the current click (0.0 to 1.0)
+    click_phase: f64,
+    /// Pitch of the current click in Hz
+    click_hz: f64,
+    /// Peak level of the current click
+    click_level: f64,
+}
+
+impl Metronome {
+    /// Create a metronome in 4/4 at the given tempo, switched off
+    pub fn new(sample_rate: usize, bpm: f64) -> Self {
+        Self {
+            sample_rate: sample_rate as f64,
+            enabled: false,
+            bpm,
+            volume: 0.5,
+            beats_per_bar: 4,
+            beat_unit: 4,
+            position: 0,
+            origin: 0.0,
+            beats: 0,
+            next_beat: 0,
+            click_remaining: 0,
+            click_samples: ((CLICK_SECONDS * sample_rate as f64) as usize).max(1),
+            click_phase: 0.0,
+            click_hz: CLICK_HZ,
+            click_level: 0.0,
+        }
+    }
+
+    /// Generate the next output sample of the click track
+    pub fn tick(&mut self) -> f64 {
+        if !self.enabled {
+            return 0.0;
+        }
+
+        let next_beat_time = self.origin + self.beats as f64 * self.beat_samples();
+        if self.position as f64 + TIME_EPSILON >= next_beat_time {
+            self.start_click();
+            self.beats += 1;
+        }
+        self.position += 1;
+
+        if self.click_remaining == 0 {
+            return 0.0;
+        }
+        // Linear decay over the click keeps it short and free of a hard cut-off
+        let envelope = self.click_remaining as f64 / self.click_samples as f64;
+        let sample = (TAU * self.click_phase).sin() * envelope * self.click_level;
+        self.click_phase = (self.click_phase + self.click_hz / self.sample_rate) % 1.0;
+        self.click_remaining -= 1;
+        sample
+    }
+
+    /// Switch the click on or off; switching on starts a bar right away
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled && !self.enabled {
+            self.restart();
+        }
+        self.enabled = enabled;
+    }
+
+    /// Whether the click is on
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Set the tempo in quarter notes per minute (30 to 300)
+    ///
+    /// The last beat keeps its time, so the next click moves to the new spacing.
+    pub fn set_bpm(&mut self, bpm: f64) {
+        if self.beats > 0 {
+            self.origin += (self.beats - 1) as f64 * self.beat_samples();
+            self.beats = 1;
+        }
+        self.bpm = bpm.clamp(30.0, 300.0);
+    }
+
+    /// Current tempo in quarter notes per minute
+    pub fn bpm(&self) -> f64 {
+        self.bpm
+    }
+
+    /// Set the click volume (0.0 to 1.0)
+    pub fn set_volume(&mut self, volume: f64) {
+        self.volume = volume.clamp(0.0, 1.0);
+    }
+
+    /// Current click volume
+    pub fn volume(&self) -> f64 {
+        self.volume
+    }
+
+    /// Set the time signature, e.g. (3, 4) or (6, 8), and start a new bar
+    pub fn set_time_signature(&mut self, beats_per_bar: u32, beat_unit: u32) -> Result<(), String> {
+        if !(1..=MAX_BEATS_PER_BAR).contains(&beats_per_bar) {
+            return Err(format!("Beats per bar must be 1 to {}", MAX_BEATS_PER_BAR));
+        }
+        if ![1, 2, 4, 8, 16].contains(&beat_unit) {
+            return Err("Beat note value must be 1, 2, 4, 8 or 16".to_string());
+        }
+        self.beats_per_bar = beats_per_bar;
+        self.beat_unit = beat_unit;
+        self.restart();
+        Ok(())
+    }
+
+    /// Current time signature (beats per bar, beat note value)
+    pub fn time_signature(&self) -> (u32, u32) {
+        (self.beats_per_bar, self.beat_unit)
+    }
+
+    /// Start over on a downbeat at the next sample
+    pub fn restart(&mut self) {
+        self.position = 0;
+        self.origin = 0.0;
+        self.beats = 0;
+        self.next_beat = 0;
+        self.click_remaining = 0;
+    }
+
+    /// Length of one beat in samples (fractional)
+    fn beat_samples(&self) -> f64 {
+        self.sample_rate * 60.0 / self.bpm * 4.0 / self.beat_unit as f64
+    }
+
+    /// Trigger the click for the next beat
+    fn start_click(&mut self) {
+        let accent = self.next_beat == 0;
+        self.click_hz = if accent { ACCENT_HZ } else { CLICK_HZ };
+        self.click_level = self.volume * if accent { 1.0 } else { BEAT_LEVEL };
+        self.click_phase = 0.0;
+        self.click_remaining = self.click_samples;
+        self.next_beat = (self.next_beat + 1) % self.beats_per_bar;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sample indices where a click starts, and whether it was accented
+    fn click_starts(metronome: &mut Metronome, samples: usize) -> Vec<(usize, bool)> {
+        let mut starts = Vec::new();
+        for i in 0..samples {
+            metronome.tick();
+            if metronome.click_remaining == metronome.click_samples - 1 {
+                starts.push((i, metronome.click_hz == ACCENT_HZ));
+            }
+        }
+        starts
+    }
+
+    #[test]
+    fn test_clicks_on_exact_samples() {
+        // 120 BPM at 48 kHz: a beat every 24000 samples, accent every fourth
+        let mut metronome = Metronome::new(48000, 120.0);
+        metronome.set_enabled(true);
+        let starts = click_starts(&mut metronome, 24000 * 8);
+        let expected: Vec<(usize, bool)> = (0..8).map(|beat| (beat * 24000, beat % 4 == 0)).collect();
+        assert_eq!(starts, expected);
+
+        // 100 BPM at 44.1 kHz is 26460 samples; 3/4 accents every third beat
+        let mut metronome = Metronome::new(44100, 100.0);
+        metronome.set_time_signature(3, 4).unwrap();
+        metronome.set_enabled(true);
+        let starts = click_starts(&mut metronome, 26460 * 6);
+        let expected: Vec<(usize, bool)> = (0..6).map(|beat| (beat * 26460, beat % 3 == 0)).collect();
+        assert_eq!(starts, expected);
+    }
+
+    #[test]
+    fn test_fractional_beats_do_not_drift() {
+        // 130 BPM at 44.1 kHz is 20353.846... samples per beat
+        let mut metronome = Metronome::new(44100, 130.0);
+        metronome.set_enabled(true);
+        let starts = click_starts(&mut metronome, 44100 * 60);
+        assert_eq!(starts.len(), 130);
+        for (beat, &(start, _)) in starts.iter().enumerate() {
+            // Exact beat time 2646000 * beat / 130, rounded up
+            assert_eq!(start, (beat * 2_646_000).div_ceil(130), "beat {}", beat);
+        }
+    }
+
+    #[test]
+    fn test_tempo_change_keeps_last_beat() {
+        let mut metronome = Metronome::new(48000, 120.0);
+        metronome.set_enabled(true);
+        click_starts(&mut metronome, 30000);
+
+        // Beat two was at 24000; at 60 BPM the next one follows 48000 later
+        metronome.set_bpm(60.0);
+        let starts = click_starts(&mut metronome, 50000);
+        assert_eq!(starts, [(72000 - 30000, false)]);
+    }
+
+    #[test]
+    fn test_off_is_silent_and_settings_are_checked() {
+        let mut metronome = Metronome::new(44100, 120.0);
+        assert!((0..44100).all(|_| metronome.tick() == 0.0));
+
+        assert!(metronome.set_time_signature(0, 4).is_err());
+        assert!(metronome.set_time_signature(4, 3).is_err());
+        assert!(metronome.set_time_signature(6, 8).is_ok());
+        assert_eq!(metronome.time_signature(), (6, 8));
+        metronome.set_volume(2.0);
+        assert_eq!(metronome.volume(), 1.0);
+    }
+}
@@ -0,0 +1,257 @@
+//! MIDI CC control
+//!
+//! With `--midi <port>` control change messages from a MIDI controller drive
+//! effect settings. Each CC number (on any channel) is bound to a setting named
+//! like a preset entry, and its 0-127 value is spread over the setting's range;
+//! switches turn on from 64. Bindings come from the `[midi]` section of the
+//! config file:
+//!
+//! ```text
+//! [midi]
+//! cc7 = "mix.verb"
+//! cc74 = "distortion.drive"
+//! ```
+//!
+//! Learn mode saves looking up CC numbers: `midi learn <setting>` arms the map,
+//! and the next CC that arrives is bound to that setting instead of changing
+//! anything. The console arms the map and the MIDI input thread captures the CC,
+//! so both share it behind a mutex. Learned bindings are written back to the
+//! `[midi]` section of the `--config` file, if there is one.
+
+use std::sync::{Arc, Mutex};
+
+use midir::{MidiInput, MidiInputConnection};
+
+use crate::chain::EffectChain;
+use crate::config;
+use crate::ipc::status;
+use crate::preset::{apply_value, scale_setting, Value};
+
+/// Highest CC number
+const MAX_CC: u8 = 127;
+
+/// A CC number bound to a setting
+#[derive(Debug, Clone, PartialEq)]
+pub struct CcBinding {
+    /// Controller number (0 to 127)
+    pub cc: u8,
+    /// Config section of the setting
+    pub section: String,
+    /// Setting name within the section
+    pub key: String,
+}
+
+/// What an incoming CC does
+#[derive(Debug, Clone, PartialEq)]
+pub enum CcAction {
+    /// Learn mode bound the CC to the armed setting
+    Learned(CcBinding),
+    /// Change a setting
+    Set { section: String, key: String, value: Value },
+    /// The CC isn't bound to anything
+    Unbound,
+}
+
+/// CC bindings and the learn state
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MidiMap {
+    /// Bindings, at most one per CC number and one per setting
+    bindings: Vec<CcBinding>,
+    /// Setting waiting for the next CC, as (section, key)
+    learning: Option<(String, String)>,
+}
+
+impl MidiMap {
+    /// Bind `cc` to a setting such as `mix.verb`, replacing any earlier binding
+    /// of the CC or of the setting
+    pub fn bind(&mut self, cc: u8, setting: &str) -> Result<(), String> {
+        if cc > MAX_CC {
+            return Err(format!("CC {} is out of range (0 to {})", cc, MAX_CC));
+        }
+        let (section, key) = parse_setting(setting)?;
+        self.insert(CcBinding { cc, section, key });
+        Ok(())
+    }
+
+    /// Arm learn mode: the next CC is bound to `setting`
+    pub fn learn(&mut self, setting: &str) -> Result<(), String> {
+        self.learning = Some(parse_setting(setting)?);
+        Ok(())
+    }
+
+    /// Disarm learn mode, returning whether it was armed
+    pub fn cancel_learn(&mut self) -> bool {
+        self.learning.take().is_some()
+    }
+
+    /// Setting waiting for a CC in learn mode, as `section.key`
+    pub fn learning(&self) -> Option<String> {
+        self.learning.as_ref().map(|(section, key)| format!("{}.{}", section, key))
+    }
+
+    /// Current bindings, by CC number
+    pub fn bindings(&self) -> &[CcBinding] {
+        &self.bindings
+    }
+
+    /// Handle a CC value (0 to 127): bind it in learn mode, or map it to its setting
+    pub fn handle_cc(&mut self, cc: u8, value: u8) -> CcAction {
+        if let Some((section, key)) = self.learning.take() {
+            let binding = CcBinding { cc, section, key };
+            self.insert(binding.clone());
+            return CcAction::Learned(binding);
+        }
+
+        let Some(binding) = self.bindings.iter().find(|binding| binding.cc == cc) else {
+            return CcAction::Unbound;
+        };
+        match scale_setting(&binding.section, &binding.key, f64::from(value) / f64::from(MAX_CC)) {
+            Ok(value) => CcAction::Set {
+                section: binding.section.clone(),
+                key: binding.key.clone(),
+                value,
+            },
+            Err(_) => CcAction::Unbound,
+        }
+    }
+
+    /// Add a binding, dropping any other binding of its CC or setting
+    fn insert(&mut self, binding: CcBinding) {
+        self.bindings
+            .retain(|other| other.cc != binding.cc && (other.section != binding.section || other.key != binding.key));
+        self.bindings.push(binding);
+        self.bindings.sort_by_key(|binding| binding.cc);
+    }
+
+    /// Bindings as `[midi]` config lines
+    pub fn config_lines(&self) -> Vec<String> {
+        self.bindings
+            .iter()
+            .map(|binding| format!("cc{} = {}", binding.cc, Value::Text(format!("{}.{}", binding.section, binding.key))))
+            .collect()
+    }
+}
+
+/// Split `section.key` and check that a single control can drive it
+fn parse_setting(setting: &str) -> Result<(String, String), String> {
+    let (section, key) = setting
+        .split_once('.')
+        .ok_or_else(|| format!("expected a setting such as mix.verb, got '{}'", setting))?;
+    scale_setting(section, key, 0.0)?;
+    Ok((section.to_string(), key.to_string()))
+}
+
+/// Controller number and value of a control change message
+pub fn parse_cc_message(message: &[u8]) -> Option<(u8, u8)> {
+    match *message {
+        [status, cc, value] if status & 0xF0 == 0xB0 && cc <= MAX_CC && value <= MAX_CC => Some((cc, value)),
+        _ => None,
+    }
+}
+
+/// Open the MIDI input port named `port_name` and handle its CCs until the
+/// returned connection is dropped
+///
+/// Learned bindings are saved to the `[midi]` section of `config_path`, if given.
+pub fn connect(
+    port_name: &str,
+    map: Arc<Mutex<MidiMap>>,
+    chain: Arc<Mutex<EffectChain>>,
+    config_path: Option<String>,
+) -> Result<MidiInputConnection<()>, String> {
+    let input = MidiInput::new("mac-pedals").map_err(|e| format!("Cannot open MIDI input: {}", e))?;
+    let ports = input.ports();
+    let names: Vec<String> = ports.iter().map(|port| input.port_name(port).unwrap_or_default()).collect();
+    let Some(index) = names.iter().position(|name| name == port_name) else {
+        let available = if names.is_empty() { "none".to_string() } else { names.join(", ") };
+        return Err(format!("MIDI port '{}' not found (available: {})", port_name, available));
+    };
+
+    let callback = move |_: u64, message: &[u8], _: &mut ()| {
+        let Some((cc, value)) = parse_cc_message(message) else {
+            return;
+        };
+        // Not holding the map while the chain is locked
+        let action = map.lock().unwrap().handle_cc(cc, value);
+        match action {
+            CcAction::Learned(binding) => {
+                status!("MIDI CC {} -> {}.{}", binding.cc, binding.section, binding.key);
+                let lines = map.lock().unwrap().config_lines();
+                if let Some(path) = &config_path
+                    && let Err(e) = config::save_section(path, "midi", &lines)
+                {
+                    status!("MIDI bindings not saved: {}", e);
+                }
+            }
+            CcAction::Set { section, key, value } => {
+                if let Err(e) = apply_value(&mut chain.lock().unwrap(), &section, &key, value) {
+                    status!("MIDI CC {}: {}", cc, e);
+                }
+            }
+            CcAction::Unbound => {}
+        }
+    };
+    input
+        .connect(&ports[index], "mac-pedals-in", callback, ())
+        .map_err(|e| format!("Cannot connect to MIDI port '{}': {}", port_name, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_learn_binds_the_next_cc() {
+        let mut map = MidiMap::default();
+        assert_eq!(map.handle_cc(7, 127), CcAction::Unbound);
+
+        // Arming validates the setting and waits for a CC
+        assert!(map.learn("reverb.room").is_err());
+        assert!(map.learn("distortion.type").is_err());
+        map.learn("mix.verb").unwrap();
+        assert_eq!(map.learning().as_deref(), Some("mix.verb"));
+
+        // The next CC is captured instead of changing anything
+        let binding = CcBinding {
+            cc: 7,
+            section: "mix".to_string(),
+            key: "verb".to_string(),
+        };
+        assert_eq!(map.handle_cc(7, 100), CcAction::Learned(binding.clone()));
+        assert_eq!(map.learning(), None);
+        assert_eq!(map.bindings(), &[binding]);
+        assert_eq!(map.config_lines(), vec!["cc7 = \"mix.verb\"".to_string()]);
+
+        // From then on the CC drives the setting over its range
+        let set = |section: &str, key: &str, value: Value| CcAction::Set {
+            section: section.to_string(),
+            key: key.to_string(),
+            value,
+        };
+        assert_eq!(map.handle_cc(7, 127), set("mix", "verb", Value::Number(1.0)));
+        assert_eq!(map.handle_cc(7, 0), set("mix", "verb", Value::Number(0.0)));
+
+        // Re-learning a setting moves it to the new CC
+        map.learn("mix.verb").unwrap();
+        map.handle_cc(21, 0);
+        assert_eq!(map.handle_cc(7, 64), CcAction::Unbound);
+        map.bind(22, "reverb.predelay_ms").unwrap();
+        map.bind(23, "reverb.gated").unwrap();
+        assert_eq!(map.handle_cc(22, 127), set("reverb", "predelay_ms", Value::Number(250.0)));
+        assert_eq!(map.handle_cc(23, 64), set("reverb", "gated", Value::Bool(true)));
+
+        // Learn mode can be called off
+        map.learn("mix.dist").unwrap();
+        assert!(map.cancel_learn());
+        assert!(matches!(map.handle_cc(21, 0), CcAction::Set { .. }));
+    }
+
+    #[test]
+    fn test_parse_cc_message() {
+        assert_eq!(parse_cc_message(&[0xB0, 7, 100]), Some((7, 100)));
+        assert_eq!(parse_cc_message(&[0xBF, 74, 0]), Some((74, 0)));
+        // Note on, and a truncated message
+        assert_eq!(parse_cc_message(&[0x90, 60, 100]), None);
+        assert_eq!(parse_cc_message(&[0xB0, 7]), None);
+    }
+}
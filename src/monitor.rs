@@ -0,0 +1,42 @@
+//! Output monitoring switch
+//!
+//! Turning monitoring off silences what reaches the speakers without stopping
+//! anything else: the input callback keeps capturing into the ring buffer, the
+//! chain keeps processing and the meters keep running, so nothing jumps when it is
+//! switched back on. Unlike bypass it doesn't change what is processed, only
+//! whether it is heard, which avoids feedback through the speakers while setting
+//! up next to them. The switch is an atomic the engine shares with its consoles
+//! and the output callback, so a console can switch it while the callback reads it.
+
+/// A processed frame as the output callback writes it: silence while monitoring is off
+pub fn monitored(frame: (f64, f64), monitor: bool) -> (f64, f64) {
+    if monitor { frame } else { (0.0, 0.0) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ringbuf::RingBuffer;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn test_monitor_off_silences_output_but_keeps_capturing() {
+        let (mut producer, mut consumer) = RingBuffer::<f32>::new(16).split();
+
+        let monitor = AtomicBool::new(false);
+        // The input side keeps filling the ring buffer
+        for i in 1..=8 {
+            producer.push(i as f32 * 0.1).unwrap();
+        }
+        assert_eq!(consumer.len(), 8);
+
+        // The output side still drains it, but writes silence
+        let sample = f64::from(consumer.pop().unwrap());
+        assert_eq!(monitored((sample, sample), monitor.load(Ordering::Relaxed)), (0.0, 0.0));
+        assert_eq!(consumer.len(), 7);
+
+        monitor.store(true, Ordering::Relaxed);
+        let sample = f64::from(consumer.pop().unwrap());
+        assert_eq!(monitored((sample, -sample), monitor.load(Ordering::Relaxed)), (sample, -sample));
+    }
+}
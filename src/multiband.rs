@@ -0,0 +1,250 @@
+//! Multiband distortion
+//!
+//! Splits the signal into low/mid/high bands with 4th-order Linkwitz-Riley
+//! crossovers, distorts each band with its own Distortion instance and sums the
+//! bands back together. Keeping the lows clean while saturating the highs gives a
+//! tight high-gain sound.
+//!
+//! The low band is passed through an all-pass matching the upper crossover so the
+//! three bands sum to a flat magnitude response when no band is distorted.
+
+use crate::distortion::{Distortion, DistortionType};
+use crate::filter::{Biquad, BiquadCoeffs, Crossover, BUTTERWORTH_Q};
+
+/// Frequency band of the multiband distortion
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Band {
+    Low,
+    Mid,
+    High,
+}
+
+impl Band {
+    /// Parse a band name (`low`, `mid`, `high`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "low" => Some(Band::Low),
+            "mid" => Some(Band::Mid),
+            "high" => Some(Band::High),
+            _ => None,
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Band::Low => 0,
+            Band::Mid => 1,
+            Band::High => 2,
+        }
+    }
+}
+
+/// Three-band splitter for one channel
+struct BandSplitter {
+    /// Low/mid crossover
+    low_crossover: Crossover,
+    /// Mid/high crossover
+    high_crossover: Crossover,
+    /// Phase compensation for the low band (matches the mid/high crossover's all-pass response)
+    low_allpass: Biquad,
+}
+
+impl BandSplitter {
+    fn new(sample_rate: f64, low_freq: f64, high_freq: f64) -> Self {
+        Self {
+            low_crossover: Crossover::new(sample_rate, low_freq),
+            high_crossover: Crossover::new(sample_rate, high_freq),
+            low_allpass: Biquad::new(BiquadCoeffs::allpass(sample_rate, high_freq, BUTTERWORTH_Q)),
+        }
+    }
+
+    fn set_frequencies(&mut self, sample_rate: f64, low_freq: f64, high_freq: f64) {
+        self.low_crossover.set_frequency(sample_rate, low_freq);
+        self.high_crossover.set_frequency(sample_rate, high_freq);
+        self.low_allpass.set_coeffs(BiquadCoeffs::allpass(sample_rate, high_freq, BUTTERWORTH_Q));
+    }
+
+    /// Split into [low, mid, high]
+    fn split(&mut self, input: f64) -> [f64; 3] {
+        let (low, rest) = self.low_crossover.split(input);
+        let (mid, high) = self.high_crossover.split(rest);
+        [self.low_allpass.process(low), mid, high]
+    }
+
+    fn reset(&mut self) {
+        self.low_crossover.reset();
+        self.high_crossover.reset();
+        self.low_allpass.reset();
+    }
+}
+
+/// Processing for one band
+struct BandProcessor {
+    /// Distortion applied to this band
+    distortion: Distortion,
+    /// Whether the band is distorted (false = clean)
+    enabled: bool,
+}
+
+/// Three-band distortion processor
+pub struct MultibandDistortion {
+    /// Band splitters for the left and right channels
+    splitters: [BandSplitter; 2],
+    /// Per-band processing (low, mid, high)
+    bands: [BandProcessor; 3],
+    /// Low/mid crossover frequency in Hz
+    low_freq: f64,
+    /// Mid/high crossover frequency in Hz
+    high_freq: f64,
+    /// Sample rate for the crossover filters
+    sample_rate: f64,
+}
+
+impl MultibandDistortion {
+    /// Create a new multiband distortion with crossovers at 200Hz and 2kHz
+    ///
+    /// The low band starts clean, the mid and high bands soft-clipped.
+    pub fn new(sample_rate: usize) -> Self {
+        let low_freq = 200.0;
+        let high_freq = 2000.0;
+        let band = |enabled| BandProcessor {
+            distortion: Distortion::new(sample_rate),
+            enabled,
+        };
+        let rate = sample_rate as f64;
+
+        Self {
+            splitters: [
+                BandSplitter::new(rate, low_freq, high_freq),
+                BandSplitter::new(rate, low_freq, high_freq),
+            ],
+            bands: [band(false), band(true), band(true)],
+            low_freq,
+            high_freq,
+            sample_rate: rate,
+        }
+    }
+
+    /// Process a stereo input sample and return stereo output
+    pub fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let left_bands = self.splitters[0].split(input.0);
+        let right_bands = self.splitters[1].split(input.1);
+
+        let mut output = (0.0, 0.0);
+        for (i, band) in self.bands.iter_mut().enumerate() {
+            let band_in = (left_bands[i], right_bands[i]);
+            let (left, right) = if band.enabled {
+                band.distortion.tick(band_in)
+            } else {
+                band_in
+            };
+            output.0 += left;
+            output.1 += right;
+        }
+        output
+    }
+
+    /// Set the crossover frequencies in Hz
+    ///
+    /// The low crossover is limited to 20Hz-1kHz and the high crossover to at
+    /// least one octave above it and below 12kHz.
+    pub fn set_crossovers(&mut self, low_freq: f64, high_freq: f64) {
+        self.low_freq = low_freq.clamp(20.0, 1000.0);
+        self.high_freq = high_freq.clamp(self.low_freq * 2.0, 12000.0);
+        for splitter in self.splitters.iter_mut() {
+            splitter.set_frequencies(self.sample_rate, self.low_freq, self.high_freq);
+        }
+    }
+
+    /// Current crossover frequencies (low, high) in Hz
+    pub fn crossovers(&self) -> (f64, f64) {
+        (self.low_freq, self.high_freq)
+    }
+
+    /// Enable distortion on a band, or leave it clean
+    pub fn set_band_enabled(&mut self, band: Band, enabled: bool) {
+        self.bands[band.index()].enabled = enabled;
+    }
+
+    /// Set the distortion type of a band (also enables the band)
+    pub fn set_band_type(&mut self, band: Band, distortion_type: DistortionType) {
+        let band = &mut self.bands[band.index()];
+        band.distortion.set_distortion_type(distortion_type);
+        band.enabled = true;
+    }
+
+    /// Set the drive of a band (0.0 to 1.0)
+    pub fn set_band_drive(&mut self, band: Band, drive: f64) {
+        self.bands[band.index()].distortion.set_drive(drive);
+    }
+
+    /// Set the output level of a band (0.0 to 1.0)
+    pub fn set_band_level(&mut self, band: Band, level: f64) {
+        self.bands[band.index()].distortion.set_level(level);
+    }
+
+    /// Reset all filter and distortion state
+    pub fn reset(&mut self) {
+        for splitter in self.splitters.iter_mut() {
+            splitter.reset();
+        }
+        for band in self.bands.iter_mut() {
+            band.distortion.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    /// Steady-state peak amplitude of the left output for a sine input
+    fn sine_gain(multiband: &mut MultibandDistortion, freq: f64) -> f64 {
+        let sample_rate = 44100.0;
+        let mut peak: f64 = 0.0;
+        for i in 0..44100 {
+            let input = (2.0 * PI * freq * i as f64 / sample_rate).sin() * 0.5;
+            let (left, _) = multiband.tick((input, input));
+            if i > 22050 {
+                peak = peak.max(left.abs());
+            }
+        }
+        peak / 0.5
+    }
+
+    #[test]
+    fn test_clean_bands_sum_flat() {
+        for &freq in &[50.0, 200.0, 700.0, 2000.0, 6000.0, 15000.0] {
+            let mut multiband = MultibandDistortion::new(44100);
+            for band in [Band::Low, Band::Mid, Band::High] {
+                multiband.set_band_enabled(band, false);
+            }
+            let gain = sine_gain(&mut multiband, freq);
+            assert!((gain - 1.0).abs() < 0.01, "{}Hz: gain {}", freq, gain);
+        }
+    }
+
+    #[test]
+    fn test_only_distorted_band_changes() {
+        // Only the high band distorted: a low tone passes through clean
+        let mut multiband = MultibandDistortion::new(44100);
+        multiband.set_band_enabled(Band::Low, false);
+        multiband.set_band_enabled(Band::Mid, false);
+        multiband.set_band_type(Band::High, DistortionType::Hard);
+        multiband.set_band_drive(Band::High, 1.0);
+
+        let gain = sine_gain(&mut multiband, 60.0);
+        assert!((gain - 1.0).abs() < 0.02, "gain {}", gain);
+    }
+
+    #[test]
+    fn test_crossover_limits() {
+        let mut multiband = MultibandDistortion::new(44100);
+        multiband.set_crossovers(5.0, 30000.0);
+        assert_eq!(multiband.crossovers(), (20.0, 12000.0));
+
+        multiband.set_crossovers(800.0, 900.0);
+        assert_eq!(multiband.crossovers(), (800.0, 1600.0));
+    }
+}
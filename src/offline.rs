@@ -0,0 +1,532 @@
+//! Offline processing
+//!
+//! Renders a WAV file through the effect chain instead of the live audio streams,
+//! writing a stereo WAV in the requested sample format. A stereo file is processed
+//! as true stereo: its left and right channels go through the chain's left and
+//! right effect state separately, so they stay independent. Other channel counts,
+//! or a single channel picked with `--input-channel`, are mixed to mono like the
+//! live input path.
+//!
+//! The rendered output can be peak-normalized: the whole file is rendered into
+//! memory first, then scaled so its loudest sample sits at the target level, so a
+//! batch of files comes out at consistent levels.
+//!
+//! `process_buffer` runs samples held in memory through the chain the same way,
+//! for regression tests of whole chains and for embedding the effects.
+//!
+//! The reverb's impulse response can also be exported, for analysis or for
+//! loading into a convolution reverb.
+
+use std::fs::File;
+use std::io::BufWriter;
+
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+
+use crate::chain::EffectChain;
+use crate::dsp::Xorshift32;
+use crate::error::Error;
+use crate::input_channel::InputChannel;
+use crate::reverb::Reverb;
+
+/// Peak level below which an impulse response has died away (-90 dBFS)
+const IR_SILENCE: f64 = 3.16e-5;
+/// Longest exported impulse response in seconds
+const MAX_IR_SECONDS: f64 = 10.0;
+/// Length of the blocks checked for the end of the tail in seconds
+const IR_BLOCK_SECONDS: f64 = 0.01;
+/// Lowest peak level a render can be normalized to, in dBFS
+pub const MIN_NORMALIZE_DBFS: f64 = -60.0;
+
+/// Sample encoding of the output WAV file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WavFormat {
+    /// Bits per sample (16, 24 or 32)
+    bits: u16,
+    /// Integer or floating point samples
+    sample_format: SampleFormat,
+}
+
+impl Default for WavFormat {
+    /// 24-bit integer
+    fn default() -> Self {
+        Self {
+            bits: 24,
+            sample_format: SampleFormat::Int,
+        }
+    }
+}
+
+impl WavFormat {
+    /// Create an output format from the `--wav-bits` and `--wav-format` options
+    ///
+    /// Integer output supports 16, 24 and 32 bits; float output only 32 bits.
+    pub fn new(bits: u16, sample_format: SampleFormat) -> Result<Self, Error> {
+        match (sample_format, bits) {
+            (SampleFormat::Int, 16 | 24 | 32) | (SampleFormat::Float, 32) => Ok(Self { bits, sample_format }),
+            (SampleFormat::Float, _) => Err(Error::UnsupportedFormat(format!(
+                "{}-bit float WAV (float output must be 32-bit)",
+                bits
+            ))),
+            (SampleFormat::Int, _) => Err(Error::UnsupportedFormat(format!(
+                "{}-bit WAV (expected 16, 24 or 32)",
+                bits
+            ))),
+        }
+    }
+
+    /// Parse a `--wav-format` value (`int` or `float`)
+    pub fn parse_sample_format(value: &str) -> Result<SampleFormat, String> {
+        match value {
+            "int" => Ok(SampleFormat::Int),
+            "float" => Ok(SampleFormat::Float),
+            _ => Err(format!("Unknown WAV format '{}' (expected int or float)", value)),
+        }
+    }
+
+    /// WAV header spec for this format
+    pub fn spec(&self, channels: u16, sample_rate: u32) -> WavSpec {
+        WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: self.bits,
+            sample_format: self.sample_format,
+        }
+    }
+}
+
+impl std::fmt::Display for WavFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.sample_format {
+            SampleFormat::Int => write!(f, "{}-bit int", self.bits),
+            SampleFormat::Float => write!(f, "{}-bit float", self.bits),
+        }
+    }
+}
+
+/// WAV file writer converting f64 samples to the output format
+pub struct WavOutput {
+    /// Underlying WAV writer
+    writer: WavWriter<BufWriter<File>>,
+    /// Output sample encoding
+    format: WavFormat,
+    /// Dither noise (16-bit output only)
+    dither: Xorshift32,
+}
+
+impl WavOutput {
+    /// Create a WAV file for writing
+    pub fn create(path: &str, format: WavFormat, channels: u16, sample_rate: u32) -> Result<Self, hound::Error> {
+        Ok(Self {
+            writer: WavWriter::create(path, format.spec(channels, sample_rate))?,
+            format,
+            dither: Xorshift32::new(0x1234_5678),
+        })
+    }
+
+    /// Write one sample (channels interleaved)
+    ///
+    /// Integer samples are limited to the representable range. 16-bit output gets
+    /// TPDF dither before quantization.
+    pub fn write_sample(&mut self, sample: f64) -> Result<(), hound::Error> {
+        match self.format.sample_format {
+            SampleFormat::Float => self.writer.write_sample(sample as f32),
+            SampleFormat::Int => {
+                let dither = if self.format.bits == 16 { self.dither.tpdf() } else { 0.0 };
+                self.writer.write_sample(quantize(sample, self.format.bits, dither))
+            }
+        }
+    }
+
+    /// Finish the file and update the header
+    pub fn finalize(self) -> Result<(), hound::Error> {
+        self.writer.finalize()
+    }
+}
+
+/// Convert a sample to a signed integer of the given bit depth, adding `dither` LSBs
+fn quantize(sample: f64, bits: u16, dither: f64) -> i32 {
+    let full_scale = (1_i64 << (bits - 1)) as f64;
+    let scaled = sample * full_scale + dither;
+    scaled.round().clamp(-full_scale, full_scale - 1.0) as i32
+}
+
+/// Read a WAV file into interleaved f64 samples in the range -1.0 to 1.0
+pub fn read_wav(path: &str) -> Result<(WavSpec, Vec<f64>), hound::Error> {
+    let mut reader = WavReader::open(path)?;
+    let spec = reader.spec();
+
+    let samples = match spec.sample_format {
+        SampleFormat::Float => reader
+            .samples::<f32>()
+            .map(|sample| sample.map(f64::from))
+            .collect::<Result<Vec<_>, _>>()?,
+        SampleFormat::Int => {
+            let full_scale = (1_i64 << (spec.bits_per_sample - 1)) as f64;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|s| s as f64 / full_scale))
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+
+    Ok((spec, samples))
+}
+
+/// Render a WAV file through the effect chain into a WAV file with the same
+/// channel count
+///
+/// The chain should be built for the input file's sample rate. A stereo file is
+/// rendered as true stereo unless `input_channel` picks a single channel. A mono
+/// file comes out mono, the chain's two sides averaged. A file with more than two
+/// channels has the chain's output on the first two and the rest passed through
+/// unprocessed. With a `normalize` target in dBFS the processed channels are
+/// scaled to peak there (passed-through channels get the same gain); the gain
+/// applied is returned (None when not normalizing or the output is silent).
+pub fn render_file(
+    input_path: &str,
+    output_path: &str,
+    chain: &mut EffectChain,
+    format: WavFormat,
+    input_channel: InputChannel,
+    normalize: Option<f64>,
+) -> Result<Option<f64>, Box<dyn std::error::Error>> {
+    let (spec, samples) = read_wav(input_path)?;
+    let channels = spec.channels as usize;
+
+    let true_stereo = channels == 2 && matches!(input_channel, InputChannel::Mix(_));
+
+    // Render everything first, so the peak is known before anything is written
+    let mut rendered: Vec<(f64, f64)> = samples
+        .chunks_exact(channels)
+        .map(|frame| {
+            let input = if true_stereo {
+                (frame[0], frame[1])
+            } else {
+                // Pick or average the channels down to mono, like the live input
+                let sample = input_channel.select(frame, |sample| sample);
+                (sample, sample)
+            };
+            let processed = chain.process_frame(input);
+            let (left, right) = chain.block_dc(processed);
+            if channels == 1 {
+                // Fold to mono before normalizing, so the peak is the one written
+                let mono = (left + right) * 0.5;
+                (mono, mono)
+            } else {
+                (left, right)
+            }
+        })
+        .collect();
+    let gain = normalize.and_then(|target_dbfs| normalize_peak(&mut rendered, target_dbfs));
+
+    let mut output = WavOutput::create(output_path, format, spec.channels, spec.sample_rate)?;
+    for (&(left, right), frame) in rendered.iter().zip(samples.chunks_exact(channels)) {
+        output.write_sample(left)?;
+        if channels == 1 {
+            continue;
+        }
+        output.write_sample(right)?;
+        for &extra in &frame[2..] {
+            output.write_sample(extra * gain.unwrap_or(1.0))?;
+        }
+    }
+    output.finalize()?;
+
+    Ok(gain)
+}
+
+/// Scale frames so their peak sits at `target_dbfs`, returning the gain applied
+///
+/// Silent frames are left alone and give None.
+pub fn normalize_peak(frames: &mut [(f64, f64)], target_dbfs: f64) -> Option<f64> {
+    let peak = frames.iter().fold(0.0_f64, |peak, frame| peak.max(frame.0.abs()).max(frame.1.abs()));
+    if peak == 0.0 {
+        return None;
+    }
+    let gain = 10f64.powf(target_dbfs / 20.0) / peak;
+    for frame in frames.iter_mut() {
+        *frame = (frame.0 * gain, frame.1 * gain);
+    }
+    Some(gain)
+}
+
+/// Run mono samples through the effect chain and return the interleaved stereo
+/// output, as the live path would play it (including the DC blocker)
+///
+/// No device is involved, so the result only depends on the chain and the input.
+/// The chain keeps its state afterwards: a signal split across several calls
+/// comes out the same as in one call, like consecutive device buffers.
+pub fn process_buffer(input: &[f32], chain: &mut EffectChain) -> Vec<f32> {
+    let mut output = Vec::with_capacity(input.len() * 2);
+    for &sample in input {
+        let processed = chain.process_frame((sample as f64, sample as f64));
+        let (left, right) = chain.block_dc(processed);
+        output.push(left as f32);
+        output.push(right as f32);
+    }
+    output
+}
+
+/// Run a single full-scale impulse through the reverb and return the wet tail
+///
+/// The tail ends at the first block that falls below -90 dBFS once the reverb has
+/// started to sound (the pre-delay can hold it back), or after ten seconds.
+pub fn impulse_response(reverb: &mut Reverb) -> Vec<(f64, f64)> {
+    let sample_rate = reverb.sample_rate() as f64;
+    let block = ((IR_BLOCK_SECONDS * sample_rate) as usize).max(1);
+    let max_frames = (MAX_IR_SECONDS * sample_rate) as usize;
+
+    let mut response = Vec::new();
+    let mut sounding = false;
+    while response.len() < max_frames {
+        let start = response.len();
+        for i in start..start + block {
+            let input = if i == 0 { 1.0 } else { 0.0 };
+            response.push(reverb.tick_wet_only((input, input)));
+        }
+        let peak = response[start..].iter().fold(0.0_f64, |peak, frame| peak.max(frame.0.abs()).max(frame.1.abs()));
+        if peak >= IR_SILENCE {
+            sounding = true;
+        } else if sounding {
+            response.truncate(start);
+            break;
+        }
+    }
+    response.truncate(max_frames);
+    response
+}
+
+/// Write the impulse response of a reverb to a 32-bit float stereo WAV file
+///
+/// Returns the length of the response in frames.
+pub fn export_ir(reverb: &mut Reverb, path: &str) -> Result<usize, Box<dyn std::error::Error>> {
+    let response = impulse_response(reverb);
+    let format = WavFormat::new(32, SampleFormat::Float)?;
+    let mut output = WavOutput::create(path, format, 2, reverb.sample_rate() as u32)?;
+    for &(left, right) in &response {
+        output.write_sample(left)?;
+        output.write_sample(right)?;
+    }
+    output.finalize()?;
+    Ok(response.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("mac-pedals-{}-{}.wav", name, std::process::id()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn test_signal() -> Vec<f64> {
+        (0..1000).map(|i| (i as f64 * 0.01).sin() * 0.8).collect()
+    }
+
+    fn write_and_read(name: &str, format: WavFormat, samples: &[f64]) -> Vec<f64> {
+        let path = temp_path(name);
+        let mut output = WavOutput::create(&path, format, 1, 44100).unwrap();
+        for &sample in samples {
+            output.write_sample(sample).unwrap();
+        }
+        output.finalize().unwrap();
+
+        let (spec, read_back) = read_wav(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(spec, format.spec(1, 44100));
+        read_back
+    }
+
+    #[test]
+    fn test_16_bit_round_trip_within_quantization_error() {
+        let samples = test_signal();
+        let format = WavFormat::new(16, SampleFormat::Int).unwrap();
+        let read_back = write_and_read("int16", format, &samples);
+
+        // Rounding plus up to 1 LSB of dither
+        let lsb = 1.0 / 32768.0;
+        assert_eq!(read_back.len(), samples.len());
+        for (original, read) in samples.iter().zip(&read_back) {
+            assert!((original - read).abs() <= 1.5 * lsb, "{} vs {}", original, read);
+        }
+    }
+
+    #[test]
+    fn test_float32_round_trips_exactly() {
+        let samples: Vec<f64> = test_signal().iter().map(|&s| s as f32 as f64).collect();
+        let format = WavFormat::new(32, SampleFormat::Float).unwrap();
+        let read_back = write_and_read("float32", format, &samples);
+        assert_eq!(read_back, samples);
+    }
+
+    #[test]
+    fn test_int_output_is_limited() {
+        assert_eq!(quantize(2.0, 16, 0.0), i16::MAX as i32);
+        assert_eq!(quantize(-2.0, 16, 0.0), i16::MIN as i32);
+        assert_eq!(quantize(1.0, 24, 0.0), (1 << 23) - 1);
+        assert_eq!(quantize(-1.0, 24, 0.0), -(1 << 23));
+    }
+
+    #[test]
+    fn test_stereo_file_keeps_channels_independent() {
+        let input_path = temp_path("stereo-in");
+        let output_path = temp_path("stereo-out");
+        let format = WavFormat::new(32, SampleFormat::Float).unwrap();
+
+        // Different tones on each side, with the right falling silent halfway
+        let left = |i: usize| 0.5 * (i as f64 * 0.06).sin();
+        let right = |i: usize| if i < 2000 { 0.3 * (i as f64 * 0.11).sin() } else { 0.0 };
+        let mut input = WavOutput::create(&input_path, format, 2, 44100).unwrap();
+        for i in 0..4000 {
+            input.write_sample(left(i)).unwrap();
+            input.write_sample(right(i)).unwrap();
+        }
+        input.finalize().unwrap();
+
+        let mut chain = EffectChain::new(44100);
+        chain.set_dc_block(false);
+        render_file(&input_path, &output_path, &mut chain, format, InputChannel::default(), None).unwrap();
+        let (spec, output) = read_wav(&output_path).unwrap();
+        std::fs::remove_file(&input_path).unwrap();
+        std::fs::remove_file(&output_path).unwrap();
+
+        // The passthrough chain returns each channel as it went in, not their mix
+        assert_eq!(spec.channels, 2);
+        assert_eq!(output.len(), 8000);
+        for (i, frame) in output.chunks(2).enumerate() {
+            assert!((frame[0] - left(i)).abs() < 1e-6, "left sample {}: {}", i, frame[0]);
+            assert!((frame[1] - right(i)).abs() < 1e-6, "right sample {}: {}", i, frame[1]);
+        }
+    }
+
+    #[test]
+    fn test_mono_and_multichannel_files_keep_their_channel_count() {
+        let format = WavFormat::new(32, SampleFormat::Float).unwrap();
+        let tone = |i: usize, channel: usize| 0.2 * (channel + 1) as f64 * (i as f64 * 0.05).sin();
+        for channels in [1_u16, 4] {
+            let input_path = temp_path(&format!("channels-{}-in", channels));
+            let output_path = temp_path(&format!("channels-{}-out", channels));
+            let mut input = WavOutput::create(&input_path, format, channels, 44100).unwrap();
+            for i in 0..2000 {
+                for channel in 0..channels as usize {
+                    input.write_sample(tone(i, channel)).unwrap();
+                }
+            }
+            input.finalize().unwrap();
+
+            let mut chain = EffectChain::new(44100);
+            chain.set_dc_block(false);
+            render_file(&input_path, &output_path, &mut chain, format, InputChannel::Left, None).unwrap();
+            let (spec, output) = read_wav(&output_path).unwrap();
+            std::fs::remove_file(&input_path).unwrap();
+            std::fs::remove_file(&output_path).unwrap();
+
+            // The first channel goes through the passthrough chain, the others beyond
+            // the stereo pair come out as they went in
+            assert_eq!(spec.channels, channels);
+            assert_eq!(output.len(), 2000 * channels as usize);
+            for (i, frame) in output.chunks(channels as usize).enumerate() {
+                assert!((frame[0] - tone(i, 0)).abs() < 1e-6, "{} channels, sample {}: {}", channels, i, frame[0]);
+                for (channel, &sample) in frame.iter().enumerate().skip(2) {
+                    assert!((sample - tone(i, channel)).abs() < 1e-6);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_impulse_through_the_default_chain_is_reproducible() {
+        let mut input = vec![0.0_f32; 8192];
+        input[0] = 1.0;
+        let configured_chain = || {
+            let mut chain = crate::engine::build_chain(44100, &crate::safety::FeedbackCeiling::default()).unwrap();
+            chain.set_enabled("delay", true).unwrap();
+            chain
+        };
+
+        let first = process_buffer(&input, &mut configured_chain());
+        let second = process_buffer(&input, &mut configured_chain());
+        assert_eq!(first.len(), 2 * input.len());
+        assert_eq!(first, second);
+        assert!(first[4096..].iter().any(|&s| s.abs() > 1e-6), "no tail after the impulse");
+
+        // Split into uneven buffers, the state carries across as in real time
+        let mut chain = configured_chain();
+        let mut split = process_buffer(&input[..1000], &mut chain);
+        split.extend(process_buffer(&input[1000..1001], &mut chain));
+        split.extend(process_buffer(&input[1001..], &mut chain));
+        assert_eq!(split, first);
+    }
+
+    #[test]
+    fn test_normalize_scales_the_peak_to_the_target() {
+        let mut frames: Vec<(f64, f64)> = (0..1000).map(|i| (0.2 * (i as f64 * 0.05).sin(), -0.3 * (i as f64 * 0.07).cos())).collect();
+        let original = frames.clone();
+        let gain = normalize_peak(&mut frames, -1.0).unwrap();
+
+        let peak = frames.iter().fold(0.0_f64, |peak, frame| peak.max(frame.0.abs()).max(frame.1.abs()));
+        assert!((20.0 * peak.log10() + 1.0).abs() < 1e-9, "peak {} dBFS", 20.0 * peak.log10());
+        // One gain for the whole buffer, so the balance and shape are kept
+        for (frame, original) in frames.iter().zip(&original) {
+            assert!((frame.0 - original.0 * gain).abs() < 1e-12 && (frame.1 - original.1 * gain).abs() < 1e-12);
+        }
+
+        // Silence is left as it is
+        let mut silence = vec![(0.0, 0.0); 100];
+        assert_eq!(normalize_peak(&mut silence, -1.0), None);
+        assert!(silence.iter().all(|&frame| frame == (0.0, 0.0)));
+    }
+
+    #[test]
+    fn test_rendered_file_is_normalized_before_writing() {
+        let input_path = temp_path("normalize-in");
+        let output_path = temp_path("normalize-out");
+        let format = WavFormat::new(32, SampleFormat::Float).unwrap();
+        let mut input = WavOutput::create(&input_path, format, 1, 44100).unwrap();
+        for i in 0..4000 {
+            input.write_sample(0.1 * (i as f64 * 0.06).sin()).unwrap();
+        }
+        input.finalize().unwrap();
+
+        let mut chain = EffectChain::new(44100);
+        let gain = render_file(&input_path, &output_path, &mut chain, format, InputChannel::default(), Some(-6.0)).unwrap();
+        let (_, output) = read_wav(&output_path).unwrap();
+        std::fs::remove_file(&input_path).unwrap();
+        std::fs::remove_file(&output_path).unwrap();
+
+        // Peaks at -6 dBFS within the rounding of the 32-bit float file
+        assert!(gain.unwrap() > 1.0);
+        let peak = output.iter().fold(0.0_f64, |peak, sample| peak.max(sample.abs()));
+        assert!((peak - 10f64.powf(-6.0 / 20.0)).abs() < 1e-6, "peak {}", peak);
+    }
+
+    #[test]
+    fn test_format_validation() {
+        assert_eq!(WavFormat::default(), WavFormat::new(24, SampleFormat::Int).unwrap());
+        assert!(matches!(WavFormat::new(24, SampleFormat::Float), Err(Error::UnsupportedFormat(_))));
+        assert!(matches!(WavFormat::new(8, SampleFormat::Int), Err(Error::UnsupportedFormat(_))));
+        assert!(WavFormat::parse_sample_format("double").is_err());
+    }
+
+    #[test]
+    fn test_exported_ir_starts_loud_and_decays() {
+        let mut reverb = Reverb::new(44100);
+        reverb.set_room_size(0.6);
+        let path = temp_path("ir");
+        let frames = export_ir(&mut reverb.clone_settings(), &path).unwrap();
+
+        let (spec, samples) = read_wav(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((spec.channels, spec.sample_rate), (2, 44100));
+        assert_eq!(samples.len(), frames * 2);
+        assert!(frames < 441000, "{} frames", frames);
+
+        // Sounding within the first 100ms, near silence in the last 10ms
+        let peak = |samples: &[f64]| samples.iter().fold(0.0_f64, |peak, s| peak.max(s.abs()));
+        assert!(peak(&samples[..8820]) > 0.01, "start peak {}", peak(&samples[..8820]));
+        assert!(peak(&samples[samples.len() - 882..]) < 1e-3);
+    }
+}
@@ -0,0 +1,312 @@
+//! OSC control server
+//!
+//! Listens for OSC messages on a UDP port (`--osc-port`) so control surfaces such
+//! as TouchOSC or Max/MSP can drive the effects. An address names a stage (or a
+//! config file section) and a setting, with the same setting names as config files:
+//!
+//! ```text
+//! /dist/drive 0.7             distortion drive (also /distortion/drive)
+//! /verb/room_size 0.8
+//! /mix/verb 0.5
+//! /chain/order "verb" "dist"
+//! /dist/on 1                  enable (1) or disable (0) a stage
+//! /tap                        tap tempo
+//! /panic
+//! ```
+//!
+//! Numbers are clamped into the setting's range and read as on/off for switches,
+//! so faders and toggles can be mapped directly. Settings go through the same
+//! validation and chain lock as the console controls.
+//!
+//! With `--osc-return <ip:port>` the meters are sent back every 100ms as
+//! `/meter/input` and `/meter/output` (peaks since the previous send),
+//! `/meter/envelope` (smoothed input envelope), `/meter/underruns` (total) and
+//! `/meter/load` (smoothed processing load in percent).
+
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rosc::{decoder, encoder, OscMessage, OscPacket, OscType};
+
+use crate::chain::EffectChain;
+use crate::ipc::status;
+use crate::meter::MeterReading;
+use crate::preset::{apply_value, coerce_setting, Value};
+use crate::tempo::TapTempo;
+
+/// Config file section for each chain stage name
+const STAGE_SECTIONS: &[(&str, &str)] = &[
+    ("nr", "denoise"),
+    ("dist", "distortion"),
+    ("multi", "multiband"),
+    ("excite", "exciter"),
+    ("trem", "tremolo"),
+    ("reverse", "reverse"),
+    ("delay", "delay"),
+    ("verb", "reverb"),
+    ("adt", "adt"),
+    ("width", "stereo"),
+    ("pan", "autopan"),
+    ("boost", "boost"),
+];
+
+/// What an OSC message asks for
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscAction {
+    /// Change a setting, addressed like a preset entry
+    Set { section: String, key: String, value: Value },
+    /// Enable or disable a chain stage
+    Enable { stage: String, enabled: bool },
+    /// Register a tap tempo tap
+    Tap,
+    /// Mute, clear all effect state and return to passthrough
+    Panic,
+}
+
+/// Map an OSC message to an action, fitting numbers to the setting's range
+pub fn parse_message(message: &OscMessage) -> Result<OscAction, String> {
+    let parts: Vec<&str> = message.addr.trim_start_matches('/').split('/').collect();
+    match parts.as_slice() {
+        ["tap"] => Ok(OscAction::Tap),
+        ["panic"] => Ok(OscAction::Panic),
+        [stage, "on"] => match args_to_value(&message.args)? {
+            Value::Bool(enabled) => Ok(OscAction::Enable { stage: stage.to_string(), enabled }),
+            Value::Number(number) => Ok(OscAction::Enable { stage: stage.to_string(), enabled: number >= 0.5 }),
+            value => Err(format!("expected 0/1 or true/false, got {}", value)),
+        },
+        [section, key] => {
+            let section = STAGE_SECTIONS
+                .iter()
+                .find(|&&(stage, _)| stage == *section)
+                .map_or(*section, |&(_, section)| section);
+            let value = match (section, args_to_value(&message.args)?) {
+                // A single stage name is a one-stage list
+                ("chain", Value::Text(stage)) => Value::List(vec![stage]),
+                (_, value) => value,
+            };
+            Ok(OscAction::Set {
+                section: section.to_string(),
+                key: key.to_string(),
+                value: coerce_setting(section, key, value),
+            })
+        }
+        _ => Err("unknown address".to_string()),
+    }
+}
+
+/// Convert OSC arguments to a setting value
+fn args_to_value(args: &[OscType]) -> Result<Value, String> {
+    match args {
+        [OscType::Float(number)] => Ok(Value::Number(f64::from(*number))),
+        [OscType::Double(number)] => Ok(Value::Number(*number)),
+        [OscType::Int(number)] => Ok(Value::Number(f64::from(*number))),
+        [OscType::Long(number)] => Ok(Value::Number(*number as f64)),
+        [OscType::Bool(flag)] => Ok(Value::Bool(*flag)),
+        [OscType::String(text)] => Ok(Value::Text(text.clone())),
+        _ => args
+            .iter()
+            .map(|arg| match arg {
+                OscType::String(text) => Some(text.clone()),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(Value::List)
+            .ok_or_else(|| "expected one number, bool or string, or a list of strings".to_string()),
+    }
+}
+
+/// Apply an action to the chain
+fn apply(action: OscAction, chain: &mut EffectChain, tap_tempo: &mut TapTempo) -> Result<(), String> {
+    match action {
+        OscAction::Set { section, key, value } => apply_value(chain, &section, &key, value),
+        OscAction::Enable { stage, enabled } => chain.set_enabled(&stage, enabled),
+        OscAction::Tap => {
+            if let Some(bpm) = tap_tempo.tap(Instant::now()) {
+                chain.set_tempo_bpm(bpm);
+            }
+            Ok(())
+        }
+        OscAction::Panic => {
+            chain.panic();
+            Ok(())
+        }
+    }
+}
+
+/// UDP socket for OSC control and meter feedback
+pub struct OscServer {
+    /// Socket bound to the control port, also used to send meters
+    socket: UdpSocket,
+    /// Where meter messages are sent (None = no feedback)
+    return_address: Option<SocketAddr>,
+}
+
+impl OscServer {
+    /// Bind the control port on all interfaces
+    pub fn bind(port: u16, return_address: Option<SocketAddr>) -> std::io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind(("0.0.0.0", port))?,
+            return_address,
+        })
+    }
+
+    /// Handle incoming messages on their own thread until `running` is cleared
+    pub fn spawn_listener(&self, chain: Arc<Mutex<EffectChain>>, running: Arc<AtomicBool>) -> std::io::Result<()> {
+        let socket = self.socket.try_clone()?;
+        // Wake up regularly to notice shutdown
+        socket.set_read_timeout(Some(Duration::from_millis(200)))?;
+        thread::spawn(move || listen(socket, chain, running));
+        Ok(())
+    }
+
+    /// Send a meter reading to the return address, if there is one
+    ///
+    /// Meters are best-effort: failed sends are dropped.
+    pub fn send_meters(&self, reading: &MeterReading) {
+        let Some(return_address) = self.return_address else {
+            return;
+        };
+
+        let messages = [
+            ("/meter/input", OscType::Float(reading.input_peak)),
+            ("/meter/output", OscType::Float(reading.output_peak)),
+            ("/meter/envelope", OscType::Float(reading.input_envelope)),
+            ("/meter/underruns", OscType::Int(reading.underruns.min(i32::MAX as u64) as i32)),
+            ("/meter/load", OscType::Float(reading.load_percent)),
+        ];
+        for (addr, arg) in messages {
+            let packet = OscPacket::Message(OscMessage {
+                addr: addr.to_string(),
+                args: vec![arg],
+            });
+            if let Ok(bytes) = encoder::encode(&packet) {
+                let _ = self.socket.send_to(&bytes, return_address);
+            }
+        }
+    }
+}
+
+/// Receive loop of the listener thread
+fn listen(socket: UdpSocket, chain: Arc<Mutex<EffectChain>>, running: Arc<AtomicBool>) {
+    let mut buffer = [0u8; decoder::MTU];
+    let mut tap_tempo = TapTempo::new();
+
+    while running.load(Ordering::Relaxed) {
+        let size = match socket.recv_from(&mut buffer) {
+            Ok((size, _)) => size,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+            Err(e) => {
+                status!("OSC listener stopped: {}", e);
+                return;
+            }
+        };
+
+        match decoder::decode_udp(&buffer[..size]) {
+            Ok((_, packet)) => handle_packet(packet, &chain, &mut tap_tempo),
+            Err(e) => status!("Invalid OSC packet: {:?}", e),
+        }
+    }
+}
+
+/// Apply a message, or each message in a bundle
+fn handle_packet(packet: OscPacket, chain: &Mutex<EffectChain>, tap_tempo: &mut TapTempo) {
+    match packet {
+        OscPacket::Message(message) => {
+            let result = parse_message(&message).and_then(|action| apply(action, &mut chain.lock().unwrap(), tap_tempo));
+            if let Err(e) = result {
+                status!("OSC {}: {}", message.addr, e);
+            }
+        }
+        OscPacket::Bundle(bundle) => {
+            for packet in bundle.content {
+                handle_packet(packet, chain, tap_tempo);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(addr: &str, args: Vec<OscType>) -> OscMessage {
+        OscMessage {
+            addr: addr.to_string(),
+            args,
+        }
+    }
+
+    fn set(section: &str, key: &str, value: Value) -> OscAction {
+        OscAction::Set {
+            section: section.to_string(),
+            key: key.to_string(),
+            value,
+        }
+    }
+
+    #[test]
+    fn test_address_mapping() {
+        let parse = |addr: &str, args| parse_message(&message(addr, args)).unwrap();
+
+        assert_eq!(parse("/dist/drive", vec![OscType::Float(0.5)]), set("distortion", "drive", Value::Number(0.5)));
+        assert_eq!(parse("/reverb/room_size", vec![OscType::Double(0.25)]), set("reverb", "room_size", Value::Number(0.25)));
+        assert_eq!(parse("/mix/verb", vec![OscType::Int(1)]), set("mix", "verb", Value::Number(1.0)));
+        assert_eq!(
+            parse("/chain/order", vec![OscType::String("verb".into()), OscType::String("dist".into())]),
+            set("chain", "order", Value::List(vec!["verb".into(), "dist".into()]))
+        );
+        assert_eq!(
+            parse("/chain/enabled", vec![OscType::String("verb".into())]),
+            set("chain", "enabled", Value::List(vec!["verb".into()]))
+        );
+        assert_eq!(
+            parse("/delay/sync", vec![OscType::String("1/8.".into())]),
+            set("delay", "sync", Value::Text("1/8.".into()))
+        );
+        assert_eq!(
+            parse("/dist/on", vec![OscType::Float(1.0)]),
+            OscAction::Enable { stage: "dist".into(), enabled: true }
+        );
+        assert_eq!(
+            parse("/verb/on", vec![OscType::Bool(false)]),
+            OscAction::Enable { stage: "verb".into(), enabled: false }
+        );
+        assert_eq!(parse("/tap", vec![]), OscAction::Tap);
+
+        assert!(parse_message(&message("/dist", vec![OscType::Float(0.5)])).is_err());
+        assert!(parse_message(&message("/dist/drive", vec![OscType::Float(0.5), OscType::Int(1)])).is_err());
+    }
+
+    #[test]
+    fn test_values_are_clamped() {
+        let parse = |addr: &str, number: f32| parse_message(&message(addr, vec![OscType::Float(number)])).unwrap();
+
+        assert_eq!(parse("/dist/drive", 1.5), set("distortion", "drive", Value::Number(1.0)));
+        assert_eq!(parse("/verb/room_size", -0.5), set("reverb", "room_size", Value::Number(0.0)));
+        assert_eq!(parse("/delay/feedback", 1.0), set("delay", "feedback", Value::Number(0.95)));
+        assert_eq!(parse("/chain/trim_db", -40.0), set("chain", "trim_db", Value::Number(-24.0)));
+        // Toggles send 0/1 for switches
+        assert_eq!(parse("/verb/gated", 1.0), set("reverb", "gated", Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_apply_updates_chain() {
+        let mut chain = EffectChain::new(44100);
+        chain.add("dist", Box::new(crate::distortion::Distortion::new(44100)));
+        let mut tap_tempo = TapTempo::new();
+
+        let action = parse_message(&message("/chain/trim_db", vec![OscType::Float(-40.0)])).unwrap();
+        apply(action, &mut chain, &mut tap_tempo).unwrap();
+        let action = parse_message(&message("/dist/on", vec![OscType::Int(0)])).unwrap();
+        apply(action, &mut chain, &mut tap_tempo).unwrap();
+        assert_eq!(chain.describe(), "in -> (dist off) -> out (trim -24.0 dB)");
+
+        // Settings for stages that aren't in the chain are reported
+        let action = parse_message(&message("/verb/room_size", vec![OscType::Float(0.5)])).unwrap();
+        assert!(apply(action, &mut chain, &mut tap_tempo).is_err());
+    }
+}
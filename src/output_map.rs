@@ -0,0 +1,237 @@
+//! Output channel mapping for multi-channel devices
+//!
+//! Decides what each output channel receives when the device has more than two
+//! channels. The effects always produce a stereo pair; this module routes that pair
+//! onto the device's channel layout.
+//!
+//! Every policy comes down to an upmix matrix: one row per output channel with the
+//! gains of the left and right signal in it, so the output callback only has to
+//! multiply. The presets build their rows from the channel sources (left is
+//! `[1, 0]`, the mono sum `[0.5, 0.5]`); an upmix from the config file gives every
+//! row itself, for surround layouts that blend the pair into the centre and rears.
+
+use std::fmt;
+
+/// Source feeding a single output channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelSource {
+    /// Left channel of the processed stereo pair
+    Left,
+    /// Right channel of the processed stereo pair
+    Right,
+    /// Mono sum of the stereo pair ((L + R) / 2)
+    Mid,
+    /// Channel is silenced
+    Silent,
+}
+
+/// Channel-mapping policy for multi-channel output
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum OutputMap {
+    /// Left on even channels, right on odd channels (original behavior)
+    #[default]
+    Duplicate,
+    /// Stereo pair on channels 0/1, all other channels silenced
+    FrontOnly,
+    /// Explicit per-channel sources; channels past the end of the list are silenced
+    Custom(Vec<ChannelSource>),
+    /// Explicit (left gain, right gain) row per channel, from the config file
+    Matrix(Vec<[f32; 2]>),
+}
+
+impl ChannelSource {
+    /// Left and right gains of the source
+    fn gains(self) -> [f32; 2] {
+        match self {
+            ChannelSource::Left => [1.0, 0.0],
+            ChannelSource::Right => [0.0, 1.0],
+            ChannelSource::Mid => [0.5, 0.5],
+            ChannelSource::Silent => [0.0, 0.0],
+        }
+    }
+}
+
+impl OutputMap {
+    /// Parse a policy from the `--output-map` argument
+    ///
+    /// Accepts `duplicate`, `front-only`, or `custom:<list>` where `<list>` is a
+    /// comma-separated list of `L`, `R`, `M` (mono sum) or `-` (silent), one per channel.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "duplicate" => Ok(OutputMap::Duplicate),
+            "front-only" => Ok(OutputMap::FrontOnly),
+            _ => {
+                let spec = value
+                    .strip_prefix("custom:")
+                    .ok_or_else(|| format!("Unknown output map '{}' (expected duplicate, front-only or custom:<L,R,M,->)", value))?;
+                let sources = spec
+                    .split(',')
+                    .map(|token| match token.trim() {
+                        "L" | "l" => Ok(ChannelSource::Left),
+                        "R" | "r" => Ok(ChannelSource::Right),
+                        "M" | "m" => Ok(ChannelSource::Mid),
+                        "-" => Ok(ChannelSource::Silent),
+                        other => Err(format!("Unknown channel source '{}' in custom output map", other)),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(OutputMap::Custom(sources))
+            }
+        }
+    }
+
+    /// Parse upmix rows from the config file, each a left and a right gain
+    /// separated by a space (e.g. `"0.7 0.7"` for a centre channel)
+    pub fn parse_matrix(rows: &[String]) -> Result<Self, String> {
+        if rows.is_empty() {
+            return Err("the upmix matrix needs a row per output channel".to_string());
+        }
+        let rows = rows
+            .iter()
+            .map(|row| {
+                let invalid = || format!("Invalid upmix row '{}' (expected a left and a right gain, e.g. \"0.7 0.7\")", row);
+                let gains: Vec<f32> = row
+                    .split_whitespace()
+                    .map(|gain| gain.parse::<f32>().map_err(|_| invalid()))
+                    .collect::<Result<_, _>>()?;
+                match gains[..] {
+                    [left, right] if left.is_finite() && right.is_finite() => Ok([left, right]),
+                    _ => Err(invalid()),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(OutputMap::Matrix(rows))
+    }
+
+    /// Check the map fits a device with `channels` outputs
+    ///
+    /// An upmix matrix must have exactly one row per channel; the presets fit any
+    /// layout.
+    pub fn validate(&self, channels: usize) -> Result<(), String> {
+        match self {
+            OutputMap::Matrix(rows) if rows.len() != channels => Err(format!(
+                "The upmix matrix has {} rows but the output device has {} channels",
+                rows.len(),
+                channels
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Source routed to the given output channel (None for an upmix matrix)
+    pub fn source(&self, channel: usize) -> Option<ChannelSource> {
+        match self {
+            OutputMap::Duplicate => {
+                Some(if channel.is_multiple_of(2) { ChannelSource::Left } else { ChannelSource::Right })
+            }
+            OutputMap::FrontOnly => Some(match channel {
+                0 => ChannelSource::Left,
+                1 => ChannelSource::Right,
+                _ => ChannelSource::Silent,
+            }),
+            OutputMap::Custom(sources) => Some(sources.get(channel).copied().unwrap_or(ChannelSource::Silent)),
+            OutputMap::Matrix(_) => None,
+        }
+    }
+
+    /// Upmix rows, (left gain, right gain) for each of `channels` output channels
+    ///
+    /// Channels past the end of a matrix are silenced.
+    pub fn matrix(&self, channels: usize) -> Vec<[f32; 2]> {
+        (0..channels)
+            .map(|channel| match self {
+                OutputMap::Matrix(rows) => rows.get(channel).copied().unwrap_or([0.0, 0.0]),
+                _ => self.source(channel).map_or([0.0, 0.0], ChannelSource::gains),
+            })
+            .collect()
+    }
+}
+
+/// Output channel sample for an upmix row from a processed stereo pair
+pub fn upmix(row: [f32; 2], left: f32, right: f32) -> f32 {
+    row[0] * left + row[1] * right
+}
+
+impl fmt::Display for OutputMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputMap::Duplicate => write!(f, "duplicate"),
+            OutputMap::FrontOnly => write!(f, "front-only"),
+            OutputMap::Custom(sources) => {
+                let tokens: Vec<&str> = sources
+                    .iter()
+                    .map(|source| match source {
+                        ChannelSource::Left => "L",
+                        ChannelSource::Right => "R",
+                        ChannelSource::Mid => "M",
+                        ChannelSource::Silent => "-",
+                    })
+                    .collect();
+                write!(f, "custom:{}", tokens.join(","))
+            }
+            OutputMap::Matrix(rows) => {
+                let rows: Vec<String> = rows.iter().map(|row| format!("[{} {}]", row[0], row[1])).collect();
+                write!(f, "upmix {}", rows.join(" "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(map: &OutputMap, channels: usize, left: f32, right: f32) -> Vec<f32> {
+        map.matrix(channels).into_iter().map(|row| upmix(row, left, right)).collect()
+    }
+
+    #[test]
+    fn test_duplicate_alternates_left_right() {
+        let frame = render(&OutputMap::Duplicate, 6, 0.5, -0.25);
+        assert_eq!(frame, vec![0.5, -0.25, 0.5, -0.25, 0.5, -0.25]);
+    }
+
+    #[test]
+    fn test_front_only_silences_rest() {
+        let frame = render(&OutputMap::FrontOnly, 6, 0.5, -0.25);
+        assert_eq!(frame, vec![0.5, -0.25, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_custom_map() {
+        let map = OutputMap::parse("custom:L,R,M,-").unwrap();
+        let frame = render(&map, 6, 0.5, -0.25);
+        // Channels beyond the list are silent
+        assert_eq!(frame, vec![0.5, -0.25, 0.125, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_upmix_matrix() {
+        // 5.1-style: front pair, centre blend, silent LFE, rears at half level
+        let rows: Vec<String> = ["1 0", "0 1", "0.5 0.5", "0 0", "0.5 0", "0 0.5"].iter().map(|row| row.to_string()).collect();
+        let map = OutputMap::parse_matrix(&rows).unwrap();
+        let frame = render(&map, 6, 0.5, -0.25);
+        assert_eq!(frame, vec![0.5, -0.25, 0.125, 0.0, 0.25, -0.125]);
+        assert_eq!(map.to_string(), "upmix [1 0] [0 1] [0.5 0.5] [0 0] [0.5 0] [0 0.5]");
+
+        // The rows have to match the device
+        assert!(map.validate(6).is_ok());
+        assert!(map.validate(8).unwrap_err().contains("6 rows but the output device has 8 channels"));
+        assert!(OutputMap::Duplicate.validate(8).is_ok());
+
+        assert!(OutputMap::parse_matrix(&["1".to_string()]).is_err());
+        assert!(OutputMap::parse_matrix(&["1 0 0".to_string()]).is_err());
+        assert!(OutputMap::parse_matrix(&["left right".to_string()]).is_err());
+        assert!(OutputMap::parse_matrix(&[]).is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(OutputMap::parse("duplicate").unwrap(), OutputMap::Duplicate);
+        assert_eq!(OutputMap::parse("front-only").unwrap(), OutputMap::FrontOnly);
+        assert!(OutputMap::parse("surround").is_err());
+        assert!(OutputMap::parse("custom:L,X").is_err());
+
+        let map = OutputMap::parse("custom:R,L").unwrap();
+        assert_eq!(map.to_string(), "custom:R,L");
+    }
+}
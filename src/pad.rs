@@ -0,0 +1,236 @@
+//! Freeze-and-layer pad
+//!
+//! Keeps a short history of the wet signal. A capture turns the latest window into
+//! a loop that plays under the live signal until it is cleared, optionally pitch
+//! shifted. The loop length is nudged to the point where the end of the window
+//! lines up best with its start, and the last stretch of the window is crossfaded
+//! into the beginning, so a held note loops without a bump or a click at the seam.
+//! Captures and clears fade the pad out and in rather than switching it.
+
+use crate::pitchshift::PitchShifter;
+
+/// Length of the captured loop in seconds (before alignment)
+const LOOP_SECONDS: f64 = 0.4;
+/// Length of the crossfade at the loop seam in seconds
+const CROSSFADE_SECONDS: f64 = 0.05;
+/// How far the loop end may move to line up with the start, in seconds
+/// (one period of a 50 Hz note)
+const ALIGN_SECONDS: f64 = 0.02;
+/// Length of the fade when the pad starts, stops or changes, in seconds
+const FADE_SECONDS: f64 = 0.02;
+/// Pitch shifter window in milliseconds
+const SHIFT_WINDOW_MS: f64 = 60.0;
+/// Largest pitch shift in semitones either way
+const MAX_SEMITONES: f64 = 24.0;
+
+/// Captured loop layered under the live signal
+pub struct PadLayer {
+    /// Sample rate in Hz
+    sample_rate: usize,
+    /// Ring buffer of the latest wet frames
+    history: Vec<(f64, f64)>,
+    /// Write position in the history
+    write_index: usize,
+    /// The loop being played (empty when there is no pad)
+    loop_frames: Vec<(f64, f64)>,
+    /// Play position in the loop
+    position: usize,
+    /// Newly captured loop waiting for the current one to fade out
+    pending: Option<Vec<(f64, f64)>>,
+    /// Whether the pad should be heard (false after a clear)
+    playing: bool,
+    /// Start/stop fade gain (0.0 to 1.0)
+    fade: f64,
+    /// Fade change per frame
+    fade_step: f64,
+    /// Pad level under the live signal (0.0 to 1.0)
+    level: f64,
+    /// Pitch shift in semitones
+    semitones: f64,
+    /// Pitch shifters for the left and right loop
+    shifters: [PitchShifter; 2],
+}
+
+impl PadLayer {
+    /// Create an empty pad at half level
+    pub fn new(sample_rate: usize) -> Self {
+        let loop_frames = (LOOP_SECONDS * sample_rate as f64) as usize;
+        let crossfade = (CROSSFADE_SECONDS * sample_rate as f64) as usize;
+        Self {
+            sample_rate,
+            history: vec![(0.0, 0.0); loop_frames + crossfade],
+            write_index: 0,
+            loop_frames: Vec::new(),
+            position: 0,
+            pending: None,
+            playing: false,
+            fade: 0.0,
+            fade_step: 1.0 / (FADE_SECONDS * sample_rate as f64),
+            level: 0.5,
+            semitones: 0.0,
+            shifters: std::array::from_fn(|_| PitchShifter::new(sample_rate, SHIFT_WINDOW_MS, 1.0)),
+        }
+    }
+
+    /// Record a wet frame and return it with the pad mixed underneath
+    pub fn tick(&mut self, wet: (f64, f64)) -> (f64, f64) {
+        self.history[self.write_index] = wet;
+        self.write_index = (self.write_index + 1) % self.history.len();
+
+        let target = if self.playing && self.pending.is_none() { 1.0 } else { 0.0 };
+        if self.fade < target {
+            self.fade = (self.fade + self.fade_step).min(1.0);
+        } else if self.fade > target {
+            self.fade = (self.fade - self.fade_step).max(0.0);
+        }
+        if self.fade == 0.0 && let Some(frames) = self.pending.take() {
+            self.loop_frames = frames;
+            self.position = 0;
+            for shifter in self.shifters.iter_mut() {
+                shifter.reset();
+            }
+        }
+        if self.fade == 0.0 || self.loop_frames.is_empty() {
+            return wet;
+        }
+
+        let (mut left, mut right) = self.loop_frames[self.position];
+        self.position = (self.position + 1) % self.loop_frames.len();
+        if self.semitones != 0.0 {
+            left = self.shifters[0].process(left);
+            right = self.shifters[1].process(right);
+        }
+        let gain = self.fade * self.level;
+        (wet.0 + left * gain, wet.1 + right * gain)
+    }
+
+    /// Turn the latest window of the wet signal into the pad loop
+    ///
+    /// A pad that is already playing fades out and the new loop fades in.
+    pub fn capture(&mut self) {
+        let len = self.history.len();
+        let window: Vec<(f64, f64)> = (0..len).map(|i| self.history[(self.write_index + i) % len]).collect();
+        let crossfade = (CROSSFADE_SECONDS * self.sample_rate as f64) as usize;
+        let longest = len - crossfade;
+        let shortest = longest - (ALIGN_SECONDS * self.sample_rate as f64) as usize;
+
+        // The loop restarts where the window started, so end it where the window
+        // best matches its own beginning
+        let mono = |frame: (f64, f64)| frame.0 + frame.1;
+        let loop_len = (shortest..=longest)
+            .max_by(|&a, &b| {
+                let score = |end: usize| (0..crossfade).map(|i| mono(window[i]) * mono(window[end + i])).sum::<f64>();
+                score(a).total_cmp(&score(b))
+            })
+            .unwrap_or(longest);
+
+        // Fade the frames past the loop end into the start so the seam is continuous
+        let mut frames = window[..loop_len].to_vec();
+        for (i, frame) in frames.iter_mut().take(crossfade).enumerate() {
+            let rise = i as f64 / crossfade as f64;
+            let tail = window[loop_len + i];
+            frame.0 = frame.0 * rise + tail.0 * (1.0 - rise);
+            frame.1 = frame.1 * rise + tail.1 * (1.0 - rise);
+        }
+
+        self.pending = Some(frames);
+        self.playing = true;
+    }
+
+    /// Fade the pad out and drop it
+    pub fn clear(&mut self) {
+        self.pending = None;
+        self.playing = false;
+    }
+
+    /// Whether a pad is playing
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Set the pad level under the live signal (0.0 to 1.0)
+    pub fn set_pad_level(&mut self, level: f64) {
+        self.level = level.clamp(0.0, 1.0);
+    }
+
+    /// Current pad level
+    pub fn pad_level(&self) -> f64 {
+        self.level
+    }
+
+    /// Set the pitch shift in semitones (-24 to 24; 0 plays the capture as is)
+    pub fn set_semitones(&mut self, semitones: f64) {
+        self.semitones = semitones.clamp(-MAX_SEMITONES, MAX_SEMITONES);
+        let ratio = 2.0_f64.powf(self.semitones / 12.0);
+        self.shifters = std::array::from_fn(|_| PitchShifter::new(self.sample_rate, SHIFT_WINDOW_MS, ratio));
+    }
+
+    /// Current pitch shift in semitones
+    pub fn semitones(&self) -> f64 {
+        self.semitones
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::TAU;
+
+    /// RMS of the left channel over consecutive windows
+    fn window_levels(output: &[(f64, f64)], window: usize) -> Vec<f64> {
+        output
+            .chunks_exact(window)
+            .map(|chunk| (chunk.iter().map(|frame| frame.0 * frame.0).sum::<f64>() / window as f64).sqrt())
+            .collect()
+    }
+
+    #[test]
+    fn test_pad_sustains_at_constant_level() {
+        let sample_rate = 44100;
+        let mut pad = PadLayer::new(sample_rate);
+        pad.set_pad_level(1.0);
+
+        // A note that doesn't fit the loop a whole number of times
+        for i in 0..sample_rate {
+            let sample = 0.5 * (TAU * 233.0 * i as f64 / sample_rate as f64).sin();
+            pad.tick((sample, sample));
+        }
+        pad.capture();
+
+        // The note stops; the pad carries on for several loop passes
+        let output: Vec<(f64, f64)> = (0..sample_rate * 3).map(|_| pad.tick((0.0, 0.0))).collect();
+        let levels = window_levels(&output[4410..], 441);
+        let expected = 0.5 / 2.0_f64.sqrt();
+        for (i, level) in levels.iter().enumerate() {
+            assert!((level - expected).abs() < 0.05 * expected, "window {} level {}", i, level);
+        }
+
+        // Clearing fades it out to silence
+        pad.clear();
+        let output: Vec<(f64, f64)> = (0..4410).map(|_| pad.tick((0.0, 0.0))).collect();
+        assert!(output[..10].iter().all(|frame| frame.0.abs() <= 0.5));
+        assert!(output[2000..].iter().all(|frame| frame.0 == 0.0));
+        assert!(!pad.is_playing());
+    }
+
+    #[test]
+    fn test_pitched_pad_sustains() {
+        let sample_rate = 44100;
+        let mut pad = PadLayer::new(sample_rate);
+        pad.set_pad_level(1.0);
+        pad.set_semitones(12.0);
+        for i in 0..sample_rate {
+            let sample = 0.5 * (TAU * 220.0 * i as f64 / sample_rate as f64).sin();
+            pad.tick((sample, sample));
+        }
+        pad.capture();
+
+        let output: Vec<(f64, f64)> = (0..sample_rate * 2).map(|_| pad.tick((0.0, 0.0))).collect();
+        let levels = window_levels(&output[8820..], 4410);
+        let mean = levels.iter().sum::<f64>() / levels.len() as f64;
+        assert!(mean > 0.2, "mean level {}", mean);
+        for level in levels {
+            assert!((level - mean).abs() < 0.25 * mean, "level {} vs mean {}", level, mean);
+        }
+    }
+}
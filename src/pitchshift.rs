@@ -0,0 +1,114 @@
+//! Pitch shifter
+//!
+//! A simple overlap-add pitch shifter built on a delay line. Two read taps sweep
+//! through the delay at a rate set by the pitch ratio, half a window apart, and are
+//! crossfaded with complementary sin² windows so each tap is silent when it wraps
+//! around. Cheap enough for feedback paths where a few artifacts don't matter.
+
+use std::f64::consts::PI;
+
+/// Mono delay-line pitch shifter
+pub struct PitchShifter {
+    /// Delay line holding the most recent input
+    buffer: Vec<f64>,
+    /// Write position in the delay line
+    write_index: usize,
+    /// Window length in samples
+    window: f64,
+    /// Position of the first tap within the window (0.0 to 1.0)
+    phase: f64,
+    /// Pitch ratio (2.0 = one octave up)
+    ratio: f64,
+}
+
+impl PitchShifter {
+    /// Create a pitch shifter with the given window length and pitch ratio
+    /// (2.0 = one octave up, 0.5 = one octave down)
+    ///
+    /// Longer windows give smoother sound on low notes but smear transients.
+    pub fn new(sample_rate: usize, window_ms: f64, ratio: f64) -> Self {
+        let window = (window_ms * sample_rate as f64 / 1000.0).max(2.0);
+        Self {
+            buffer: vec![0.0; window.ceil() as usize + 2],
+            write_index: 0,
+            window,
+            phase: 0.0,
+            ratio,
+        }
+    }
+
+    /// Shift one sample
+    pub fn process(&mut self, input: f64) -> f64 {
+        self.buffer[self.write_index] = input;
+
+        let mut output = 0.0;
+        for offset in [0.0, 0.5] {
+            let phase = (self.phase + offset).fract();
+            // Shifting up reads from a shrinking delay, shifting down from a growing one
+            let delay = if self.ratio >= 1.0 { (1.0 - phase) * self.window } else { phase * self.window };
+            let gain = (PI * phase).sin().powi(2);
+            output += self.read(delay) * gain;
+        }
+
+        self.phase = (self.phase + (self.ratio - 1.0).abs() / self.window).fract();
+        self.write_index = (self.write_index + 1) % self.buffer.len();
+        output
+    }
+
+    /// Clear the delay line
+    pub fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.phase = 0.0;
+    }
+
+    /// Read `delay` samples behind the write position with linear interpolation
+    fn read(&self, delay: f64) -> f64 {
+        let len = self.buffer.len();
+        let position = self.write_index as f64 + len as f64 - delay;
+        let index = position.floor() as usize;
+        let frac = position - position.floor();
+        let a = self.buffer[index % len];
+        let b = self.buffer[(index + 1) % len];
+        a + (b - a) * frac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Signal power at `freq` (Goertzel algorithm)
+    fn power_at(samples: &[f64], freq: f64, sample_rate: f64) -> f64 {
+        let coeff = 2.0 * (2.0 * PI * freq / sample_rate).cos();
+        let (mut s1, mut s2) = (0.0, 0.0);
+        for &sample in samples {
+            let s0 = sample + coeff * s1 - s2;
+            s2 = s1;
+            s1 = s0;
+        }
+        (s1 * s1 + s2 * s2 - coeff * s1 * s2) / (samples.len() as f64).powi(2)
+    }
+
+    #[test]
+    fn test_octave_up() {
+        let sample_rate = 44100.0;
+        let mut shifter = PitchShifter::new(44100, 50.0, 2.0);
+        let output: Vec<f64> = (0..44100)
+            .map(|i| shifter.process((2.0 * PI * 220.0 * i as f64 / sample_rate).sin()))
+            .skip(4410)
+            .collect();
+
+        let octave = power_at(&output, 440.0, sample_rate);
+        let fundamental = power_at(&output, 220.0, sample_rate);
+        assert!(octave > 100.0 * fundamental, "440Hz {} vs 220Hz {}", octave, fundamental);
+    }
+
+    #[test]
+    fn test_unity_ratio_is_delay() {
+        let mut shifter = PitchShifter::new(1000, 10.0, 1.0);
+        // Taps stay put at full and half window; the full-window tap is silent
+        let output: Vec<f64> = (0..20).map(|i| shifter.process(if i == 0 { 1.0 } else { 0.0 })).collect();
+        assert_eq!(output[5], 1.0);
+        assert!(output.iter().enumerate().all(|(i, &s)| i == 5 || s == 0.0));
+    }
+}
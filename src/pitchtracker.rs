@@ -0,0 +1,184 @@
+//! Monophonic pitch tracker
+//!
+//! A YIN-style tracker: the cumulative mean normalised difference of the last
+//! analysis window against lagged copies of itself dips towards 0 at the period of
+//! a periodic input. The depth of that dip doubles as a confidence: a single note
+//! is close to periodic and scores near 1, while the notes of a chord rarely share a
+//! period in range (equal temperament detunes the simple ratios) and score low.
+//! Fifths and octaves are the exception: a power chord tracks as its root. Pitched effects use
+//! the confidence to blend out their pitched component on chords, where it would
+//! only add warble.
+//!
+//! The analysis runs once per hop rather than per sample, so `estimate` returns
+//! the result of the latest completed window.
+
+/// Lowest tracked frequency in Hz (a little below a drop-D low string)
+const MIN_FREQ_HZ: f64 = 70.0;
+/// Highest tracked frequency in Hz
+const MAX_FREQ_HZ: f64 = 1500.0;
+/// Normalised difference below which the first dip is taken as the period
+const DIP_THRESHOLD: f64 = 0.15;
+/// Analysis window length in periods of the lowest frequency (long enough to
+/// span the beating between the notes of a chord)
+const WINDOW_PERIODS: usize = 2;
+/// Mean square below which the input counts as silence
+const SILENCE_POWER: f64 = 1e-8;
+
+/// Frequency and confidence tracker for a single voice
+pub struct PitchTracker {
+    /// Sample rate in Hz
+    sample_rate: f64,
+    /// Ring buffer of the latest window plus the longest lag of input
+    buffer: Vec<f64>,
+    /// Write position in the ring buffer
+    write_index: usize,
+    /// Samples until the next analysis
+    hop_remaining: usize,
+    /// Shortest period searched, in samples
+    min_lag: usize,
+    /// Longest period searched, in samples
+    max_lag: usize,
+    /// Analysis window (and hop) length in samples
+    window: usize,
+    /// Input unrolled in time order for the analysis
+    frame: Vec<f64>,
+    /// Cumulative mean normalised difference per lag
+    difference: Vec<f64>,
+    /// Latest (frequency in Hz, confidence 0.0 to 1.0)
+    estimate: (f64, f64),
+}
+
+impl PitchTracker {
+    /// Create a tracker with nothing detected yet
+    pub fn new(sample_rate: usize) -> Self {
+        let min_lag = (sample_rate as f64 / MAX_FREQ_HZ).floor() as usize;
+        let max_lag = (sample_rate as f64 / MIN_FREQ_HZ).ceil() as usize;
+        let window = WINDOW_PERIODS * max_lag;
+        Self {
+            sample_rate: sample_rate as f64,
+            buffer: vec![0.0; window + max_lag],
+            write_index: 0,
+            hop_remaining: window,
+            min_lag: min_lag.max(2),
+            max_lag,
+            window,
+            frame: vec![0.0; window + max_lag],
+            difference: vec![0.0; max_lag + 1],
+            estimate: (0.0, 0.0),
+        }
+    }
+
+    /// Feed one input sample
+    pub fn push(&mut self, sample: f64) {
+        self.buffer[self.write_index] = sample;
+        self.write_index = (self.write_index + 1) % self.buffer.len();
+        self.hop_remaining -= 1;
+        if self.hop_remaining == 0 {
+            self.hop_remaining = self.window;
+            self.estimate = self.analyse();
+        }
+    }
+
+    /// Latest frequency in Hz and confidence (0.0 = no pitch, 1.0 = clean single note)
+    ///
+    /// The frequency is 0.0 while nothing has been detected.
+    pub fn estimate(&self) -> (f64, f64) {
+        self.estimate
+    }
+
+    /// Forget the input and the latest estimate
+    pub fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.write_index = 0;
+        self.hop_remaining = self.window;
+        self.estimate = (0.0, 0.0);
+    }
+
+    /// Run the difference function over the buffered input
+    fn analyse(&mut self) -> (f64, f64) {
+        let (older, newer) = self.buffer.split_at(self.write_index);
+        self.frame[..newer.len()].copy_from_slice(newer);
+        self.frame[newer.len()..].copy_from_slice(older);
+
+        let window = self.window;
+        let frame = &self.frame;
+        let power = frame[..window].iter().map(|x| x * x).sum::<f64>() / window as f64;
+        if power < SILENCE_POWER {
+            return (0.0, 0.0);
+        }
+
+        // Cumulative mean normalised difference
+        self.difference[0] = 1.0;
+        let mut running_sum = 0.0;
+        for lag in 1..=self.max_lag {
+            let difference: f64 = (0..window).map(|j| (frame[j] - frame[j + lag]).powi(2)).sum();
+            running_sum += difference;
+            self.difference[lag] = if running_sum > 0.0 {
+                difference * lag as f64 / running_sum
+            } else {
+                1.0
+            };
+        }
+
+        // First dip under the threshold, followed to its bottom, else the deepest dip
+        let search = self.min_lag..self.max_lag;
+        let lag = match search.clone().find(|&lag| self.difference[lag] < DIP_THRESHOLD) {
+            Some(mut lag) => {
+                while lag + 1 < self.max_lag && self.difference[lag + 1] < self.difference[lag] {
+                    lag += 1;
+                }
+                lag
+            }
+            None => search
+                .min_by(|&a, &b| self.difference[a].total_cmp(&self.difference[b]))
+                .unwrap_or(self.min_lag),
+        };
+
+        let confidence = (1.0 - self.difference[lag]).clamp(0.0, 1.0);
+        (self.sample_rate / self.refine(lag), confidence)
+    }
+
+    /// Parabolic interpolation of the dip around `lag` for a fractional period
+    fn refine(&self, lag: usize) -> f64 {
+        let (before, at, after) = (self.difference[lag - 1], self.difference[lag], self.difference[lag + 1]);
+        let curvature = before - 2.0 * at + after;
+        if curvature <= 0.0 {
+            return lag as f64;
+        }
+        lag as f64 + 0.5 * (before - after) / curvature
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::TAU;
+
+    /// Run a signal through a fresh tracker and return its final estimate
+    fn track(signal: impl Fn(f64) -> f64) -> (f64, f64) {
+        let mut tracker = PitchTracker::new(44100);
+        for i in 0..13230 {
+            tracker.push(signal(i as f64 / 44100.0));
+        }
+        tracker.estimate()
+    }
+
+    #[test]
+    fn test_single_note_is_confident() {
+        let (freq, confidence) = track(|t| 0.5 * (TAU * 220.0 * t).sin());
+        assert!((freq - 220.0).abs() < 1.0, "freq {}", freq);
+        assert!(confidence > 0.9, "confidence {}", confidence);
+    }
+
+    #[test]
+    fn test_two_tones_are_less_confident() {
+        let (_, single) = track(|t| 0.5 * (TAU * 196.0 * t).sin());
+        // An equal-tempered minor third: no common period in range
+        let (_, chord) = track(|t| 0.25 * (TAU * 196.0 * t).sin() + 0.25 * (TAU * 233.08 * t).sin());
+        assert!(single > 0.97, "single confidence {}", single);
+        assert!(chord < 0.9, "chord confidence {}", chord);
+
+        // Silence has no pitch at all
+        assert_eq!(track(|_| 0.0), (0.0, 0.0));
+    }
+}
@@ -0,0 +1,208 @@
+//! Plate reverb
+//!
+//! A feedback delay network tuned to sound like a plate: four short delay lines
+//! whose outputs are mixed back into each other through a Hadamard matrix, with a
+//! one-pole low-pass in each loop for the dampening. Because the matrix is
+//! orthogonal the network loses energy only through the loop gain, so the decay
+//! follows the room size alone, and every echo is spread over all four lines: the
+//! echo density builds up much faster than in freeverb's parallel combs, giving
+//! the bright, dense, smooth onset of a plate instead of a room's discrete early
+//! reflections. Two allpasses per side thicken the input further.
+//!
+//! The controls mirror freeverb's so the reverb wrapper can drive either one:
+//! room size sets the loop gain (0.7 to 0.98, like freeverb's comb feedback),
+//! dampening the loop low-pass, width the stereo spread of the two outputs, and
+//! freeze switches the loop gain to 1 without dampening and mutes the input.
+
+use crate::denormal;
+
+/// Delay line lengths in milliseconds (mutually prime in samples at common rates)
+const LINE_MS: [f64; 4] = [29.71, 37.13, 41.11, 43.73];
+/// Input allpass delays in milliseconds for the left side
+const INPUT_ALLPASS_MS: [f64; 2] = [4.77, 3.59];
+/// Extra input allpass delay for the right side
+const STEREO_SPREAD_MS: f64 = 0.53;
+/// Input allpass gain
+const INPUT_ALLPASS_GAIN: f64 = 0.6;
+/// Loop gain at room size 0
+const ROOM_OFFSET: f64 = 0.7;
+/// Loop gain added per unit of room size
+const ROOM_SCALE: f64 = 0.28;
+/// Loop low-pass coefficient at full dampening
+const MAX_DAMPING: f64 = 0.4;
+/// Input level into the network, matching freeverb's output level roughly
+const INPUT_GAIN: f64 = 0.25;
+
+/// Delay line with a single tap at its full length
+struct DelayLine {
+    buffer: Vec<f64>,
+    index: usize,
+}
+
+impl DelayLine {
+    fn new(length: usize) -> Self {
+        Self {
+            buffer: vec![0.0; length.max(1)],
+            index: 0,
+        }
+    }
+
+    /// Oldest sample in the line
+    fn read(&self) -> f64 {
+        self.buffer[self.index]
+    }
+
+    /// Replace the oldest sample and advance
+    fn write(&mut self, value: f64) {
+        self.buffer[self.index] = value;
+        self.index = (self.index + 1) % self.buffer.len();
+    }
+
+    /// Schroeder allpass around the line
+    fn allpass(&mut self, input: f64, gain: f64) -> f64 {
+        let delayed = self.read();
+        let fed_back = input + gain * delayed;
+        self.write(fed_back);
+        delayed - gain * fed_back
+    }
+
+    fn reset(&mut self) {
+        self.buffer.fill(0.0);
+    }
+}
+
+fn ms_to_samples(sample_rate: usize, ms: f64) -> usize {
+    (ms * sample_rate as f64 / 1000.0).round() as usize
+}
+
+/// Four-line feedback delay network plate reverb
+pub struct Plate {
+    /// Feedback delay lines
+    lines: [DelayLine; 4],
+    /// Loop low-pass states, one per line
+    damping_states: [f64; 4],
+    /// Input allpasses for the left and right side
+    input_allpasses: [[DelayLine; 2]; 2],
+    /// Room size (0.0 to 1.0)
+    room_size: f64,
+    /// Dampening (0.0 to 1.0)
+    dampening: f64,
+    /// Stereo width (0.0 to 1.0)
+    width: f64,
+    /// Whether the tail is frozen
+    frozen: bool,
+}
+
+impl Plate {
+    /// Create a plate with freeverb's default settings
+    pub fn new(sample_rate: usize) -> Self {
+        let allpasses = |spread_ms: f64| INPUT_ALLPASS_MS.map(|ms| DelayLine::new(ms_to_samples(sample_rate, ms + spread_ms)));
+        Self {
+            lines: LINE_MS.map(|ms| DelayLine::new(ms_to_samples(sample_rate, ms))),
+            damping_states: [0.0; 4],
+            input_allpasses: [allpasses(0.0), allpasses(STEREO_SPREAD_MS)],
+            room_size: 0.5,
+            dampening: 0.5,
+            width: 0.5,
+            frozen: false,
+        }
+    }
+
+    /// Process a stereo input sample and return the wet tail
+    pub fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let (gain, damping, input_gain) = if self.frozen {
+            (1.0, 0.0, 0.0)
+        } else {
+            (ROOM_OFFSET + ROOM_SCALE * self.room_size, self.dampening * MAX_DAMPING, INPUT_GAIN)
+        };
+
+        // Thicken each side before it enters the loop
+        let mut sides = [input.0 * input_gain, input.1 * input_gain];
+        for (side, allpasses) in sides.iter_mut().zip(self.input_allpasses.iter_mut()) {
+            for allpass in allpasses.iter_mut() {
+                *side = allpass.allpass(*side, INPUT_ALLPASS_GAIN);
+            }
+        }
+
+        // Damp each line's output in the loop
+        let mut outputs = [0.0; 4];
+        for ((output, line), state) in outputs.iter_mut().zip(&self.lines).zip(self.damping_states.iter_mut()) {
+            *state = denormal::flush(line.read() * (1.0 - damping) + *state * damping);
+            *output = *state;
+        }
+
+        // Orthogonal 4x4 Hadamard mix, so only the loop gain takes energy out
+        let [a, b, c, d] = outputs;
+        let mixed = [a + b + c + d, a - b + c - d, a + b - c - d, a - b - c + d];
+        let feeds = [sides[0], sides[1], sides[0], sides[1]];
+        for ((line, mixed), feed) in self.lines.iter_mut().zip(mixed).zip(feeds) {
+            line.write(mixed * 0.5 * gain + feed);
+        }
+
+        // Two decorrelated taps, spread by the width like freeverb's wet1/wet2
+        let left = a + c;
+        let right = b + d;
+        let wet1 = self.width / 2.0 + 0.5;
+        let wet2 = (1.0 - self.width) / 2.0;
+        (left * wet1 + right * wet2, right * wet1 + left * wet2)
+    }
+
+    /// Set the room size (0.0 to 1.0), the loop gain from 0.7 to 0.98
+    pub fn set_room_size(&mut self, room_size: f64) {
+        self.room_size = room_size;
+    }
+
+    /// Set the dampening (0.0 to 1.0)
+    pub fn set_dampening(&mut self, dampening: f64) {
+        self.dampening = dampening;
+    }
+
+    /// Set the stereo width (0.0 to 1.0)
+    pub fn set_width(&mut self, width: f64) {
+        self.width = width;
+    }
+
+    /// Freeze or release the tail
+    pub fn set_freeze(&mut self, frozen: bool) {
+        self.frozen = frozen;
+    }
+
+    /// Clear the tail, keeping the settings
+    pub fn reset(&mut self) {
+        for line in self.lines.iter_mut().chain(self.input_allpasses.iter_mut().flatten()) {
+            line.reset();
+        }
+        self.damping_states = [0.0; 4];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Left tail energy in consecutive quarter-second windows
+    fn window_energies(plate: &mut Plate, windows: usize) -> Vec<f64> {
+        (0..windows)
+            .map(|_| (0..11025).map(|_| plate.tick((0.0, 0.0)).0.powi(2)).sum())
+            .collect()
+    }
+
+    #[test]
+    fn test_freeze_holds_the_tail_and_reset_clears_it() {
+        let mut plate = Plate::new(44100);
+        plate.set_room_size(0.5);
+        plate.tick((1.0, 1.0));
+        let decaying = window_energies(&mut plate, 4);
+        assert!(decaying[3] < decaying[0] * 0.01, "{:?}", decaying);
+
+        // Frozen, the lossless loop neither decays nor takes new input
+        plate.set_freeze(true);
+        let frozen = window_energies(&mut plate, 4);
+        for energy in &frozen {
+            assert!((energy / frozen[0] - 1.0).abs() < 0.2, "{:?}", frozen);
+        }
+
+        plate.reset();
+        assert!((0..5000).all(|_| plate.tick((0.5, 0.5)) == (0.0, 0.0)));
+    }
+}
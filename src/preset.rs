@@ -0,0 +1,952 @@
+//! Effect presets
+//!
+//! A preset is a set of effect parameters stored in a small TOML subset:
+//!
+//! ```text
+//! [chain]
+//! order = ["dist", "verb", "multi", "width"]
+//! enabled = ["dist", "verb"]
+//! trim_db = -3
+//!
+//! [mix]
+//! verb = 0.3
+//!
+//! [reverb]
+//! room_size = 0.8
+//! ```
+//!
+//! Only `[section]` headers and `key = value` lines are supported, with numbers,
+//! `true`/`false`, quoted strings and lists of quoted strings as values. Every key
+//! is checked against a fixed schema so typos and out-of-range values are reported
+//! with the offending field. Settings that a preset leaves out keep their current
+//! value when it is applied.
+//!
+//! For live use, `next` and `prev` step through the files in the `presets`
+//! directory in name order. The directory is listed again on every step, so
+//! presets can be added or deleted while playing: the scroll remembers the name
+//! of the preset it loaded last, not its position, and carries on from where that
+//! name sorts even after the file is gone.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::lfo::LfoShape;
+use crate::chain::EffectChain;
+use crate::distortion::DistortionType;
+use crate::ipc::status;
+use crate::multiband::Band;
+use crate::reverb::ReverbAlgo;
+use crate::tempo::NoteValue;
+
+/// A parsed setting value
+///
+/// In JSON a value is written as the plain number, boolean, string or array.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+    Number(f64),
+    Bool(bool),
+    Text(String),
+    List(Vec<String>),
+}
+
+impl Value {
+    /// Parse the right-hand side of a `key = value` line
+    fn parse(text: &str) -> Result<Self, String> {
+        if text == "true" || text == "false" {
+            return Ok(Value::Bool(text == "true"));
+        }
+        if let Some(inner) = text.strip_prefix('[') {
+            let inner = inner.strip_suffix(']').ok_or("unterminated list")?;
+            return inner
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| match Value::parse(item)? {
+                    Value::Text(text) => Ok(text),
+                    _ => Err(format!("list items must be quoted strings, got {}", item)),
+                })
+                .collect::<Result<_, _>>()
+                .map(Value::List);
+        }
+        if let Some(inner) = text.strip_prefix('"') {
+            let inner = inner.strip_suffix('"').ok_or("unterminated string")?;
+            return Ok(Value::Text(inner.to_string()));
+        }
+        text.parse::<f64>()
+            .map(Value::Number)
+            .map_err(|_| format!("invalid value {}", text))
+    }
+
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(flag) => Some(*flag),
+            _ => None,
+        }
+    }
+
+    fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn as_list(&self) -> Option<&[String]> {
+        match self {
+            Value::List(list) => Some(list),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Number(number) => write!(f, "{}", number),
+            Value::Bool(flag) => write!(f, "{}", flag),
+            Value::Text(text) => write!(f, "\"{}\"", text),
+            Value::List(list) => {
+                let items: Vec<String> = list.iter().map(|item| format!("\"{}\"", item)).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+        }
+    }
+}
+
+/// One `key = value` line of a settings file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// 1-based line number, for error messages
+    pub line: usize,
+    /// Section the key appeared in
+    pub section: String,
+    /// Key name
+    pub key: String,
+    /// Parsed value
+    pub value: Value,
+}
+
+/// Split a settings file into entries
+///
+/// Blank lines and `#` comments are skipped. Errors name the line number.
+pub fn parse_document(text: &str) -> Result<Vec<Entry>, String> {
+    let mut section = None;
+    let mut entries = Vec::new();
+
+    for (index, raw) in text.lines().enumerate() {
+        let line = index + 1;
+        let content = strip_comment(raw).trim();
+        if content.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = content.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| format!("line {}: malformed section header {}", line, content))?;
+            section = Some(name.trim().to_string());
+            continue;
+        }
+
+        let (key, value) = content
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected key = value, got {}", line, content))?;
+        let key = key.trim();
+        let section = section
+            .clone()
+            .ok_or_else(|| format!("line {}: '{}' must be inside a [section]", line, key))?;
+        let value = Value::parse(value.trim()).map_err(|e| format!("line {}: {}.{}: {}", line, section, key, e))?;
+
+        entries.push(Entry {
+            line,
+            section,
+            key: key.to_string(),
+            value,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Remove a trailing `#` comment, ignoring `#` inside quoted strings
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Expected type and range of a preset setting
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    /// Number from 0.0 to 1.0
+    Unit,
+    /// Number within the given range
+    Range(f64, f64),
+    /// true or false
+    Bool,
+    /// Distortion type name (soft, hard, bit, wave, over, cascade)
+    DistortionType,
+    /// Distortion type name or `clean`
+    BandType,
+    /// List of stage names
+    Stages,
+    /// Note value such as "1/8." (see `NoteValue::parse`)
+    NoteValue,
+    /// LFO waveform name (sine, tri, square, sawup, sawdown, random)
+    LfoShape,
+    /// Reverb algorithm name (freeverb, plate)
+    ReverbAlgo,
+}
+
+impl Kind {
+    /// Pull a number into this kind's range, or read it as on/off (at least 0.5)
+    /// for a boolean; other values are returned unchanged
+    fn coerce(self, value: Value) -> Value {
+        match (self, value) {
+            (Kind::Unit, Value::Number(number)) => Value::Number(number.clamp(0.0, 1.0)),
+            (Kind::Range(min, max), Value::Number(number)) => Value::Number(number.clamp(min, max)),
+            (Kind::Bool, Value::Number(number)) => Value::Bool(number >= 0.5),
+            (_, value) => value,
+        }
+    }
+
+    /// Check a value against this kind, describing the problem on failure
+    fn check(self, value: &Value) -> Result<(), String> {
+        let ok = match self {
+            Kind::Unit => value.as_number().is_some_and(|n| (0.0..=1.0).contains(&n)),
+            Kind::Range(min, max) => value.as_number().is_some_and(|n| (min..=max).contains(&n)),
+            Kind::Bool => value.as_bool().is_some(),
+            Kind::DistortionType => value.as_text().and_then(DistortionType::from_name).is_some(),
+            Kind::BandType => value
+                .as_text()
+                .is_some_and(|name| name == "clean" || DistortionType::from_name(name).is_some()),
+            Kind::Stages => value.as_list().is_some(),
+            Kind::NoteValue => value.as_text().is_some_and(|text| NoteValue::parse(text).is_ok()),
+            Kind::LfoShape => value.as_text().and_then(LfoShape::from_name).is_some(),
+            Kind::ReverbAlgo => value.as_text().and_then(ReverbAlgo::from_name).is_some(),
+        };
+        if ok {
+            return Ok(());
+        }
+
+        let expected = match self {
+            Kind::Unit => "a number from 0 to 1".to_string(),
+            Kind::Range(min, max) => format!("a number from {} to {}", min, max),
+            Kind::Bool => "true or false".to_string(),
+            Kind::DistortionType => "one of \"soft\", \"hard\", \"bit\", \"wave\", \"over\", \"cascade\"".to_string(),
+            Kind::BandType => "one of \"soft\", \"hard\", \"bit\", \"wave\", \"over\", \"cascade\", \"clean\"".to_string(),
+            Kind::Stages => "a list of stage names".to_string(),
+            Kind::NoteValue => "a note value such as \"1/4\", \"1/8.\" or \"1/8t\"".to_string(),
+            Kind::LfoShape => format!("one of \"{}\"", LfoShape::names("\", \"")),
+            Kind::ReverbAlgo => format!("one of \"{}\"", ReverbAlgo::names("\", \"")),
+        };
+        Err(format!("expected {}, got {}", expected, value))
+    }
+}
+
+/// Every setting a preset can hold, in the order they are applied and written
+const SCHEMA: &[(&str, &str, Kind)] = &[
+    ("chain", "order", Kind::Stages),
+    ("chain", "enabled", Kind::Stages),
+    ("chain", "trim_db", Kind::Range(-24.0, 12.0)),
+    ("chain", "input_tilt", Kind::Range(-1.0, 1.0)),
+    ("chain", "autogain", Kind::Bool),
+    ("chain", "dc_block", Kind::Bool),
+    ("chain", "trails", Kind::Bool),
+    ("chain", "switch_fade_ms", Kind::Range(0.0, 100.0)),
+    ("chain", "autosuspend", Kind::Bool),
+    ("chain", "suspend_after_s", Kind::Range(1.0, 600.0)),
+    ("chain", "tempo_bpm", Kind::Range(30.0, 300.0)),
+    ("mix", "nr", Kind::Unit),
+    ("mix", "dist", Kind::Unit),
+    ("mix", "multi", Kind::Unit),
+    ("mix", "excite", Kind::Unit),
+    ("mix", "trem", Kind::Unit),
+    ("mix", "reverse", Kind::Unit),
+    ("mix", "delay", Kind::Unit),
+    ("mix", "verb", Kind::Unit),
+    ("mix", "adt", Kind::Unit),
+    ("mix", "width", Kind::Unit),
+    ("mix", "pan", Kind::Unit),
+    ("mix", "boost", Kind::Unit),
+    ("reverb", "algo", Kind::ReverbAlgo),
+    ("reverb", "room_size", Kind::Unit),
+    ("reverb", "dampening", Kind::Unit),
+    ("reverb", "width", Kind::Unit),
+    ("reverb", "diffusion", Kind::Unit),
+    ("reverb", "predelay_ms", Kind::Range(0.0, 250.0)),
+    ("reverb", "shimmer", Kind::Unit),
+    ("reverb", "mod_depth", Kind::Unit),
+    ("reverb", "mod_rate_hz", Kind::Range(0.05, 5.0)),
+    ("reverb", "damp_lp_hz", Kind::Range(200.0, 20000.0)),
+    ("reverb", "send_hp_hz", Kind::Range(20.0, 2000.0)),
+    ("reverb", "send_lp_hz", Kind::Range(500.0, 20000.0)),
+    ("reverb", "gated", Kind::Bool),
+    ("reverb", "freeze", Kind::Bool),
+    ("reverb", "freeze_release_ms", Kind::Range(0.0, 10000.0)),
+    ("reverb", "gate_threshold", Kind::Unit),
+    ("reverb", "gate_hysteresis_db", Kind::Range(0.0, 24.0)),
+    ("reverb", "gate_hold_ms", Kind::Range(0.0, 2000.0)),
+    ("reverb", "gate_release_ms", Kind::Range(0.0, 2000.0)),
+    ("reverb", "duck_amount", Kind::Unit),
+    ("reverb", "duck_attack_ms", Kind::Range(1.0, 500.0)),
+    ("reverb", "duck_release_ms", Kind::Range(10.0, 5000.0)),
+    ("distortion", "type", Kind::DistortionType),
+    ("distortion", "drive", Kind::Unit),
+    ("distortion", "level", Kind::Unit),
+    ("distortion", "tone", Kind::Unit),
+    ("distortion", "crush_rate", Kind::Unit),
+    ("distortion", "crush_depth", Kind::Unit),
+    ("distortion", "crush_dither", Kind::Bool),
+    ("distortion", "crush_jitter", Kind::Unit),
+    ("distortion", "sag", Kind::Unit),
+    ("distortion", "soft_knee", Kind::Unit),
+    ("distortion", "bass_preserve_hz", Kind::Range(0.0, 500.0)),
+    ("distortion", "tightness_hz", Kind::Range(0.0, 1000.0)),
+    ("distortion", "oversample", Kind::Bool),
+    ("multiband", "low_freq", Kind::Range(20.0, 1000.0)),
+    ("multiband", "high_freq", Kind::Range(40.0, 12000.0)),
+    ("multiband", "low", Kind::BandType),
+    ("multiband", "mid", Kind::BandType),
+    ("multiband", "high", Kind::BandType),
+    ("multiband", "low_drive", Kind::Unit),
+    ("multiband", "mid_drive", Kind::Unit),
+    ("multiband", "high_drive", Kind::Unit),
+    ("multiband", "low_level", Kind::Unit),
+    ("multiband", "mid_level", Kind::Unit),
+    ("multiband", "high_level", Kind::Unit),
+    ("reverse", "time_ms", Kind::Range(50.0, 2000.0)),
+    ("reverse", "mix", Kind::Unit),
+    ("delay", "time_ms", Kind::Range(1.0, 2000.0)),
+    ("delay", "sync", Kind::NoteValue),
+    ("delay", "feedback", Kind::Range(0.0, 0.95)),
+    ("delay", "level", Kind::Unit),
+    ("delay", "ping_pong", Kind::Bool),
+    ("denoise", "reduction_db", Kind::Range(0.0, 60.0)),
+    ("stereo", "width", Kind::Range(0.0, 2.0)),
+    ("autopan", "rate_hz", Kind::Range(0.05, 20.0)),
+    ("autopan", "depth", Kind::Unit),
+    ("autopan", "phase", Kind::Unit),
+    ("autopan", "shape", Kind::LfoShape),
+    ("autopan", "sync", Kind::NoteValue),
+    ("autopan", "restart", Kind::Bool),
+    ("tremolo", "rate_hz", Kind::Range(0.05, 20.0)),
+    ("tremolo", "depth", Kind::Unit),
+    ("tremolo", "shape", Kind::LfoShape),
+    ("tremolo", "sync", Kind::NoteValue),
+    ("tremolo", "restart", Kind::Bool),
+    ("adt", "delay_ms", Kind::Range(20.0, 40.0)),
+    ("adt", "spread", Kind::Unit),
+    ("exciter", "amount", Kind::Unit),
+    ("boost", "gain_db", Kind::Range(0.0, 12.0)),
+];
+
+/// Fit a value from a control surface to a setting
+///
+/// Numbers are clamped into the setting's range, and read as on/off for boolean
+/// settings. Anything else is returned unchanged, to be checked by `Preset::set`.
+pub fn coerce_setting(section: &str, key: &str, value: Value) -> Value {
+    match SCHEMA.iter().find(|&&(s, k, _)| s == section && k == key) {
+        Some(&(_, _, kind)) => kind.coerce(value),
+        None => value,
+    }
+}
+
+/// Map a control position (0.0 to 1.0) onto a setting's range
+///
+/// Switches turn on from the halfway point. Settings that are neither numbers nor
+/// switches (types, stage lists, note values) can't be set from a single control.
+pub fn scale_setting(section: &str, key: &str, position: f64) -> Result<Value, String> {
+    let &(_, _, kind) = SCHEMA
+        .iter()
+        .find(|&&(s, k, _)| s == section && k == key)
+        .ok_or_else(|| format!("unknown setting {}.{}", section, key))?;
+    let position = position.clamp(0.0, 1.0);
+    match kind {
+        Kind::Unit => Ok(Value::Number(position)),
+        Kind::Range(min, max) => Ok(Value::Number(min + position * (max - min))),
+        Kind::Bool => Ok(Value::Bool(position >= 0.5)),
+        _ => Err(format!("{}.{} is not a number or switch", section, key)),
+    }
+}
+
+/// Validate a single setting and apply it to the chain
+pub fn apply_value(chain: &mut EffectChain, section: &str, key: &str, value: Value) -> Result<(), String> {
+    let mut preset = Preset::default();
+    preset.set(section, key, value)?;
+    preset.apply(chain)
+}
+
+/// Settings that decide which stages run and in what order
+const ROUTING: [(&str, &str); 2] = [("chain", "order"), ("chain", "enabled")];
+
+/// A validated set of effect parameters
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Preset {
+    /// Settings as (section, key, value), at most one per key
+    settings: Vec<(&'static str, &'static str, Value)>,
+}
+
+impl Preset {
+    /// Read and parse a preset file
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Cannot read preset {}: {}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("Invalid preset {}: {}", path, e))
+    }
+
+    /// Parse preset text
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut preset = Preset::default();
+        for entry in parse_document(text)? {
+            preset
+                .set(&entry.section, &entry.key, entry.value)
+                .map_err(|e| format!("line {}: {}", entry.line, e))?;
+        }
+        Ok(preset)
+    }
+
+    /// Whether `section` holds preset settings (as opposed to other config sections)
+    pub fn has_section(section: &str) -> bool {
+        SCHEMA.iter().any(|&(s, _, _)| s == section)
+    }
+
+    /// Validate and store a setting, replacing any previous value for the key
+    pub fn set(&mut self, section: &str, key: &str, value: Value) -> Result<(), String> {
+        let &(section, key, kind) = SCHEMA
+            .iter()
+            .find(|&&(s, k, _)| s == section && k == key)
+            .ok_or_else(|| format!("unknown setting {}.{}", section, key))?;
+        kind.check(&value).map_err(|e| format!("{}.{}: {}", section, key, e))?;
+
+        self.settings.retain(|&(s, k, _)| !(s == section && k == key));
+        self.settings.push((section, key, value));
+        Ok(())
+    }
+
+    /// Stored value for a setting
+    pub fn get(&self, section: &str, key: &str) -> Option<&Value> {
+        self.settings
+            .iter()
+            .find(|&&(s, k, _)| s == section && k == key)
+            .map(|(_, _, value)| value)
+    }
+
+    /// Whether the preset holds no settings
+    pub fn is_empty(&self) -> bool {
+        self.settings.is_empty()
+    }
+
+    /// The parameter settings of this preset, without its routing
+    ///
+    /// Routing is the `chain.order` and `chain.enabled` lists: which stages run and
+    /// in what order. Applying only the parameters leaves those as they are.
+    pub fn parameters(&self) -> Preset {
+        let settings = self
+            .settings
+            .iter()
+            .filter(|&&(section, key, _)| !ROUTING.contains(&(section, key)))
+            .cloned()
+            .collect();
+        Preset { settings }
+    }
+
+    /// Settings of this preset whose values differ from (or are missing in) `previous`
+    pub fn changes_from(&self, previous: &Preset) -> Preset {
+        let settings = self
+            .settings
+            .iter()
+            .filter(|&(section, key, value)| previous.get(section, key) != Some(value))
+            .cloned()
+            .collect();
+        Preset { settings }
+    }
+
+    /// Blend two presets by `amount` (0.0 = `a`, 1.0 = `b`)
+    ///
+    /// Numbers are interpolated linearly; switches, names and stage lists change
+    /// from `a` to `b` at the midpoint. A setting only one of them holds keeps that
+    /// value all the way across.
+    pub fn morph(a: &Preset, b: &Preset, amount: f64) -> Preset {
+        let amount = amount.clamp(0.0, 1.0);
+        let mut settings = Vec::new();
+        for &(section, key, _) in SCHEMA {
+            let value = match (a.get(section, key), b.get(section, key)) {
+                (Some(Value::Number(from)), Some(Value::Number(to))) => Value::Number(from * (1.0 - amount) + to * amount),
+                (Some(from), Some(to)) => if amount < 0.5 { from } else { to }.clone(),
+                (Some(value), None) | (None, Some(value)) => value.clone(),
+                (None, None) => continue,
+            };
+            settings.push((section, key, value));
+        }
+        Preset { settings }
+    }
+
+    /// Apply every stored setting to the chain
+    ///
+    /// Fails if the chain order or enabled lists name unknown stages.
+    pub fn apply(&self, chain: &mut EffectChain) -> Result<(), String> {
+        for &(section, key, _) in SCHEMA {
+            if let Some(value) = self.get(section, key) {
+                apply_setting(chain, section, key, value).map_err(|e| format!("{}.{}: {}", section, key, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Apply one validated setting
+fn apply_setting(chain: &mut EffectChain, section: &str, key: &str, value: &Value) -> Result<(), String> {
+    let number = value.as_number().unwrap_or_default();
+    let flag = value.as_bool().unwrap_or_default();
+    let text = value.as_text().unwrap_or_default();
+
+    match section {
+        "chain" => match key {
+            "order" => {
+                let order: Vec<&str> = value.as_list().unwrap_or_default().iter().map(String::as_str).collect();
+                chain.set_order(&order)?;
+            }
+            "enabled" => {
+                let enabled = value.as_list().unwrap_or_default();
+                if let Some(unknown) = enabled.iter().find(|name| !chain.stage_names().contains(&name.as_str())) {
+                    return Err(format!("unknown stage '{}'", unknown));
+                }
+                for name in chain.stage_names() {
+                    chain.set_enabled(name, enabled.iter().any(|stage| stage == name))?;
+                }
+            }
+            "trim_db" => chain.set_output_trim_db(number),
+            "input_tilt" => chain.set_input_tilt(number),
+            "tempo_bpm" => chain.set_tempo_bpm(number),
+            "dc_block" => chain.set_dc_block(flag),
+            "trails" => chain.set_trails(flag),
+            "switch_fade_ms" => chain.set_switch_fade_ms(number),
+            "autosuspend" => chain.set_auto_suspend(flag),
+            "suspend_after_s" => chain.set_suspend_after(number),
+            _ => chain.set_auto_gain(flag),
+        },
+        "mix" => chain.set_mix(key, number)?,
+        "reverb" => {
+            let reverb = chain.reverb_mut().ok_or("no reverb stage in the chain")?;
+            match key {
+                "algo" => reverb.set_reverb_algo(ReverbAlgo::from_name(text).unwrap_or_default()),
+                "room_size" => reverb.set_room_size(number),
+                "dampening" => reverb.set_dampening(number),
+                "width" => reverb.set_width(number),
+                "diffusion" => reverb.set_diffusion(number),
+                "predelay_ms" => reverb.set_predelay_ms(number),
+                "shimmer" => reverb.set_shimmer(number),
+                "mod_depth" => reverb.set_reverb_mod_depth(number),
+                "mod_rate_hz" => reverb.set_reverb_mod_rate(number),
+                "damp_lp_hz" => reverb.set_reverb_damp_lp_hz(number),
+                "send_hp_hz" => reverb.set_reverb_send_hp_hz(number),
+                "send_lp_hz" => reverb.set_reverb_send_lp_hz(number),
+                "gated" => reverb.set_gated(flag),
+                "freeze" => reverb.set_freeze(flag),
+                "freeze_release_ms" => reverb.set_freeze_release_ms(number),
+                "gate_threshold" => reverb.gate_mut().set_threshold(number),
+                "gate_hysteresis_db" => reverb.gate_mut().set_hysteresis_db(number),
+                "gate_hold_ms" => reverb.gate_mut().set_hold_ms(number),
+                "duck_amount" => reverb.set_duck_amount(number),
+                "duck_attack_ms" => reverb.set_duck_attack(number),
+                "duck_release_ms" => reverb.set_duck_release(number),
+                _ => reverb.gate_mut().set_release_ms(number),
+            }
+        }
+        "distortion" => {
+            let distortion = chain.distortion_mut().ok_or("no distortion stage in the chain")?;
+            match key {
+                "type" => distortion.set_distortion_type(DistortionType::from_name(text).ok_or("unknown type")?),
+                "drive" => distortion.set_drive(number),
+                "level" => distortion.set_level(number),
+                "tone" => distortion.set_tone(number),
+                "sag" => distortion.set_sag(number),
+                "soft_knee" => distortion.set_soft_knee(number),
+                "bass_preserve_hz" => distortion.set_bass_preserve(number),
+                "tightness_hz" => distortion.set_tightness_hz(number),
+                "crush_dither" => distortion.set_bit_crusher_dither(flag),
+                "crush_jitter" => distortion.set_bit_crusher_jitter(number),
+                "oversample" => distortion.set_oversampling(flag),
+                "crush_rate" => {
+                    let (_, depth) = distortion.bit_crusher_params();
+                    distortion.set_bit_crusher_params(number, depth);
+                }
+                _ => {
+                    let (rate, _) = distortion.bit_crusher_params();
+                    distortion.set_bit_crusher_params(rate, number);
+                }
+            }
+        }
+        "multiband" => {
+            let multiband = chain.multiband_mut().ok_or("no multiband stage in the chain")?;
+            let (low_freq, high_freq) = multiband.crossovers();
+            let (band_name, param) = key.split_once('_').unwrap_or((key, ""));
+            match (key, Band::from_name(band_name)) {
+                ("low_freq", _) => multiband.set_crossovers(number, high_freq),
+                ("high_freq", _) => multiband.set_crossovers(low_freq, number),
+                (_, Some(band)) => match param {
+                    "drive" => multiband.set_band_drive(band, number),
+                    "level" => multiband.set_band_level(band, number),
+                    _ => match DistortionType::from_name(text) {
+                        Some(distortion_type) => multiband.set_band_type(band, distortion_type),
+                        None => multiband.set_band_enabled(band, false),
+                    },
+                },
+                (_, None) => return Err("unknown band".to_string()),
+            }
+        }
+        "delay" => {
+            let bpm = chain.tempo_bpm();
+            let delay = chain.delay_mut().ok_or("no delay stage in the chain")?;
+            match key {
+                "time_ms" => delay.set_time_ms(number),
+                "sync" => delay.set_sync(NoteValue::parse(text)?, bpm),
+                "feedback" => delay.set_feedback(number),
+                "level" => delay.set_level(number),
+                _ => delay.set_ping_pong(flag),
+            }
+        }
+        "denoise" => {
+            let denoiser = chain.denoiser_mut().ok_or("no noise reduction stage in the chain")?;
+            denoiser.set_reduction_db(number);
+        }
+        "autopan" => {
+            let bpm = chain.tempo_bpm();
+            let autopan = chain.autopan_mut().ok_or("no auto-pan stage in the chain")?;
+            match key {
+                "rate_hz" => autopan.set_rate_hz(number),
+                "depth" => autopan.set_depth(number),
+                "phase" => autopan.set_phase(number),
+                "sync" => autopan.set_sync(NoteValue::parse(text)?, bpm),
+                "restart" => autopan.set_restart_on_sync(flag),
+                _ => autopan.set_shape(LfoShape::from_name(text).unwrap_or(LfoShape::Sine)),
+            }
+        }
+        "tremolo" => {
+            let bpm = chain.tempo_bpm();
+            let tremolo = chain.tremolo_mut().ok_or("no tremolo stage in the chain")?;
+            match key {
+                "rate_hz" => tremolo.set_rate_hz(number),
+                "depth" => tremolo.set_depth(number),
+                "sync" => tremolo.set_sync(NoteValue::parse(text)?, bpm),
+                "restart" => tremolo.set_restart_on_sync(flag),
+                _ => tremolo.set_shape(LfoShape::from_name(text).unwrap_or(LfoShape::Sine)),
+            }
+        }
+        "reverse" => {
+            let reverse = chain.reverse_delay_mut().ok_or("no reverse delay stage in the chain")?;
+            match key {
+                "time_ms" => reverse.set_time_ms(number),
+                _ => reverse.set_mix(number),
+            }
+        }
+        "adt" => {
+            let adt = chain.adt_mut().ok_or("no double tracking stage in the chain")?;
+            match key {
+                "delay_ms" => adt.set_delay_ms(number),
+                _ => adt.set_spread(number),
+            }
+        }
+        "exciter" => {
+            let exciter = chain.exciter_mut().ok_or("no exciter stage in the chain")?;
+            exciter.set_amount(number);
+        }
+        "boost" => {
+            let boost = chain.boost_mut().ok_or("no boost stage in the chain")?;
+            boost.set_gain_db(number);
+        }
+        _ => {
+            let stereo = chain.stereo_width_mut().ok_or("no stereo width stage in the chain")?;
+            stereo.set_width(number);
+        }
+    }
+    Ok(())
+}
+
+/// Presets in the A and B slots of the morph control
+#[derive(Debug, Default)]
+pub struct MorphSlots {
+    a: Option<Preset>,
+    b: Option<Preset>,
+    /// The blend applied last, so moving the morph only applies what changes
+    applied: Preset,
+}
+
+impl MorphSlots {
+    /// Put a preset into slot A or B
+    pub fn load(&mut self, slot_b: bool, preset: Preset) {
+        if slot_b {
+            self.b = Some(preset);
+        } else {
+            self.a = Some(preset);
+        }
+        // The next move applies the whole blend
+        self.applied = Preset::default();
+    }
+
+    /// Settings to apply to move the morph to `amount`, or None until both slots are loaded
+    pub fn morph_to(&mut self, amount: f64) -> Option<Preset> {
+        let morphed = Preset::morph(self.a.as_ref()?, self.b.as_ref()?, amount);
+        let changes = morphed.changes_from(&self.applied);
+        self.applied = morphed;
+        Some(changes)
+    }
+}
+
+/// Directory the `next` and `prev` commands step through
+pub const PRESET_DIR: &str = "presets";
+
+/// Position while stepping through a preset directory
+#[derive(Debug, Default)]
+pub struct PresetScroll {
+    /// File name of the preset loaded last
+    current: Option<String>,
+}
+
+impl PresetScroll {
+    /// Load the preset after the current one in `dir` (or before it, going
+    /// back), sorted by name and wrapping around
+    ///
+    /// Files that can't be read or parsed are reported and skipped. Returns the
+    /// file name with the preset.
+    pub fn step(&mut self, dir: &str, forward: bool) -> Result<(String, Preset), String> {
+        let names = list_presets(dir)?;
+        let mut from = self.current.clone();
+        for _ in 0..names.len() {
+            let Some(index) = scroll_index(&names, from.as_deref(), forward) else {
+                break;
+            };
+            let name = &names[index];
+            match Preset::load(&Path::new(dir).join(name).to_string_lossy()) {
+                Ok(preset) => {
+                    self.current = Some(name.clone());
+                    return Ok((name.clone(), preset));
+                }
+                Err(e) => {
+                    status!("{} (skipped)", e);
+                    from = Some(name.clone());
+                }
+            }
+        }
+        Err(format!("No presets to load in {}", dir))
+    }
+}
+
+/// File names in a preset directory, sorted, leaving out hidden files
+fn list_presets(dir: &str) -> Result<Vec<String>, String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("Cannot list presets in {}: {}", dir, e))?;
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| !name.starts_with('.'))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Index of the name after `current` in sorted `names` (before it going back),
+/// wrapping around
+///
+/// A `current` that is no longer in the list steps from where it would sort.
+/// Without a current name, stepping forward starts at the first name and going
+/// back at the last.
+fn scroll_index(names: &[String], current: Option<&str>, forward: bool) -> Option<usize> {
+    if names.is_empty() {
+        return None;
+    }
+    let Some(current) = current else {
+        return Some(if forward { 0 } else { names.len() - 1 });
+    };
+    let index = match (names.binary_search_by(|name| name.as_str().cmp(current)), forward) {
+        (Ok(found), true) => found + 1,
+        (Err(insert), true) => insert,
+        (Ok(position) | Err(position), false) => position + names.len() - 1,
+    };
+    Some(index % names.len())
+}
+
+impl std::fmt::Display for Preset {
+    /// Write the preset in the same format it is read from
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut current_section = None;
+        for &(section, key, _) in SCHEMA {
+            let Some(value) = self.get(section, key) else {
+                continue;
+            };
+            if current_section != Some(section) {
+                if current_section.is_some() {
+                    writeln!(f)?;
+                }
+                writeln!(f, "[{}]", section)?;
+                current_section = Some(section);
+            }
+            writeln!(f, "{} = {}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distortion::Distortion;
+    use crate::reverb::Reverb;
+
+    const SAMPLE: &str = r#"
+# Crunchy lead
+[chain]
+order = ["dist", "verb", "multi", "width"]
+enabled = ["dist", "verb"]   # everything else off
+trim_db = -3
+
+[mix]
+verb = 0.4
+
+[reverb]
+predelay_ms = 30
+gated = true
+
+[distortion]
+type = "over"
+drive = 0.7
+
+[multiband]
+low = "clean"
+high_drive = 0.9
+"#;
+
+    #[test]
+    fn test_parse_values() {
+        let preset = Preset::parse(SAMPLE).unwrap();
+        assert_eq!(
+            preset.get("chain", "order"),
+            Some(&Value::List(vec!["dist".into(), "verb".into(), "multi".into(), "width".into()]))
+        );
+        assert_eq!(preset.get("chain", "trim_db"), Some(&Value::Number(-3.0)));
+        assert_eq!(preset.get("reverb", "gated"), Some(&Value::Bool(true)));
+        assert_eq!(preset.get("distortion", "type"), Some(&Value::Text("over".into())));
+        assert_eq!(preset.get("mix", "verb"), Some(&Value::Number(0.4)));
+        assert_eq!(preset.get("reverb", "room_size"), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let preset = Preset::parse(SAMPLE).unwrap();
+        assert_eq!(Preset::parse(&preset.to_string()).unwrap(), preset);
+    }
+
+    #[test]
+    fn test_errors_name_the_field() {
+        let error = Preset::parse("[reverb]\nroom_size = 1.5").unwrap_err();
+        assert!(error.contains("line 2") && error.contains("reverb.room_size"), "{}", error);
+
+        let error = Preset::parse("[reverb]\nroom = 0.5").unwrap_err();
+        assert!(error.contains("unknown setting reverb.room"), "{}", error);
+
+        let error = Preset::parse("[distortion]\ntype = \"fuzz\"").unwrap_err();
+        assert!(error.contains("distortion.type"), "{}", error);
+
+        let error = Preset::parse("room_size = 0.5").unwrap_err();
+        assert!(error.contains("line 1"), "{}", error);
+
+        let error = Preset::parse("[reverb]\nroom_size 0.5").unwrap_err();
+        assert!(error.contains("expected key = value"), "{}", error);
+    }
+
+    #[test]
+    fn test_scrolling_wraps_and_survives_deletions() {
+        let names = |list: &[&str]| list.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        let presets = names(&["ambient", "clean", "crunch", "lead"]);
+
+        // From nothing, forward starts at the first and back at the last
+        assert_eq!(scroll_index(&presets, None, true), Some(0));
+        assert_eq!(scroll_index(&presets, None, false), Some(3));
+
+        // Stepping wraps around at both ends
+        let mut current: Option<String> = None;
+        let mut order = Vec::new();
+        for _ in 0..6 {
+            let index = scroll_index(&presets, current.as_deref(), true).unwrap();
+            current = Some(presets[index].clone());
+            order.push(index);
+        }
+        assert_eq!(order, [0, 1, 2, 3, 0, 1]);
+        assert_eq!(scroll_index(&presets, Some("ambient"), false), Some(3));
+        assert_eq!(scroll_index(&presets, Some("lead"), true), Some(0));
+
+        // After "crunch" is deleted, the neighbours of where it sorted come next
+        let remaining = names(&["ambient", "clean", "lead"]);
+        assert_eq!(scroll_index(&remaining, Some("crunch"), true), Some(2));
+        assert_eq!(scroll_index(&remaining, Some("crunch"), false), Some(1));
+        // ... also when it sorted last or first
+        assert_eq!(scroll_index(&remaining, Some("zzz"), true), Some(0));
+        assert_eq!(scroll_index(&remaining, Some("aaa"), false), Some(2));
+
+        // Nothing left to scroll through
+        assert_eq!(scroll_index(&[], Some("lead"), true), None);
+    }
+
+    #[test]
+    fn test_morph_between_presets() {
+        let a = Preset::parse("[distortion]\ntype = \"soft\"\ndrive = 0.2\n[mix]\nverb = 0.1\n[reverb]\nroom_size = 0.3\ngated = false").unwrap();
+        let b = Preset::parse("[distortion]\ntype = \"hard\"\ndrive = 0.8\n[mix]\nverb = 0.5\n[reverb]\nroom_size = 0.9\ngated = true").unwrap();
+
+        // Compared as text, which lists the settings in schema order
+        assert_eq!(Preset::morph(&a, &b, 0.0).to_string(), a.to_string());
+        assert_eq!(Preset::morph(&a, &b, 1.0).to_string(), b.to_string());
+
+        // Numbers average halfway; the distortion type and gate snap to B there
+        let half = Preset::morph(&a, &b, 0.5);
+        for (section, key) in [("distortion", "drive"), ("mix", "verb"), ("reverb", "room_size")] {
+            let (Some(Value::Number(from)), Some(Value::Number(to)), Some(Value::Number(mid))) =
+                (a.get(section, key), b.get(section, key), half.get(section, key))
+            else {
+                panic!("{}.{} missing", section, key);
+            };
+            assert!((mid - (from + to) / 2.0).abs() < 1e-12, "{}.{} = {}", section, key, mid);
+        }
+        assert_eq!(half.get("distortion", "type"), Some(&Value::Text("hard".into())));
+        assert_eq!(half.get("reverb", "gated"), Some(&Value::Bool(true)));
+        assert_eq!(Preset::morph(&a, &b, 0.49).get("distortion", "type"), Some(&Value::Text("soft".into())));
+
+        // A setting in only one preset holds its value across the morph
+        let b_with_trim = Preset::parse("[chain]\ntrim_db = -6").unwrap();
+        assert_eq!(Preset::morph(&a, &b_with_trim, 0.2).get("chain", "trim_db"), Some(&Value::Number(-6.0)));
+    }
+
+    #[test]
+    fn test_parameters_only_load_keeps_enable_flags() {
+        let preset = Preset::parse("[chain]\nenabled = [\"verb\"]\ntrim_db = -6").unwrap();
+        let mut chain = EffectChain::new(44100);
+        chain.add("dist", Box::new(Distortion::new(44100)));
+        chain.add("verb", Box::new(Reverb::new(44100)));
+        chain.set_enabled("dist", true).unwrap();
+        chain.set_enabled("verb", false).unwrap();
+
+        preset.parameters().apply(&mut chain).unwrap();
+        assert_eq!(chain.is_enabled("dist"), Ok(true));
+        assert_eq!(chain.is_enabled("verb"), Ok(false));
+        assert_eq!(chain.output_trim_db(), -6.0);
+
+        // A full load switches the stages too
+        preset.apply(&mut chain).unwrap();
+        assert_eq!(chain.is_enabled("dist"), Ok(false));
+        assert_eq!(chain.is_enabled("verb"), Ok(true));
+    }
+}
@@ -0,0 +1,1448 @@
+//! Reverb effect
+//!
+//! Wraps freeverb (see `freeverb`) so we can keep track of the current parameters
+//! and add processing around it without touching the algorithm itself.
+//!
+//! The decay can be set in seconds instead of the room size: the time for the
+//! tail to fall by 60 dB (RT60) maps to the loop feedback that loses 60 dB over
+//! that many trips round the longest comb, the one that rings longest. This
+//! ignores the dampening, which only shortens the highs, and the plate's lines are
+//! a little longer than freeverb's combs, so its tail rings somewhat longer.
+//!
+//! Freeverb is one of two algorithms for the tail itself; the other is a plate
+//! (see `plate`), with a denser, brighter onset. Both take the same controls and
+//! everything around the tail is shared, so the chain doesn't know which one is
+//! running. Switching crossfades from the old tail to the new one over 100ms:
+//! the new algorithm starts empty and both run on the same input until the old
+//! one has faded out.
+//!
+//! The reverb always runs fully wet, with freeverb's own wet/dry fixed at 1/0:
+//! `tick` returns only the tail, and the chain's per-stage mix blends it with the
+//! dry signal like a parallel send. This keeps the mix separate from the
+//! algorithm, and the dry signal never passes through the pre-delay or the
+//! stages around the tail.
+//!
+//! Shimmer feeds an octave-up copy of the wet output back into the reverb input,
+//! so the tail keeps climbing in pitch as it decays. The feedback level is limited
+//! relative to a slowly released envelope of the input, so the loop always dies
+//! away once the input stops, whatever the room size. On chords the octave-up
+//! copy only adds warble, so the feedback is blended out while the pitch tracker
+//! has low confidence that a single note is playing, and back in for single notes.
+//!
+//! The dark control low-passes the wet signal with a biquad. Unlike freeverb's
+//! dampening, which only darkens the tail as it recirculates, it also takes the
+//! edge off the early reflections.
+//!
+//! The send filters EQ the input on its way into the reverb instead: a high-pass
+//! keeps the lows out of the tail so it doesn't get boomy, and a low-pass keeps
+//! pick attack and fizz from turning into a harsh wash. The dry signal, and the
+//! gate, ducker and shimmer keyed from it, still see the unfiltered input.
+//!
+//! Diffusion runs the pre-delayed input through allpass stages before freeverb,
+//! from discrete comb echoes at 0 to a smeared, dense onset at 1.
+//!
+//! Width sets freeverb's own stereo spread and also drives a decorrelator on the
+//! wet output. Freeverb's two sides come from the same input, so with a mono
+//! source they stay close; the decorrelator gives each side a different phase
+//! response, so a mono input still yields a wide tail at high width.
+//!
+//! Tail modulation runs the wet output through a short delay swept by a slow
+//! sine LFO (a quarter turn apart on the two sides), detuning the tail by a few
+//! cents so it drifts and evolves instead of ringing with freeverb's fixed comb
+//! resonances. The delay sweeps from zero up to twice the depth, so there is no
+//! jump when modulation is switched on.
+//!
+//! Gated mode runs the wet output through a noise gate keyed from the dry input,
+//! cutting the tail off abruptly once the player stops (the 80s gated drum sound).
+//!
+//! Ducking turns the wet output down while the dry input is loud and lets it
+//! swell back in the gaps, keeping the playing clear of its own tail.
+//!
+//! Freeze holds the current tail indefinitely to play over: freeverb's combs are
+//! switched to full feedback without dampening and its input is muted, so what is
+//! in the delay lines recirculates unchanged until the freeze is released.
+//! Releasing doesn't drop straight back to the room's feedback and dampening,
+//! which would swallow the held tail within a few echoes: over the freeze release
+//! time the loop crossfades from the frozen settings to the live ones, and the
+//! input fades back in, so the tail eases from sustaining into its normal decay.
+
+use crate::diffuser::{Decorrelator, Diffuser};
+use crate::denormal;
+use crate::filter::{Biquad, BiquadCoeffs, BUTTERWORTH_Q};
+use crate::freeverb::Freeverb;
+use crate::gate::{Ducker, KeySource, NoiseGate};
+use crate::lfo::Lfo;
+use crate::mix::mix_constant_power;
+use crate::pitchshift::PitchShifter;
+use crate::plate::Plate;
+use crate::pitchtracker::PitchTracker;
+use crate::safety::FeedbackCeiling;
+
+/// Longest supported pre-delay in milliseconds
+const MAX_PREDELAY_MS: f64 = 250.0;
+/// Lowest dark low-pass cutoff in Hz (lower cutoffs would all but silence the tail)
+const MIN_DAMP_LP_HZ: f64 = 200.0;
+/// Dark low-pass cutoffs at or above this switch the filter off
+const MAX_DAMP_LP_HZ: f64 = 20000.0;
+/// Send high-pass cutoffs at or below this are off, in Hz
+const MIN_SEND_HP_HZ: f64 = 20.0;
+/// Highest send high-pass cutoff in Hz
+const MAX_SEND_HP_HZ: f64 = 2000.0;
+/// Lowest send low-pass cutoff in Hz (20kHz and above are off)
+const MIN_SEND_LP_HZ: f64 = 500.0;
+/// Shimmer feedback gain at full amount
+const MAX_SHIMMER_FEEDBACK: f64 = 0.5;
+/// Pitch shifter window for the shimmer feedback in milliseconds
+const SHIMMER_WINDOW_MS: f64 = 60.0;
+/// Release time of the input envelope that bounds the shimmer feedback in seconds
+const SHIMMER_RELEASE_SECONDS: f64 = 1.0;
+/// Release time of the shimmer feedback level detector in seconds
+const FEEDBACK_RELEASE_SECONDS: f64 = 0.05;
+/// Highest shimmer feedback level relative to the input envelope
+const SHIMMER_CEILING: f64 = 0.25;
+/// Pitch confidence at or below which the shimmer is fully blended out (chords)
+const SHIMMER_POLY_CONFIDENCE: f64 = 0.9;
+/// Pitch confidence at or above which the shimmer is fully in (single notes)
+const SHIMMER_MONO_CONFIDENCE: f64 = 0.97;
+/// Input level relative to its envelope above which the shimmer blend follows the tracker
+const SHIMMER_TRACK_LEVEL: f64 = 0.5;
+/// Smoothing time of the shimmer blend in seconds
+const SHIMMER_BLEND_SECONDS: f64 = 0.1;
+/// Tail modulation delay sweep at full depth in milliseconds (half the peak delay)
+const MAX_MOD_DEPTH_MS: f64 = 3.0;
+/// Slowest and fastest tail modulation rate in Hz
+const MIN_MOD_RATE_HZ: f64 = 0.05;
+const MAX_MOD_RATE_HZ: f64 = 5.0;
+/// Default tail modulation rate in Hz
+const DEFAULT_MOD_RATE_HZ: f64 = 0.5;
+/// Length of the crossfade when switching algorithms, in milliseconds
+const ALGO_CROSSFADE_MS: f64 = 100.0;
+/// Longest freeze release in milliseconds
+const MAX_FREEZE_RELEASE_MS: f64 = 10000.0;
+/// Default freeze release in milliseconds
+const DEFAULT_FREEZE_RELEASE_MS: f64 = 500.0;
+/// Freeverb's comb feedback at room size 0
+const FREEVERB_ROOM_OFFSET: f64 = 0.7;
+/// Freeverb's comb feedback added per unit of room size
+const FREEVERB_ROOM_SCALE: f64 = 0.28;
+/// Loop time of freeverb's longest comb in milliseconds (1640 samples at 44.1 kHz)
+const DECAY_LOOP_MS: f64 = 37.19;
+/// Shortest decay time in seconds, about room size 0
+const MIN_DECAY_SECONDS: f64 = 0.75;
+/// Longest decay time in seconds, about room size 1
+const MAX_DECAY_SECONDS: f64 = 12.0;
+
+/// Short delay on the wet output swept by a slow LFO, detuning the tail
+struct TailModulation {
+    /// Depth (0.0 to 1.0, 0 = off)
+    depth: f64,
+    /// Delay lines for the left and right wet signal
+    buffers: [Vec<f64>; 2],
+    /// Write position in the delay lines
+    index: usize,
+    /// LFOs for the left and right delay, a quarter turn apart
+    lfos: [Lfo; 2],
+    /// Sample rate in Hz
+    sample_rate: f64,
+}
+
+impl TailModulation {
+    fn new(sample_rate: usize) -> Self {
+        let mut lfos = [Lfo::new(), Lfo::new()];
+        lfos[1].set_phase_offset(0.25);
+        for lfo in lfos.iter_mut() {
+            lfo.set_rate_hz(DEFAULT_MOD_RATE_HZ);
+        }
+        let len = (2.0 * MAX_MOD_DEPTH_MS * sample_rate as f64 / 1000.0).ceil() as usize + 2;
+        Self {
+            depth: 0.0,
+            buffers: [vec![0.0; len], vec![0.0; len]],
+            index: 0,
+            lfos,
+            sample_rate: sample_rate as f64,
+        }
+    }
+
+    fn set_depth(&mut self, depth: f64) {
+        self.depth = depth.clamp(0.0, 1.0);
+    }
+
+    fn set_rate_hz(&mut self, rate_hz: f64) {
+        for lfo in self.lfos.iter_mut() {
+            lfo.set_rate_hz(rate_hz.clamp(MIN_MOD_RATE_HZ, MAX_MOD_RATE_HZ));
+        }
+    }
+
+    fn process(&mut self, input: (f64, f64)) -> (f64, f64) {
+        if self.depth == 0.0 {
+            return input;
+        }
+
+        let len = self.buffers[0].len();
+        let depth_samples = self.depth * MAX_MOD_DEPTH_MS * self.sample_rate / 1000.0;
+        let mut output = [0.0; 2];
+        for (channel, sample) in [input.0, input.1].into_iter().enumerate() {
+            let buffer = &mut self.buffers[channel];
+            buffer[self.index] = sample;
+
+            // Read between two samples, from zero up to twice the depth back
+            let delay = depth_samples * (1.0 + self.lfos[channel].next(self.sample_rate));
+            let position = (self.index as f64 - delay).rem_euclid(len as f64);
+            let before = position.floor() as usize % len;
+            let after = (before + 1) % len;
+            let fraction = position - position.floor();
+            output[channel] = buffer[before] * (1.0 - fraction) + buffer[after] * fraction;
+        }
+        self.index = (self.index + 1) % len;
+        (output[0], output[1])
+    }
+
+    fn reset(&mut self) {
+        for buffer in self.buffers.iter_mut() {
+            buffer.fill(0.0);
+        }
+        for lfo in self.lfos.iter_mut() {
+            lfo.reset();
+        }
+    }
+}
+
+/// Algorithm producing the reverb tail
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ReverbAlgo {
+    /// Freeverb's parallel combs, a room (original behavior)
+    #[default]
+    Freeverb,
+    /// Feedback delay network plate
+    Plate,
+}
+
+impl ReverbAlgo {
+    /// Every algorithm, in the order the controls list them
+    pub const ALL: [ReverbAlgo; 2] = [ReverbAlgo::Freeverb, ReverbAlgo::Plate];
+
+    /// Parse the name used by the controls (freeverb, plate)
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|algo| algo.name() == name)
+    }
+
+    /// Name used by the controls
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReverbAlgo::Freeverb => "freeverb",
+            ReverbAlgo::Plate => "plate",
+        }
+    }
+
+    /// Names of every algorithm joined by `separator`, for messages
+    pub fn names(separator: &str) -> String {
+        Self::ALL.map(|algo| algo.name()).join(separator)
+    }
+}
+
+/// Reverb wrapper around freeverb or the plate
+pub struct Reverb {
+    /// Underlying freeverb processor
+    freeverb: Freeverb,
+    /// Plate processor, the alternative to freeverb
+    plate: Plate,
+    /// Algorithm producing the tail
+    algo: ReverbAlgo,
+    /// Algorithm fading out after a switch, if any
+    previous_algo: Option<ReverbAlgo>,
+    /// Progress of the crossfade to `algo` (0.0 to 1.0)
+    crossfade: f64,
+    /// Crossfade progress per sample
+    crossfade_step: f64,
+    /// Sample rate, for converting times and cutoffs
+    sample_rate: usize,
+    /// Room size (0.0 to 1.0)
+    room_size: f64,
+    /// Dampening (0.0 to 1.0)
+    dampening: f64,
+    /// Stereo width (0.0 to 1.0)
+    width: f64,
+    /// Decorrelator on the wet output, driven by the width
+    decorrelator: Decorrelator,
+    /// Pre-delay buffer for the wet path, sized for the maximum pre-delay
+    predelay_buffer: Vec<(f64, f64)>,
+    /// Write position in the pre-delay buffer
+    predelay_index: usize,
+    /// Pre-delay in samples
+    predelay_samples: usize,
+    /// Pitch modulation of the wet output
+    modulation: TailModulation,
+    /// Allpass diffusion ahead of freeverb
+    diffuser: Diffuser,
+    /// Shimmer amount (0.0 to 1.0)
+    shimmer: f64,
+    /// Feedback ceiling shared with the engine, for the room size and shimmer
+    ceiling: FeedbackCeiling,
+    /// Octave-up pitch shifter for the shimmer feedback
+    shimmer_shifter: PitchShifter,
+    /// Pitch-shifted wet signal fed into the next input sample
+    shimmer_feedback: f64,
+    /// Peak envelope of the input, the ceiling for the feedback level
+    input_envelope: f64,
+    /// Peak envelope of the unlimited feedback signal
+    feedback_envelope: f64,
+    /// Release coefficient of the input envelope
+    input_release: f64,
+    /// Release coefficient of the feedback envelope
+    feedback_release: f64,
+    /// Pitch tracker on the input, telling single notes from chords
+    pitch_tracker: PitchTracker,
+    /// Smoothed shimmer blend from the pitch confidence (0.0 = out, 1.0 = in)
+    shimmer_blend: f64,
+    /// Smoothing coefficient of the shimmer blend
+    shimmer_blend_coefficient: f64,
+    /// Dark low-pass cutoff for the wet signal in Hz (None = off)
+    damp_lp_hz: Option<f64>,
+    /// Dark low-pass filters for the left and right wet signal
+    damp_lp: [Biquad; 2],
+    /// Send high-pass cutoff for the reverb input in Hz (None = off)
+    send_hp_hz: Option<f64>,
+    /// Send high-pass filters for the left and right input
+    send_hp: [Biquad; 2],
+    /// Send low-pass cutoff for the reverb input in Hz (None = off)
+    send_lp_hz: Option<f64>,
+    /// Send low-pass filters for the left and right input
+    send_lp: [Biquad; 2],
+    /// Gate on the wet output, keyed from the dry input
+    gate: NoiseGate,
+    /// Whether the wet output is gated
+    gated: bool,
+    /// Ducker on the wet output, keyed from the dry input
+    ducker: Ducker,
+    /// Whether the tail is frozen
+    frozen: bool,
+    /// Share of the frozen settings still in the loop after a release (0.0 = live)
+    freeze_release: f64,
+    /// Freeze release time in samples
+    freeze_release_samples: usize,
+}
+
+impl Reverb {
+    /// Create a new reverb processor
+    pub fn new(sample_rate: usize) -> Self {
+        let mut reverb = Self {
+            freeverb: Freeverb::new(sample_rate),
+            plate: Plate::new(sample_rate),
+            algo: ReverbAlgo::default(),
+            previous_algo: None,
+            crossfade: 1.0,
+            crossfade_step: 1000.0 / (ALGO_CROSSFADE_MS * sample_rate as f64),
+            sample_rate,
+            room_size: 0.5,
+            dampening: 0.5,
+            width: 0.5,
+            decorrelator: Decorrelator::new(sample_rate),
+            predelay_buffer: vec![(0.0, 0.0); Self::ms_to_samples(sample_rate, MAX_PREDELAY_MS) + 1],
+            predelay_index: 0,
+            predelay_samples: 0,
+            modulation: TailModulation::new(sample_rate),
+            diffuser: Diffuser::new(sample_rate),
+            shimmer: 0.0,
+            ceiling: FeedbackCeiling::default(),
+            shimmer_shifter: PitchShifter::new(sample_rate, SHIMMER_WINDOW_MS, 2.0),
+            shimmer_feedback: 0.0,
+            input_envelope: 0.0,
+            feedback_envelope: 0.0,
+            input_release: (-1.0 / (SHIMMER_RELEASE_SECONDS * sample_rate as f64)).exp(),
+            feedback_release: (-1.0 / (FEEDBACK_RELEASE_SECONDS * sample_rate as f64)).exp(),
+            pitch_tracker: PitchTracker::new(sample_rate),
+            shimmer_blend: 1.0,
+            shimmer_blend_coefficient: (-1.0 / (SHIMMER_BLEND_SECONDS * sample_rate as f64)).exp(),
+            damp_lp_hz: None,
+            damp_lp: std::array::from_fn(|_| {
+                Biquad::new(BiquadCoeffs::lowpass(sample_rate as f64, MAX_DAMP_LP_HZ, BUTTERWORTH_Q))
+            }),
+            send_hp_hz: None,
+            send_hp: std::array::from_fn(|_| {
+                Biquad::new(BiquadCoeffs::highpass(sample_rate as f64, MIN_SEND_HP_HZ, BUTTERWORTH_Q))
+            }),
+            send_lp_hz: None,
+            send_lp: std::array::from_fn(|_| {
+                Biquad::new(BiquadCoeffs::lowpass(sample_rate as f64, MAX_DAMP_LP_HZ, BUTTERWORTH_Q))
+            }),
+            gate: NoiseGate::new(sample_rate),
+            gated: false,
+            ducker: Ducker::new(sample_rate),
+            frozen: false,
+            freeze_release: 0.0,
+            freeze_release_samples: Self::ms_to_samples(sample_rate, DEFAULT_FREEZE_RELEASE_MS),
+        };
+        reverb.apply_params();
+        reverb.set_room_size(reverb.room_size);
+        reverb
+    }
+
+    /// Process a stereo input sample and return the wet tail only
+    ///
+    /// Freeverb's dry path is fixed at zero, so no input reaches the output
+    /// directly; the chain's mix blends the dry signal back in.
+    pub fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let send = self.filter_send(input);
+        let len = self.predelay_buffer.len();
+        self.predelay_buffer[self.predelay_index] = send;
+        let delayed = self.predelay_buffer[(self.predelay_index + len - self.predelay_samples) % len];
+        self.predelay_index = (self.predelay_index + 1) % len;
+
+        let diffused = self.diffuser.process(delayed);
+        let feedback = self.shimmer_feedback;
+        let wet = self.tail((diffused.0 + feedback, diffused.1 + feedback));
+
+        if self.shimmer > 0.0 {
+            let input_peak = input.0.abs().max(input.1.abs());
+            self.input_envelope = input_peak.max(denormal::flush(self.input_envelope * self.input_release));
+
+            // Blend out on chords, following the tracker only while the input is
+            // near its envelope, so a note's release and the tail keep the blend
+            self.pitch_tracker.push((input.0 + input.1) * 0.5);
+            let (freq, confidence) = self.pitch_tracker.estimate();
+            if freq > 0.0 && input_peak > self.input_envelope * SHIMMER_TRACK_LEVEL {
+                let target = ((confidence - SHIMMER_POLY_CONFIDENCE)
+                    / (SHIMMER_MONO_CONFIDENCE - SHIMMER_POLY_CONFIDENCE))
+                    .clamp(0.0, 1.0);
+                self.shimmer_blend = target + self.shimmer_blend_coefficient * (self.shimmer_blend - target);
+            }
+
+            let shifted = self.shimmer_shifter.process((wet.0 + wet.1) * 0.5)
+                * self.ceiling.clamp(self.shimmer * MAX_SHIMMER_FEEDBACK)
+                * self.shimmer_blend;
+
+            // Keep the feedback below the input envelope so a resonant room can't build up
+            self.feedback_envelope = shifted.abs().max(denormal::flush(self.feedback_envelope * self.feedback_release));
+            let ceiling = self.input_envelope * SHIMMER_CEILING;
+            self.shimmer_feedback = denormal::flush(if self.feedback_envelope > ceiling {
+                shifted * ceiling / self.feedback_envelope
+            } else {
+                shifted
+            });
+        }
+
+        // Detune and widen after the shimmer tap, whose pitch shifter would only
+        // smear the modulation and whose feedback is summed to mono anyway
+        let wet = self.modulation.process(wet);
+        let wet = self.decorrelator.process(wet);
+
+        // Darken after the shimmer tap so the octave-up feedback keeps its sparkle
+        let wet = if self.damp_lp_hz.is_some() {
+            (self.damp_lp[0].process(wet.0), self.damp_lp[1].process(wet.1))
+        } else {
+            wet
+        };
+
+        // Key from the dry input so the gate closes while the tail still rings and
+        // the ducker dips it while playing (the gate may key from the tail instead)
+        let key = input.0.abs().max(input.1.abs());
+        let wet = if self.gated { self.gate.process(wet, key) } else { wet };
+        self.ducker.process(wet, key)
+    }
+
+    /// Process a stereo input sample and return the tail alone, for callers that
+    /// must never hear the input (the IR export)
+    ///
+    /// `tick` has no dry path to skip: `apply_params` holds freeverb's dry gain at
+    /// zero, the plate has none, and the chain's mix adds the dry signal outside
+    /// the reverb. This is `tick` under a name that states the guarantee where it
+    /// is relied on.
+    pub fn tick_wet_only(&mut self, input: (f64, f64)) -> (f64, f64) {
+        self.tick(input)
+    }
+
+    /// EQ the input on its way into the reverb
+    fn filter_send(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let input = if self.send_hp_hz.is_some() {
+            (self.send_hp[0].process(input.0), self.send_hp[1].process(input.1))
+        } else {
+            input
+        };
+        if self.send_lp_hz.is_some() {
+            (self.send_lp[0].process(input.0), self.send_lp[1].process(input.1))
+        } else {
+            input
+        }
+    }
+
+    /// Run the tail algorithm, crossfading from the previous one after a switch
+    fn tail(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let input = if self.freeze_release > 0.0 {
+            let held = self.release_freeze_step();
+            (input.0 * (1.0 - held), input.1 * (1.0 - held))
+        } else {
+            input
+        };
+        let wet = self.tick_algo(self.algo, input);
+        let Some(previous) = self.previous_algo else {
+            return wet;
+        };
+
+        let old = self.tick_algo(previous, input);
+        self.crossfade = (self.crossfade + self.crossfade_step).min(1.0);
+        if self.crossfade >= 1.0 {
+            self.previous_algo = None;
+        }
+        // The two tails are unrelated, so fade on the constant-power law
+        let fade = self.crossfade;
+        (mix_constant_power(old.0, wet.0, fade), mix_constant_power(old.1, wet.1, fade))
+    }
+
+    /// Move the loop one sample further from the frozen settings to the live
+    /// ones, returning the share of the freeze still held
+    ///
+    /// Both algorithms take the loop feedback through the room size (0.7 to 0.98
+    /// over 0 to 1), so a feedback between the room's and the frozen 1.0 is a room
+    /// size just above 1.
+    fn release_freeze_step(&mut self) -> f64 {
+        let step = 1.0 / self.freeze_release_samples.max(1) as f64;
+        self.freeze_release = (self.freeze_release - step).max(0.0);
+        let held = self.freeze_release;
+        if held == 0.0 {
+            self.set_room_size(self.room_size);
+            self.set_dampening(self.dampening);
+            return 0.0;
+        }
+
+        let live = FREEVERB_ROOM_OFFSET + FREEVERB_ROOM_SCALE * self.room_size;
+        let feedback = live + (1.0 - live) * held;
+        let room_size = (feedback - FREEVERB_ROOM_OFFSET) / FREEVERB_ROOM_SCALE;
+        let dampening = self.dampening * (1.0 - held);
+        self.freeverb.set_room_size(room_size);
+        self.freeverb.set_dampening(dampening);
+        self.plate.set_room_size(room_size);
+        self.plate.set_dampening(dampening);
+        held
+    }
+
+    fn tick_algo(&mut self, algo: ReverbAlgo, input: (f64, f64)) -> (f64, f64) {
+        match algo {
+            ReverbAlgo::Freeverb => self.freeverb.tick(input),
+            ReverbAlgo::Plate => self.plate.tick(input),
+        }
+    }
+
+    /// Switch the tail algorithm, crossfading from the current one
+    ///
+    /// The new algorithm starts from silence; switching back during a crossfade
+    /// reverses it instead, so the tail that is still fading in keeps ringing.
+    pub fn set_reverb_algo(&mut self, algo: ReverbAlgo) {
+        if algo == self.algo {
+            return;
+        }
+        if self.previous_algo == Some(algo) {
+            self.crossfade = 1.0 - self.crossfade;
+        } else {
+            self.clear_algo(algo);
+            self.crossfade = 0.0;
+        }
+        self.previous_algo = Some(self.algo);
+        self.algo = algo;
+    }
+
+    /// Current tail algorithm
+    pub fn algo(&self) -> ReverbAlgo {
+        self.algo
+    }
+
+    /// Set the room size (0.0 to 1.0)
+    ///
+    /// The room size sets the comb or loop feedback (0.7 to 0.98), so it is
+    /// limited to keep that under the feedback ceiling.
+    pub fn set_room_size(&mut self, room_size: f64) {
+        let largest = (self.ceiling.get() - FREEVERB_ROOM_OFFSET) / FREEVERB_ROOM_SCALE;
+        self.room_size = room_size.min(largest.max(0.0));
+        self.freeverb.set_room_size(self.room_size);
+        self.plate.set_room_size(self.room_size);
+    }
+
+    /// Current room size
+    pub fn room_size(&self) -> f64 {
+        self.room_size
+    }
+
+    /// Follow a shared feedback ceiling instead of the default one, limiting the
+    /// current room size to it
+    pub fn set_feedback_ceiling(&mut self, ceiling: FeedbackCeiling) {
+        self.ceiling = ceiling;
+        self.set_room_size(self.room_size);
+    }
+
+    /// Set the decay time in seconds for the tail to fall by 60 dB (0.75 to 12)
+    ///
+    /// Sets the room size whose loop feedback gives that decay; like the room
+    /// size, it is held under the feedback ceiling.
+    pub fn set_decay_seconds(&mut self, seconds: f64) {
+        let seconds = seconds.clamp(MIN_DECAY_SECONDS, MAX_DECAY_SECONDS);
+        let feedback = 10f64.powf(-3.0 * DECAY_LOOP_MS / 1000.0 / seconds);
+        let room_size = (feedback - FREEVERB_ROOM_OFFSET) / FREEVERB_ROOM_SCALE;
+        self.set_room_size(room_size.clamp(0.0, 1.0));
+    }
+
+    /// Decay time in seconds of the current room size
+    pub fn decay_seconds(&self) -> f64 {
+        let feedback = FREEVERB_ROOM_OFFSET + FREEVERB_ROOM_SCALE * self.room_size;
+        -3.0 * DECAY_LOOP_MS / 1000.0 / feedback.log10()
+    }
+
+    /// Set the dampening (0.0 to 1.0)
+    pub fn set_dampening(&mut self, dampening: f64) {
+        self.dampening = dampening;
+        self.freeverb.set_dampening(dampening);
+        self.plate.set_dampening(dampening);
+    }
+
+    /// Current dampening
+    pub fn dampening(&self) -> f64 {
+        self.dampening
+    }
+
+    /// Set the stereo width (0.0 to 1.0)
+    pub fn set_width(&mut self, width: f64) {
+        self.width = width;
+        self.freeverb.set_width(width);
+        self.plate.set_width(width);
+        self.decorrelator.set_amount(width);
+    }
+
+    /// Current stereo width
+    pub fn width(&self) -> f64 {
+        self.width
+    }
+
+    /// Set the pre-delay of the tail in milliseconds (0 to 250)
+    pub fn set_predelay_ms(&mut self, predelay_ms: f64) {
+        let predelay_ms = predelay_ms.clamp(0.0, MAX_PREDELAY_MS);
+        self.predelay_samples = Self::ms_to_samples(self.sample_rate, predelay_ms);
+    }
+
+    /// Set the diffusion ahead of the tail (0.0 = discrete echoes, 1.0 = smeared)
+    pub fn set_diffusion(&mut self, diffusion: f64) {
+        self.diffuser.set_amount(diffusion);
+    }
+
+    /// Current diffusion amount
+    pub fn diffusion(&self) -> f64 {
+        self.diffuser.amount()
+    }
+
+    /// Set the shimmer amount (0.0 to 1.0, 0 = off)
+    pub fn set_shimmer(&mut self, shimmer: f64) {
+        self.shimmer = shimmer.clamp(0.0, 1.0);
+        if self.shimmer == 0.0 {
+            self.clear_shimmer();
+        }
+    }
+
+    /// Current shimmer amount
+    pub fn shimmer(&self) -> f64 {
+        self.shimmer
+    }
+
+    /// Set the tail modulation depth (0.0 to 1.0, 0 = off)
+    ///
+    /// At full depth the tail's delay sweeps over 6ms, a few cents of detune at
+    /// the default rate.
+    pub fn set_reverb_mod_depth(&mut self, depth: f64) {
+        self.modulation.set_depth(depth);
+    }
+
+    /// Current tail modulation depth
+    pub fn mod_depth(&self) -> f64 {
+        self.modulation.depth
+    }
+
+    /// Set the tail modulation rate in Hz (0.05 to 5)
+    pub fn set_reverb_mod_rate(&mut self, rate_hz: f64) {
+        self.modulation.set_rate_hz(rate_hz);
+    }
+
+    /// Current tail modulation rate in Hz
+    pub fn mod_rate(&self) -> f64 {
+        self.modulation.lfos[0].rate_hz()
+    }
+
+    /// Set the dark low-pass cutoff for the wet signal in Hz
+    ///
+    /// Cutoffs are limited to at least 200Hz; 20kHz and above switch the filter off.
+    pub fn set_reverb_damp_lp_hz(&mut self, cutoff_hz: f64) {
+        if cutoff_hz >= MAX_DAMP_LP_HZ {
+            self.damp_lp_hz = None;
+            return;
+        }
+
+        let cutoff_hz = cutoff_hz.max(MIN_DAMP_LP_HZ);
+        let coeffs = BiquadCoeffs::lowpass(self.sample_rate as f64, cutoff_hz, BUTTERWORTH_Q);
+        for filter in self.damp_lp.iter_mut() {
+            filter.set_coeffs(coeffs);
+        }
+        self.damp_lp_hz = Some(cutoff_hz);
+    }
+
+    /// Current dark low-pass cutoff in Hz (None = off)
+    pub fn damp_lp_hz(&self) -> Option<f64> {
+        self.damp_lp_hz
+    }
+
+    /// Set the high-pass cutoff on the reverb input in Hz
+    ///
+    /// Cutoffs are limited to 2kHz; 20Hz and below switch the filter off.
+    pub fn set_reverb_send_hp_hz(&mut self, cutoff_hz: f64) {
+        if cutoff_hz <= MIN_SEND_HP_HZ {
+            self.send_hp_hz = None;
+            return;
+        }
+
+        let cutoff_hz = cutoff_hz.min(MAX_SEND_HP_HZ);
+        let coeffs = BiquadCoeffs::highpass(self.sample_rate as f64, cutoff_hz, BUTTERWORTH_Q);
+        for filter in self.send_hp.iter_mut() {
+            filter.set_coeffs(coeffs);
+        }
+        self.send_hp_hz = Some(cutoff_hz);
+    }
+
+    /// Current send high-pass cutoff in Hz (None = off)
+    pub fn send_hp_hz(&self) -> Option<f64> {
+        self.send_hp_hz
+    }
+
+    /// Set the low-pass cutoff on the reverb input in Hz
+    ///
+    /// Cutoffs are limited to at least 500Hz; 20kHz and above switch the filter off.
+    pub fn set_reverb_send_lp_hz(&mut self, cutoff_hz: f64) {
+        if cutoff_hz >= MAX_DAMP_LP_HZ {
+            self.send_lp_hz = None;
+            return;
+        }
+
+        let cutoff_hz = cutoff_hz.max(MIN_SEND_LP_HZ);
+        let coeffs = BiquadCoeffs::lowpass(self.sample_rate as f64, cutoff_hz, BUTTERWORTH_Q);
+        for filter in self.send_lp.iter_mut() {
+            filter.set_coeffs(coeffs);
+        }
+        self.send_lp_hz = Some(cutoff_hz);
+    }
+
+    /// Current send low-pass cutoff in Hz (None = off)
+    pub fn send_lp_hz(&self) -> Option<f64> {
+        self.send_lp_hz
+    }
+
+    /// Enable or disable gated mode
+    pub fn set_gated(&mut self, gated: bool) {
+        self.gated = gated;
+        self.gate.reset();
+    }
+
+    /// Whether gated mode is enabled
+    pub fn is_gated(&self) -> bool {
+        self.gated
+    }
+
+    /// Freeze or release the tail
+    ///
+    /// While frozen, new input doesn't reach the reverb and the tail sustains
+    /// instead of decaying; releasing crossfades back to the current room size
+    /// and dampening over the freeze release time, then lets it decay.
+    pub fn set_freeze(&mut self, frozen: bool) {
+        let releasing = self.frozen && !frozen && self.freeze_release_samples > 0;
+        self.frozen = frozen;
+        self.freeverb.set_freeze(frozen);
+        self.plate.set_freeze(frozen);
+        if releasing {
+            self.freeze_release = 1.0;
+        } else if self.freeze_release > 0.0 {
+            // Freezing again in the middle of a release, or leaving one early
+            self.freeze_release = 0.0;
+            self.set_room_size(self.room_size);
+            self.set_dampening(self.dampening);
+        }
+    }
+
+    /// Set how long releasing a freeze takes to return to the normal decay, in
+    /// milliseconds (0 to 10000, 0 = at once)
+    pub fn set_freeze_release_ms(&mut self, release_ms: f64) {
+        let release_ms = release_ms.clamp(0.0, MAX_FREEZE_RELEASE_MS);
+        self.freeze_release_samples = Self::ms_to_samples(self.sample_rate, release_ms);
+    }
+
+    /// Current freeze release time in milliseconds
+    pub fn freeze_release_ms(&self) -> f64 {
+        self.freeze_release_samples as f64 * 1000.0 / self.sample_rate as f64
+    }
+
+    /// Whether the tail is frozen
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Set how far the tail dips while the dry input is playing (0.0 to 1.0, 0 = off)
+    pub fn set_duck_amount(&mut self, amount: f64) {
+        self.ducker.set_amount(amount);
+    }
+
+    /// Set how fast the tail dips when playing starts (1 to 500ms)
+    pub fn set_duck_attack(&mut self, attack_ms: f64) {
+        self.ducker.set_attack_ms(attack_ms);
+    }
+
+    /// Set how slowly the tail swells back when playing stops (10 to 5000ms)
+    pub fn set_duck_release(&mut self, release_ms: f64) {
+        self.ducker.set_release_ms(release_ms);
+    }
+
+    /// Key the gate from the dry input (the default) or from the tail itself
+    pub fn set_gate_key(&mut self, key_source: KeySource) {
+        self.gate.set_key_source(key_source);
+        self.gate.reset();
+    }
+
+    /// Ducker applied to the wet output
+    pub fn ducker(&self) -> &Ducker {
+        &self.ducker
+    }
+
+    /// Gate applied to the wet output in gated mode
+    pub fn gate_mut(&mut self) -> &mut NoiseGate {
+        &mut self.gate
+    }
+
+    /// Sample rate in Hz
+    pub fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    /// A new reverb with the same settings and an empty tail, for offline use
+    ///
+    /// Freeverb can't be cloned, so the copy is rebuilt from the parameters. The
+    /// copy isn't frozen, since a frozen reverb ignores its input.
+    pub fn clone_settings(&self) -> Self {
+        let mut reverb = Self::new(self.sample_rate);
+        reverb.algo = self.algo;
+        reverb.set_room_size(self.room_size);
+        reverb.set_dampening(self.dampening);
+        reverb.set_width(self.width);
+        reverb.predelay_samples = self.predelay_samples;
+        reverb.freeze_release_samples = self.freeze_release_samples;
+        reverb.set_diffusion(self.diffusion());
+        reverb.set_shimmer(self.shimmer);
+        reverb.set_reverb_mod_depth(self.mod_depth());
+        reverb.set_reverb_mod_rate(self.mod_rate());
+        if let Some(cutoff_hz) = self.damp_lp_hz {
+            reverb.set_reverb_damp_lp_hz(cutoff_hz);
+        }
+        if let Some(cutoff_hz) = self.send_hp_hz {
+            reverb.set_reverb_send_hp_hz(cutoff_hz);
+        }
+        if let Some(cutoff_hz) = self.send_lp_hz {
+            reverb.set_reverb_send_lp_hz(cutoff_hz);
+        }
+        reverb.set_gated(self.gated);
+        reverb.gate.set_threshold(self.gate.threshold());
+        reverb.gate.set_hold_ms(self.gate.hold_ms());
+        reverb.gate.set_release_ms(self.gate.release_ms());
+        reverb.gate.set_key_source(self.gate.key_source());
+        reverb.gate.set_hysteresis_db(self.gate.hysteresis_db());
+        reverb.set_duck_amount(self.ducker.amount());
+        reverb.set_duck_attack(self.ducker.attack_ms());
+        reverb.set_duck_release(self.ducker.release_ms());
+        reverb
+    }
+
+    /// Current pre-delay in milliseconds
+    pub fn predelay_ms(&self) -> f64 {
+        self.predelay_samples as f64 * 1000.0 / self.sample_rate as f64
+    }
+
+    /// Clear the reverb tail, keeping the current parameters
+    ///
+    /// A freeze is released, since it would only hold the silence.
+    pub fn reset(&mut self) {
+        self.frozen = false;
+        self.freeverb.set_freeze(false);
+        self.plate.set_freeze(false);
+        self.freeze_release = 0.0;
+        self.clear_algo(ReverbAlgo::Freeverb);
+        self.clear_algo(ReverbAlgo::Plate);
+        self.set_room_size(self.room_size);
+        self.set_dampening(self.dampening);
+        self.previous_algo = None;
+        self.crossfade = 1.0;
+        self.predelay_buffer.fill((0.0, 0.0));
+        self.diffuser.reset();
+        self.decorrelator.reset();
+        self.modulation.reset();
+        self.clear_shimmer();
+        for filter in self.damp_lp.iter_mut().chain(&mut self.send_hp).chain(&mut self.send_lp) {
+            filter.reset();
+        }
+        self.gate.reset();
+        self.ducker.reset();
+    }
+
+    /// Clear the tail of one algorithm in place, without allocating, so the chain
+    /// can reset from the audio thread
+    fn clear_algo(&mut self, algo: ReverbAlgo) {
+        match algo {
+            ReverbAlgo::Freeverb => self.freeverb.reset(),
+            ReverbAlgo::Plate => self.plate.reset(),
+        }
+    }
+
+    /// Clear the shimmer feedback loop
+    fn clear_shimmer(&mut self) {
+        self.shimmer_shifter.reset();
+        self.shimmer_feedback = 0.0;
+        self.pitch_tracker.reset();
+        self.shimmer_blend = 1.0;
+        self.input_envelope = 0.0;
+        self.feedback_envelope = 0.0;
+    }
+
+    fn ms_to_samples(sample_rate: usize, ms: f64) -> usize {
+        (ms * sample_rate as f64 / 1000.0).round() as usize
+    }
+
+    /// Push the stored parameters into the freeverb processor
+    ///
+    /// Freeverb always runs fully wet; the dry signal is mixed by the chain.
+    fn apply_params(&mut self) {
+        self.freeverb.set_wet(1.0);
+        self.freeverb.set_dry(0.0);
+        self.freeverb.set_room_size(self.room_size);
+        self.freeverb.set_dampening(self.dampening);
+        self.freeverb.set_width(self.width);
+        self.decorrelator.set_amount(self.width);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::EffectChain;
+    use crate::filter::{Biquad, BiquadCoeffs, BUTTERWORTH_Q};
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_reset_clears_tail() {
+        let mut reverb = Reverb::new(44100);
+        reverb.set_room_size(0.9);
+
+        // Excite the reverb and let it ring
+        reverb.tick((1.0, 1.0));
+        let ringing = (0..5000).any(|_| reverb.tick((0.0, 0.0)).0.abs() > 1e-6);
+        assert!(ringing);
+
+        reverb.reset();
+        for _ in 0..5000 {
+            assert_eq!(reverb.tick((0.0, 0.0)), (0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn test_reset_keeps_parameters() {
+        let configure = |reverb: &mut Reverb| {
+            reverb.set_room_size(0.9);
+            reverb.set_dampening(0.1);
+            reverb.set_width(1.0);
+        };
+        let mut fresh = Reverb::new(44100);
+        configure(&mut fresh);
+        let mut reverb = Reverb::new(44100);
+        configure(&mut reverb);
+        reverb.tick((1.0, -1.0));
+        reverb.reset();
+
+        // Same tail as a freshly configured reverb
+        for i in 0..5000 {
+            let input = if i == 0 { (1.0, 0.5) } else { (0.0, 0.0) };
+            assert_eq!(reverb.tick(input), fresh.tick(input));
+        }
+    }
+
+    #[test]
+    fn test_chain_mix_blends_dry_and_tail() {
+        let input = |i: usize| ((i as f64 * 0.01).sin() * 0.5, (i as f64 * 0.013).cos() * 0.5);
+        let mut reference = Reverb::new(44100);
+        reference.set_predelay_ms(20.0);
+        let tail: Vec<(f64, f64)> = (0..10000).map(|i| reference.tick(input(i))).collect();
+        assert!(tail.iter().any(|frame| frame.0.abs() > 1e-3));
+
+        let run = |mix: f64| {
+            let mut reverb = Reverb::new(44100);
+            reverb.set_predelay_ms(20.0);
+            let mut chain = EffectChain::new(44100);
+            chain.add("verb", Box::new(reverb));
+            chain.set_mix("verb", mix).unwrap();
+            (0..10000).map(|i| chain.process_frame(input(i))).collect::<Vec<_>>()
+        };
+
+        // Mix 0 is the dry signal, untouched by the pre-delay
+        let dry: Vec<(f64, f64)> = (0..10000).map(input).collect();
+        assert_eq!(run(0.0), dry);
+        // Mix 1 is the pure tail
+        assert_eq!(run(1.0), tail);
+    }
+
+    /// Index of the first non-silent wet output sample after an impulse
+    fn wet_onset(reverb: &mut Reverb) -> usize {
+        std::iter::once((1.0, 1.0))
+            .chain(std::iter::repeat((0.0, 0.0)))
+            .take(20000)
+            .position(|input| reverb.tick(input).0.abs() > 1e-9)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_predelay_delays_wet_onset() {
+        let mut reverb = Reverb::new(44100);
+        let onset = wet_onset(&mut reverb);
+
+        let mut delayed = Reverb::new(44100);
+        delayed.set_predelay_ms(10.0);
+        assert_eq!(wet_onset(&mut delayed), onset + 441);
+    }
+
+    #[test]
+    fn test_predelay_is_limited() {
+        let mut reverb = Reverb::new(44100);
+        reverb.set_predelay_ms(50.0);
+        assert_eq!(reverb.predelay_ms(), 50.0);
+        reverb.set_predelay_ms(1000.0);
+        assert_eq!(reverb.predelay_ms(), MAX_PREDELAY_MS);
+    }
+
+    /// Tail energy above 600Hz and overall after a 220Hz burst
+    fn tail_energy(shimmer: f64) -> (f64, f64) {
+        let mut reverb = Reverb::new(44100);
+        reverb.set_room_size(0.9);
+        reverb.set_shimmer(shimmer);
+        let mut highpass = Biquad::new(BiquadCoeffs::highpass(44100.0, 600.0, BUTTERWORTH_Q));
+
+        let (mut high, mut total) = (0.0, 0.0);
+        for i in 0..88200 {
+            let input = if i < 22050 { (2.0 * PI * 220.0 * i as f64 / 44100.0).sin() * 0.5 } else { 0.0 };
+            let (left, _) = reverb.tick((input, input));
+            let filtered = highpass.process(left);
+            if i >= 44100 {
+                high += filtered * filtered;
+                total += left * left;
+            }
+        }
+        (high, total)
+    }
+
+    #[test]
+    fn test_shimmer_adds_octave_energy() {
+        let (plain_high, plain_total) = tail_energy(0.0);
+        let (shimmer_high, shimmer_total) = tail_energy(1.0);
+        assert!(shimmer_high / shimmer_total > 2.0 * plain_high / plain_total,
+            "plain {} / {}, shimmer {} / {}", plain_high, plain_total, shimmer_high, shimmer_total);
+    }
+
+    #[test]
+    fn test_shimmer_blends_out_on_chords() {
+        let play = |freqs: &[f64]| {
+            let mut reverb = Reverb::new(44100);
+            reverb.set_shimmer(1.0);
+            for i in 0..44100 {
+                let t = i as f64 / 44100.0;
+                let input = freqs.iter().map(|freq| (2.0 * PI * freq * t).sin()).sum::<f64>() * 0.25;
+                reverb.tick((input, input));
+            }
+            let blend = reverb.shimmer_blend;
+
+            // The blend holds through the tail after the input stops
+            for _ in 0..44100 {
+                reverb.tick((0.0, 0.0));
+            }
+            assert_eq!(reverb.shimmer_blend, blend);
+            blend
+        };
+
+        assert!(play(&[196.0]) > 0.9);
+        // G and B flat, a minor third
+        assert!(play(&[196.0, 233.08]) < 0.2);
+    }
+
+    #[test]
+    fn test_shimmer_tail_decays() {
+        let mut reverb = Reverb::new(44100);
+        reverb.set_room_size(1.0);
+        reverb.set_shimmer(1.0);
+        for i in 0..44100 {
+            let input = ((i * 7919) % 1000) as f64 / 1000.0 - 0.5;
+            reverb.tick((input, input));
+        }
+
+        // The loop dies away even in the most resonant room
+        let energy = |reverb: &mut Reverb| (0..44100).map(|_| reverb.tick((0.0, 0.0)).0.powi(2)).sum::<f64>();
+        let first = energy(&mut reverb);
+        for _ in 0..4 {
+            energy(&mut reverb);
+        }
+        assert!(energy(&mut reverb) < first * 0.1);
+    }
+
+    /// Tail energy above 4kHz and the overall tail energy after an impulse
+    fn bright_tail_energy(reverb: &mut Reverb) -> (f64, f64) {
+        let mut highpass = Biquad::new(BiquadCoeffs::highpass(44100.0, 4000.0, BUTTERWORTH_Q));
+        let (mut high, mut total) = (0.0, 0.0);
+        for i in 0..44100 {
+            let (left, _) = reverb.tick(if i == 0 { (1.0, 1.0) } else { (0.0, 0.0) });
+            let filtered = highpass.process(left);
+            high += filtered * filtered;
+            total += left * left;
+        }
+        (high, total)
+    }
+
+    #[test]
+    fn test_dark_lowpass_reduces_highs() {
+        let (plain_high, _) = bright_tail_energy(&mut Reverb::new(44100));
+
+        let mut dark = Reverb::new(44100);
+        dark.set_reverb_damp_lp_hz(1000.0);
+        let (dark_high, _) = bright_tail_energy(&mut dark);
+        assert!(dark_high < plain_high * 0.05, "plain {} dark {}", plain_high, dark_high);
+
+        // Even the lowest cutoff keeps an audible tail
+        dark.set_reverb_damp_lp_hz(1.0);
+        assert_eq!(dark.damp_lp_hz(), Some(MIN_DAMP_LP_HZ));
+        dark.reset();
+        let (_, total) = bright_tail_energy(&mut dark);
+        assert!(total > 1e-3, "total {}", total);
+
+        dark.set_reverb_damp_lp_hz(MAX_DAMP_LP_HZ);
+        assert_eq!(dark.damp_lp_hz(), None);
+    }
+
+    #[test]
+    fn test_send_highpass_keeps_lows_out_of_the_tail() {
+        // Tail energy below 200Hz after a 100Hz burst
+        let low_tail_energy = |reverb: &mut Reverb| {
+            let mut lowpass = Biquad::new(BiquadCoeffs::lowpass(44100.0, 200.0, BUTTERWORTH_Q));
+            let mut energy = 0.0;
+            for i in 0..44100 {
+                let input = if i < 11025 { (2.0 * PI * 100.0 * i as f64 / 44100.0).sin() * 0.5 } else { 0.0 };
+                let filtered = lowpass.process(reverb.tick((input, input)).0);
+                if i >= 11025 {
+                    energy += filtered * filtered;
+                }
+            }
+            energy
+        };
+
+        let plain = low_tail_energy(&mut Reverb::new(44100));
+        let mut filtered = Reverb::new(44100);
+        filtered.set_reverb_send_hp_hz(400.0);
+        let thinned = low_tail_energy(&mut filtered);
+        filtered.set_reverb_send_hp_hz(1000.0);
+        filtered.reset();
+        let thinner = low_tail_energy(&mut filtered);
+        assert!(thinned < plain * 0.1, "plain {} thinned {}", plain, thinned);
+        assert!(thinner < thinned, "thinned {} thinner {}", thinned, thinner);
+
+        // Out-of-range cutoffs are limited, and the ends switch the filters off
+        filtered.set_reverb_send_hp_hz(5000.0);
+        assert_eq!(filtered.send_hp_hz(), Some(MAX_SEND_HP_HZ));
+        filtered.set_reverb_send_hp_hz(0.0);
+        assert_eq!(filtered.send_hp_hz(), None);
+        filtered.set_reverb_send_lp_hz(100.0);
+        assert_eq!(filtered.send_lp_hz(), Some(MIN_SEND_LP_HZ));
+        filtered.set_reverb_send_lp_hz(MAX_DAMP_LP_HZ);
+        assert_eq!(filtered.send_lp_hz(), None);
+    }
+
+    #[test]
+    fn test_gated_tail_is_cut_after_hold() {
+        let run = |gated: bool| {
+            let mut reverb = Reverb::new(44100);
+            reverb.set_room_size(0.9);
+            reverb.set_gated(gated);
+            reverb.gate_mut().set_hold_ms(100.0);
+            reverb.gate_mut().set_release_ms(10.0);
+
+            // 50ms burst, then silence
+            (0..22050)
+                .map(|i| {
+                    let input = if i < 2205 { (i as f64 * 0.05).sin() * 0.5 } else { 0.0 };
+                    reverb.tick((input, input))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Burst + detector release + hold + release is well under 200ms
+        let tail_peak = |output: &[(f64, f64)]| output[8820..].iter().map(|s| s.0.abs()).fold(0.0, f64::max);
+        assert!(tail_peak(&run(false)) > 0.01);
+        assert_eq!(tail_peak(&run(true)), 0.0);
+    }
+
+    /// Peak-to-RMS ratio of the first 100ms of the wet impulse response
+    fn impulse_crest_factor(diffusion: f64) -> f64 {
+        let mut reverb = Reverb::new(44100);
+        reverb.set_diffusion(diffusion);
+        let response: Vec<f64> = (0..4410)
+            .map(|i| reverb.tick(if i == 0 { (1.0, 1.0) } else { (0.0, 0.0) }).0)
+            .collect();
+
+        let peak = response.iter().map(|s| s.abs()).fold(0.0, f64::max);
+        let rms = (response.iter().map(|s| s * s).sum::<f64>() / response.len() as f64).sqrt();
+        peak / rms
+    }
+
+    #[test]
+    fn test_diffusion_smooths_impulse_response() {
+        let discrete = impulse_crest_factor(0.0);
+        let smeared = impulse_crest_factor(1.0);
+        assert!(smeared < discrete * 0.7, "crest factor {} -> {}", discrete, smeared);
+        assert!(impulse_crest_factor(0.5) < discrete);
+    }
+
+    /// Tail energy of the left channel in consecutive half-second windows
+    fn window_energies(reverb: &mut Reverb, windows: usize) -> Vec<f64> {
+        (0..windows)
+            .map(|_| (0..22050).map(|_| reverb.tick((0.0, 0.0)).0.powi(2)).sum())
+            .collect()
+    }
+
+    #[test]
+    fn test_tick_wet_only_returns_a_decaying_tail_without_dry() {
+        let mut reverb = Reverb::new(44100);
+        reverb.set_room_size(0.8);
+
+        // The impulse itself never comes out: the first output is silent
+        assert_eq!(reverb.tick_wet_only((1.0, 1.0)), (0.0, 0.0));
+
+        // What follows is the tail, ringing and then dying away
+        let energies: Vec<f64> = (0..6)
+            .map(|_| (0..22050).map(|_| reverb.tick_wet_only((0.0, 0.0)).0.powi(2)).sum())
+            .collect();
+        assert!(energies[0] > 1e-3, "tail energy {:?}", energies);
+        assert!(energies.windows(2).all(|pair| pair[1] < pair[0]), "{:?}", energies);
+    }
+
+    #[test]
+    fn test_width_decorrelates_a_mono_impulse() {
+        // Normalized cross-correlation of the two sides of a mono impulse's tail
+        let correlation = |width: f64| {
+            let mut reverb = Reverb::new(44100);
+            reverb.set_width(width);
+            let tail: Vec<(f64, f64)> = std::iter::once(reverb.tick((1.0, 1.0)))
+                .chain((0..44100).map(|_| reverb.tick((0.0, 0.0))))
+                .collect();
+            let (mut lr, mut ll, mut rr) = (0.0, 0.0, 0.0);
+            for &(left, right) in &tail {
+                lr += left * right;
+                ll += left * left;
+                rr += right * right;
+            }
+            lr / (ll * rr).sqrt()
+        };
+
+        let narrow = correlation(0.0);
+        let wide = correlation(1.0);
+        assert!(narrow > 0.99, "width 0 correlation {}", narrow);
+        assert!(wide.abs() < 0.2, "width 1 correlation {}", wide);
+        assert!(correlation(0.5) > wide, "width 0.5 should sit between");
+    }
+
+    #[test]
+    fn test_freeze_sustains_tail() {
+        let excite = |reverb: &mut Reverb| {
+            for i in 0..22050 {
+                let input = ((i * 7919) % 1000) as f64 / 1000.0 - 0.5;
+                reverb.tick((input, input));
+            }
+        };
+
+        // Unfrozen, the tail dies away
+        let mut reverb = Reverb::new(44100);
+        excite(&mut reverb);
+        let decaying = window_energies(&mut reverb, 4);
+        assert!(decaying[3] < decaying[0] * 0.01, "{:?}", decaying);
+
+        // Frozen, it holds its level, and new input doesn't get in
+        let mut reverb = Reverb::new(44100);
+        excite(&mut reverb);
+        reverb.set_freeze(true);
+        let frozen = window_energies(&mut reverb, 4);
+        for energy in &frozen {
+            assert!((energy / frozen[0] - 1.0).abs() < 0.2, "{:?}", frozen);
+        }
+        let before = window_energies(&mut reverb, 1)[0];
+        for _ in 0..22050 {
+            reverb.tick((0.5, 0.5));
+        }
+        let after = window_energies(&mut reverb, 1)[0];
+        assert!((after / before - 1.0).abs() < 0.2, "{} -> {}", before, after);
+
+        // Released, it decays again
+        reverb.set_freeze(false);
+        let released = window_energies(&mut reverb, 4);
+        assert!(released[3] < released[0] * 0.01, "{:?}", released);
+
+        // Reset releases the freeze
+        reverb.set_freeze(true);
+        reverb.reset();
+        assert!(!reverb.is_frozen());
+    }
+
+    #[test]
+    fn test_freeze_release_crossfades_into_the_decay() {
+        // Energy of a released tail in consecutive 50ms windows, relative to the
+        // same tail kept frozen (which evens out the beating of the held echoes)
+        let release = |release_ms: f64| {
+            let frozen_tail = || {
+                let mut reverb = Reverb::new(44100);
+                reverb.set_freeze_release_ms(release_ms);
+                for i in 0..22050 {
+                    let input = ((i * 7919) % 1000) as f64 / 1000.0 - 0.5;
+                    reverb.tick((input, input));
+                }
+                reverb.set_freeze(true);
+                reverb
+            };
+            let (mut held, mut released) = (frozen_tail(), frozen_tail());
+            released.set_freeze(false);
+            let energy = |reverb: &mut Reverb| (0..2205).map(|_| reverb.tick((0.0, 0.0)).0.powi(2)).sum::<f64>();
+            (0..40).map(|_| energy(&mut released) / energy(&mut held)).collect::<Vec<f64>>()
+        };
+
+        // Released at once, most of the tail is gone within 150ms
+        let abrupt = release(0.0);
+        assert!(abrupt[2] < 0.3, "{:?}", &abrupt[..4]);
+
+        // With a release, it eases out of the freeze, falling steadily and still
+        // dying away
+        let eased = release(500.0);
+        assert!(eased[..4].iter().all(|&ratio| ratio > 0.8), "{:?}", &eased[..4]);
+        assert!(eased[..10].windows(2).all(|pair| pair[1] < pair[0]), "{:?}", &eased[..10]);
+        assert!(eased[39] < 1e-3, "{:?}", eased);
+    }
+
+    #[test]
+    fn test_modulation_makes_the_tail_time_varying() {
+        // A steady 441 Hz sine repeats every 100 samples; once freeverb has settled
+        // a static tail repeats with it, a modulated one drifts against it
+        let drift = |depth: f64| {
+            let mut reverb = Reverb::new(44100);
+            reverb.set_reverb_mod_depth(depth);
+            let output: Vec<f64> = (0..88200)
+                .map(|i| reverb.tick(((2.0 * PI * i as f64 / 100.0).sin(), 0.0)).0)
+                .skip(66150)
+                .collect();
+            let (mut difference, mut power) = (0.0, 0.0);
+            for (sample, later) in output.iter().zip(&output[10000..]) {
+                difference += (later - sample).powi(2);
+                power += sample * sample;
+            }
+            (difference / power).sqrt()
+        };
+
+        let static_tail = drift(0.0);
+        let modulated = drift(1.0);
+        assert!(static_tail < 0.01, "static tail drift {}", static_tail);
+        assert!(modulated > 0.1, "modulated tail drift {}", modulated);
+    }
+
+    /// Seconds until the 10 ms RMS of the impulse response stays below -60 dB
+    /// of its loudest window
+    fn time_to_minus_60_db(decay_seconds: f64) -> f64 {
+        let mut reverb = Reverb::new(44100);
+        reverb.set_decay_seconds(decay_seconds);
+        reverb.tick((1.0, 1.0));
+        let windows: Vec<f64> = (0..(decay_seconds * 150.0) as usize)
+            .map(|_| (0..441).map(|_| reverb.tick((0.0, 0.0)).0.powi(2)).sum::<f64>())
+            .collect();
+        let peak = windows.iter().cloned().fold(0.0, f64::max);
+        let last_loud = windows.iter().rposition(|&energy| energy > peak * 1e-6).unwrap();
+        (last_loud + 1) as f64 * 0.01
+    }
+
+    #[test]
+    fn test_doubling_the_decay_doubles_the_tail() {
+        let short = time_to_minus_60_db(1.0);
+        let long = time_to_minus_60_db(2.0);
+        assert!((0.6..1.4).contains(&short), "{}", short);
+        assert!((1.7..2.3).contains(&(long / short)), "{} then {}", short, long);
+
+        // The decay reads back through the room size, and stays in a stable range
+        let mut reverb = Reverb::new(44100);
+        reverb.set_decay_seconds(3.0);
+        assert!((reverb.decay_seconds() - 3.0).abs() < 1e-9);
+        reverb.set_decay_seconds(100.0);
+        assert!((reverb.decay_seconds() - 12.0).abs() < 1e-9);
+        reverb.set_decay_seconds(0.0);
+        assert!((reverb.decay_seconds() - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_every_algorithm_has_a_decaying_impulse_response() {
+        for algo in ReverbAlgo::ALL {
+            let mut reverb = Reverb::new(44100);
+            reverb.set_reverb_algo(algo);
+            reverb.reset();
+            reverb.set_room_size(0.8);
+            reverb.tick((1.0, 1.0));
+
+            let energies = window_energies(&mut reverb, 6);
+            assert!(energies[0] > 1e-3, "{}: tail energy {:?}", algo.name(), energies);
+            assert!(energies.windows(2).all(|pair| pair[1] < pair[0]), "{}: {:?}", algo.name(), energies);
+            assert!(energies[5] < energies[0] * 0.01, "{}: {:?}", algo.name(), energies);
+        }
+    }
+
+    #[test]
+    fn test_switching_algorithms_crossfades() {
+        let mut reverb = Reverb::new(44100);
+        reverb.set_room_size(0.9);
+        let noise = |i: usize| ((i * 7919) % 1000) as f64 / 1000.0 - 0.5;
+        let mut last = (0.0, 0.0);
+        let mut largest_step: f64 = 0.0;
+        for i in 0..88200 {
+            // Switch back and forth, once in the middle of a crossfade
+            match i {
+                22050 => reverb.set_reverb_algo(ReverbAlgo::Plate),
+                24000 => reverb.set_reverb_algo(ReverbAlgo::Freeverb),
+                44100 => reverb.set_reverb_algo(ReverbAlgo::Plate),
+                _ => {}
+            }
+            let output = reverb.tick((noise(i) * 0.1, noise(i + 500) * 0.1));
+            assert!(output.0.is_finite() && output.1.is_finite());
+            if i > 4410 {
+                largest_step = largest_step.max((output.0 - last.0).abs());
+            }
+            last = output;
+        }
+        assert_eq!(reverb.algo(), ReverbAlgo::Plate);
+        assert!(largest_step < 0.5, "largest step {}", largest_step);
+
+        // Settings carry over to a copy, and a reset ends any crossfade
+        assert_eq!(reverb.clone_settings().algo(), ReverbAlgo::Plate);
+        reverb.set_reverb_algo(ReverbAlgo::Freeverb);
+        reverb.reset();
+        for _ in 0..5000 {
+            assert_eq!(reverb.tick((0.0, 0.0)), (0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn test_clone_settings_keeps_the_gate_settings() {
+        let mut reverb = Reverb::new(44100);
+        reverb.set_gated(true);
+        reverb.set_gate_key(KeySource::Signal);
+        let gate = reverb.gate_mut();
+        gate.set_threshold(0.05);
+        gate.set_hold_ms(150.0);
+        gate.set_release_ms(80.0);
+        gate.set_hysteresis_db(9.0);
+
+        let settings = |gate: &mut NoiseGate| {
+            (gate.threshold(), gate.hold_ms(), gate.release_ms(), gate.key_source(), gate.hysteresis_db())
+        };
+        let original = settings(reverb.gate_mut());
+        assert_eq!(settings(reverb.clone_settings().gate_mut()), original);
+        assert_eq!(original.4, 9.0);
+    }
+}
@@ -0,0 +1,184 @@
+//! Reverse delay
+//!
+//! Records the input in chunks of the delay time and, once a chunk is full,
+//! plays it back backwards while the next one records, so every phrase comes
+//! back reversed one chunk later: notes swell in from their tails and cut off at
+//! their attacks. Each reversed chunk fades in and out over a few milliseconds,
+//! since the audio at a chunk's edges was cut out of running sound and would
+//! click. The reversed signal is blended with the input on the constant-power
+//! law.
+//!
+//! A new delay time takes effect at the next chunk boundary, so the chunk that
+//! is recording is never cut short.
+
+use crate::mix::constant_power_gains;
+
+/// Shortest chunk in milliseconds
+const MIN_TIME_MS: f64 = 50.0;
+/// Longest chunk in milliseconds
+const MAX_TIME_MS: f64 = 2000.0;
+/// Fade at each end of a reversed chunk in milliseconds
+const FADE_MS: f64 = 5.0;
+
+/// Chunked reverse playback
+pub struct ReverseDelay {
+    /// Chunk being recorded and chunk being played back, each sized for the
+    /// longest time
+    buffers: [Vec<(f64, f64)>; 2],
+    /// Which buffer is recording (the other plays)
+    recording: usize,
+    /// Position within the current chunk
+    position: usize,
+    /// Length of the chunk being recorded in samples
+    chunk: usize,
+    /// Length of the chunk being played back in samples
+    played: usize,
+    /// Length of the next chunk in samples, from the delay time
+    next_chunk: usize,
+    /// Sample rate for converting times to samples
+    sample_rate: f64,
+    /// Fade at each end of a chunk in samples
+    fade: usize,
+    /// Reversed signal in the output (0.0 = dry only, 1.0 = reversed only)
+    mix: f64,
+}
+
+impl ReverseDelay {
+    /// Create a reverse delay with 500 ms chunks at half mix
+    pub fn new(sample_rate: usize) -> Self {
+        let sample_rate = sample_rate as f64;
+        let length = (MAX_TIME_MS * sample_rate / 1000.0) as usize;
+        let mut reverse = Self {
+            buffers: [vec![(0.0, 0.0); length], vec![(0.0, 0.0); length]],
+            recording: 0,
+            position: 0,
+            chunk: 1,
+            played: 1,
+            next_chunk: 1,
+            sample_rate,
+            fade: ((FADE_MS * sample_rate / 1000.0) as usize).max(1),
+            mix: 0.5,
+        };
+        reverse.set_time_ms(500.0);
+        reverse.reset();
+        reverse
+    }
+
+    /// Process a stereo input sample and return the input blended with the
+    /// previous chunk played backwards
+    pub fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        // After a time change the chunks differ in length: a longer one loses
+        // its oldest audio, a shorter one is followed by silence
+        let length = self.played.min(self.chunk);
+        let (reversed, fade) = if self.position < length {
+            // Fade in at the start of the chunk and out at its end
+            let edge = self.position.min(length - 1 - self.position);
+            let fade = ((edge as f64 + 0.5) / self.fade as f64).min(1.0);
+            (self.buffers[1 - self.recording][self.played - 1 - self.position], fade)
+        } else {
+            ((0.0, 0.0), 0.0)
+        };
+
+        self.buffers[self.recording][self.position] = input;
+        self.position += 1;
+        if self.position == self.chunk {
+            self.recording = 1 - self.recording;
+            self.position = 0;
+            self.played = self.chunk;
+            self.chunk = self.next_chunk;
+        }
+
+        let (dry_gain, wet_gain) = constant_power_gains(self.mix);
+        (
+            input.0 * dry_gain + reversed.0 * fade * wet_gain,
+            input.1 * dry_gain + reversed.1 * fade * wet_gain,
+        )
+    }
+
+    /// Set the chunk length in milliseconds (50 to 2000), from the next chunk
+    pub fn set_time_ms(&mut self, time_ms: f64) {
+        let time_ms = time_ms.clamp(MIN_TIME_MS, MAX_TIME_MS);
+        self.next_chunk = ((time_ms * self.sample_rate / 1000.0) as usize).clamp(1, self.buffers[0].len());
+    }
+
+    /// Current chunk length in milliseconds
+    pub fn time_ms(&self) -> f64 {
+        self.next_chunk as f64 * 1000.0 / self.sample_rate
+    }
+
+    /// Set the reversed signal's share of the output (0.0 to 1.0)
+    pub fn set_mix(&mut self, mix: f64) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    /// Current mix
+    pub fn mix(&self) -> f64 {
+        self.mix
+    }
+
+    /// Clear both chunks and start recording afresh
+    pub fn reset(&mut self) {
+        for buffer in &mut self.buffers {
+            buffer.fill((0.0, 0.0));
+        }
+        self.position = 0;
+        self.chunk = self.next_chunk;
+        self.played = self.chunk;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ramp_comes_back_reversed_each_cycle() {
+        // 100 ms chunks at 1 kHz: 100 samples, faded over 5
+        let mut reverse = ReverseDelay::new(1000);
+        reverse.set_time_ms(100.0);
+        reverse.reset();
+        reverse.set_mix(1.0);
+
+        let input: Vec<f64> = (0..400).map(|i| i as f64).collect();
+        let output: Vec<f64> = input.iter().map(|&x| reverse.tick((x, -x)).0).collect();
+
+        // The first chunk is silent while it records
+        assert!(output[..100].iter().all(|&y| y == 0.0));
+
+        // Each later chunk plays the one before it backwards, away from the fades
+        for cycle in 1..4 {
+            let start = cycle * 100;
+            for i in 5..95 {
+                let expected = (start - 1 - i) as f64;
+                assert!((output[start + i] - expected).abs() < 1e-9, "sample {}: {}", start + i, output[start + i]);
+            }
+
+            // Each boundary fades to near silence, so the jump doesn't click
+            assert!(output[start].abs() < 0.1 * (start as f64));
+            assert!(output[start + 99].abs() < 0.1 * (start as f64));
+        }
+    }
+
+    #[test]
+    fn test_time_changes_at_the_next_chunk() {
+        let mut reverse = ReverseDelay::new(1000);
+        reverse.set_time_ms(100.0);
+        reverse.reset();
+        reverse.set_mix(1.0);
+        for i in 0..50 {
+            reverse.tick((i as f64, 0.0));
+        }
+
+        // The chunk that is recording still runs its full 100 ms
+        reverse.set_time_ms(60.0);
+        assert_eq!(reverse.time_ms(), 60.0);
+        for i in 50..100 {
+            reverse.tick((i as f64, 0.0));
+        }
+        let output: Vec<f64> = (100..160).map(|i| reverse.tick((i as f64, 0.0)).0).collect();
+        assert!((output[10] - 89.0).abs() < 1e-9, "{}", output[10]);
+
+        reverse.set_time_ms(10000.0);
+        assert_eq!(reverse.time_ms(), 2000.0);
+    }
+}
@@ -0,0 +1,127 @@
+//! Feedback safety
+//!
+//! Two guards against feedback running away and blowing the speakers. Every
+//! effect's feedback setter goes through a `FeedbackCeiling`, which limits it to
+//! the ceiling the engine shares with its chain (set at startup with
+//! `--max-feedback`), so no command, preset or OSC message can exceed it. Behind
+//! that, the runaway detector watches the output RMS and trips when it stays over
+//! full scale, and the output callback then panics the chain (mute, clear, bypass).
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Highest allowed feedback ceiling, and the default
+pub const DEFAULT_MAX_FEEDBACK: f64 = 0.99;
+/// Output RMS over which the signal counts as running away (0 dBFS)
+const RUNAWAY_RMS: f64 = 1.0;
+/// How long the output must stay over the runaway level, in seconds
+const RUNAWAY_SECONDS: f64 = 0.5;
+/// Time constant of the RMS measurement in seconds
+const RMS_SECONDS: f64 = 0.05;
+
+/// Feedback ceiling, shared by the engine and the effects it builds
+///
+/// Clones share the setting, so raising or lowering it through one handle applies
+/// to feedback set through all of them from then on.
+#[derive(Debug, Clone)]
+pub struct FeedbackCeiling(Arc<AtomicU64>);
+
+impl Default for FeedbackCeiling {
+    fn default() -> Self {
+        Self(Arc::new(AtomicU64::new(DEFAULT_MAX_FEEDBACK.to_bits())))
+    }
+}
+
+impl FeedbackCeiling {
+    /// Set the ceiling (0.0 to 0.99)
+    pub fn set(&self, ceiling: f64) {
+        self.0.store(ceiling.clamp(0.0, DEFAULT_MAX_FEEDBACK).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Current ceiling
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    /// Limit a feedback amount to 0.0 and the ceiling
+    pub fn clamp(&self, feedback: f64) -> f64 {
+        feedback.clamp(0.0, self.get())
+    }
+}
+
+/// Trips when the output stays over full scale
+pub struct RunawayDetector {
+    /// Smoothed mean square of the output
+    mean_square: f64,
+    /// One-pole coefficient of the RMS measurement
+    coefficient: f64,
+    /// Consecutive samples over the runaway level
+    over_samples: usize,
+    /// Samples over the level that trip the detector
+    trip_samples: usize,
+}
+
+impl RunawayDetector {
+    /// Create a detector for the given sample rate
+    pub fn new(sample_rate: usize) -> Self {
+        Self {
+            mean_square: 0.0,
+            coefficient: (-1.0 / (RMS_SECONDS * sample_rate as f64)).exp(),
+            over_samples: 0,
+            trip_samples: (RUNAWAY_SECONDS * sample_rate as f64) as usize,
+        }
+    }
+
+    /// Measure an output frame; returns true once when the output has run away
+    pub fn process(&mut self, frame: (f64, f64)) -> bool {
+        let energy = (frame.0 * frame.0 + frame.1 * frame.1) / 2.0;
+        self.mean_square = energy + self.coefficient * (self.mean_square - energy);
+        if self.mean_square > RUNAWAY_RMS * RUNAWAY_RMS {
+            self.over_samples += 1;
+        } else {
+            self.over_samples = 0;
+        }
+
+        if self.over_samples >= self.trip_samples {
+            self.mean_square = 0.0;
+            self.over_samples = 0;
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feedback_is_clamped_to_the_ceiling() {
+        // The default ceiling stays below unity
+        let ceiling = FeedbackCeiling::default();
+        assert!(ceiling.clamp(5.0) <= DEFAULT_MAX_FEEDBACK);
+
+        // A clone shares the setting
+        ceiling.clone().set(0.6);
+        assert_eq!(ceiling.clamp(1.5), 0.6);
+        assert_eq!(ceiling.clamp(0.4), 0.4);
+        assert_eq!(ceiling.clamp(-0.2), 0.0);
+        ceiling.set(2.0);
+        assert_eq!(ceiling.get(), DEFAULT_MAX_FEEDBACK);
+    }
+
+    #[test]
+    fn test_runaway_trips_on_sustained_over_level() {
+        let mut detector = RunawayDetector::new(1000);
+
+        // Loud but legal playing, and short over-level bursts, never trip
+        assert!(!(0..5000).any(|i| detector.process(if i % 2 == 0 { (0.99, -0.99) } else { (-0.99, 0.99) })));
+        let burst = |i: usize| if i % 1000 < 300 { 2.0 } else { 0.1 };
+        assert!(!(0..5000).any(|i| detector.process((burst(i), burst(i)))));
+
+        // Output stuck at twice full scale trips after the RMS rises and half a second passes
+        let tripped = (0..2000).position(|_| detector.process((2.0, -2.0)));
+        assert!(matches!(tripped, Some(samples) if (500..600).contains(&samples)), "{:?}", tripped);
+        assert!(!detector.process((2.0, -2.0)));
+    }
+}
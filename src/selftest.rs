@@ -0,0 +1,177 @@
+//! Audio round-trip self-test
+//!
+//! `--selftest` opens the streams as usual, but the input callback replaces the
+//! device input with a known test tone before pushing it into the ring buffer.
+//! The output callback reports what it pops back out, and the main thread checks
+//! the observations until the tone has made it through or the timeout runs out.
+//! This exercises device opening and the buffer plumbing without a guitar.
+//!
+//! The callbacks only touch the probe's atomic counters, so the pass/fail decision
+//! can be tested by calling the probe directly in place of the audio callbacks.
+
+use std::f64::consts::TAU;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// Test tone frequency in Hz
+const TONE_HZ: f64 = 1000.0;
+/// Test tone peak level
+const TONE_LEVEL: f64 = 0.25;
+/// Audio that must make the round trip, in seconds
+pub const REQUIRED_SECONDS: f64 = 0.1;
+/// How long to wait for the round trip, in seconds
+pub const TIMEOUT_SECONDS: f64 = 3.0;
+
+/// Sample flow counters shared with the audio callbacks
+#[derive(Debug, Default)]
+pub struct SelfTestProbe {
+    /// Input callbacks seen
+    input_callbacks: AtomicU64,
+    /// Test tone samples pushed into the ring buffer
+    samples_sent: AtomicU64,
+    /// Output callbacks seen
+    output_callbacks: AtomicU64,
+    /// Samples popped from the ring buffer in the output callback
+    samples_received: AtomicU64,
+    /// Peak of the received samples, as f32 bits
+    received_peak: AtomicU32,
+}
+
+/// A snapshot of the sample flow
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Observations {
+    pub input_callbacks: u64,
+    pub samples_sent: u64,
+    pub output_callbacks: u64,
+    pub samples_received: u64,
+    pub received_peak: f32,
+}
+
+impl SelfTestProbe {
+    /// Create a probe with nothing observed
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Test tone sample `index` at the input sample rate
+    pub fn tone_sample(index: u64, sample_rate: f64) -> f32 {
+        (TONE_LEVEL * (TAU * TONE_HZ * index as f64 / sample_rate).sin()) as f32
+    }
+
+    /// Called by the input callback; returns the index of its first tone sample
+    pub fn input_callback(&self) -> u64 {
+        self.input_callbacks.fetch_add(1, Ordering::Relaxed);
+        self.samples_sent.load(Ordering::Relaxed)
+    }
+
+    /// Called by the input callback with the number of tone samples it pushed
+    pub fn record_sent(&self, samples: u64) {
+        self.samples_sent.fetch_add(samples, Ordering::Relaxed);
+    }
+
+    /// Called by the output callback with the samples it popped and their peak
+    pub fn record_received(&self, samples: u64, peak: f32) {
+        self.output_callbacks.fetch_add(1, Ordering::Relaxed);
+        self.samples_received.fetch_add(samples, Ordering::Relaxed);
+        self.received_peak.fetch_max(peak.abs().to_bits(), Ordering::Relaxed);
+    }
+
+    /// Current counters
+    pub fn observations(&self) -> Observations {
+        Observations {
+            input_callbacks: self.input_callbacks.load(Ordering::Relaxed),
+            samples_sent: self.samples_sent.load(Ordering::Relaxed),
+            output_callbacks: self.output_callbacks.load(Ordering::Relaxed),
+            samples_received: self.samples_received.load(Ordering::Relaxed),
+            received_peak: f32::from_bits(self.received_peak.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl std::fmt::Display for Observations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} input callbacks, {} samples sent, {} output callbacks, {} samples received, peak {:.3}",
+            self.input_callbacks, self.samples_sent, self.output_callbacks, self.samples_received, self.received_peak
+        )
+    }
+}
+
+/// Decide whether `required` samples of the tone made the round trip intact
+///
+/// The error names the first stage of the path that fell short.
+pub fn evaluate(observations: &Observations, required: u64) -> Result<(), String> {
+    if observations.input_callbacks == 0 {
+        return Err("the input stream never called back (is the input device delivering audio?)".to_string());
+    }
+    if observations.samples_sent < required {
+        return Err(format!(
+            "only {} of {} test tone samples reached the ring buffer",
+            observations.samples_sent, required
+        ));
+    }
+    if observations.output_callbacks == 0 {
+        return Err("the output stream never called back (is the output device running?)".to_string());
+    }
+    if observations.samples_received < required {
+        return Err(format!(
+            "only {} of {} samples came out of the ring buffer in the output callback",
+            observations.samples_received, required
+        ));
+    }
+    let peak = observations.received_peak as f64;
+    if !(TONE_LEVEL * 0.9..=TONE_LEVEL * 1.01).contains(&peak) {
+        return Err(format!(
+            "the test tone came out at peak {:.3} instead of {:.3} (samples altered in the buffer)",
+            peak, TONE_LEVEL
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run mocked input and output callbacks moving `buffers` buffers of 256
+    /// samples, popping `received_per_buffer` of each
+    fn simulate(buffers: usize, received_per_buffer: usize, gain: f32) -> Observations {
+        let probe = SelfTestProbe::new();
+        let mut ring = std::collections::VecDeque::new();
+        for _ in 0..buffers {
+            let start = probe.input_callback();
+            ring.extend((0..256).map(|i| SelfTestProbe::tone_sample(start + i, 48000.0)));
+            probe.record_sent(256);
+
+            let popped: Vec<f32> = (0..received_per_buffer).filter_map(|_| ring.pop_front()).collect();
+            let peak = popped.iter().fold(0.0_f32, |peak, sample| peak.max((sample * gain).abs()));
+            probe.record_received(popped.len() as u64, peak);
+        }
+        probe.observations()
+    }
+
+    #[test]
+    fn test_pass_fail_decision() {
+        let required = 4800;
+
+        // A clean round trip passes
+        let clean = simulate(40, 256, 1.0);
+        assert_eq!(clean.samples_received, 10240);
+        assert_eq!(evaluate(&clean, required), Ok(()));
+
+        // Not enough time yet, or the output starved
+        assert!(evaluate(&simulate(10, 256, 1.0), required).unwrap_err().contains("ring buffer"));
+        let starved = evaluate(&simulate(40, 64, 1.0), required).unwrap_err();
+        assert!(starved.contains("output callback"), "{}", starved);
+
+        // Samples that come out altered fail
+        let altered = evaluate(&simulate(40, 256, 0.5), required).unwrap_err();
+        assert!(altered.contains("peak"), "{}", altered);
+
+        // Streams that never ran
+        let silent = SelfTestProbe::new().observations();
+        assert!(evaluate(&silent, required).unwrap_err().contains("input stream"));
+        let no_output = Observations { output_callbacks: 0, samples_received: 0, received_peak: 0.0, ..clean };
+        assert!(evaluate(&no_output, required).unwrap_err().contains("output stream"));
+    }
+}
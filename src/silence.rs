@@ -0,0 +1,190 @@
+//! Silence detector for auto-suspend
+//!
+//! Follows the level of the signal with a peak envelope and, once it has stayed
+//! below the silence threshold for the timeout, fades the processed output out and
+//! suspends the effects so the stages aren't ticked while nothing is playing. The
+//! chain feeds it the louder of the input and the last processed output, so a
+//! ringing tail or a frozen reverb keeps it running. Any input over the threshold
+//! resumes processing straight away, fading back in so the restart doesn't click.
+
+/// Envelope level below which the signal counts as silence (-60 dBFS)
+const SILENCE_THRESHOLD: f64 = 0.001;
+/// Release time of the envelope in seconds
+const RELEASE_SECONDS: f64 = 0.05;
+/// Length of the fades into and out of suspension in seconds
+const FADE_SECONDS: f64 = 0.01;
+/// Default time of silence before suspending, in seconds
+pub const DEFAULT_TIMEOUT_SECONDS: f64 = 10.0;
+/// Shortest and longest timeout in seconds
+const MIN_TIMEOUT_SECONDS: f64 = 1.0;
+const MAX_TIMEOUT_SECONDS: f64 = 600.0;
+
+/// Where the detector is in the suspend cycle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SuspendState {
+    /// Processing (fading back in after a resume until the gain reaches 1.0)
+    Active,
+    /// Silent for the timeout; fading the output out before suspending
+    FadingOut,
+    /// The effects are skipped and the output is silent
+    Suspended,
+}
+
+/// Silence detection state machine
+pub struct SilenceDetector {
+    /// Sample rate in Hz
+    sample_rate: f64,
+    /// Whether the detector may suspend processing
+    enabled: bool,
+    /// Time of silence before suspending, in seconds
+    timeout_seconds: f64,
+    /// Time of silence before suspending, in samples
+    timeout_samples: usize,
+    /// Consecutive samples with the envelope under the threshold
+    quiet_samples: usize,
+    /// Peak envelope of the signal
+    envelope: f64,
+    /// Envelope release coefficient per sample
+    release: f64,
+    /// Current state
+    state: SuspendState,
+    /// Gain applied to the processed output (0.0 to 1.0)
+    gain: f64,
+    /// Gain change per sample while fading
+    fade_step: f64,
+}
+
+impl SilenceDetector {
+    /// Create a detector with the default timeout, switched off
+    pub fn new(sample_rate: usize) -> Self {
+        let sample_rate = sample_rate as f64;
+        Self {
+            sample_rate,
+            enabled: false,
+            timeout_seconds: DEFAULT_TIMEOUT_SECONDS,
+            timeout_samples: (DEFAULT_TIMEOUT_SECONDS * sample_rate) as usize,
+            quiet_samples: 0,
+            envelope: 0.0,
+            release: (-1.0 / (RELEASE_SECONDS * sample_rate)).exp(),
+            state: SuspendState::Active,
+            gain: 1.0,
+            fade_step: 1.0 / (FADE_SECONDS * sample_rate),
+        }
+    }
+
+    /// Feed the level of the next sample and return the new state
+    pub fn update(&mut self, level: f64) -> SuspendState {
+        self.envelope = level.max(self.envelope * self.release);
+        let silent = self.envelope < SILENCE_THRESHOLD;
+        self.quiet_samples = if silent { self.quiet_samples + 1 } else { 0 };
+
+        self.state = match self.state {
+            _ if !self.enabled || !silent => SuspendState::Active,
+            SuspendState::Active if self.quiet_samples >= self.timeout_samples => SuspendState::FadingOut,
+            SuspendState::FadingOut if self.gain <= 0.0 => SuspendState::Suspended,
+            state => state,
+        };
+
+        self.gain = match self.state {
+            SuspendState::Active => (self.gain + self.fade_step).min(1.0),
+            _ => (self.gain - self.fade_step).max(0.0),
+        };
+        self.state
+    }
+
+    /// Gain to apply to the processed output
+    pub fn gain(&self) -> f64 {
+        self.gain
+    }
+
+    /// Current state
+    pub fn state(&self) -> SuspendState {
+        self.state
+    }
+
+    /// Allow or stop suspending; switching off resumes at once
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.state = SuspendState::Active;
+        }
+        self.quiet_samples = 0;
+    }
+
+    /// Whether auto-suspend is on
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Set the time of silence before suspending, in seconds (1 to 600)
+    pub fn set_timeout_seconds(&mut self, seconds: f64) {
+        self.timeout_seconds = seconds.clamp(MIN_TIMEOUT_SECONDS, MAX_TIMEOUT_SECONDS);
+        self.timeout_samples = (self.timeout_seconds * self.sample_rate) as usize;
+    }
+
+    /// Current timeout in seconds
+    pub fn timeout_seconds(&self) -> f64 {
+        self.timeout_seconds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed a constant level for a number of samples and return the final state
+    fn feed(detector: &mut SilenceDetector, level: f64, samples: usize) -> SuspendState {
+        let mut state = detector.state();
+        for _ in 0..samples {
+            state = detector.update(level);
+        }
+        state
+    }
+
+    #[test]
+    fn test_suspends_after_timeout_and_resumes_on_signal() {
+        let mut detector = SilenceDetector::new(1000);
+        detector.set_enabled(true);
+        detector.set_timeout_seconds(2.0);
+
+        // Playing, then silence: the envelope takes 311 samples to release from 0.5
+        // below the threshold, then the two-second timeout runs
+        assert_eq!(feed(&mut detector, 0.5, 500), SuspendState::Active);
+        let active = (0..).take_while(|_| detector.update(0.0) == SuspendState::Active).count();
+        assert_eq!(active, 310 + 2000 - 1);
+        assert_eq!(detector.state(), SuspendState::FadingOut);
+        assert!(detector.gain() < 1.0 && detector.gain() > 0.0);
+
+        // Suspended once the 10 ms fade has run out
+        assert_eq!(feed(&mut detector, 0.0, 20), SuspendState::Suspended);
+        assert_eq!(detector.gain(), 0.0);
+
+        // Noise under the threshold stays suspended; a note resumes and fades in
+        assert_eq!(feed(&mut detector, 0.0005, 1000), SuspendState::Suspended);
+        assert_eq!(detector.update(0.2), SuspendState::Active);
+        assert!(detector.gain() > 0.0 && detector.gain() < 0.2);
+        feed(&mut detector, 0.2, 10);
+        assert_eq!(detector.gain(), 1.0);
+    }
+
+    #[test]
+    fn test_signal_during_fade_and_switching_off() {
+        let mut detector = SilenceDetector::new(1000);
+        detector.set_timeout_seconds(1.0);
+
+        // Switched off it never leaves the active state
+        assert_eq!(feed(&mut detector, 0.0, 5000), SuspendState::Active);
+
+        // A note during the fade-out cancels it and restarts the timeout
+        detector.set_enabled(true);
+        assert_eq!(feed(&mut detector, 0.0, 1000), SuspendState::FadingOut);
+        assert_eq!(detector.update(0.1), SuspendState::Active);
+        assert_eq!(feed(&mut detector, 0.0, 900), SuspendState::Active);
+
+        // Switching off while suspended resumes
+        feed(&mut detector, 0.0, 2000);
+        assert_eq!(detector.state(), SuspendState::Suspended);
+        detector.set_enabled(false);
+        assert_eq!(detector.update(0.0), SuspendState::Active);
+    }
+}
@@ -0,0 +1,138 @@
+//! Stereo width processor
+//!
+//! Mid/side encode, scale the side component, decode. A width of 1.0 leaves the
+//! signal unchanged, 0.0 collapses it to mono and 2.0 doubles the side level.
+
+/// Mid/side stereo width processor
+pub struct StereoWidth {
+    /// Width (0.0 = mono, 1.0 = unchanged, 2.0 = maximally wide)
+    width: f64,
+    /// Smoothed mid power
+    mid_power: f64,
+    /// Smoothed side power
+    side_power: f64,
+    /// Power smoothing coefficient
+    power_coeff: f64,
+}
+
+impl StereoWidth {
+    /// Create a new stereo width processor (width 1.0)
+    pub fn new(sample_rate: usize) -> Self {
+        // ~50ms power averaging
+        let power_coeff = (-1.0 / (0.05 * sample_rate as f64)).exp();
+        Self {
+            width: 1.0,
+            mid_power: 0.0,
+            side_power: 0.0,
+            power_coeff,
+        }
+    }
+
+    /// Process a stereo input sample and return stereo output
+    pub fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let (left_in, right_in) = input;
+
+        // Encode
+        let mid = (left_in + right_in) * 0.5;
+        let side = (left_in - right_in) * 0.5;
+
+        let side_gain = self.side_gain(mid, side);
+        let side = side * side_gain;
+
+        // Decode
+        (mid + side, mid - side)
+    }
+
+    /// Set the stereo width (0.0 to 2.0)
+    pub fn set_width(&mut self, width: f64) {
+        self.width = width.clamp(0.0, 2.0);
+    }
+
+    /// Current stereo width
+    pub fn width(&self) -> f64 {
+        self.width
+    }
+
+    /// Reset the power trackers
+    pub fn reset(&mut self) {
+        self.mid_power = 0.0;
+        self.side_power = 0.0;
+    }
+
+    /// Gain applied to the side component
+    ///
+    /// Narrowing is always applied as set. Widening is limited so the side level
+    /// doesn't exceed the mid level: past that point L and R become negatively
+    /// correlated and the image collapses on a mono downmix.
+    fn side_gain(&mut self, mid: f64, side: f64) -> f64 {
+        self.mid_power = self.power_coeff * self.mid_power + (1.0 - self.power_coeff) * mid * mid;
+        self.side_power = self.power_coeff * self.side_power + (1.0 - self.power_coeff) * side * side;
+
+        if self.width <= 1.0 || self.side_power <= 1e-12 {
+            return self.width;
+        }
+
+        let max_gain = (self.mid_power / self.side_power).sqrt().max(1.0);
+        self.width.min(max_gain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zero_width_is_mono() {
+        let mut stereo = StereoWidth::new(44100);
+        stereo.set_width(0.0);
+
+        let (left, right) = stereo.tick((0.8, 0.2));
+        assert_eq!(left, right);
+        assert!((left - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_unity_width_is_passthrough() {
+        let mut stereo = StereoWidth::new(44100);
+
+        for &(left_in, right_in) in &[(0.8, 0.2), (-0.3, 0.6), (0.0, 1.0)] {
+            let (left, right) = stereo.tick((left_in, right_in));
+            assert!((left - left_in).abs() < 1e-12);
+            assert!((right - right_in).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_wide_increases_side() {
+        let mut stereo = StereoWidth::new(44100);
+        stereo.set_width(2.0);
+
+        // Mid-dominant material, so the mono-compatibility limit doesn't engage
+        let mut output = (0.0, 0.0);
+        for _ in 0..1000 {
+            output = stereo.tick((1.0, 0.5));
+        }
+        let side_in = (1.0 - 0.5) * 0.5;
+        let side_out = (output.0 - output.1) * 0.5;
+        assert!((side_out - 2.0 * side_in).abs() < 1e-9);
+
+        // Mid is untouched
+        assert!(((output.0 + output.1) * 0.5 - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_mono_compatibility_limits_widening() {
+        let mut stereo = StereoWidth::new(44100);
+        stereo.set_width(2.0);
+
+        // Side-heavy material: widening would make L and R anti-correlated
+        let mut output = (0.0, 0.0);
+        for _ in 0..5000 {
+            output = stereo.tick((0.6, -0.4));
+        }
+        let mid_out = (output.0 + output.1) * 0.5;
+        let side_out = (output.0 - output.1) * 0.5;
+        assert!((side_out - 0.5).abs() < 1e-9);
+        assert!((mid_out - 0.1).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,226 @@
+//! Stutter (beat repeat)
+//!
+//! Keeps the last second of audio in a history buffer and passes the input
+//! through untouched until triggered. A trigger captures the most recent slice
+//! and loops it for the hold time, then hands back to the live signal.
+//!
+//! A slice cut out of running audio doesn't end where it starts, so looping it
+//! as is would click on every repeat. The capture also takes a few milliseconds
+//! from just before the slice, and the end of each repeat crossfades into them:
+//! that audio runs straight on into the slice's first sample, so the wrap is
+//! seamless. Going into and out of the stutter crossfades with the live signal
+//! over the same few milliseconds.
+
+/// Longest slice in milliseconds (also the length of the history)
+const MAX_SLICE_MS: f64 = 1000.0;
+/// Shortest slice in milliseconds
+const MIN_SLICE_MS: f64 = 10.0;
+/// Longest hold in milliseconds
+const MAX_HOLD_MS: f64 = 10000.0;
+/// Crossfade at the loop point and in and out of the stutter, in milliseconds
+const FADE_MS: f64 = 2.0;
+
+/// Beat repeat effect
+pub struct Stutter {
+    /// Sample rate in Hz
+    sample_rate: usize,
+    /// Recent input, always recording (ring buffer)
+    history: Vec<(f64, f64)>,
+    /// Write position in the history
+    history_index: usize,
+    /// Captured pre-roll followed by the slice
+    slice: Vec<(f64, f64)>,
+    /// Slice length in samples
+    slice_samples: usize,
+    /// Loop crossfade length in samples (at most a quarter of the slice)
+    fade_samples: usize,
+    /// Playback position within the slice
+    position: usize,
+    /// Hold length in samples
+    hold_samples: usize,
+    /// Samples left in the current hold (0 = releasing or live)
+    remaining: usize,
+    /// Share of the stutter in the output (0.0 = live, 1.0 = repeating)
+    blend: f64,
+    /// Blend change per sample
+    blend_step: f64,
+}
+
+impl Stutter {
+    /// Create a stutter with 125ms slices held for 500ms
+    pub fn new(sample_rate: usize) -> Self {
+        let history_len = ms_to_samples(sample_rate, MAX_SLICE_MS + FADE_MS) + 1;
+        let mut stutter = Self {
+            sample_rate,
+            history: vec![(0.0, 0.0); history_len],
+            history_index: 0,
+            slice: Vec::with_capacity(history_len),
+            slice_samples: 1,
+            fade_samples: 0,
+            position: 0,
+            hold_samples: 0,
+            remaining: 0,
+            blend: 0.0,
+            blend_step: 1.0 / ms_to_samples(sample_rate, FADE_MS).max(1) as f64,
+        };
+        stutter.set_slice_ms(125.0);
+        stutter.set_hold_ms(500.0);
+        stutter
+    }
+
+    /// Process a stereo input sample and return stereo output
+    pub fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        self.history[self.history_index] = input;
+        self.history_index = (self.history_index + 1) % self.history.len();
+
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            self.blend = (self.blend + self.blend_step).min(1.0);
+        } else if self.blend > 0.0 {
+            self.blend = (self.blend - self.blend_step).max(0.0);
+        } else {
+            return input;
+        }
+
+        let repeat = self.repeat_sample();
+        if self.blend == 1.0 {
+            return repeat;
+        }
+        (
+            input.0 + (repeat.0 - input.0) * self.blend,
+            input.1 + (repeat.1 - input.1) * self.blend,
+        )
+    }
+
+    /// Next sample of the looping slice, crossfading its end into the pre-roll
+    fn repeat_sample(&mut self) -> (f64, f64) {
+        let fade = self.fade_samples;
+        let length = self.slice.len() - fade;
+        let sample = self.slice[fade + self.position];
+        let fade_start = length - fade;
+        let output = if self.position >= fade_start {
+            let preroll = self.slice[self.position - fade_start];
+            let t = (self.position - fade_start) as f64 / fade as f64;
+            (sample.0 + (preroll.0 - sample.0) * t, sample.1 + (preroll.1 - sample.1) * t)
+        } else {
+            sample
+        };
+        self.position = (self.position + 1) % length;
+        output
+    }
+
+    /// Capture the latest slice and repeat it for the hold time
+    ///
+    /// Triggering again while repeating captures a fresh slice and restarts the hold.
+    pub fn trigger(&mut self) {
+        self.fade_samples = ms_to_samples(self.sample_rate, FADE_MS).min(self.slice_samples / 4);
+        let len = self.history.len();
+        let captured = self.slice_samples + self.fade_samples;
+        let start = self.history_index + len - captured;
+        self.slice.clear();
+        self.slice.extend((0..captured).map(|i| self.history[(start + i) % len]));
+        self.position = 0;
+        self.remaining = self.hold_samples.max(1);
+    }
+
+    /// Whether the stutter is repeating (including its fade back to live)
+    pub fn is_stuttering(&self) -> bool {
+        self.remaining > 0 || self.blend > 0.0
+    }
+
+    /// Set the slice length in milliseconds (10 to 1000), used from the next trigger
+    pub fn set_slice_ms(&mut self, slice_ms: f64) {
+        self.slice_samples = ms_to_samples(self.sample_rate, slice_ms.clamp(MIN_SLICE_MS, MAX_SLICE_MS));
+    }
+
+    /// Current slice length in milliseconds
+    pub fn slice_ms(&self) -> f64 {
+        self.slice_samples as f64 * 1000.0 / self.sample_rate as f64
+    }
+
+    /// Set how long a trigger repeats the slice, in milliseconds (up to 10000)
+    pub fn set_hold_ms(&mut self, hold_ms: f64) {
+        self.hold_samples = ms_to_samples(self.sample_rate, hold_ms.clamp(0.0, MAX_HOLD_MS));
+    }
+
+    /// Current hold time in milliseconds
+    pub fn hold_ms(&self) -> f64 {
+        self.hold_samples as f64 * 1000.0 / self.sample_rate as f64
+    }
+
+    /// Clear the history and stop any repeat
+    pub fn reset(&mut self) {
+        self.history.fill((0.0, 0.0));
+        self.slice.clear();
+        self.position = 0;
+        self.remaining = 0;
+        self.blend = 0.0;
+    }
+}
+
+fn ms_to_samples(sample_rate: usize, ms: f64) -> usize {
+    (ms * sample_rate as f64 / 1000.0).round() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A ramp that never repeats, so any repeat in the output comes from the stutter
+    fn ramp(i: usize) -> (f64, f64) {
+        (i as f64 * 1e-4, -(i as f64) * 1e-4)
+    }
+
+    #[test]
+    fn test_hold_repeats_the_captured_slice() {
+        let mut stutter = Stutter::new(1000);
+        stutter.set_slice_ms(40.0);
+        stutter.set_hold_ms(300.0);
+
+        // Passes through untouched until triggered
+        for i in 0..500 {
+            assert_eq!(stutter.tick(ramp(i)), ramp(i));
+        }
+        stutter.trigger();
+        assert!(stutter.is_stuttering());
+        let output: Vec<(f64, f64)> = (500..800).map(|i| stutter.tick(ramp(i))).collect();
+
+        // Once faded in, every repeat matches the slice from 40 samples back,
+        // and away from the loop point it is the captured audio itself
+        for (n, frame) in output.iter().enumerate().skip(2) {
+            assert_eq!(*frame, output[n % 40 + 40], "sample {}", n);
+        }
+        for n in 2..38 {
+            assert_eq!(output[40 + n], ramp(460 + n));
+        }
+
+        // The loop point runs from the pre-roll into the slice without a jump
+        let largest_step = output[2..].windows(2).map(|pair| (pair[1].0 - pair[0].0).abs()).fold(0.0, f64::max);
+        assert!(largest_step < 0.0025, "largest step {}", largest_step);
+
+        // After the hold it fades back to the live signal
+        for i in 800..810 {
+            stutter.tick(ramp(i));
+        }
+        assert!(!stutter.is_stuttering());
+        assert_eq!(stutter.tick(ramp(810)), ramp(810));
+    }
+
+    #[test]
+    fn test_slice_and_hold_are_limited() {
+        let mut stutter = Stutter::new(44100);
+        stutter.set_slice_ms(5000.0);
+        assert_eq!(stutter.slice_ms(), MAX_SLICE_MS);
+        stutter.set_slice_ms(1.0);
+        assert_eq!(stutter.slice_ms(), MIN_SLICE_MS);
+        stutter.set_hold_ms(-1.0);
+        assert_eq!(stutter.hold_ms(), 0.0);
+
+        // Even the longest slice fits the history
+        stutter.set_slice_ms(MAX_SLICE_MS);
+        stutter.trigger();
+        for _ in 0..100 {
+            stutter.tick((0.1, 0.1));
+        }
+    }
+}
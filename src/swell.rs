@@ -0,0 +1,179 @@
+//! Volume swell (auto-violining)
+//!
+//! Fades each note in from silence, like rolling a volume pedal up after every
+//! pick: the attack disappears and notes bloom in like a bowed string or a
+//! pedal steel. Onsets are found by comparing a fast peak envelope of the input
+//! with a slow one; when the fast one jumps well above the slow one (and above
+//! the noise floor), a new note has started and the gain ramp restarts from zero,
+//! rising linearly to unity over the swell time. Once the input falls silent the
+//! gain parks at zero, so the next note's first samples are already muted before
+//! its onset is detected. A retrigger pulls the gain down over a millisecond
+//! rather than cutting it, so restarting the ramp mid-note doesn't click.
+
+use crate::gate::EnvelopeFollower;
+
+/// Shortest and longest swell in milliseconds
+const MIN_SWELL_MS: f64 = 10.0;
+const MAX_SWELL_MS: f64 = 2000.0;
+/// Fast envelope release in milliseconds (its attack is instant)
+const FAST_RELEASE_MS: f64 = 10.0;
+/// Slow envelope attack and release in milliseconds
+const SLOW_ATTACK_MS: f64 = 30.0;
+const SLOW_RELEASE_MS: f64 = 300.0;
+/// How far the fast envelope must jump above the slow one for an onset (+6 dB)
+const ONSET_RATIO: f64 = 2.0;
+/// Input level below which the input counts as silent (-50 dBFS)
+const SILENCE_LEVEL: f64 = 0.003;
+/// Shortest time between onsets in milliseconds, so one pick triggers once
+const MIN_ONSET_INTERVAL_MS: f64 = 50.0;
+/// Time for the gain to drop when the ramp restarts, in milliseconds
+const RETRIGGER_FALL_MS: f64 = 1.0;
+
+/// Automatic volume swell
+pub struct Swell {
+    /// Sample rate in Hz
+    sample_rate: usize,
+    /// Instant-attack envelope that jumps with each pick
+    fast: EnvelopeFollower,
+    /// Slow envelope the fast one is compared with
+    slow: EnvelopeFollower,
+    /// Swell time in samples
+    swell_samples: usize,
+    /// Samples since the last onset (capped at the swell time)
+    position: usize,
+    /// Samples until another onset can be detected
+    holdoff: usize,
+    /// Minimum onset interval in samples
+    min_interval: usize,
+    /// Gain applied to the signal (0.0 to 1.0)
+    gain: f64,
+    /// Smoothing coefficient while the gain falls
+    fall: f64,
+}
+
+impl Swell {
+    /// Create a swell of 300ms
+    pub fn new(sample_rate: usize) -> Self {
+        let mut swell = Self {
+            sample_rate,
+            fast: EnvelopeFollower::new(sample_rate, 0.0, FAST_RELEASE_MS),
+            slow: EnvelopeFollower::new(sample_rate, SLOW_ATTACK_MS, SLOW_RELEASE_MS),
+            swell_samples: 1,
+            position: 0,
+            holdoff: 0,
+            min_interval: ms_to_samples(sample_rate, MIN_ONSET_INTERVAL_MS),
+            gain: 0.0,
+            fall: (-1000.0 / (RETRIGGER_FALL_MS * sample_rate as f64)).exp(),
+        };
+        swell.set_swell_ms(300.0);
+        swell
+    }
+
+    /// Process a stereo input sample and return stereo output
+    pub fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        let level = input.0.abs().max(input.1.abs());
+        let fast = self.fast.process(level);
+        let slow = self.slow.process(level);
+
+        self.holdoff = self.holdoff.saturating_sub(1);
+        if fast < SILENCE_LEVEL {
+            self.position = 0;
+        } else if self.holdoff == 0 && fast > slow * ONSET_RATIO {
+            self.position = 0;
+            self.holdoff = self.min_interval;
+        } else {
+            self.position = (self.position + 1).min(self.swell_samples);
+        }
+
+        let target = self.position as f64 / self.swell_samples as f64;
+        self.gain = if target < self.gain {
+            target + self.fall * (self.gain - target)
+        } else {
+            target
+        };
+        (input.0 * self.gain, input.1 * self.gain)
+    }
+
+    /// Set the swell time in milliseconds (10 to 2000)
+    pub fn set_swell_ms(&mut self, swell_ms: f64) {
+        self.swell_samples = ms_to_samples(self.sample_rate, swell_ms.clamp(MIN_SWELL_MS, MAX_SWELL_MS)).max(1);
+        self.position = self.position.min(self.swell_samples);
+    }
+
+    /// Current swell time in milliseconds
+    pub fn swell_ms(&self) -> f64 {
+        self.swell_samples as f64 * 1000.0 / self.sample_rate as f64
+    }
+
+    /// Clear the envelopes and mute until the next note
+    pub fn reset(&mut self) {
+        self.fast.reset();
+        self.slow.reset();
+        self.position = 0;
+        self.holdoff = 0;
+        self.gain = 0.0;
+    }
+}
+
+fn ms_to_samples(sample_rate: usize, ms: f64) -> usize {
+    (ms * sample_rate as f64 / 1000.0).round() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    /// A plucked 220Hz note starting at sample `start`: full level at once, then decaying
+    fn pluck(i: usize, start: usize) -> f64 {
+        if i < start {
+            return 0.0;
+        }
+        let t = (i - start) as f64 / 44100.0;
+        0.8 * (-t * 2.0).exp() * (2.0 * PI * 220.0 * t).sin()
+    }
+
+    /// Ratio of output to input peak over `range`
+    fn gain_over(output: &[f64], input: &[f64], range: std::ops::Range<usize>) -> f64 {
+        let peak = |signal: &[f64]| signal[range.clone()].iter().map(|s| s.abs()).fold(0.0, f64::max);
+        peak(output) / peak(input)
+    }
+
+    #[test]
+    fn test_attack_is_muted_and_swells_to_full_level() {
+        let mut swell = Swell::new(44100);
+        swell.set_swell_ms(200.0);
+        let input: Vec<f64> = (0..44100).map(|i| pluck(i, 4410)).collect();
+        let output: Vec<f64> = input.iter().map(|&s| swell.tick((s, s)).0).collect();
+
+        // Silent before the note, the attack held down, about halfway after
+        // half the swell, and the full note once the swell has finished
+        assert!(output[..4410].iter().all(|&s| s == 0.0));
+        let onset = gain_over(&output, &input, 4410..4410 + 220);
+        assert!(onset < 0.05, "gain over the first 5ms {}", onset);
+        let halfway = gain_over(&output, &input, 4410 + 4300..4410 + 4500);
+        assert!((halfway - 0.5).abs() < 0.05, "gain halfway {}", halfway);
+        assert_eq!(output[4410 + 8900..], input[4410 + 8900..]);
+    }
+
+    #[test]
+    fn test_new_note_restarts_the_swell() {
+        let mut swell = Swell::new(44100);
+        swell.set_swell_ms(100.0);
+        // A second, louder pick half a second in, while the first still rings
+        let input: Vec<f64> = (0..44100).map(|i| pluck(i, 0) + pluck(i, 22050)).collect();
+        let output: Vec<f64> = input.iter().map(|&s| swell.tick((s, s)).0).collect();
+
+        assert!(gain_over(&output, &input, 20000..22000) > 0.99);
+        let retriggered = gain_over(&output, &input, 22050 + 250..22050 + 450);
+        assert!(retriggered < 0.1, "gain after the second pick {}", retriggered);
+        assert!(gain_over(&output, &input, 22050 + 4500..22050 + 6000) > 0.99);
+
+        // No step bigger than the signal itself moves in a sample: the drop is smoothed
+        let largest_step = output.windows(2).map(|pair| (pair[1] - pair[0]).abs()).fold(0.0, f64::max);
+        assert!(largest_step < 0.05, "largest step {}", largest_step);
+
+        swell.reset();
+        assert_eq!(swell.tick((0.5, 0.5)), (0.0, 0.0));
+    }
+}
@@ -0,0 +1,206 @@
+//! Tempo handling
+//!
+//! Tap tempo averages the intervals between the last few taps and converts
+//! them to beats per minute. Note values (`1/8`, dotted `1/8.`, triplet `1/8t`)
+//! turn the tempo into times for tempo-synced effects.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Gaps longer than this start a new tap sequence (30 BPM)
+const MAX_TAP_GAP: Duration = Duration::from_secs(2);
+/// Number of tap intervals averaged
+const MAX_INTERVALS: usize = 4;
+
+/// Tempo used until a tempo is tapped or set
+pub const DEFAULT_BPM: f64 = 120.0;
+
+/// Length of one beat (a quarter note) in milliseconds
+pub fn beat_ms(bpm: f64) -> f64 {
+    60000.0 / bpm
+}
+
+/// Rhythmic feel of a note value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoteFeel {
+    Straight,
+    /// One and a half times as long
+    Dotted,
+    /// Three in the time of two
+    Triplet,
+}
+
+/// Musical note division such as a dotted eighth
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoteValue {
+    /// Fraction of a whole note numerator
+    numerator: u32,
+    /// Fraction of a whole note denominator
+    denominator: u32,
+    /// Straight, dotted or triplet
+    feel: NoteFeel,
+}
+
+impl NoteValue {
+    /// One beat
+    pub const QUARTER: NoteValue = NoteValue {
+        numerator: 1,
+        denominator: 4,
+        feel: NoteFeel::Straight,
+    };
+
+    /// Parse a division like `1/4`, `1/8.` (dotted) or `1/8t` (triplet)
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid note value '{}' (expected e.g. 1/4, 1/8. or 1/8t)", text);
+
+        let (fraction, feel) = if let Some(fraction) = text.strip_suffix('.') {
+            (fraction, NoteFeel::Dotted)
+        } else if let Some(fraction) = text.strip_suffix(['t', 'T']) {
+            (fraction, NoteFeel::Triplet)
+        } else {
+            (text, NoteFeel::Straight)
+        };
+
+        let (numerator, denominator) = fraction.split_once('/').ok_or_else(invalid)?;
+        let numerator: u32 = numerator.parse().map_err(|_| invalid())?;
+        let denominator: u32 = denominator.parse().map_err(|_| invalid())?;
+        if numerator == 0 || denominator == 0 {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            numerator,
+            denominator,
+            feel,
+        })
+    }
+
+    /// Length of the note in milliseconds at the given tempo
+    pub fn duration_ms(&self, bpm: f64) -> f64 {
+        let whole_note_ms = beat_ms(bpm) * 4.0;
+        let straight = whole_note_ms * self.numerator as f64 / self.denominator as f64;
+        match self.feel {
+            NoteFeel::Straight => straight,
+            NoteFeel::Dotted => straight * 1.5,
+            NoteFeel::Triplet => straight * 2.0 / 3.0,
+        }
+    }
+
+    /// Rate in Hz of one cycle per note at the given tempo
+    pub fn rate_hz(&self, bpm: f64) -> f64 {
+        1000.0 / self.duration_ms(bpm)
+    }
+}
+
+impl std::fmt::Display for NoteValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)?;
+        match self.feel {
+            NoteFeel::Straight => Ok(()),
+            NoteFeel::Dotted => write!(f, "."),
+            NoteFeel::Triplet => write!(f, "t"),
+        }
+    }
+}
+
+/// Tap tempo tracker
+#[derive(Debug, Default)]
+pub struct TapTempo {
+    /// Time of the previous tap
+    last_tap: Option<Instant>,
+    /// Most recent tap intervals, oldest first
+    intervals: VecDeque<Duration>,
+}
+
+impl TapTempo {
+    /// Create a tracker with no taps
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a tap at `now` and return the tempo in BPM once there are two taps
+    ///
+    /// A gap longer than two seconds discards the previous taps.
+    pub fn tap(&mut self, now: Instant) -> Option<f64> {
+        let last_tap = self.last_tap.replace(now);
+        let gap = now.saturating_duration_since(last_tap?);
+
+        if gap > MAX_TAP_GAP || gap.is_zero() {
+            self.intervals.clear();
+            return None;
+        }
+
+        if self.intervals.len() == MAX_INTERVALS {
+            self.intervals.pop_front();
+        }
+        self.intervals.push_back(gap);
+
+        let average = self.intervals.iter().sum::<Duration>() / self.intervals.len() as u32;
+        Some(60.0 / average.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tap_averaging() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut tap_tempo = TapTempo::new();
+
+        assert_eq!(tap_tempo.tap(at(0)), None);
+        // 400ms is 150 BPM; 400ms and 600ms average to 500ms = 120 BPM
+        assert!((tap_tempo.tap(at(400)).unwrap() - 150.0).abs() < 1e-9);
+        assert!((tap_tempo.tap(at(1000)).unwrap() - 120.0).abs() < 1e-9);
+
+        // Only the last four intervals count
+        for (i, ms) in [1250, 1500, 1750, 2000].into_iter().enumerate() {
+            let bpm = tap_tempo.tap(at(ms)).unwrap();
+            if i == 3 {
+                assert!((bpm - 240.0).abs() < 1e-9, "{}", bpm);
+            }
+        }
+    }
+
+    #[test]
+    fn test_long_gap_resets() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut tap_tempo = TapTempo::new();
+
+        tap_tempo.tap(at(0));
+        tap_tempo.tap(at(1000));
+        // Too long a gap starts over
+        assert_eq!(tap_tempo.tap(at(4000)), None);
+        // The old 1000ms interval is gone
+        assert!((tap_tempo.tap(at(4500)).unwrap() - 120.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_beat_ms() {
+        assert_eq!(beat_ms(120.0), 500.0);
+        assert_eq!(beat_ms(60.0), 1000.0);
+    }
+
+    #[test]
+    fn test_note_value_durations() {
+        let ms = |text: &str, bpm: f64| NoteValue::parse(text).unwrap().duration_ms(bpm);
+        assert_eq!(ms("1/8", 120.0), 250.0);
+        assert!((ms("1/8t", 120.0) - 500.0 / 3.0).abs() < 1e-9);
+        assert_eq!(ms("1/8.", 120.0), 375.0);
+        assert_eq!(ms("1/4", 90.0), beat_ms(90.0));
+        assert_eq!(ms("3/16", 120.0), 375.0);
+        assert_eq!(NoteValue::QUARTER.duration_ms(120.0), 500.0);
+    }
+
+    #[test]
+    fn test_note_value_parsing() {
+        assert_eq!(NoteValue::parse("1/16T").unwrap().to_string(), "1/16t");
+        assert_eq!(NoteValue::parse("1/8.").unwrap().to_string(), "1/8.");
+        assert!(NoteValue::parse("eighth").is_err());
+        assert!(NoteValue::parse("1/0").is_err());
+        assert!(NoteValue::parse("1/8x").is_err());
+    }
+}
@@ -0,0 +1,113 @@
+//! Input tilt
+//!
+//! Simulates how a pedal's input impedance loads a guitar pickup: a low
+//! impedance rolls off the highs, a high one keeps them. Implemented as a
+//! first-order high shelf (one-pole split around SHELF_HZ) at the very front
+//! of the chain, before any effect. Positive amounts darken, negative amounts
+//! brighten, and the shelf is limited to +/-6 dB so it stays a subtle colour.
+
+use std::f64::consts::PI;
+
+/// Corner frequency of the shelf in Hz
+const SHELF_HZ: f64 = 2000.0;
+/// Shelf gain in dB at full tilt
+const MAX_TILT_DB: f64 = 6.0;
+
+/// First-order high shelf applied to the input
+pub struct InputTilt {
+    /// Tilt amount (-1.0 = bright to 1.0 = dark, 0.0 = flat)
+    amount: f64,
+    /// Linear gain of the band above the corner
+    high_gain: f64,
+    /// One-pole low-pass coefficient for the band split
+    coefficient: f64,
+    /// Low-pass state per channel
+    lowpass: [f64; 2],
+}
+
+impl InputTilt {
+    /// Create a flat tilt
+    pub fn new(sample_rate: usize) -> Self {
+        Self {
+            amount: 0.0,
+            high_gain: 1.0,
+            coefficient: (-2.0 * PI * SHELF_HZ / sample_rate as f64).exp(),
+            lowpass: [0.0; 2],
+        }
+    }
+
+    /// Process a stereo input sample and return stereo output
+    pub fn tick(&mut self, input: (f64, f64)) -> (f64, f64) {
+        if self.amount == 0.0 {
+            return input;
+        }
+        (self.process(0, input.0), self.process(1, input.1))
+    }
+
+    /// Shelve one channel: the low band passes, the high band is scaled
+    fn process(&mut self, channel: usize, input: f64) -> f64 {
+        let low = input + self.coefficient * (self.lowpass[channel] - input);
+        self.lowpass[channel] = low;
+        low + (input - low) * self.high_gain
+    }
+
+    /// Set the tilt (-1.0 brighter to 1.0 darker)
+    pub fn set_amount(&mut self, amount: f64) {
+        self.amount = amount.clamp(-1.0, 1.0);
+        self.high_gain = 10f64.powf(-self.amount * MAX_TILT_DB / 20.0);
+    }
+
+    /// Current tilt amount
+    pub fn amount(&self) -> f64 {
+        self.amount
+    }
+
+    /// Clear the filter state
+    pub fn reset(&mut self) {
+        self.lowpass = [0.0; 2];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Energy of the sample-to-sample difference, a rough high-frequency measure
+    fn high_frequency_energy(samples: &[f64]) -> f64 {
+        samples.windows(2).map(|pair| (pair[1] - pair[0]).powi(2)).sum()
+    }
+
+    #[test]
+    fn test_positive_tilt_darkens() {
+        // Deterministic white noise as a broadband input
+        let mut state: u32 = 0x2468_ace0;
+        let noise: Vec<f64> = (0..44100)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f64 / (1u32 << 24) as f64 - 0.5
+            })
+            .collect();
+
+        let render = |amount: f64| {
+            let mut tilt = InputTilt::new(44100);
+            tilt.set_amount(amount);
+            noise.iter().map(|&sample| tilt.tick((sample, sample)).0).collect::<Vec<_>>()
+        };
+
+        let flat = render(0.0);
+        assert_eq!(flat, noise);
+        let dark = high_frequency_energy(&render(1.0));
+        let bright = high_frequency_energy(&render(-1.0));
+        assert!(dark < high_frequency_energy(&flat) * 0.5, "dark {}", dark);
+        assert!(bright > high_frequency_energy(&flat) * 2.0, "bright {}", bright);
+    }
+
+    #[test]
+    fn test_amount_is_clamped() {
+        let mut tilt = InputTilt::new(44100);
+        tilt.set_amount(5.0);
+        assert_eq!(tilt.amount(), 1.0);
+        tilt.set_amount(-5.0);
+        assert_eq!(tilt.amount(), -1.0);
+    }
+}
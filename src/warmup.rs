@@ -0,0 +1,137 @@
+//! Startup fade-in
+//!
+//! When an output stream starts, the effects' state is empty and the first buffer
+//! can jump from silence to full level with an audible pop. The output callback
+//! ramps its gain from 0 to 1 over the first 50ms instead, counting frames. Every
+//! output stream gets a fresh counter when it is built, so a rebuilt stream fades
+//! in as well.
+//!
+//! Before that, a safe start keeps the output muted until the input has filled the
+//! ring buffer to a threshold. Otherwise the output callback can start draining the
+//! buffer before the input stream has delivered anything and play a stutter of
+//! underrun zeros. The output callback checks the fill at the start of each buffer
+//! and sets a shared "primed" flag the first time it reaches the threshold; from
+//! then on the audio passes (through the fade-in) whatever the fill. The fill the
+//! output starts from stays in the buffer, so a higher threshold adds latency.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Length of the fade-in in seconds
+const FADE_IN_SECONDS: f64 = 0.05;
+/// Share of the ring buffer filled before the output unmutes, unless configured
+pub const DEFAULT_PRIME_FILL: f64 = 0.25;
+
+/// Frame counter ramping the output gain up after a stream starts
+pub struct Warmup {
+    /// Frames output so far (stops counting once the fade is over)
+    frames: usize,
+    /// Length of the fade in frames
+    fade_frames: usize,
+}
+
+impl Warmup {
+    /// Start a fade-in for a stream at `sample_rate`
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            frames: 0,
+            fade_frames: ((FADE_IN_SECONDS * f64::from(sample_rate)) as usize).max(1),
+        }
+    }
+
+    /// Gain for the next output frame (0.0 rising to 1.0)
+    pub fn next_gain(&mut self) -> f64 {
+        if self.frames >= self.fade_frames {
+            return 1.0;
+        }
+        let gain = self.frames as f64 / self.fade_frames as f64;
+        self.frames += 1;
+        gain
+    }
+}
+
+/// Mutes the output until the ring buffer has filled to a threshold
+pub struct SafeStart {
+    /// Samples in the ring buffer needed to unmute (0 = unmuted from the start)
+    threshold: usize,
+    /// Set once the threshold has been reached, shared with whoever started the stream
+    primed: Arc<AtomicBool>,
+}
+
+impl SafeStart {
+    /// Wait for `fill` (0.0 to 1.0) of a ring buffer of `capacity` samples
+    pub fn new(fill: f64, capacity: usize, primed: Arc<AtomicBool>) -> Self {
+        let threshold = (fill.clamp(0.0, 1.0) * capacity as f64).round() as usize;
+        primed.store(threshold == 0, Ordering::Relaxed);
+        Self { threshold, primed }
+    }
+
+    /// Whether the output may play, given the ring buffer's current fill in samples
+    pub fn ready(&mut self, fill: usize) -> bool {
+        if self.primed.load(Ordering::Relaxed) {
+            return true;
+        }
+        if fill >= self.threshold {
+            self.primed.store(true, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn test_safe_start_stays_silent_until_the_buffer_fills() {
+        let primed = Arc::new(AtomicBool::new(true));
+        let mut safe_start = SafeStart::new(0.5, 256, primed.clone());
+        let mut warmup = Warmup::new(1000);
+        assert!(!primed.load(Ordering::Relaxed));
+
+        // The input delivers 32 samples per buffer, the output asks for 16: the
+        // output holds off (and takes nothing) until 128 samples are waiting
+        let mut ring = VecDeque::new();
+        let mut output = Vec::new();
+        let mut next_input = 1.0;
+        for _ in 0..20 {
+            for _ in 0..32 {
+                ring.push_back(next_input);
+                next_input += 1.0;
+            }
+            if safe_start.ready(ring.len()) {
+                output.extend((0..16).map(|_| ring.pop_front().unwrap_or(0.0) * warmup.next_gain()));
+            } else {
+                output.extend([0.0; 16]);
+            }
+        }
+
+        // Silent for the first three buffers, then the input from its first sample,
+        // fading in, with no underrun gaps
+        assert!(primed.load(Ordering::Relaxed));
+        assert!(output[..48].iter().all(|&sample| sample == 0.0));
+        assert_eq!(output[48], 0.0);
+        assert!(output[49..].iter().all(|&sample| sample > 0.0));
+        assert!((output[49] - 2.0 * 0.02).abs() < 1e-12);
+
+        // Once primed it stays open, even if the buffer runs low
+        assert!(safe_start.ready(0));
+
+        // No threshold: open from the start
+        assert!(SafeStart::new(0.0, 256, Arc::new(AtomicBool::new(false))).ready(0));
+    }
+
+    #[test]
+    fn test_output_ramps_up_over_the_first_50ms() {
+        // 50ms at 1 kHz is 50 frames
+        let mut warmup = Warmup::new(1000);
+        let gains: Vec<f64> = (0..100).map(|_| warmup.next_gain()).collect();
+
+        assert_eq!(gains[0], 0.0);
+        assert!(gains[..50].windows(2).all(|pair| pair[1] > pair[0]));
+        assert!((gains[25] - 0.5).abs() < 1e-12);
+        assert!(gains[50..].iter().all(|&gain| gain == 1.0));
+    }
+}
@@ -0,0 +1,177 @@
+//! Golden-file regression tests for the distortion types
+//!
+//! A fixed swept sine runs through each distortion type at fixed settings, and the
+//! output is compared with the one stored in `tests/golden`, so a refactor that
+//! changes the tone of a type fails here. When a change to the sound is intended,
+//! regenerate the files with `BLESS=1 cargo test --test distortion_golden` and
+//! check them in with it.
+//!
+//! The files hold the interleaved stereo output as text, one sample per line,
+//! each printed so it reads back to the same `f32`.
+
+use std::f64::consts::PI;
+use std::path::PathBuf;
+
+use mac_pedals::chain::EffectChain;
+use mac_pedals::distortion::{Distortion, DistortionType};
+use mac_pedals::offline::process_buffer;
+
+const SAMPLE_RATE: usize = 44100;
+/// Length of the test signal in samples
+const SIGNAL_LENGTH: usize = 4096;
+/// Largest difference from the golden output accepted, for rounding differences
+/// between platforms
+const TOLERANCE: f32 = 1e-5;
+/// Mismatching samples listed in a failure report
+const REPORTED_MISMATCHES: usize = 10;
+
+/// Exponential sine sweep from 40 Hz to 8 kHz at -2 dBFS
+fn swept_sine() -> Vec<f32> {
+    let (start, end) = (40.0_f64, 8000.0_f64);
+    let duration = SIGNAL_LENGTH as f64 / SAMPLE_RATE as f64;
+    let rate = (end / start).ln() / duration;
+    (0..SIGNAL_LENGTH)
+        .map(|i| {
+            let t = i as f64 / SAMPLE_RATE as f64;
+            let phase = 2.0 * PI * start * ((rate * t).exp() - 1.0) / rate;
+            (0.8 * phase.sin()) as f32
+        })
+        .collect()
+}
+
+/// Render the sweep through a chain holding only the distortion
+fn render(distortion_type: DistortionType) -> Vec<f32> {
+    let mut distortion = Distortion::new(SAMPLE_RATE);
+    distortion.set_distortion_type(distortion_type);
+    distortion.set_drive(0.6);
+    distortion.set_level(0.8);
+    distortion.set_tone(0.5);
+    distortion.set_bit_crusher_params(0.3, 0.5);
+
+    let mut chain = EffectChain::new(SAMPLE_RATE);
+    chain.set_switch_fade_ms(0.0);
+    chain.add("dist", Box::new(distortion));
+    process_buffer(&swept_sine(), &mut chain)
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("distortion_{}.txt", name))
+}
+
+fn write_golden(name: &str, output: &[f32]) {
+    let text: String = output.iter().map(|sample| format!("{}\n", sample)).collect();
+    std::fs::write(golden_path(name), text).unwrap();
+}
+
+fn read_golden(name: &str) -> Vec<f32> {
+    let path = golden_path(name);
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!("Couldn't read {}: {} (run with BLESS=1 to create it)", path.display(), e)
+    });
+    text.lines().map(|line| line.parse().unwrap()).collect()
+}
+
+/// Describe how `actual` differs from `expected`, or None if it matches
+fn diff_report(expected: &[f32], actual: &[f32]) -> Option<String> {
+    if expected.len() != actual.len() {
+        return Some(format!("expected {} samples, got {}", expected.len(), actual.len()));
+    }
+    let mismatches: Vec<(usize, f32, f32)> = expected
+        .iter()
+        .zip(actual)
+        .enumerate()
+        .filter(|(_, (expected, actual))| (*expected - *actual).abs() > TOLERANCE)
+        .map(|(i, (&expected, &actual))| (i, expected, actual))
+        .collect();
+    if mismatches.is_empty() {
+        return None;
+    }
+
+    let max_diff = mismatches.iter().map(|&(_, expected, actual)| (expected - actual).abs()).fold(0.0, f32::max);
+    let mut report = format!(
+        "{} of {} samples differ by more than {:e} (largest difference {:e})\n",
+        mismatches.len(),
+        expected.len(),
+        TOLERANCE,
+        max_diff
+    );
+    for &(i, expected, actual) in mismatches.iter().take(REPORTED_MISMATCHES) {
+        let channel = if i % 2 == 0 { "L" } else { "R" };
+        report.push_str(&format!(
+            "  frame {:5} {}: expected {:>14e}, got {:>14e} (diff {:e})\n",
+            i / 2,
+            channel,
+            expected,
+            actual,
+            actual - expected
+        ));
+    }
+    if mismatches.len() > REPORTED_MISMATCHES {
+        report.push_str(&format!("  ... and {} more\n", mismatches.len() - REPORTED_MISMATCHES));
+    }
+    Some(report)
+}
+
+/// Compare a type's output with its golden file, or rewrite the file under BLESS=1
+fn check(name: &str) {
+    let distortion_type = DistortionType::from_name(name).unwrap();
+    let output = render(distortion_type);
+
+    // The render itself has to be deterministic for the comparison to mean anything
+    assert_eq!(output, render(distortion_type), "{} renders differently each time", name);
+
+    if std::env::var_os("BLESS").is_some_and(|value| value != "0") {
+        write_golden(name, &output);
+        return;
+    }
+    if let Some(report) = diff_report(&read_golden(name), &output) {
+        panic!(
+            "{} distortion output changed from {}:\n{}If the change is intended, rerun with BLESS=1 and check in the new file",
+            name,
+            golden_path(name).display(),
+            report
+        );
+    }
+}
+
+#[test]
+fn test_soft_matches_golden() {
+    check("soft");
+}
+
+#[test]
+fn test_hard_matches_golden() {
+    check("hard");
+}
+
+#[test]
+fn test_bit_crusher_matches_golden() {
+    check("bit");
+}
+
+#[test]
+fn test_wavefolder_matches_golden() {
+    check("wave");
+}
+
+#[test]
+fn test_overdrive_matches_golden() {
+    check("over");
+}
+
+#[test]
+fn test_cascade_matches_golden() {
+    check("cascade");
+}
+
+#[test]
+fn test_diff_report_lists_mismatches() {
+    let expected = vec![0.0, 0.5, -0.5, 0.25];
+    assert_eq!(diff_report(&expected, &expected), None);
+
+    let report = diff_report(&expected, &[0.0, 0.5, -0.4, 0.25]).unwrap();
+    assert!(report.starts_with("1 of 4 samples differ"), "{}", report);
+    assert!(report.contains("frame     1 L"), "{}", report);
+
+    assert_eq!(diff_report(&expected, &[0.0]).unwrap(), "expected 4 samples, got 1");
+}
@@ -0,0 +1,98 @@
+//! Allocation checks for the audio thread
+//!
+//! The output callback runs the chain with its lock held, so nothing it reaches
+//! may allocate: an allocation can take a lock inside the allocator and stall the
+//! callback. These tests count the allocations made on the test's own thread
+//! while the engine's chain, every stage switched on, runs through the paths the
+//! callback takes.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use mac_pedals::Engine;
+use mac_pedals::chain::EffectChain;
+
+const SAMPLE_RATE: usize = 48000;
+
+/// Counts allocations per thread, so tests running in parallel don't mix
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_allocation() {
+    let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Allocations made on this thread while running `f`
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+/// Switch every stage on and play a second of a chord into the chain, so every
+/// delay line, tail and envelope holds something to clear
+fn ring(chain: &mut EffectChain) {
+    for name in chain.stage_names() {
+        chain.set_enabled(name, true).unwrap();
+    }
+    for i in 0..SAMPLE_RATE {
+        let t = i as f64 / SAMPLE_RATE as f64;
+        let chord = [110.0, 164.8, 220.0].iter().map(|hz| (std::f64::consts::TAU * hz * t).sin()).sum::<f64>();
+        chain.process_frame((chord * 0.2, chord * 0.2));
+    }
+}
+
+#[test]
+fn test_auto_suspend_clears_the_chain_without_allocating() {
+    let engine = Engine::offline(SAMPLE_RATE).unwrap();
+    let mut chain = engine.chain().lock().unwrap();
+    ring(&mut chain);
+    chain.set_auto_suspend(true);
+    chain.set_suspend_after(1.0);
+
+    // Silence until the tails have died out and the chain suspends, resetting
+    let count = allocations(|| {
+        for _ in 0..SAMPLE_RATE * 30 {
+            chain.process_frame((0.0, 0.0));
+            if chain.is_suspended() {
+                break;
+            }
+        }
+    });
+    assert!(chain.is_suspended());
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn test_panic_clears_the_chain_without_allocating() {
+    let engine = Engine::offline(SAMPLE_RATE).unwrap();
+    let mut chain = engine.chain().lock().unwrap();
+    ring(&mut chain);
+
+    // The runaway detector panics the chain from the output callback
+    let count = allocations(|| chain.panic());
+    assert!(chain.stage_names().iter().all(|name| !chain.is_enabled(name).unwrap()));
+    assert_eq!(count, 0);
+}